@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Any key bindings file must parse or be rejected with an error, never panic.
+fuzz_target!(|data: &[u8]| {
+    let _ = roguelike::fuzzing::keybindings(data);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Whatever a leaderboard server sends back must be rejected with an error, never a panic.
+fuzz_target!(|data: &[u8]| {
+    let _ = roguelike::fuzzing::leaderboard_response(data);
+});
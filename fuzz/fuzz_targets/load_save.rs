@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Malformed or truncated saves must be rejected with an error, never a panic.
+fuzz_target!(|data: &[u8]| {
+    let _ = roguelike::fuzzing::load_save(data);
+});
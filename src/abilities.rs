@@ -0,0 +1,237 @@
+//! Abilities: things the player can do without an item, each on a key of its
+//! own. Every use costs stamina, which comes back slowly, and an ability can't
+//! be used again until its cooldown has run out.
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::{self, GameEvent};
+use crate::colors::{LIGHT_BLUE, LIGHT_GREY, RED, WHITE};
+use crate::effects::{self, Effect, StatusEffect};
+use crate::entities::Objects;
+use crate::targeting::HOW_TO_AIM;
+use crate::{
+    move_by, mut_two, target_monster, traps, Category, Chooser, Game, Item, PLAYER,
+};
+
+/// the most stamina the player can have
+pub const MAX_STAMINA: i32 = 20;
+/// turns it takes to get one point of stamina back
+const STAMINA_REGEN_TURNS: u32 = 2;
+
+/// how many times the player's power a power strike hits with
+const POWER_STRIKE_MULTIPLIER: i32 = 2;
+/// how far a dash goes, at most
+const DASH_RANGE: i32 = 4;
+const SHIELD_BASH_DAMAGE: i32 = 3;
+/// how long a monster hit by a shield bash is dazed for
+const SHIELD_BASH_DAZE_TURNS: i32 = 2;
+
+/// abilities can only be used on monsters right next to the player
+const MELEE_RANGE: f32 = 1.5;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Ability {
+    /// an attack with twice the player's power behind it
+    PowerStrike,
+    /// a run of several tiles in one turn
+    Dash,
+    /// knocks a monster back and dazes it; needs a shield
+    ShieldBash,
+}
+
+/// every ability, in the order of their keys
+pub const ABILITIES: [Ability; 3] = [Ability::PowerStrike, Ability::Dash, Ability::ShieldBash];
+
+impl Ability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Ability::PowerStrike => "Power strike",
+            Ability::Dash => "Dash",
+            Ability::ShieldBash => "Shield bash",
+        }
+    }
+
+    /// the stamina it takes
+    fn cost(self) -> i32 {
+        match self {
+            Ability::PowerStrike => 6,
+            Ability::Dash => 5,
+            Ability::ShieldBash => 8,
+        }
+    }
+
+    /// turns before it can be used again
+    fn cooldown(self) -> i32 {
+        match self {
+            Ability::PowerStrike => 5,
+            Ability::Dash => 8,
+            Ability::ShieldBash => 10,
+        }
+    }
+}
+
+/// An ability the player has used recently, and the turns until it's ready.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Cooldown {
+    pub ability: Ability,
+    pub turns: i32,
+}
+
+/// turns until an ability can be used again; 0 if it's ready
+pub fn cooldown_left(game: &Game, ability: Ability) -> i32 {
+    game.cooldowns
+        .iter()
+        .find(|cooldown| cooldown.ability == ability)
+        .map_or(0, |cooldown| cooldown.turns)
+}
+
+/// how much stamina the player has in a game saved before there was any
+pub fn rested() -> i32 {
+    MAX_STAMINA
+}
+
+/// Use an ability, if the player can. True if it took the player's turn.
+pub fn use_ability(
+    ability: Ability,
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> bool {
+    let turns = cooldown_left(game, ability);
+    if turns > 0 {
+        let msg = format!("{} won't be ready for {} more turns.", ability.name(), turns);
+        game.messages.add_in(Category::Combat, msg, RED);
+        return false;
+    }
+    if game.stamina < ability.cost() {
+        let msg = format!("You're too tired to use {}.", ability.name().to_lowercase());
+        game.messages.add_in(Category::Combat, msg, RED);
+        return false;
+    }
+    let used = match ability {
+        Ability::PowerStrike => power_strike(chooser, game, objects),
+        Ability::Dash => dash(chooser, game, objects),
+        Ability::ShieldBash => shield_bash(chooser, game, objects),
+    };
+    if used {
+        game.stamina -= ability.cost();
+        game.cooldowns.push(Cooldown {
+            ability,
+            turns: ability.cooldown(),
+        });
+    }
+    used
+}
+
+fn power_strike(chooser: &mut impl Chooser, game: &mut Game, objects: &mut Objects) -> bool {
+    game.messages.add_in(
+        Category::Combat,
+        format!("Pick an enemy next to you to strike. {}", HOW_TO_AIM),
+        LIGHT_BLUE,
+    );
+    let monster_id = match target_monster(chooser, game, objects, Some(MELEE_RANGE)) {
+        Some(monster_id) => monster_id,
+        None => return false,
+    };
+    game.messages
+        .add_in(Category::Combat, "You put all your strength into the blow!", WHITE);
+    let (player, monster) = mut_two(PLAYER, monster_id, objects);
+    player.strike(monster, POWER_STRIKE_MULTIPLIER, game);
+    true
+}
+
+/// run in a straight line towards the chosen spot, until something's in the
+/// way or the dash is spent
+fn dash(chooser: &mut impl Chooser, game: &mut Game, objects: &mut Objects) -> bool {
+    game.messages.add_in(
+        Category::Combat,
+        format!("Pick where to dash to. {}", HOW_TO_AIM),
+        LIGHT_BLUE,
+    );
+    let (x, y) = match chooser.choose_tile(game, objects, Some(DASH_RANGE as f32), true, None) {
+        Some(tile) => tile,
+        None => return false,
+    };
+    let (start_x, start_y) = objects[PLAYER].pos();
+    let dx = (x - start_x).signum();
+    let dy = (y - start_y).signum();
+    for _ in 0..DASH_RANGE {
+        let before = objects[PLAYER].pos();
+        if before == (x, y) {
+            break;
+        }
+        move_by(PLAYER, dx, dy, &mut game.map, objects);
+        if objects[PLAYER].pos() == before {
+            break;
+        }
+        traps::spring(game, objects);
+        if !objects[PLAYER].alive {
+            break;
+        }
+    }
+    if objects[PLAYER].pos() == (start_x, start_y) {
+        game.messages.add_in(Category::Combat, "Something is in the way.", LIGHT_GREY);
+        return false;
+    }
+    true
+}
+
+fn shield_bash(chooser: &mut impl Chooser, game: &mut Game, objects: &mut Objects) -> bool {
+    let has_shield = game.inventory.iter().any(|item| {
+        item.item == Some(Item::Shield) && item.equipment.map_or(false, |e| e.equipped)
+    });
+    if !has_shield {
+        game.messages
+            .add_in(Category::Combat, "You need a shield in hand to bash with.", RED);
+        return false;
+    }
+    game.messages.add_in(
+        Category::Combat,
+        format!("Pick an enemy next to you to bash. {}", HOW_TO_AIM),
+        LIGHT_BLUE,
+    );
+    let monster_id = match target_monster(chooser, game, objects, Some(MELEE_RANGE)) {
+        Some(monster_id) => monster_id,
+        None => return false,
+    };
+    let name = objects[monster_id].name.clone();
+    game.messages.add_in(
+        Category::Combat,
+        format!("You slam your shield into the {}!", name),
+        WHITE,
+    );
+    // knocked back a step, if there's room
+    let dx = objects[monster_id].x - objects[PLAYER].x;
+    let dy = objects[monster_id].y - objects[PLAYER].y;
+    move_by(monster_id, dx, dy, &mut game.map, objects);
+    bus::publish(
+        game,
+        GameEvent::DamageDealt {
+            source: "shield bash".into(),
+            target: name.clone(),
+            amount: SHIELD_BASH_DAMAGE,
+        },
+    );
+    if let Some(xp) = objects[monster_id].take_damage(SHIELD_BASH_DAMAGE, game) {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+    }
+    if objects[monster_id].alive {
+        let daze = StatusEffect {
+            effect: Effect::Confusion,
+            turns: SHIELD_BASH_DAZE_TURNS,
+        };
+        effects::apply(&mut objects[monster_id], daze, game);
+    }
+    true
+}
+
+/// a turn's rest: stamina comes back, and cooldowns run down
+pub fn tick(game: &mut Game) {
+    if game.turn.is_multiple_of(STAMINA_REGEN_TURNS) {
+        game.stamina = (game.stamina + 1).min(MAX_STAMINA);
+    }
+    for cooldown in game.cooldowns.iter_mut() {
+        cooldown.turns -= 1;
+    }
+    game.cooldowns.retain(|cooldown| cooldown.turns > 0);
+}
@@ -0,0 +1,304 @@
+//! Actions: everything the player can do, as a value. A key press is turned
+//! into an `Action` first, and only then carried out, all in one place, so
+//! what a key does can change without touching what the action does. The bot
+//! and scripts play through the same actions, for everything that doesn't
+//! need a window to ask the player something.
+
+use crate::abilities::{self, Ability};
+use crate::colors::{LIGHT_GREY, WHITE};
+use crate::entities::Objects;
+use crate::input::Key;
+use crate::keybindings::Keybindings;
+use crate::resting::Activity;
+use crate::states::InventoryAction;
+use crate::{
+    archery, character, companions, dungeon, eat_corpse, initialise_fov, look, menu, message_log,
+    msgbox, next_level, overview, pick_item_up, player_move_or_attack, player_on,
+    player_on_stairs, player_step, spells, Door, Game, PlayerAction, Tcod,
+    BESTIARY_WIDTH, CHARACTER_SCREEN_WIDTH, DIRECTIONS, INVENTORY_WIDTH, PLAYER,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// step that way, talking to or trading with whoever's there, or
+    /// attacking them if they're hostile
+    Step { dx: i32, dy: i32 },
+    /// move that way, attacking whoever's there, even someone who means no
+    /// harm
+    MoveOrAttack { dx: i32, dy: i32 },
+    Wait,
+    PickUp,
+    /// eat a corpse lying here
+    Eat,
+    UseAbility(Ability),
+    /// pick a spell the player knows, and cast it
+    Cast,
+    /// shoot the equipped bow or crossbow
+    Fire,
+    /// open the inventory, to do something with an item
+    Inventory(InventoryAction),
+    /// tell the player's companions to stay or follow
+    Orders,
+    ShowBestiary,
+    ShowCharacter,
+    /// look back over the messages so far
+    ShowMessages,
+    /// move a cursor about the map, to see what's where
+    Look,
+    /// see the whole level, as far as it's been explored
+    ShowMap,
+    /// close an open door next to the player
+    CloseDoor,
+    /// wait until fully healed, or interrupted
+    Rest,
+    /// search the surroundings for traps and hidden passages, for a while
+    Search,
+    ToggleSneak,
+    /// go down the stairs, or into a side branch
+    Descend,
+    /// go back up the stairs
+    Ascend,
+    ToggleFullscreen,
+    Exit,
+}
+
+impl Action {
+    /// whether the action still means anything once the player has died
+    fn open_to_the_dead(self) -> bool {
+        matches!(self, Action::ToggleFullscreen | Action::Exit)
+    }
+}
+
+/// The action a key stands for, if any. Escape and Alt+Enter always do the
+/// same, so there's no binding the player away from leaving the game.
+pub fn from_key(key: Key, keys: &Keybindings) -> Option<Action> {
+    use crate::input::KeyCode::*;
+    match key {
+        Key {
+            code: Enter,
+            alt: true,
+            ..
+        } => Some(Action::ToggleFullscreen),
+        Key { code: Escape, .. } => Some(Action::Exit),
+        key => keys.action_for(key),
+    }
+}
+
+/// Carry out the player's action, asking them whatever it needs to know, and
+/// say whether it took a turn.
+pub fn perform(
+    action: Action,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> PlayerAction {
+    if !objects[PLAYER].alive && !action.open_to_the_dead() {
+        return PlayerAction::DidntTakeTurn;
+    }
+    let took_turn = match action {
+        Action::Step { dx, dy } => return player_step(dx, dy, tcod, game, objects),
+        Action::UseAbility(ability) => abilities::use_ability(ability, tcod, game, objects),
+        Action::Cast => cast(tcod, game, objects),
+        Action::Fire => archery::fire(tcod, game, objects),
+        Action::Inventory(action) => return PlayerAction::Inventory(action),
+        Action::Rest => return PlayerAction::Busy(Activity::Rest),
+        Action::Search => {
+            game.messages.add("You search your surroundings.", LIGHT_GREY);
+            return PlayerAction::Busy(Activity::Search);
+        }
+        Action::Orders => {
+            companions::give_orders(tcod, game, objects);
+            false
+        }
+        Action::ShowBestiary => {
+            let msg = format!("Bestiary\n\n{}", game.bestiary.describe());
+            msgbox(&msg, BESTIARY_WIDTH, tcod);
+            false
+        }
+        Action::ShowCharacter => {
+            character::show(tcod, game, objects);
+            false
+        }
+        Action::Look => {
+            look::look(tcod, game, objects);
+            false
+        }
+        Action::ShowMap => {
+            overview::show(tcod, game, objects);
+            false
+        }
+        Action::ShowMessages => {
+            message_log::show(tcod, &game.messages);
+            false
+        }
+        Action::Descend if player_on_stairs(objects) && game.tutorial.is_some() => {
+            let msg = "\nWell done, that's the tutorial finished! The real dungeon \
+                       awaits from the main menu.\n";
+            msgbox(msg, CHARACTER_SCREEN_WIDTH * 2, tcod);
+            return PlayerAction::Exit;
+        }
+        Action::Descend | Action::Ascend => {
+            // the FOV map has to be rebuilt for the new level's map
+            if resolve(action, game, objects) == Some(true) {
+                initialise_fov(tcod, &game.map);
+            }
+            false
+        }
+        Action::ToggleFullscreen => {
+            tcod.backend.toggle_fullscreen();
+            false
+        }
+        Action::Exit => return PlayerAction::Exit,
+        _ => resolve(action, game, objects).unwrap_or(false),
+    };
+    if took_turn {
+        PlayerAction::TookTurn
+    } else {
+        PlayerAction::DidntTakeTurn
+    }
+}
+
+/// Carry out an action that needs nothing more from the player, as the bot
+/// and scripts can. Returns whether it took a turn, or for taking the stairs
+/// whether the player went anywhere; `None` for an action that needs a
+/// window, or goes on for turns.
+pub fn resolve(action: Action, game: &mut Game, objects: &mut Objects) -> Option<bool> {
+    let took_turn = match action {
+        Action::MoveOrAttack { dx, dy } => {
+            player_move_or_attack(dx, dy, game, objects);
+            true
+        }
+        Action::Wait => true,
+        Action::PickUp => {
+            let item_id = objects
+                .iter()
+                .position(|object| object.pos() == objects[PLAYER].pos() && object.can_pick_up());
+            if let Some(item_id) = item_id {
+                pick_item_up(item_id, game, objects);
+            }
+            false
+        }
+        Action::Eat => {
+            let player_pos = objects[PLAYER].pos();
+            let corpse_id = objects
+                .iter()
+                .position(|object| object.pos() == player_pos && object.corpse.is_some());
+            match corpse_id {
+                Some(corpse_id) => {
+                    eat_corpse(corpse_id, game, objects);
+                    true
+                }
+                None => {
+                    game.messages.add("There is nothing here to eat.", WHITE);
+                    false
+                }
+            }
+        }
+        Action::CloseDoor => close_door(game, objects),
+        Action::ToggleSneak => {
+            game.sneaking = !game.sneaking;
+            if game.sneaking {
+                game.messages
+                    .add("You start moving slowly and quietly.", LIGHT_GREY);
+            } else {
+                game.messages.add("You stop sneaking.", LIGHT_GREY);
+            }
+            false
+        }
+        Action::Descend => {
+            let level = (game.dungeon.branch, game.dungeon_level);
+            if player_on_stairs(objects) {
+                next_level(game, objects);
+            } else if let Some(branch) = dungeon::branch_stairs_here(objects) {
+                dungeon::enter_branch(branch, game, objects);
+            }
+            level != (game.dungeon.branch, game.dungeon_level)
+        }
+        Action::Ascend => {
+            if !player_on(dungeon::UP_STAIRS, objects) {
+                return Some(false);
+            }
+            dungeon::ascend(game, objects);
+            true
+        }
+        _ => return None,
+    };
+    Some(took_turn)
+}
+
+/// cast a spell the player knows, if they pick one
+fn cast(tcod: &mut Tcod, game: &mut Game, objects: &mut Objects) -> bool {
+    if game.spells.is_empty() {
+        game.messages.add("You don't know any spells.", WHITE);
+        return false;
+    }
+    let options: Vec<String> = game
+        .spells
+        .iter()
+        .map(|spell| format!("{} ({} mana)", spell.name(), spell.cost()))
+        .collect();
+    let header = "Press the key next to a spell to cast it, or any other to cancel.\n";
+    match menu(header, &options, INVENTORY_WIDTH, tcod) {
+        Some(choice) => spells::cast(game.spells[choice], tcod, game, objects),
+        None => false,
+    }
+}
+
+/// close an open door next to the player, unless something's in the way
+fn close_door(game: &mut Game, objects: &Objects) -> bool {
+    let (x, y) = objects[PLAYER].pos();
+    let door = DIRECTIONS.iter().map(|&(dx, dy)| (x + dx, y + dy)).find(|&(x, y)| {
+        game.map.get(x, y).map_or(false, |tile| tile.door == Some(Door::Open))
+            && !objects.iter().any(|object| object.pos() == (x, y))
+    });
+    match door {
+        Some(door) => {
+            game.map[door].close_door();
+            game.messages.add("You close the door.", LIGHT_GREY);
+            true
+        }
+        None => {
+            game.messages.add("There's no open door to close here.", LIGHT_GREY);
+            false
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::KeyCode;
+    use crate::test_support::open_field;
+
+    #[test]
+    fn keys_stand_for_their_bound_actions_and_alt_enter_for_fullscreen() {
+        let keys = Keybindings::default();
+        let key = |code, printable, alt| Key {
+            code,
+            printable,
+            alt,
+            pressed: true,
+            ..Default::default()
+        };
+        assert_eq!(from_key(key(KeyCode::Text, '.', false), &keys), Some(Action::Wait));
+        let alt_enter = key(KeyCode::Enter, '\0', true);
+        assert_eq!(from_key(alt_enter, &keys), Some(Action::ToggleFullscreen));
+        let escape = key(KeyCode::Escape, '\0', false);
+        assert_eq!(from_key(escape, &keys), Some(Action::Exit));
+    }
+
+    #[test]
+    fn actions_needing_no_window_are_resolved_directly() {
+        let (mut game, mut objects) = open_field(20, 20);
+        let mut resolve = |action| resolve(action, &mut game, &mut objects);
+        assert_eq!(resolve(Action::Wait), Some(true));
+        assert_eq!(resolve(Action::MoveOrAttack { dx: 1, dy: 0 }), Some(true));
+        assert_eq!(resolve(Action::ToggleSneak), Some(false));
+        assert_eq!(resolve(Action::Ascend), Some(false));
+        assert_eq!(resolve(Action::Eat), Some(false));
+        assert_eq!(resolve(Action::Look), None);
+        assert_eq!(resolve(Action::Rest), None);
+        assert_eq!(objects[PLAYER].pos(), (6, 5));
+        assert!(game.sneaking);
+    }
+}
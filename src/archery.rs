@@ -0,0 +1,75 @@
+//! Archery: the player's bows and crossbows. A launcher goes in the ranged
+//! slot and shoots arrows from a stack carried in the inventory. How far it
+//! reaches and how hard it hits are the launcher's own, whatever the player's
+//! strength. Arrows that miss, and some that hit, can be picked up again.
+
+use rand::Rng;
+
+use crate::colors::{LIGHT_GREY, RED, WHITE};
+use crate::entities::Objects;
+use crate::projectile::{self, Projectile};
+use crate::throwing;
+use crate::{
+    mut_two, take_from_inventory, target_area, Category, Chooser, Game, Item, PLAYER,
+};
+
+/// the chance an arrow that hits something breaks
+const ARROW_BREAK_CHANCE: f64 = 0.5;
+
+/// Shoot an arrow from the equipped launcher at a spot the player chooses.
+/// True if it took the player's turn.
+pub fn fire(chooser: &mut impl Chooser, game: &mut Game, objects: &mut Objects) -> bool {
+    let launcher = match objects[PLAYER].ranged_attack(game) {
+        Some(launcher) => launcher,
+        None => {
+            game.messages.add_in(Category::Combat, "You have nothing to shoot with.", RED);
+            return false;
+        }
+    };
+    let quiver = game
+        .inventory
+        .iter()
+        .position(|item| item.item == Some(Item::Arrows));
+    let quiver = match quiver {
+        Some(quiver) => quiver,
+        None => {
+            game.messages.add_in(Category::Combat, "You have no arrows.", RED);
+            return false;
+        }
+    };
+    let range = Some(launcher.range as f32);
+    let target = match target_area(chooser, game, objects, "your shot", range, None) {
+        Some(target) => target,
+        None => return false,
+    };
+
+    if game.inventory[quiver].quantity() == 1 {
+        game.messages.add_in(Category::Combat, "That was your last arrow.", LIGHT_GREY);
+    }
+    let arrow = take_from_inventory(quiver, 1, &mut game.inventory);
+
+    let from = objects[PLAYER].pos();
+    let path = projectile::flight(from, target, &game.map, objects);
+    let (x, y) = path.last().copied().unwrap_or(from);
+    game.projectiles.push(Projectile {
+        path,
+        char: projectile::arrow((target.0 - from.0, target.1 - from.1)),
+        color: WHITE,
+    });
+    let hit = objects
+        .iter()
+        .enumerate()
+        .position(|(id, obj)| id != PLAYER && obj.pos() == (x, y) && obj.fighter.is_some());
+    match hit {
+        Some(monster_id) => {
+            let (player, monster) = mut_two(PLAYER, monster_id, objects);
+            player.shoot(monster, game);
+            if game.rng.gen_bool(ARROW_BREAK_CHANCE) {
+                return true;
+            }
+        }
+        None => game.messages.add_in(Category::Combat, "Your arrow hits nothing.", LIGHT_GREY),
+    }
+    throwing::land(arrow, (x, y), game, objects);
+    true
+}
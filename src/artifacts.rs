@@ -0,0 +1,223 @@
+//! Artifacts. Now and then a weapon or piece of armor found in the dungeon
+//! turns out to be one of a kind, with a prefix, a suffix or both that each
+//! give it a bonus on top of its own, and a name to match, like "Flaming
+//! Sword of the Bear". The deeper the level, the likelier an artifact is and
+//! the stronger the affixes it can roll.
+
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+
+use crate::map::{from_dungeon_level, Transition};
+use crate::{Equipment, Object};
+
+/// the chance in percent that equipment found on each dungeon level is an
+/// artifact
+const ARTIFACT_CHANCE: &[Transition] = &[
+    Transition { level: 2, value: 2 },
+    Transition { level: 4, value: 4 },
+    Transition { level: 7, value: 8 },
+];
+/// the chance that an artifact has both a prefix and a suffix, rather than one
+const BOTH_AFFIXES_CHANCE: f64 = 0.3;
+/// what merchants charge on top of the usual for an artifact
+pub const ARTIFACT_VALUE: i32 = 100;
+
+/// Where an affix goes in an artifact's name.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Place {
+    Prefix,
+    Suffix,
+}
+
+/// A part of an artifact's name, and what it gives.
+struct Affix {
+    name: &'static str,
+    place: Place,
+    /// whether it only turns up on weapons
+    weapons_only: bool,
+    /// how likely it is to be picked, against the others, from each dungeon
+    /// level on
+    spawn: &'static [Transition],
+    apply: fn(&mut Equipment),
+}
+
+const AFFIXES: &[Affix] = &[
+    Affix {
+        name: "Flaming",
+        place: Place::Prefix,
+        weapons_only: true,
+        spawn: &[Transition { level: 2, value: 20 }, Transition { level: 5, value: 30 }],
+        apply: |e| e.fire_damage += 3,
+    },
+    Affix {
+        name: "Keen",
+        place: Place::Prefix,
+        weapons_only: true,
+        spawn: &[Transition { level: 2, value: 30 }],
+        apply: |e| e.power_bonus += 2,
+    },
+    Affix {
+        name: "Sturdy",
+        place: Place::Prefix,
+        weapons_only: false,
+        spawn: &[Transition { level: 2, value: 30 }],
+        apply: |e| e.defense_bonus += 1,
+    },
+    Affix {
+        name: "Gleaming",
+        place: Place::Prefix,
+        weapons_only: false,
+        spawn: &[Transition { level: 2, value: 15 }],
+        apply: |e| e.light_bonus += 2,
+    },
+    Affix {
+        name: "Featherweight",
+        place: Place::Prefix,
+        weapons_only: false,
+        spawn: &[Transition { level: 3, value: 15 }],
+        apply: |e| e.weight = (e.weight / 2).max(1),
+    },
+    Affix {
+        name: "of the Bear",
+        place: Place::Suffix,
+        weapons_only: false,
+        spawn: &[Transition { level: 2, value: 30 }],
+        apply: |e| e.max_hp_bonus += 20,
+    },
+    Affix {
+        name: "of the Turtle",
+        place: Place::Suffix,
+        weapons_only: false,
+        spawn: &[Transition { level: 3, value: 20 }, Transition { level: 6, value: 30 }],
+        apply: |e| e.defense_bonus += 2,
+    },
+    Affix {
+        name: "of Slaying",
+        place: Place::Suffix,
+        weapons_only: true,
+        spawn: &[Transition { level: 5, value: 15 }, Transition { level: 8, value: 25 }],
+        apply: |e| e.power_bonus += 4,
+    },
+    Affix {
+        name: "of the Seer",
+        place: Place::Suffix,
+        weapons_only: false,
+        spawn: &[Transition { level: 6, value: 5 }],
+        apply: |e| e.telepathic = true,
+    },
+];
+
+/// Maybe turn a piece of equipment found on the given dungeon level into an
+/// artifact, the more likely the deeper it is. Cursed equipment never is.
+pub fn maybe_make_artifact(item: &mut Object, level: u32, rng: &mut impl Rng) {
+    let is_weapon = match item.equipment {
+        Some(equipment) if !equipment.cursed => equipment.is_weapon(),
+        _ => return,
+    };
+    if rng.gen_range(0..100) >= from_dungeon_level(ARTIFACT_CHANCE, level) {
+        return;
+    }
+    let places = if rng.gen_bool(BOTH_AFFIXES_CHANCE) {
+        vec![Place::Prefix, Place::Suffix]
+    } else if rng.gen() {
+        vec![Place::Prefix]
+    } else {
+        vec![Place::Suffix]
+    };
+    let affixes: Vec<&Affix> = places
+        .into_iter()
+        .filter_map(|place| random_affix(place, is_weapon, level, rng))
+        .collect();
+    if affixes.is_empty() {
+        return;
+    }
+
+    let equipment = item.equipment.as_mut().unwrap();
+    let mut name = title_case(&item.name);
+    for affix in affixes {
+        (affix.apply)(equipment);
+        name = match affix.place {
+            Place::Prefix => format!("{} {}", affix.name, name),
+            Place::Suffix => format!("{} {}", name, affix.name),
+        };
+    }
+    item.artifact = Some(name);
+}
+
+/// an affix that goes in the given place, picked from those that fit the
+/// item and turn up on the given dungeon level; None if none do
+fn random_affix(
+    place: Place,
+    is_weapon: bool,
+    level: u32,
+    rng: &mut impl Rng,
+) -> Option<&'static Affix> {
+    let weights = AFFIXES.iter().map(|affix| {
+        if affix.place == place && (is_weapon || !affix.weapons_only) {
+            from_dungeon_level(affix.spawn, level)
+        } else {
+            0
+        }
+    });
+    let dist = WeightedIndex::new(weights).ok()?;
+    Some(&AFFIXES[rng.sample(&dist)])
+}
+
+/// "leather armor" as an artifact names it: "Leather Armor"
+fn title_case(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(' ')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => first.to_uppercase().chain(chars).collect(),
+                None => String::new(),
+            }
+        })
+        .collect();
+    words.join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::items;
+
+    #[test]
+    fn only_uncursed_gear_from_deep_enough_becomes_artifacts() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..1000 {
+            let mut dagger = items::template("dagger").spawn(0, 0);
+            maybe_make_artifact(&mut dagger, 1, &mut rng);
+            assert_eq!(dagger.artifact, None);
+            let mut cursed = items::template("dagger").spawn(0, 0);
+            cursed.equipment.as_mut().unwrap().cursed = true;
+            maybe_make_artifact(&mut cursed, 10, &mut rng);
+            assert_eq!(cursed.artifact, None);
+        }
+    }
+
+    #[test]
+    fn artifacts_are_named_for_their_affixes_and_armor_gets_no_weapon_ones() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut named = 0;
+        for _ in 0..1000 {
+            let mut helmet = items::template("helmet").spawn(0, 0);
+            maybe_make_artifact(&mut helmet, 10, &mut rng);
+            let name = match helmet.artifact {
+                Some(name) => name,
+                None => continue,
+            };
+            named += 1;
+            assert!(name.contains("Helmet"), "{}", name);
+            for affix in AFFIXES.iter().filter(|affix| affix.weapons_only) {
+                assert!(!name.contains(affix.name), "{}", name);
+            }
+        }
+        assert!(named > 0);
+        assert_eq!(title_case("leather armor"), "Leather Armor");
+    }
+}
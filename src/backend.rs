@@ -0,0 +1,249 @@
+//! Backends: where the finished screen is shown and where key presses and
+//! the mouse come from. Everything is drawn onto an offscreen console the
+//! size of the screen, and a backend then puts that in front of the player:
+//! libtcod's own window, with the `tcod` feature, or with the `terminal`
+//! feature, the terminal the game was started from. The screen and the keys
+//! are the game's own types, so nothing but the window needs libtcod.
+
+#[cfg(feature = "tcod")]
+use tcod::console::{Console, FontLayout, FontType, Offscreen as TcodOffscreen, Root};
+#[cfg(feature = "tcod")]
+use tcod::input::{self, Event as TcodEvent, Key as TcodKey, KeyCode as TcodKeyCode};
+
+#[cfg(feature = "tcod")]
+use crate::colors::Color;
+use crate::console::Offscreen;
+#[cfg(any(test, feature = "tcod"))]
+use crate::input::KeyCode;
+#[cfg(feature = "tcod")]
+use crate::input::Mouse;
+use crate::input::{Event, Key};
+
+/// Something that can show the screen to the player.
+pub trait Renderer {
+    /// Show everything drawn on the screen so far. `map_cells` marks, for
+    /// each cell by row, whether it shows the map rather than text.
+    fn present(&mut self, screen: &Offscreen, map_cells: &[bool]);
+
+    /// whether the player has closed the game's window
+    fn is_closed(&self) -> bool;
+
+    /// switch between a window and the full screen, where that means anything
+    fn toggle_fullscreen(&mut self);
+}
+
+/// Something the player's key presses and mouse come from.
+pub trait Input {
+    /// the key press or mouse movement since last asked, if there's been one
+    fn check_for_event(&mut self) -> Option<Event>;
+
+    /// wait until a key is pressed, and return it
+    fn wait_for_keypress(&mut self) -> Key;
+}
+
+/// A backend both shows the screen and takes input.
+pub trait Backend: Renderer + Input {}
+
+impl<T: Renderer + Input> Backend for T {}
+
+/// libtcod's window, built with the `tcod` feature.
+#[cfg(feature = "tcod")]
+pub struct Window {
+    root: Root,
+}
+
+#[cfg(feature = "tcod")]
+impl Window {
+    /// Open a window big enough for a screen of the given size, in
+    /// characters, drawing at most `fps` frames a second.
+    pub fn open(width: i32, height: i32, title: &str, fps: i32) -> Self {
+        tcod::system::set_fps(fps);
+        let root = Root::initializer()
+            .font("arial12x12.png", FontLayout::Tcod)
+            .font_type(FontType::Greyscale)
+            .size(width, height)
+            .title(title)
+            .init();
+        Window { root }
+    }
+}
+
+#[cfg(feature = "tcod")]
+impl Renderer for Window {
+    fn present(&mut self, screen: &Offscreen, _map_cells: &[bool]) {
+        for y in 0..screen.height() {
+            for x in 0..screen.width() {
+                let cell = screen.cell(x, y);
+                let foreground = tcod_color(cell.foreground);
+                let background = tcod_color(cell.background);
+                self.root.put_char_ex(x, y, cell.char, foreground, background);
+            }
+        }
+        self.root.flush();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.root.window_closed()
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let fullscreen = self.root.is_fullscreen();
+        self.root.set_fullscreen(!fullscreen);
+    }
+}
+
+#[cfg(feature = "tcod")]
+impl Input for Window {
+    fn check_for_event(&mut self) -> Option<Event> {
+        match input::check_for_event(input::MOUSE | input::KEY_PRESS) {
+            Some((_, TcodEvent::Key(key))) => Some(Event::Key(key_from(key))),
+            Some((_, TcodEvent::Mouse(mouse))) => Some(Event::Mouse(Mouse {
+                x: mouse.x as i32,
+                y: mouse.y as i32,
+                cx: mouse.cx as i32,
+                cy: mouse.cy as i32,
+                lbutton_pressed: mouse.lbutton_pressed,
+                rbutton_pressed: mouse.rbutton_pressed,
+                mbutton_pressed: mouse.mbutton_pressed,
+                wheel_up: mouse.wheel_up,
+                wheel_down: mouse.wheel_down,
+            })),
+            None => None,
+        }
+    }
+
+    fn wait_for_keypress(&mut self) -> Key {
+        key_from(self.root.wait_for_keypress(true))
+    }
+}
+
+#[cfg(feature = "tcod")]
+fn tcod_color(color: Color) -> tcod::colors::Color {
+    tcod::colors::Color {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+    }
+}
+
+/// The key libtcod reported, as the game knows it. Libtcod reports typed
+/// characters as text, rather than as the key's printable character;
+/// they're put there, so every backend's keys look the same.
+#[cfg(feature = "tcod")]
+fn key_from(key: TcodKey) -> Key {
+    let code = match key.code {
+        TcodKeyCode::Escape => KeyCode::Escape,
+        TcodKeyCode::Backspace => KeyCode::Backspace,
+        TcodKeyCode::Tab => KeyCode::Tab,
+        TcodKeyCode::Enter => KeyCode::Enter,
+        TcodKeyCode::Spacebar => KeyCode::Spacebar,
+        TcodKeyCode::Up => KeyCode::Up,
+        TcodKeyCode::Down => KeyCode::Down,
+        TcodKeyCode::Left => KeyCode::Left,
+        TcodKeyCode::Right => KeyCode::Right,
+        TcodKeyCode::Home => KeyCode::Home,
+        TcodKeyCode::End => KeyCode::End,
+        TcodKeyCode::PageUp => KeyCode::PageUp,
+        TcodKeyCode::PageDown => KeyCode::PageDown,
+        TcodKeyCode::Insert => KeyCode::Insert,
+        TcodKeyCode::Delete => KeyCode::Delete,
+        TcodKeyCode::NumPad0 => KeyCode::NumPad0,
+        TcodKeyCode::NumPad1 => KeyCode::NumPad1,
+        TcodKeyCode::NumPad2 => KeyCode::NumPad2,
+        TcodKeyCode::NumPad3 => KeyCode::NumPad3,
+        TcodKeyCode::NumPad4 => KeyCode::NumPad4,
+        TcodKeyCode::NumPad5 => KeyCode::NumPad5,
+        TcodKeyCode::NumPad6 => KeyCode::NumPad6,
+        TcodKeyCode::NumPad7 => KeyCode::NumPad7,
+        TcodKeyCode::NumPad8 => KeyCode::NumPad8,
+        TcodKeyCode::NumPad9 => KeyCode::NumPad9,
+        TcodKeyCode::NumPadEnter => KeyCode::NumPadEnter,
+        TcodKeyCode::Char => KeyCode::Char,
+        TcodKeyCode::Text => KeyCode::Text,
+        _ => KeyCode::NoKey,
+    };
+    let printable = match key.code {
+        TcodKeyCode::Text => key.text().chars().next().unwrap_or('\0'),
+        _ => key.printable,
+    };
+    Key {
+        code,
+        printable,
+        pressed: key.pressed,
+        alt: key.alt,
+        ctrl: key.ctrl,
+        shift: key.shift,
+    }
+}
+
+/// Draw the image in the given file over the whole screen, at twice the
+/// resolution of its characters, as libtcod draws images.
+#[cfg(feature = "tcod")]
+pub fn draw_image(path: &str, screen: &mut Offscreen) -> Result<(), String> {
+    let image = tcod::image::Image::from_file(path).map_err(|_| format!("can't read {}", path))?;
+    let mut drawn = TcodOffscreen::new(screen.width(), screen.height());
+    tcod::image::blit_2x(&image, (0, 0), (-1, -1), &mut drawn, (0, 0));
+    for y in 0..screen.height() {
+        for x in 0..screen.width() {
+            let foreground = drawn.get_char_foreground(x, y);
+            let background = drawn.get_char_background(x, y);
+            screen.put_char_ex(
+                x,
+                y,
+                drawn.get_char(x, y),
+                Color::new(foreground.r, foreground.g, foreground.b),
+                Color::new(background.r, background.g, background.b),
+            );
+        }
+    }
+    Ok(())
+}
+
+/// A backend with no window, for tests: the player presses the given keys
+/// in turn, and then nothing. Tab, Enter and Escape are written as `\t`,
+/// `\n` and `\x1b`, and anything else is typed.
+#[cfg(test)]
+pub struct Presses(pub Vec<char>);
+
+#[cfg(test)]
+impl Presses {
+    fn next(&mut self) -> Option<Key> {
+        if self.0.is_empty() {
+            return None;
+        }
+        let (code, printable) = match self.0.remove(0) {
+            '\t' => (KeyCode::Tab, '\0'),
+            '\n' => (KeyCode::Enter, '\0'),
+            '\x1b' => (KeyCode::Escape, '\0'),
+            char => (KeyCode::Char, char),
+        };
+        Some(Key {
+            code,
+            printable,
+            pressed: true,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+impl Renderer for Presses {
+    fn present(&mut self, _screen: &Offscreen, _map_cells: &[bool]) {}
+
+    fn is_closed(&self) -> bool {
+        false
+    }
+
+    fn toggle_fullscreen(&mut self) {}
+}
+
+#[cfg(test)]
+impl Input for Presses {
+    fn check_for_event(&mut self) -> Option<Event> {
+        self.next().map(Event::Key)
+    }
+
+    fn wait_for_keypress(&mut self) -> Key {
+        self.next().unwrap_or_default()
+    }
+}
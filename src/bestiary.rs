@@ -0,0 +1,390 @@
+//! The bestiary: every monster species, as defined in `data/monsters.json`,
+//! and what the player has learned about each, kept across games in a
+//! profile file. A species is listed once it's been seen, and more about it
+//! is revealed the more of it the player has killed.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::colors::Color;
+use crate::dungeon::Branch;
+use crate::effects::{Effect, StatusEffect};
+use crate::factions::Faction;
+use crate::items;
+use crate::map::Transition;
+use crate::powers::Power;
+use crate::speed::NORMAL_SPEED;
+use crate::{default_speed, Ai, Attitude, Fighter, Object, RangedAttack};
+
+const MONSTER_DATA: &str = include_str!("../data/monsters.json");
+
+/// kills needed before each part of an entry is revealed
+const KILLS_FOR_STATS: u32 = 1;
+const KILLS_FOR_ABILITIES: u32 = 3;
+const KILLS_FOR_LORE: u32 = 5;
+
+/// How to make a monster of one species.
+#[derive(Debug, Deserialize)]
+pub struct MonsterTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    pub hp: i32,
+    pub defense: i32,
+    pub power: i32,
+    pub perception: i32,
+    pub sight_radius: i32,
+    /// how long its attacks blind for
+    #[serde(default)]
+    pub blinding: i32,
+    /// how hurt it has to be, in percent of its HP, to run away; 0 for never
+    #[serde(default)]
+    pub morale: i32,
+    /// how quickly it acts, against `NORMAL_SPEED`
+    #[serde(default = "default_speed")]
+    pub speed: i32,
+    pub xp: i32,
+    pub ai: Ai,
+    #[serde(default)]
+    pub swims: bool,
+    #[serde(default)]
+    pub breeds: bool,
+    #[serde(default)]
+    pub rooted: bool,
+    /// what it can do besides fight
+    #[serde(default)]
+    pub powers: Vec<Power>,
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+    /// the most gold one carries, to drop when it dies
+    #[serde(default)]
+    pub gold: i32,
+    /// the items it might leave behind when it dies
+    #[serde(default)]
+    pub loot: Vec<Loot>,
+    /// how likely this species is to be picked for a random monster, against
+    /// the others, from each dungeon level on; never, if empty
+    #[serde(default)]
+    pub spawn: Vec<Transition>,
+    /// the branches of the dungeon it turns up in; all of them, if empty
+    #[serde(default)]
+    pub branches: Vec<Branch>,
+    /// the level of the main dungeon this one-of-a-kind monster guards, as
+    /// its boss
+    #[serde(default)]
+    pub boss_of: Option<u32>,
+    /// revealed in the bestiary after enough kills
+    #[serde(default)]
+    pub lore: Option<String>,
+    /// what its hits leave on whoever they hit
+    #[serde(default)]
+    pub inflicts: Option<StatusEffect>,
+    /// how it feels about the player, for NPCs that don't attack on sight
+    #[serde(default)]
+    pub attitude: Attitude,
+    /// the side it takes in a fight, if it's hostile
+    #[serde(default)]
+    pub faction: Faction,
+    /// the name of its conversation in the dialogue data, for NPCs that talk
+    #[serde(default)]
+    pub dialogue: Option<String>,
+}
+
+/// An item a monster might drop when it dies.
+#[derive(Debug, Deserialize)]
+pub struct Loot {
+    pub item: String,
+    /// the chance in percent that it's dropped
+    pub chance: u32,
+}
+
+impl MonsterTemplate {
+    /// a monster of this species at the given spot
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut monster = Object::new(x, y, self.glyph, &self.name, self.color, true);
+        monster.fighter = Some(Fighter {
+            base_max_hp: self.hp,
+            hp: self.hp,
+            base_defense: self.defense,
+            base_power: self.power,
+            perception: self.perception,
+            sight_radius: self.sight_radius,
+            blinding: self.blinding,
+            inflicts: self.inflicts,
+            morale: self.morale,
+            speed: self.speed,
+            xp: self.xp,
+            ..Default::default()
+        });
+        monster.ai = Some(self.ai.clone());
+        monster.swims = self.swims;
+        monster.breeds = self.breeds;
+        monster.rooted = self.rooted;
+        monster.powers = self.powers.clone();
+        monster.boss = self.boss_of.is_some();
+        monster.ranged = self.ranged;
+        monster.gold = Some(self.gold).filter(|&gold| gold > 0);
+        monster.attitude = self.attitude;
+        // NPCs keep out of fights, until they're attacked
+        monster.faction = match self.attitude {
+            Attitude::Hostile => self.faction,
+            Attitude::Neutral | Attitude::Friendly => Faction::Neutral,
+        };
+        monster.dialogue = self.dialogue.clone();
+        monster.alive = true;
+        monster
+    }
+}
+
+/// Read monster species from JSON laid out like `data/monsters.json`, or say
+/// what's wrong with it. Every item they drop must be in the item data.
+pub fn parse(json: &str) -> Result<Vec<MonsterTemplate>, String> {
+    let templates: Vec<MonsterTemplate> =
+        serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let items = items::load()?;
+    for template in &templates {
+        for loot in &template.loot {
+            if !items.iter().any(|item| item.name == loot.item) {
+                return Err(format!("the {} drops unknown item {}", template.name, loot.item));
+            }
+        }
+    }
+    Ok(templates)
+}
+
+/// All monster species, read from the data file the first time they're
+/// needed, or what's wrong with it.
+pub fn load() -> Result<&'static [MonsterTemplate], String> {
+    static TEMPLATES: OnceLock<Result<Vec<MonsterTemplate>, String>> = OnceLock::new();
+    TEMPLATES
+        .get_or_init(|| {
+            parse(MONSTER_DATA).map_err(|e| format!("data/monsters.json is invalid: {}", e))
+        })
+        .as_deref()
+        .map_err(Clone::clone)
+}
+
+/// all monster species; `run` has made sure the data file reads before
+/// anything needs them
+pub fn templates() -> &'static [MonsterTemplate] {
+    load().unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// the species with the given name; the data file must define it
+pub fn template(name: &str) -> &'static MonsterTemplate {
+    templates()
+        .iter()
+        .find(|template| template.name == name)
+        .unwrap_or_else(|| panic!("no monster named {} in data/monsters.json", name))
+}
+
+/// the boss guarding the given level of the main dungeon, if it has one
+pub fn boss_of(level: u32) -> Option<&'static MonsterTemplate> {
+    templates()
+        .iter()
+        .find(|template| template.boss_of == Some(level))
+}
+
+/// the names of the items a monster of the given species drops when it
+/// dies, rolled from its loot table; none for monsters of no known species
+pub fn roll_loot(species: &str, rng: &mut impl Rng) -> Vec<String> {
+    match templates().iter().find(|template| template.name == species) {
+        Some(template) => template
+            .loot
+            .iter()
+            .filter(|loot| rng.gen_range(0..100) < loot.chance)
+            .map(|loot| loot.item.clone())
+            .collect(),
+        None => vec![],
+    }
+}
+
+/// What's known about one species.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Entry {
+    pub kills: u32,
+    /// max HP, power and defense, as of the first kill
+    pub stats: Option<(i32, i32, i32)>,
+    pub abilities: Vec<String>,
+}
+
+/// All species the player has come across. A default `Bestiary` isn't tied to
+/// a file, and forgets everything when the game ends.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Bestiary {
+    species: BTreeMap<String, Entry>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+impl Bestiary {
+    /// read the bestiary kept at `path`, starting afresh if there is none yet
+    /// (or it can't be read)
+    pub fn load(path: &Path) -> Self {
+        let mut bestiary: Bestiary = fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        bestiary.path = Some(path.into());
+        bestiary
+    }
+
+    fn save(&self) {
+        if let Some(path) = self.path.as_ref() {
+            let result = serde_json::to_string(self)
+                .map_err(|e| e.to_string())
+                .and_then(|json| fs::write(path, json).map_err(|e| e.to_string()));
+            if let Err(e) = result {
+                eprintln!("Could not save the bestiary to {}: {}", path.display(), e);
+            }
+        }
+    }
+
+    /// list the monster's species, if it's new
+    pub fn saw(&mut self, monster: &Object) {
+        if !self.species.contains_key(&monster.name) {
+            self.species.insert(monster.name.clone(), Entry::default());
+            self.save();
+        }
+    }
+
+    /// count a kill, learning the species' stats and abilities from it
+    pub fn killed(&mut self, monster: &Object) {
+        let entry = self.species.entry(monster.name.clone()).or_default();
+        entry.kills += 1;
+        if let (None, Some(fighter)) = (entry.stats, monster.fighter) {
+            entry.stats = Some((fighter.base_max_hp, fighter.base_power, fighter.base_defense));
+            entry.abilities = abilities_of(monster);
+        }
+        self.save();
+    }
+
+    /// a line or more for every species, revealing as much as has been learned
+    pub fn describe(&self) -> String {
+        if self.species.is_empty() {
+            return "You haven't met any monsters yet.".into();
+        }
+        let mut text = String::new();
+        for (name, entry) in &self.species {
+            text += &format!("{} - killed {}\n", name, entry.kills);
+            match entry.stats {
+                Some((hp, power, defense)) if entry.kills >= KILLS_FOR_STATS => {
+                    text += &format!("  HP {}, attack {}, defense {}\n", hp, power, defense);
+                }
+                _ => text += "  HP ?, attack ?, defense ?\n",
+            }
+            if entry.kills >= KILLS_FOR_ABILITIES {
+                let abilities = if entry.abilities.is_empty() {
+                    "nothing special".to_string()
+                } else {
+                    entry.abilities.join(", ")
+                };
+                text += &format!("  Abilities: {}\n", abilities);
+            }
+            if entry.kills >= KILLS_FOR_LORE {
+                let lore = templates()
+                    .iter()
+                    .find(|template| &template.name == name)
+                    .and_then(|template| template.lore.as_deref())
+                    .unwrap_or("Little is known about these.");
+                text += &format!("  {}\n", lore);
+            }
+        }
+        text
+    }
+}
+
+fn abilities_of(monster: &Object) -> Vec<String> {
+    let mut abilities = vec![];
+    if monster.swims {
+        abilities.push("crosses deep water".to_string());
+    }
+    if monster.fighter.map_or(false, |f| f.blinding > 0) {
+        abilities.push("blinding bite".to_string());
+    }
+    if let Some(status) = monster.fighter.and_then(|f| f.inflicts) {
+        let ability = match status.effect {
+            Effect::Poison => "poisonous bite",
+            Effect::Burn => "burning touch",
+            Effect::Regeneration => "heals what it hits",
+            Effect::Slow => "slowing touch",
+            Effect::Confusion => "confusing touch",
+            Effect::Webbed => "sticky touch",
+            Effect::Haste => "hastening touch",
+            Effect::Inspired => "inspiring touch",
+            Effect::Charmed => "charming touch",
+        };
+        abilities.push(ability.to_string());
+    }
+    if monster.breeds {
+        abilities.push("multiplies".to_string());
+    }
+    match monster.fighter.map_or(NORMAL_SPEED, |f| f.speed) {
+        speed if speed > NORMAL_SPEED => abilities.push("fast".to_string()),
+        speed if speed < NORMAL_SPEED => abilities.push("slow".to_string()),
+        _ => {}
+    }
+    if monster.rooted {
+        abilities.push("never moves".to_string());
+    }
+    for power in &monster.powers {
+        abilities.push(power.describe().to_string());
+    }
+    if monster.fighter.map_or(false, |f| f.morale > 0) {
+        abilities.push("flees when badly hurt".to_string());
+    }
+    if let Some(ranged) = monster.ranged {
+        abilities.push(format!("shoots from up to {} tiles away", ranged.range));
+    }
+    abilities
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    #[test]
+    fn the_bestiary_tells_more_the_more_are_killed() {
+        let mut bestiary = Bestiary::default();
+        assert_eq!(bestiary.describe(), "You haven't met any monsters yet.");
+        let orc = template("orc").spawn(0, 0);
+        bestiary.saw(&orc);
+        assert_eq!(bestiary.describe(), "orc - killed 0\n  HP ?, attack ?, defense ?\n");
+        bestiary.killed(&orc);
+        assert!(bestiary.describe().contains(&format!("HP {},", template("orc").hp)));
+        assert!(!bestiary.describe().contains("Abilities"));
+        for _ in KILLS_FOR_STATS..KILLS_FOR_ABILITIES {
+            bestiary.killed(&orc);
+        }
+        assert!(bestiary.describe().contains("Abilities: "));
+        for _ in KILLS_FOR_ABILITIES..KILLS_FOR_LORE {
+            bestiary.killed(&orc);
+        }
+        assert!(bestiary.describe().contains("Orcs came down here to loot the tombs"));
+    }
+
+    #[test]
+    fn loot_is_rolled_from_the_species_table() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let drops: Vec<String> = (0..1000).flat_map(|_| roll_loot("orc", &mut rng)).collect();
+        let clubs = drops.iter().filter(|item| *item == "crude club").count();
+        assert!((100..200).contains(&clubs), "{} clubs", clubs);
+        assert!(drops.iter().all(|item| item == "crude club" || item == "dagger"));
+        assert!(roll_loot("nobody", &mut rng).is_empty());
+    }
+
+    #[test]
+    fn monsters_must_drop_known_items() {
+        assert_eq!(parse(MONSTER_DATA).unwrap().len(), templates().len());
+        let unknown = parse(&MONSTER_DATA.replace("\"crude club\"", "\"moonbeam\""));
+        assert_eq!(unknown.unwrap_err(), "the orc drops unknown item moonbeam");
+    }
+}
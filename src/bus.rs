@@ -0,0 +1,263 @@
+//! The event bus: what happens in the game, told to whatever cares about it.
+//! Combat, dying, picking things up and taking the stairs publish an event
+//! saying so, and carry on without knowing who's listening. Events wait on
+//! the bus until they're dispatched, at the start and the end of each turn,
+//! when every subscriber hears about each of them in the order they
+//! happened: telemetry records them, and monsters that saw one of their own
+//! die take fright. The message log is the exception, hearing about each
+//! event as soon as it's published, so what it says stays in order with the
+//! messages around it.
+
+use crate::colors::{GOLD, GREEN, LIGHT_VIOLET, LIGHT_YELLOW, ORANGE, RED, VIOLET, WHITE};
+use crate::dungeon::Branch;
+use crate::entities::Objects;
+use crate::telemetry::Record;
+use crate::{Ai, Category, Game, FRIGHT_MORALE_PENALTY, FRIGHT_RADIUS};
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum GameEvent {
+    /// someone struck at or shot at someone else, for `damage` hit points if
+    /// it got through, and with the target caught unawares if `sneak`
+    Attacked {
+        attacker: String,
+        target: String,
+        damage: i32,
+        shot: bool,
+        sneak: bool,
+    },
+    /// something was hurt, by something or someone
+    DamageDealt {
+        source: String,
+        target: String,
+        amount: i32,
+    },
+    /// the player, one of their companions or a monster worth `xp` to them
+    /// died where it stood
+    Died {
+        name: String,
+        x: i32,
+        y: i32,
+        xp: i32,
+        ally: bool,
+    },
+    /// a monster lost its nerve, and is running for it
+    TookFright { name: String, x: i32, y: i32 },
+    /// the player picked something up, which looks to them like `shown_as`
+    ItemPickedUp { item: String, shown_as: String },
+    /// the player picked up some gold
+    GoldPickedUp { amount: i32 },
+    /// the player arrived on another level, climbing `up` to it or going
+    /// down, and on it for the first time if `first_visit`
+    LevelChanged {
+        branch: Branch,
+        depth: u32,
+        up: bool,
+        first_visit: bool,
+    },
+}
+
+/// put an event on the bus, for the subscribers to hear about when it's next
+/// dispatched, and the message log straight away
+pub fn publish(game: &mut Game, event: GameEvent) {
+    tell(&event, game);
+    game.events.push(event);
+}
+
+/// Tell every subscriber about each event published since the last dispatch,
+/// and clear them off the bus, along with any they publish themselves.
+pub fn dispatch(game: &mut Game, objects: &mut Objects) {
+    while !game.events.is_empty() {
+        for event in std::mem::take(&mut game.events) {
+            record(&event, game);
+            take_fright(&event, game, objects);
+        }
+    }
+}
+
+/// the message log says what happened, in the player's words
+fn tell(event: &GameEvent, game: &mut Game) {
+    let messages = &mut game.messages;
+    match event {
+        GameEvent::Attacked {
+            attacker,
+            target,
+            damage,
+            shot,
+            sneak,
+        } => {
+            let verb = if *shot { "shoots" } else { "attacks" };
+            if *sneak {
+                let msg = format!("{} sneak attacks {}!", attacker, target);
+                messages.add_in(Category::Combat, msg, LIGHT_YELLOW);
+            }
+            let msg = if *damage > 0 {
+                format!("{} {} {} for {} hit points.", attacker, verb, target, damage)
+            } else {
+                format!("{} {} {} but it has no effect!", attacker, verb, target)
+            };
+            messages.add_in(Category::Combat, msg, WHITE);
+        }
+        GameEvent::Died { name, xp, ally, .. } => {
+            let (msg, color) = if name == "player" {
+                ("You died!".to_string(), RED)
+            } else if *ally {
+                (format!("Your {} dies!", name), RED)
+            } else if *xp > 0 {
+                let msg = format!("{} is dead! You gain {} experience points.", name, xp);
+                (msg, ORANGE)
+            } else {
+                (format!("{} is dead!", name), ORANGE)
+            };
+            messages.add_in(Category::Combat, msg, color);
+        }
+        GameEvent::TookFright { name, x, y } => {
+            if game.map.is_in_view(*x, *y) {
+                messages.add_in(Category::Combat, format!("The {} turns to flee!", name), ORANGE);
+            }
+        }
+        GameEvent::ItemPickedUp { shown_as, .. } => {
+            messages.add_in(Category::Items, format!("You picked up {}!", shown_as), GREEN);
+        }
+        GameEvent::GoldPickedUp { amount } => {
+            messages.add_in(Category::Items, format!("You picked up {} gold.", amount), GOLD);
+        }
+        GameEvent::LevelChanged {
+            branch,
+            depth,
+            up,
+            first_visit,
+        } => {
+            if *up {
+                let (msg, color) = match depth {
+                    0 if game.won => (
+                        "You climb out into the daylight, the crown in your hands!".into(),
+                        LIGHT_YELLOW,
+                    ),
+                    0 => ("You climb back out into the daylight of the town.".into(), LIGHT_VIOLET),
+                    _ => (format!("You climb back up to level {}.", depth), LIGHT_VIOLET),
+                };
+                messages.add(msg, color);
+            } else {
+                if branch.entrance().map_or(false, |entrance| *depth == entrance + 1) {
+                    let msg = format!("You make your way down into {}.", branch.name());
+                    messages.add(msg, LIGHT_VIOLET);
+                }
+                if *first_visit {
+                    messages.add("You take a moment to rest, and recover your strength.", VIOLET);
+                    messages.add(
+                        "After a rare moment of peace, you descend deeper into the heart of \
+                         the dungeon...",
+                        RED,
+                    );
+                } else {
+                    messages.add(format!("You descend to level {} again.", depth), LIGHT_VIOLET);
+                }
+            }
+        }
+        GameEvent::DamageDealt { .. } => {}
+    }
+}
+
+/// telemetry keeps a record of everything but deaths, which it hears about
+/// from the damage that caused them
+fn record(event: &GameEvent, game: &mut Game) {
+    let turn = game.turn;
+    let record = match event {
+        GameEvent::DamageDealt {
+            source,
+            target,
+            amount,
+        } => Record::Damage {
+            turn,
+            source,
+            target,
+            amount: *amount,
+        },
+        GameEvent::Attacked { .. }
+        | GameEvent::Died { .. }
+        | GameEvent::TookFright { .. }
+        | GameEvent::GoldPickedUp { .. } => return,
+        GameEvent::ItemPickedUp { item, .. } => Record::ItemPickedUp { turn, item },
+        GameEvent::LevelChanged { branch, depth, .. } => Record::LevelChanged {
+            turn,
+            branch: branch.name(),
+            dungeon_level: *depth,
+        },
+    };
+    game.telemetry.record(&record);
+}
+
+/// Monsters that saw another die nearby may lose their nerve, if they're hurt
+/// already.
+fn take_fright(event: &GameEvent, game: &mut Game, objects: &mut Objects) {
+    let (x, y) = match *event {
+        GameEvent::Died { x, y, .. } => (x, y),
+        _ => return,
+    };
+    for monster in objects.iter_mut() {
+        let saw_it = matches!(monster.ai, Some(Ai::Basic | Ai::Ranged))
+            && !monster.is_blind()
+            && monster.distance(x, y) <= FRIGHT_RADIUS;
+        if saw_it && monster.loses_nerve(FRIGHT_MORALE_PENALTY) {
+            monster.ai = Some(Ai::Flee);
+            let (name, x, y) = (monster.name.clone(), monster.x, monster.y);
+            publish(game, GameEvent::TookFright { name, x, y });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::{bestiary, new_game_state, PLAYER};
+
+    /// the text of the latest `count` messages, oldest first
+    fn latest(game: &Game, count: usize) -> Vec<String> {
+        let texts: Vec<String> = game.messages.iter().map(|m| m.text.clone()).collect();
+        texts[texts.len() - count..].to_vec()
+    }
+
+    #[test]
+    fn the_message_log_hears_of_events_as_they_are_published() {
+        let (mut game, _) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let attack = GameEvent::Attacked {
+            attacker: "player".into(),
+            target: "orc".into(),
+            damage: 6,
+            shot: false,
+            sneak: true,
+        };
+        publish(&mut game, attack);
+        assert_eq!(
+            latest(&game, 2),
+            ["player sneak attacks orc!", "player attacks orc for 6 hit points."]
+        );
+    }
+
+    #[test]
+    fn fright_is_told_of_once_dispatched() {
+        let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let (x, y) = objects[PLAYER].pos();
+        let mut orc = bestiary::template("orc").spawn(x + 1, y);
+        orc.ai = Some(Ai::Basic);
+        orc.fighter.as_mut().unwrap().hp = 1;
+        objects.push(orc);
+        game.map[(x + 1, y)].in_view = true;
+        let died = GameEvent::Died {
+            name: "orc".into(),
+            x: x + 2,
+            y,
+            xp: 35,
+            ally: false,
+        };
+        publish(&mut game, died);
+        dispatch(&mut game, &mut objects);
+        assert_eq!(objects.last().unwrap().ai, Some(Ai::Flee));
+        assert_eq!(latest(&game, 1), ["The orc turns to flee!"]);
+        assert!(game.events.is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! The character screen: everything about the player on one page. Their
+//! attributes and what they make of them, what they're wearing in each slot
+//! and what it does for them, what they're suffering from, what they've
+//! killed, and how deep they've gone.
+
+use crate::colors::{BLACK, LIGHT_GREY, WHITE, YELLOW};
+use crate::console::*;
+use crate::entities::Objects;
+use crate::speed::NORMAL_SPEED;
+use crate::{
+    describe_equipment, encumbrance, place_name, player_status, Game, Object, Tcod,
+    LEVEL_UP_BASE, LEVEL_UP_FACTOR, PLAYER, SCREEN_HEIGHT, SCREEN_WIDTH,
+};
+
+/// how wide each of the two columns is
+const COLUMN_WIDTH: i32 = SCREEN_WIDTH / 2 - 2;
+/// how many kinds of monster killed are listed, the most killed first
+const KILLS_LISTED: usize = 8;
+
+/// A line of the screen: a section's heading, or what's in it.
+enum Line {
+    Heading(String),
+    Text(String),
+    Blank,
+}
+
+/// Show the character screen until the player presses a key.
+pub fn show(tcod: &mut Tcod, game: &Game, objects: &Objects) {
+    let player = &objects[PLAYER];
+    let fighter = match player.fighter {
+        Some(fighter) => fighter,
+        None => return,
+    };
+    let screen = &mut tcod.screen;
+    screen.set_default_background(BLACK);
+    screen.clear();
+    screen.set_default_foreground(WHITE);
+    let title = format!(
+        "{}, level {} {}",
+        game.character_name,
+        player.level,
+        game.class.name()
+    );
+    screen.print(1, 0, title);
+
+    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+    let load = format!("{} of {}", encumbrance::load(game), encumbrance::capacity(player));
+    let speed = player.speed(game);
+    let speed = match speed {
+        NORMAL_SPEED => format!("{} (normal)", speed),
+        _ if speed > NORMAL_SPEED => format!("{} (fast)", speed),
+        _ => format!("{} (slow)", speed),
+    };
+    let left = vec![
+        Line::Heading("Attributes".into()),
+        Line::Text(format!("Constitution: {}", fighter.base_max_hp)),
+        Line::Text(format!("Strength: {}", fighter.strength)),
+        Line::Text(format!("Agility: {}", fighter.base_defense)),
+        Line::Text(format!("Perception: {}", fighter.perception)),
+        Line::Blank,
+        Line::Heading("Derived".into()),
+        Line::Text(format!("HP: {} of {}", fighter.hp, player.max_hp(game))),
+        Line::Text(format!("Mana: {} of {}", fighter.mana, fighter.max_mana)),
+        Line::Text(format!("Power: {}", player.power(game))),
+        Line::Text(format!("Defense: {}", player.defense(game))),
+        Line::Text(format!("Speed: {}", speed)),
+        Line::Text(format!("Stealth: {}", player.stealth(game))),
+        Line::Text(format!("Light: {}", player.light_radius(game))),
+        Line::Text(format!("Load: {}", load)),
+        Line::Blank,
+        Line::Heading("Progress".into()),
+        Line::Text(format!("Experience: {} of {}", fighter.xp, level_up_xp)),
+        Line::Text(format!("Depth: {}", place_name(game))),
+        Line::Text(format!("Deepest level: {}", game.dungeon.deepest(game.dungeon_level))),
+        Line::Text(format!("Turn: {}", game.turn)),
+        Line::Text(format!("Gold: {}", game.gold)),
+    ];
+    print_column(screen, 1, &left);
+
+    let mut right = vec![Line::Heading("Equipment".into())];
+    right.extend(describe_equipment(game).lines().map(|line| Line::Text(line.into())));
+    right.push(Line::Blank);
+    right.push(Line::Heading("From your gear".into()));
+    let gifts = gear_gifts(game, player);
+    if gifts.is_empty() {
+        right.push(Line::Text("Nothing special".into()));
+    }
+    right.extend(gifts.into_iter().map(Line::Text));
+    right.push(Line::Blank);
+    right.push(Line::Heading("Status".into()));
+    let status = player_status(game, player);
+    if status.is_empty() {
+        right.push(Line::Text("Nothing out of the ordinary".into()));
+    }
+    right.extend(status.into_iter().map(Line::Text));
+    right.push(Line::Blank);
+    let kills: u32 = game.stats.kills.values().sum();
+    right.push(Line::Heading(format!("Kills: {}", kills)));
+    let mut kills: Vec<(&String, &u32)> = game.stats.kills.iter().collect();
+    // the most killed first, then by name
+    kills.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (species, count) in kills.into_iter().take(KILLS_LISTED) {
+        right.push(Line::Text(format!("{} x{}", species, count)));
+    }
+    print_column(screen, SCREEN_WIDTH / 2 + 1, &right);
+
+    screen.set_default_foreground(LIGHT_GREY);
+    screen.print(1, SCREEN_HEIGHT - 1, "Press any key to go back.");
+    tcod.mark_map((0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), false);
+    tcod.flush();
+    tcod.backend.wait_for_keypress();
+}
+
+/// print the lines down the screen from the given column, headings in their
+/// own color, each line wrapped to the column's width
+fn print_column(screen: &mut Offscreen, x: i32, lines: &[Line]) {
+    let mut y = 2;
+    for line in lines {
+        let (text, color) = match line {
+            Line::Heading(text) => (text.as_str(), YELLOW),
+            Line::Text(text) => (text.as_str(), LIGHT_GREY),
+            Line::Blank => ("", LIGHT_GREY),
+        };
+        screen.set_default_foreground(color);
+        y += screen.print_rect(x, y, COLUMN_WIDTH, 0, text).max(1);
+        if y >= SCREEN_HEIGHT - 1 {
+            return;
+        }
+    }
+}
+
+/// what the player's gear does for them beyond power and defense
+fn gear_gifts(game: &Game, player: &Object) -> Vec<String> {
+    let equipped = player.get_all_equipped(game);
+    let mut gifts = vec![];
+    if equipped.iter().any(|e| e.telepathic) {
+        gifts.push("Telepathy".into());
+    }
+    let light: i32 = equipped.iter().map(|e| e.light_bonus).sum();
+    if light != 0 {
+        gifts.push(format!("{:+} light", light));
+    }
+    let fire = player.fire_damage(game);
+    if fire > 0 {
+        gifts.push(format!("+{} fire damage", fire));
+    }
+    if equipped.iter().any(|e| e.cursed) {
+        gifts.push("Cursed: something won't come off".into());
+    }
+    gifts
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::new_game_state;
+
+    #[test]
+    fn gear_gifts_list_what_the_gear_does_beyond_power_and_defense() {
+        let (mut game, objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        assert!(gear_gifts(&game, &objects[PLAYER]).is_empty());
+        let helmet = game.inventory.iter_mut().find(|item| item.name == "helmet").unwrap();
+        let equipment = helmet.equipment.as_mut().unwrap();
+        equipment.telepathic = true;
+        equipment.light_bonus = -1;
+        equipment.fire_damage = 2;
+        equipment.cursed = true;
+        let gifts = gear_gifts(&game, &objects[PLAYER]);
+        let cursed = "Cursed: something won't come off";
+        assert_eq!(gifts, ["Telepathy", "-1 light", "+2 fire damage", cursed]);
+    }
+}
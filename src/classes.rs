@@ -0,0 +1,162 @@
+//! Character classes. A new character is a warrior, a rogue or a mage, which
+//! decides the stats, equipment and spells they start with, what they're good
+//! at, and what each level up brings them besides the stat they choose.
+
+use serde::{Deserialize, Serialize};
+
+use crate::items;
+use crate::spells::Spell;
+use crate::{DeathCallback, Fighter, Object};
+
+/// how much harder a rogue is to notice
+const ROGUE_STEALTH_BONUS: i32 = 4;
+/// what a warrior's toughness adds to their maximum HP every level
+const WARRIOR_LEVEL_HP: i32 = 10;
+/// what a mage's studies add to their maximum mana every level
+const MAGE_LEVEL_MANA: i32 = 5;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Class {
+    /// tough, and starts with a helmet; characters from before there were
+    /// classes were warriors
+    #[default]
+    Warrior,
+    /// quiet and sharp-eyed, but frailer
+    Rogue,
+    /// frail, but knows a spell from the start, and has the mana for more
+    Mage,
+}
+
+/// every class, in the order the new game menu offers them
+pub const CLASSES: [Class; 3] = [Class::Warrior, Class::Rogue, Class::Mage];
+
+impl Class {
+    pub fn name(self) -> &'static str {
+        match self {
+            Class::Warrior => "Warrior",
+            Class::Rogue => "Rogue",
+            Class::Mage => "Mage",
+        }
+    }
+
+    /// what the new game menu says about it
+    pub fn description(self) -> &'static str {
+        match self {
+            Class::Warrior => "Warrior: a dagger and a helmet, and more HP every level",
+            Class::Rogue => "Rogue: a dagger, stealthy, and more perceptive every level",
+            Class::Mage => "Mage: a dagger, magic missiles and plenty of mana, but frail",
+        }
+    }
+
+    /// the player's stats at the start of the game
+    pub fn fighter(self) -> Fighter {
+        let (hp, defense, power, perception, mana, strength) = match self {
+            Class::Warrior => (100, 1, 2, 5, 10, 6),
+            Class::Rogue => (80, 2, 2, 7, 15, 5),
+            Class::Mage => (70, 0, 1, 6, 30, 4),
+        };
+        Fighter {
+            base_max_hp: hp,
+            hp,
+            base_defense: defense,
+            base_power: power,
+            perception,
+            strength,
+            mana,
+            max_mana: mana,
+            on_death: DeathCallback::Player,
+            ..Default::default()
+        }
+    }
+
+    /// what the player starts the game carrying, with anything that can be
+    /// equipped already equipped
+    pub fn starting_items(self) -> Vec<Object> {
+        let names: &[&str] = match self {
+            Class::Warrior => &["dagger", "helmet"],
+            Class::Rogue => &["dagger"],
+            Class::Mage => &["dagger"],
+        };
+        names
+            .iter()
+            .map(|name| {
+                let mut item = items::template(name).spawn(0, 0);
+                if let Some(equipment) = item.equipment.as_mut() {
+                    equipment.equipped = true;
+                }
+                item
+            })
+            .collect()
+    }
+
+    /// the spells the player knows at the start of the game
+    pub fn starting_spells(self) -> Vec<Spell> {
+        match self {
+            Class::Mage => vec![Spell::MagicMissile],
+            _ => vec![],
+        }
+    }
+
+    /// added to the player's stealth
+    pub fn stealth_bonus(self) -> i32 {
+        match self {
+            Class::Rogue => ROGUE_STEALTH_BONUS,
+            _ => 0,
+        }
+    }
+
+    /// What every level up brings a character of this class, on top of the
+    /// stat they choose to raise.
+    pub fn level_up(self, fighter: &mut Fighter) {
+        match self {
+            Class::Warrior => {
+                fighter.base_max_hp += WARRIOR_LEVEL_HP;
+                fighter.hp += WARRIOR_LEVEL_HP;
+            }
+            Class::Rogue => fighter.perception += 1,
+            Class::Mage => {
+                fighter.max_mana += MAGE_LEVEL_MANA;
+                fighter.mana += MAGE_LEVEL_MANA;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::{new_game_state, PLAYER};
+
+    #[test]
+    fn each_class_starts_with_its_own_gear_and_spells() {
+        for class in CLASSES {
+            let (game, objects) = new_game_state(StdRng::seed_from_u64(1), class);
+            let names: Vec<&str> = game.inventory.iter().map(|item| item.name.as_str()).collect();
+            let items = class.starting_items();
+            assert_eq!(names, items.iter().map(|item| item.name.as_str()).collect::<Vec<_>>());
+            assert!(game.inventory.iter().all(|item| item.equipment.unwrap().equipped));
+            assert_eq!(game.spells, class.starting_spells());
+            assert_eq!(objects[PLAYER].fighter.unwrap().hp, class.fighter().hp);
+        }
+    }
+
+    #[test]
+    fn each_class_gets_its_own_gift_every_level() {
+        let gained = |class: Class| {
+            let mut fighter = class.fighter();
+            class.level_up(&mut fighter);
+            let before = class.fighter();
+            (
+                fighter.base_max_hp - before.base_max_hp,
+                fighter.perception - before.perception,
+                fighter.max_mana - before.max_mana,
+            )
+        };
+        assert_eq!(gained(Class::Warrior), (WARRIOR_LEVEL_HP, 0, 0));
+        assert_eq!(gained(Class::Rogue), (0, 1, 0));
+        assert_eq!(gained(Class::Mage), (0, 0, MAGE_LEVEL_MANA));
+    }
+}
@@ -0,0 +1,57 @@
+//! Colors, as the game draws and saves them, with the named ones it uses
+//! from libtcod's palette. They're the game's own, so the screen can be
+//! drawn without libtcod; the names and shades match libtcod's, so colors
+//! written in the data files look the same whichever backend shows them.
+
+use serde::{Deserialize, Serialize};
+
+/// A color, by how much red, green and blue are in it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Color {
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Color { r, g, b }
+    }
+}
+
+/// the color `amount` of the way from one color to the other, from 0 to 1
+pub fn lerp(from: Color, to: Color, amount: f32) -> Color {
+    let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount) as u8;
+    Color {
+        r: mix(from.r, to.r),
+        g: mix(from.g, to.g),
+        b: mix(from.b, to.b),
+    }
+}
+
+pub const BLACK: Color = Color::new(0, 0, 0);
+pub const DARK_GREY: Color = Color::new(95, 95, 95);
+pub const LIGHT_GREY: Color = Color::new(159, 159, 159);
+pub const WHITE: Color = Color::new(255, 255, 255);
+
+pub const RED: Color = Color::new(255, 0, 0);
+pub const LIGHT_RED: Color = Color::new(255, 63, 63);
+pub const DARK_RED: Color = Color::new(191, 0, 0);
+pub const DARKER_RED: Color = Color::new(127, 0, 0);
+pub const FLAME: Color = Color::new(255, 63, 0);
+pub const ORANGE: Color = Color::new(255, 127, 0);
+pub const YELLOW: Color = Color::new(255, 255, 0);
+pub const LIGHT_YELLOW: Color = Color::new(255, 255, 63);
+pub const GREEN: Color = Color::new(0, 255, 0);
+pub const LIGHT_GREEN: Color = Color::new(63, 255, 63);
+pub const DARKER_GREEN: Color = Color::new(0, 127, 0);
+pub const DESATURATED_GREEN: Color = Color::new(63, 127, 63);
+pub const LIGHT_CYAN: Color = Color::new(63, 255, 255);
+pub const LIGHT_BLUE: Color = Color::new(63, 63, 255);
+pub const DARKER_BLUE: Color = Color::new(0, 0, 127);
+pub const VIOLET: Color = Color::new(127, 0, 255);
+pub const LIGHT_VIOLET: Color = Color::new(159, 63, 255);
+
+pub const LIGHT_SEPIA: Color = Color::new(158, 134, 100);
+pub const DARKER_SEPIA: Color = Color::new(63, 50, 31);
+pub const GOLD: Color = Color::new(229, 191, 0);
@@ -0,0 +1,205 @@
+//! Companions: monsters on the player's side, like the wolf a scroll of
+//! summoning calls up, or a charmed monster while the charm lasts. They keep
+//! close to the player, go for any enemy that comes near, follow the player
+//! down and up the stairs, and can be told to stay where they are or to
+//! follow again.
+
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::bestiary;
+use crate::colors::{LIGHT_GREEN, ORANGE, WHITE};
+use crate::effects::Effect;
+use crate::entities::Objects;
+use crate::factions::{self, Faction};
+use crate::map::is_blocked;
+use crate::pathfinding;
+use crate::{menu, move_by, move_towards, mut_two, Ai, Attitude, Game, Object, Tcod, PLAYER};
+
+/// what a scroll of summoning calls up
+const SUMMONED_SPECIES: &str = "wolf";
+/// how far a following companion lets the player get before catching up
+const FOLLOW_DISTANCE: f32 = 2.0;
+/// how far off a companion goes after a hostile monster it sees
+const GUARD_RANGE: f32 = 6.0;
+/// how far from the player a companion arriving with them can end up
+const ARRIVAL_RANGE: i32 = 2;
+const ORDERS_WIDTH: i32 = 30;
+
+/// What the player has told their companions to do.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Order {
+    #[default]
+    Follow,
+    /// stay put, fighting only what comes to them
+    Stay,
+}
+
+/// whether the object is one of the player's companions, for good or for now
+pub fn is_companion(object: &Object) -> bool {
+    object.alive && (object.ai == Some(Ai::Ally) || object.has_effect(Effect::Charmed))
+}
+
+/// Call up a companion next to the player. Fails if there's no room for one.
+pub fn summon(game: &mut Game, objects: &mut Objects) -> bool {
+    let (x, y) = objects[PLAYER].pos();
+    let spot = free_spots_around(x, y, 1, game, objects)
+        .choose(&mut game.rng)
+        .copied();
+    let (x, y) = match spot {
+        Some(spot) => spot,
+        None => {
+            game.messages
+                .add("There's no room for anything to answer the call.", WHITE);
+            return false;
+        }
+    };
+    let companion = make_companion(bestiary::template(SUMMONED_SPECIES).spawn(x, y));
+    game.messages.add(
+        format!("A {} answers the call, and comes to your side.", companion.name),
+        LIGHT_GREEN,
+    );
+    objects.push(companion);
+    true
+}
+
+/// turn a monster into a companion of the player's
+fn make_companion(mut monster: Object) -> Object {
+    monster.ai = Some(Ai::Ally);
+    monster.attitude = Attitude::Friendly;
+    monster.faction = Faction::Player;
+    monster.pack = None;
+    if let Some(fighter) = monster.fighter.as_mut() {
+        fighter.xp = 0;
+    }
+    monster
+}
+
+/// Ask the player what their companions should do, and tell them. Takes no
+/// time.
+pub fn give_orders(tcod: &mut Tcod, game: &mut Game, objects: &Objects) {
+    if !objects.iter().any(is_companion) {
+        game.messages.add("You have no companions to order about.", WHITE);
+        return;
+    }
+    let header = "Tell your companions to:\n";
+    let order = match menu(header, &["Follow me", "Stay here"], ORDERS_WIDTH, tcod) {
+        Some(0) => Order::Follow,
+        Some(1) => Order::Stay,
+        _ => return,
+    };
+    game.order = order;
+    let msg = match order {
+        Order::Follow => "Your companions fall in behind you.",
+        Order::Stay => "Your companions stay where they are.",
+    };
+    game.messages.add(msg, LIGHT_GREEN);
+}
+
+/// a companion's turn, for good
+pub fn ai_ally(id: usize, game: &mut Game, objects: &mut Objects) -> Ai {
+    fight_for_player(id, game, objects);
+    Ai::Ally
+}
+
+/// A companion attacks an enemy it can see close by, following it if it's
+/// been told to, and otherwise keeps up with the player. What it kills, the
+/// player gets the experience for.
+pub fn fight_for_player(id: usize, game: &mut Game, objects: &mut Objects) {
+    let foe = factions::pick_enemy(id, GUARD_RANGE, game, objects);
+    match foe {
+        Some(foe) if objects[id].distance_to(&objects[foe]) < 2.0 => {
+            let (companion, target) = mut_two(id, foe, objects);
+            let xp_before = companion.fighter.map_or(0, |f| f.xp);
+            companion.facing = (target.x - companion.x, target.y - companion.y);
+            companion.attack(target, game);
+            let gained = match companion.fighter.as_mut() {
+                Some(fighter) => std::mem::replace(&mut fighter.xp, xp_before) - xp_before,
+                None => 0,
+            };
+            if let Some(player) = objects[PLAYER].fighter.as_mut() {
+                player.xp += gained;
+            }
+        }
+        Some(foe) if game.order == Order::Follow => {
+            let (x, y) = objects[foe].pos();
+            move_towards(id, x, y, &mut game.map, objects);
+        }
+        _ if game.order == Order::Follow => {
+            let (player_x, player_y) = objects[PLAYER].pos();
+            if objects[id].distance(player_x, player_y) > FOLLOW_DISTANCE {
+                let target = (player_x, player_y);
+                match pathfinding::next_step(id, target, &game.to_player, &game.map, objects) {
+                    Some((dx, dy)) => move_by(id, dx, dy, &mut game.map, objects),
+                    None => move_towards(id, player_x, player_y, &mut game.map, objects),
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Take the companions next to the player off the level they're leaving, to
+/// come along to the next; those told to stay, stay.
+pub fn take_along(game: &Game, objects: &mut Objects) -> Vec<Object> {
+    if game.order == Order::Stay {
+        return vec![];
+    }
+    let mut coming = vec![];
+    for id in (PLAYER + 1..objects.len()).rev() {
+        if is_companion(&objects[id]) && objects[id].distance_to(&objects[PLAYER]) < 2.0 {
+            coming.push(objects.set_aside(id));
+        }
+    }
+    coming
+}
+
+/// Put the companions that came along down around the player, wherever
+/// there's room. Any there's no room for are lost on the way.
+pub fn arrive(companions: Vec<Object>, game: &mut Game, objects: &mut Objects) {
+    let (x, y) = objects[PLAYER].pos();
+    for mut companion in companions {
+        let spot = free_spots_around(x, y, ARRIVAL_RANGE, game, objects)
+            .into_iter()
+            .min_by_key(|&(spot_x, spot_y)| (spot_x - x).abs().max((spot_y - y).abs()));
+        match spot {
+            Some((x, y)) => {
+                companion.set_pos(x, y);
+                objects.push(companion);
+                let companion = objects.id_of(objects.len() - 1);
+                objects.paths.remove(companion);
+            }
+            None => {
+                if let Some(id) = companion.id {
+                    objects.forget(id);
+                }
+                game.messages.add(
+                    format!("Your {} is lost on the way.", companion.name),
+                    ORANGE,
+                );
+            }
+        }
+    }
+}
+
+/// the free, dry tiles within `range` steps of a spot, not counting the spot
+/// itself
+fn free_spots_around(
+    x: i32,
+    y: i32,
+    range: i32,
+    game: &Game,
+    objects: &Objects,
+) -> Vec<(i32, i32)> {
+    let mut spots = vec![];
+    for dy in -range..=range {
+        for dx in -range..=range {
+            let (x, y) = (x + dx, y + dy);
+            let free = !is_blocked(x, y, &game.map, objects) && !game.map[(x, y)].deep_water;
+            if (dx, dy) != (0, 0) && free {
+                spots.push((x, y));
+            }
+        }
+    }
+    spots
+}
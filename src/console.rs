@@ -0,0 +1,414 @@
+//! Consoles: grids of character cells, each a character with the colors it's
+//! drawn in, that everything on the screen is drawn onto before a backend
+//! shows it. They work like libtcod's offscreen consoles, as far as the game
+//! uses them, so any backend can show one without libtcod.
+
+use crate::colors::{lerp, Color, BLACK, WHITE};
+
+/// What drawing on a cell does to its background.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackgroundFlag {
+    /// leaves it as it was
+    None,
+    /// puts the new color in its place
+    Set,
+    /// brightens it by the new color, as light does
+    Screen,
+}
+
+impl BackgroundFlag {
+    fn apply(self, old: Color, new: Color) -> Color {
+        let screen = |old: u8, new: u8| 255 - ((255 - old as u32) * (255 - new as u32) / 255) as u8;
+        match self {
+            BackgroundFlag::None => old,
+            BackgroundFlag::Set => new,
+            BackgroundFlag::Screen => Color {
+                r: screen(old.r, new.r),
+                g: screen(old.g, new.g),
+                b: screen(old.b, new.b),
+            },
+        }
+    }
+}
+
+/// Which way text runs from the spot it's printed at.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextAlignment {
+    Left,
+    Right,
+    Center,
+}
+
+/// One character on a console, with its colors.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cell {
+    pub char: char,
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell {
+            char: ' ',
+            foreground: WHITE,
+            background: BLACK,
+        }
+    }
+}
+
+/// A console of a given size, drawn on in the colors last set for it, white
+/// on black to start with.
+#[derive(Clone, Debug)]
+pub struct Offscreen {
+    width: i32,
+    height: i32,
+    cells: Vec<Cell>,
+    foreground: Color,
+    background: Color,
+}
+
+impl Offscreen {
+    pub fn new(width: i32, height: i32) -> Self {
+        Offscreen {
+            width,
+            height,
+            cells: vec![Cell::default(); (width * height).max(0) as usize],
+            foreground: WHITE,
+            background: BLACK,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && y >= 0 && x < self.width && y < self.height {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// the cell at the given spot, or a blank one off the console
+    pub fn cell(&self, x: i32, y: i32) -> Cell {
+        self.index(x, y)
+            .map_or_else(Cell::default, |i| self.cells[i])
+    }
+
+    pub fn set_default_foreground(&mut self, color: Color) {
+        self.foreground = color;
+    }
+
+    pub fn set_default_background(&mut self, color: Color) {
+        self.background = color;
+    }
+
+    /// blank every cell, in the console's colors
+    pub fn clear(&mut self) {
+        let blank = Cell {
+            char: ' ',
+            foreground: self.foreground,
+            background: self.background,
+        };
+        self.cells.iter_mut().for_each(|cell| *cell = blank);
+    }
+
+    /// put a character at the given spot, in the console's colors
+    pub fn put_char(&mut self, x: i32, y: i32, char: char, flag: BackgroundFlag) {
+        let (foreground, background) = (self.foreground, self.background);
+        if let Some(i) = self.index(x, y) {
+            let cell = &mut self.cells[i];
+            cell.char = char;
+            cell.foreground = foreground;
+            cell.background = flag.apply(cell.background, background);
+        }
+    }
+
+    /// put a character at the given spot, in the given colors
+    pub fn put_char_ex(
+        &mut self,
+        x: i32,
+        y: i32,
+        char: char,
+        foreground: Color,
+        background: Color,
+    ) {
+        if let Some(i) = self.index(x, y) {
+            self.cells[i] = Cell {
+                char,
+                foreground,
+                background,
+            };
+        }
+    }
+
+    pub fn get_char(&self, x: i32, y: i32) -> char {
+        self.cell(x, y).char
+    }
+
+    pub fn get_char_background(&self, x: i32, y: i32) -> Color {
+        self.cell(x, y).background
+    }
+
+    pub fn set_char_background(&mut self, x: i32, y: i32, color: Color, flag: BackgroundFlag) {
+        if let Some(i) = self.index(x, y) {
+            let cell = &mut self.cells[i];
+            cell.background = flag.apply(cell.background, color);
+        }
+    }
+
+    /// Fill the background of a rect with the console's background color,
+    /// and blank its characters too if `clear`.
+    pub fn rect(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        clear: bool,
+        flag: BackgroundFlag,
+    ) {
+        for y in y..y + height {
+            for x in x..x + width {
+                if clear {
+                    self.put_char(x, y, ' ', flag);
+                } else {
+                    self.set_char_background(x, y, self.background, flag);
+                }
+            }
+        }
+    }
+
+    /// print text from the given spot, left to right, leaving the background
+    pub fn print<T: AsRef<str>>(&mut self, x: i32, y: i32, text: T) {
+        self.print_ex(x, y, BackgroundFlag::None, TextAlignment::Left, text);
+    }
+
+    /// Print text lined up on the given spot, a line down for every newline
+    /// in it.
+    pub fn print_ex<T: AsRef<str>>(
+        &mut self,
+        x: i32,
+        y: i32,
+        flag: BackgroundFlag,
+        alignment: TextAlignment,
+        text: T,
+    ) {
+        for (row, line) in text.as_ref().split('\n').enumerate() {
+            self.print_line(x, y + row as i32, flag, alignment, line);
+        }
+    }
+
+    fn print_line(
+        &mut self,
+        x: i32,
+        y: i32,
+        flag: BackgroundFlag,
+        alignment: TextAlignment,
+        line: &str,
+    ) {
+        let length = line.chars().count() as i32;
+        let start = match alignment {
+            TextAlignment::Left => x,
+            TextAlignment::Right => x - length + 1,
+            TextAlignment::Center => x - length / 2,
+        };
+        for (i, char) in line.chars().enumerate() {
+            self.put_char(start + i as i32, y, char, flag);
+        }
+    }
+
+    /// Print text from the given spot, wrapped to fit `width` cells across,
+    /// and cut off after `height` lines unless that's 0. Returns how many
+    /// lines it took.
+    pub fn print_rect<T: AsRef<str>>(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        text: T,
+    ) -> i32 {
+        self.print_rect_ex(
+            x,
+            y,
+            width,
+            height,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            text,
+        )
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn print_rect_ex<T: AsRef<str>>(
+        &mut self,
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+        flag: BackgroundFlag,
+        alignment: TextAlignment,
+        text: T,
+    ) -> i32 {
+        let lines = self.wrap(x, width, height, text.as_ref());
+        for (row, line) in lines.iter().enumerate() {
+            self.print_line(x, y + row as i32, flag, alignment, line);
+        }
+        lines.len() as i32
+    }
+
+    /// how many lines `print_rect` would take to print the text
+    pub fn get_height_rect<T: AsRef<str>>(
+        &self,
+        x: i32,
+        _y: i32,
+        width: i32,
+        height: i32,
+        text: T,
+    ) -> i32 {
+        self.wrap(x, width, height, text.as_ref()).len() as i32
+    }
+
+    /// The text broken into lines `width` cells across at most, at spaces
+    /// where it can be, or as far as the edge of the console if that's 0.
+    /// There are no more than `height` of them, unless that's 0.
+    fn wrap(&self, x: i32, width: i32, height: i32, text: &str) -> Vec<String> {
+        let width = match width {
+            0 => self.width - x,
+            width => width,
+        };
+        let width = width.max(1) as usize;
+        let mut lines = vec![];
+        for paragraph in text.split('\n') {
+            let mut line: Vec<char> = vec![];
+            for word in paragraph.split(' ') {
+                let word: Vec<char> = word.chars().collect();
+                if !line.is_empty() && line.len() + 1 + word.len() > width {
+                    lines.push(line);
+                    line = vec![];
+                }
+                if !line.is_empty() {
+                    line.push(' ');
+                }
+                line.extend(word);
+                // a word too long for a line of its own is broken up
+                while line.len() > width {
+                    let rest = line.split_off(width);
+                    lines.push(line);
+                    line = rest;
+                }
+            }
+            lines.push(line);
+        }
+        if height > 0 {
+            lines.truncate(height as usize);
+        }
+        lines
+            .into_iter()
+            .map(|line| line.into_iter().collect())
+            .collect()
+    }
+}
+
+/// Copy a rect of one console onto another, all of it if the size is 0 by 0.
+/// The copy is laid over what's there with the given opacity, from 0 to 1,
+/// of its characters and of its background; where it has no character, the
+/// one underneath shows through its background.
+pub fn blit(
+    from: &Offscreen,
+    (from_x, from_y): (i32, i32),
+    (width, height): (i32, i32),
+    to: &mut Offscreen,
+    (to_x, to_y): (i32, i32),
+    foreground_alpha: f32,
+    background_alpha: f32,
+) {
+    let (width, height) = match (width, height) {
+        (0, 0) => (from.width, from.height),
+        size => size,
+    };
+    for dy in 0..height {
+        for dx in 0..width {
+            let source = match from.index(from_x + dx, from_y + dy) {
+                Some(i) => from.cells[i],
+                None => continue,
+            };
+            let i = match to.index(to_x + dx, to_y + dy) {
+                Some(i) => i,
+                None => continue,
+            };
+            let under = to.cells[i];
+            let background = lerp(under.background, source.background, background_alpha);
+            to.cells[i] = if source.char == ' ' {
+                Cell {
+                    foreground: lerp(under.foreground, source.background, background_alpha),
+                    background,
+                    ..under
+                }
+            } else {
+                Cell {
+                    char: source.char,
+                    foreground: lerp(background, source.foreground, foreground_alpha),
+                    background,
+                }
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::{LIGHT_GREY, RED};
+
+    fn text(console: &Offscreen, y: i32) -> String {
+        (0..console.width())
+            .map(|x| console.get_char(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn text_is_lined_up_and_wrapped() {
+        let mut console = Offscreen::new(10, 4);
+        console.print_ex(9, 0, BackgroundFlag::None, TextAlignment::Right, "end");
+        console.print_ex(5, 1, BackgroundFlag::None, TextAlignment::Center, "mid");
+        assert_eq!(text(&console, 0), "       end");
+        assert_eq!(text(&console, 1), "    mid   ");
+        let mut console = Offscreen::new(10, 4);
+        let wrapped = "a few words\nand a verylongword";
+        assert_eq!(console.get_height_rect(0, 0, 6, 0, wrapped), 5);
+        assert_eq!(console.print_rect(0, 0, 6, 3, wrapped), 3);
+        let lines: Vec<String> = (0..4).map(|y| text(&console, y)).collect();
+        assert_eq!(
+            lines,
+            ["a few     ", "words     ", "and a     ", "          "]
+        );
+    }
+
+    #[test]
+    fn blitting_lays_one_console_over_another() {
+        let mut under = Offscreen::new(3, 1);
+        under.set_default_background(LIGHT_GREY);
+        under.clear();
+        under.put_char(0, 0, 'x', BackgroundFlag::None);
+        let mut over = Offscreen::new(2, 1);
+        over.set_default_foreground(RED);
+        over.put_char(1, 0, '@', BackgroundFlag::None);
+        blit(&over, (0, 0), (0, 0), &mut under, (0, 0), 1.0, 0.5);
+        assert_eq!(under.get_char(0, 0), 'x');
+        assert_eq!(under.get_char(1, 0), '@');
+        assert_eq!(under.cell(1, 0).foreground, RED);
+        assert_eq!(
+            under.get_char_background(1, 0),
+            lerp(LIGHT_GREY, BLACK, 0.5)
+        );
+        assert_eq!(under.get_char_background(2, 0), LIGHT_GREY);
+    }
+}
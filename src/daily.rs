@@ -0,0 +1,97 @@
+//! The daily challenge: a run whose seed is the current date, so everyone
+//! playing on the same day gets the same dungeon, and plays it as the same
+//! class. Results are kept apart from ordinary runs, in their own file.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::classes::{Class, CLASSES};
+
+/// where daily challenge results are appended, one JSON line per run
+const DAILY_RESULTS_FILE: &str = "daily_results";
+
+/// How one daily run ended.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct DailyResult {
+    pub day: u64,
+    pub dungeon_level: u32,
+    pub turn: u32,
+    pub outcome: String,
+}
+
+/// today, counted in days since 1970-01-01 (UTC); also the day's seed
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / (24 * 60 * 60))
+}
+
+/// the class everyone plays the day's challenge as, each in turn from one
+/// day to the next
+pub fn class_of(day: u64) -> Class {
+    CLASSES[(day % CLASSES.len() as u64) as usize]
+}
+
+/// the calendar date of a day, as YYYY-MM-DD
+pub fn date_of(day: u64) -> String {
+    // from Howard Hinnant's days_from_civil algorithms, in reverse
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day_of_month = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day_of_month)
+}
+
+/// append the result of a daily run to the results file
+pub fn record_result(result: &DailyResult) {
+    let line = match serde_json::to_string(result) {
+        Ok(line) => line,
+        Err(e) => {
+            eprintln!("Could not record the daily result: {}", e);
+            return;
+        }
+    };
+    let written = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(DAILY_RESULTS_FILE)
+        .and_then(|mut file| writeln!(file, "{}", line));
+    if let Err(e) = written {
+        eprintln!("Could not record the daily result: {}", e);
+    }
+}
+
+/// the results recorded so far for `day`, deepest first
+pub fn results_for(day: u64) -> Vec<DailyResult> {
+    let mut results: Vec<DailyResult> = fs::read_to_string(DAILY_RESULTS_FILE)
+        .unwrap_or_default()
+        .lines()
+        .filter_map(|line| serde_json::from_str::<DailyResult>(line).ok())
+        .filter(|result| result.day == day)
+        .collect();
+    results.sort_by_key(|result| (std::cmp::Reverse(result.dungeon_level), result.turn));
+    results
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn everyone_plays_the_same_class_on_the_same_day() {
+        let day = 20_000;
+        assert_eq!(class_of(day), class_of(day));
+        // and every class gets its day
+        let week: Vec<Class> = (day..day + 7).map(class_of).collect();
+        assert!(CLASSES.iter().all(|class| week.contains(class)));
+    }
+}
@@ -0,0 +1,220 @@
+//! Day and night on the surface. The time of day follows the turn count,
+//! starting in the morning, and the town is only as bright as the sky above
+//! it: in full daylight by day, by starlight at night, and in between at
+//! dawn and dusk, and the less light there is the less far the player can
+//! make anything out. The shops keep to their hours, and once the sun
+//! is down things come wandering in out of the dark.
+
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+
+use crate::colors::{LIGHT_GREY, LIGHT_YELLOW};
+use crate::{bestiary, Game, Object};
+
+/// how many turns a whole day lasts
+pub const DAY_LENGTH: u32 = 1200;
+/// the hour it is on the first turn
+const START_HOUR: u32 = 8;
+
+/// how bright the town is by day, and by night
+const DAYLIGHT: f32 = 0.8;
+const NIGHT_LIGHT: f32 = 0.05;
+/// how far the player can make anything out at night, and at dawn and dusk
+const NIGHT_SIGHT: i32 = 6;
+const TWILIGHT_SIGHT: i32 = 12;
+
+/// the hours the sun takes to come up, and to go down
+const DAWN: (u32, u32) = (5, 7);
+const DUSK: (u32, u32) = (18, 20);
+/// the hours the shops open at and shut at; the inn never shuts
+const OPENING_HOUR: u32 = 8;
+const CLOSING_HOUR: u32 = 19;
+
+/// the chance each turn, in tenths of a percent, that something wanders into
+/// town by night; by day, nothing does
+const NIGHT_WANDERER_CHANCE: u32 = 6;
+/// what wanders in, with how often each does
+const NIGHT_WANDERERS: &[(&str, u32)] = &[
+    ("wolf", 4),
+    ("gloom bat", 3),
+    ("zombie", 2),
+    ("skeleton", 2),
+    ("orc", 1),
+];
+
+/// The parts of the day, as far as the light goes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeOfDay {
+    Dawn,
+    Day,
+    Dusk,
+    Night,
+}
+
+impl TimeOfDay {
+    pub fn name(self) -> &'static str {
+        match self {
+            TimeOfDay::Dawn => "dawn",
+            TimeOfDay::Day => "day",
+            TimeOfDay::Dusk => "dusk",
+            TimeOfDay::Night => "night",
+        }
+    }
+}
+
+/// the hour of the day on the given turn, with how far into it, from 0 to 24
+fn hour(turn: u32) -> f32 {
+    let start = DAY_LENGTH * START_HOUR / 24;
+    let into_day = (turn as u64 + start as u64) % DAY_LENGTH as u64;
+    into_day as f32 * 24.0 / DAY_LENGTH as f32
+}
+
+/// how far through the given hours it is, from 0 before them to 1 after
+fn through(hour: f32, (from, to): (u32, u32)) -> f32 {
+    ((hour - from as f32) / (to - from) as f32).clamp(0.0, 1.0)
+}
+
+pub fn time_of_day(turn: u32) -> TimeOfDay {
+    let hour = hour(turn);
+    let within = |(from, to): (u32, u32)| (from as f32..to as f32).contains(&hour);
+    if within(DAWN) {
+        TimeOfDay::Dawn
+    } else if within(DUSK) {
+        TimeOfDay::Dusk
+    } else if within((DAWN.1, DUSK.0)) {
+        TimeOfDay::Day
+    } else {
+        TimeOfDay::Night
+    }
+}
+
+/// whether the player is out under the sky, in town; the tutorial's level is
+/// underground, for all it's numbered like the town
+pub fn on_surface(game: &Game) -> bool {
+    game.dungeon_level == 0 && game.tutorial.is_none()
+}
+
+/// how bright the sky leaves the surface on the given turn
+pub fn ambient_light(turn: u32) -> f32 {
+    let hour = hour(turn);
+    let day = through(hour, DAWN) - through(hour, DUSK);
+    NIGHT_LIGHT + (DAYLIGHT - NIGHT_LIGHT) * day
+}
+
+/// how far the player can see in the light there is, or 0 for as far as the
+/// light goes, as it does by day and underground
+pub fn sight_radius(game: &Game) -> i32 {
+    if !on_surface(game) {
+        return 0;
+    }
+    match time_of_day(game.turn) {
+        TimeOfDay::Day => 0,
+        TimeOfDay::Dawn | TimeOfDay::Dusk => TWILIGHT_SIGHT,
+        TimeOfDay::Night => NIGHT_SIGHT,
+    }
+}
+
+/// whether the shops in town are open on the given turn
+pub fn shops_open(turn: u32) -> bool {
+    (OPENING_HOUR as f32..CLOSING_HOUR as f32).contains(&hour(turn))
+}
+
+/// Tell the player in town when the sun comes up or goes down, and when the
+/// shops open and shut, as the turn that's just begun brings it about.
+pub fn pass_time(game: &mut Game) {
+    if !on_surface(game) || game.turn == 0 {
+        return;
+    }
+    let (before, now) = (game.turn - 1, game.turn);
+    let change = match (time_of_day(before), time_of_day(now)) {
+        (was, is) if was == is => None,
+        (_, TimeOfDay::Dawn) => Some(("The sky begins to pale.", LIGHT_GREY)),
+        (_, TimeOfDay::Day) => Some(("The sun is up.", LIGHT_YELLOW)),
+        (_, TimeOfDay::Dusk) => Some(("The sun is going down.", LIGHT_YELLOW)),
+        (_, TimeOfDay::Night) => Some(("Night falls over the town.", LIGHT_GREY)),
+    };
+    if let Some((text, color)) = change {
+        game.messages.add(text, color);
+    }
+    match (shops_open(before), shops_open(now)) {
+        (false, true) => game.messages.add("The shops open their doors.", LIGHT_GREY),
+        (true, false) => game.messages.add("The shops shut for the night.", LIGHT_GREY),
+        _ => {}
+    }
+}
+
+/// the chance this turn, in tenths of a percent, that something wanders
+/// into town
+pub fn wanderer_chance(turn: u32) -> u32 {
+    match time_of_day(turn) {
+        TimeOfDay::Night => NIGHT_WANDERER_CHANCE,
+        _ => 0,
+    }
+}
+
+/// something wandering into town by night at the given spot
+pub fn wanderer(x: i32, y: i32, rng: &mut impl Rng) -> Object {
+    let weights = NIGHT_WANDERERS.iter().map(|&(_, weight)| weight);
+    let dist = WeightedIndex::new(weights).unwrap();
+    bestiary::template(NIGHT_WANDERERS[rng.sample(&dist)].0).spawn(x, y)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::new_game_state;
+
+    /// the turn on the first day at the given hour
+    fn at(hour: u32) -> u32 {
+        (hour + 24 - START_HOUR) % 24 * DAY_LENGTH / 24
+    }
+
+    #[test]
+    fn the_light_fades_at_dusk_and_comes_back_at_dawn() {
+        assert_eq!(ambient_light(0), DAYLIGHT);
+        assert_eq!(time_of_day(at(12)), TimeOfDay::Day);
+        assert_eq!(time_of_day(at(19)), TimeOfDay::Dusk);
+        assert_eq!(time_of_day(at(23)), TimeOfDay::Night);
+        assert_eq!(time_of_day(at(6)), TimeOfDay::Dawn);
+        assert_eq!(ambient_light(at(0)), NIGHT_LIGHT);
+        let dusk = ambient_light(at(19));
+        assert!(NIGHT_LIGHT < dusk && dusk < DAYLIGHT);
+        assert!(ambient_light(at(6)) < ambient_light(at(6) + DAY_LENGTH / 48));
+        assert_eq!(ambient_light(at(12) + DAY_LENGTH), DAYLIGHT);
+    }
+
+    #[test]
+    fn the_dark_cuts_how_far_the_player_sees() {
+        let (mut game, _) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        assert_eq!(sight_radius(&game), 0);
+        game.turn = at(19);
+        assert_eq!(sight_radius(&game), TWILIGHT_SIGHT);
+        game.turn = at(0);
+        assert_eq!(sight_radius(&game), NIGHT_SIGHT);
+        game.dungeon_level = 1;
+        assert_eq!(sight_radius(&game), 0);
+    }
+
+    #[test]
+    fn the_shops_keep_their_hours() {
+        assert!(shops_open(0) && shops_open(at(18)));
+        assert!(!shops_open(at(19)) && !shops_open(at(2)) && !shops_open(at(7)));
+    }
+
+    #[test]
+    fn things_only_wander_in_by_night() {
+        assert_eq!(wanderer_chance(at(12)), 0);
+        assert_eq!(wanderer_chance(at(19)), 0);
+        assert!(wanderer_chance(at(0)) > 0);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let wanderer = wanderer(3, 4, &mut rng);
+            assert!(NIGHT_WANDERERS.iter().any(|&(name, _)| name == wanderer.name));
+            assert_eq!(wanderer.pos(), (3, 4));
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Conversations with NPCs. Each NPC's dialogue is a tree of nodes read from
+//! `data/dialogue.json`: a node is something the NPC says, followed by the
+//! answers the player can choose from. Answers can depend on what the player
+//! carries or on flags set by earlier conversations, and can set flags, hand
+//! the player an item, take one off them or charge them gold for a night's
+//! rest.
+
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::colors::{GREEN, RED};
+use crate::map::make_item;
+use crate::{menu, stow, take_from_inventory, Game, Object, Tcod, DIALOGUE_WIDTH};
+
+const DIALOGUE_DATA: &str = include_str!("../data/dialogue.json");
+
+/// One NPC's dialogue tree.
+#[derive(Debug, Deserialize)]
+struct Dialogue {
+    /// the node the conversation starts at
+    start: String,
+    nodes: HashMap<String, Node>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Node {
+    text: String,
+    choices: Vec<Choice>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Choice {
+    text: String,
+    /// the node this answer leads to; the conversation ends if there is none
+    next: Option<String>,
+    /// only offered if the player carries an item with this name
+    requires_item: Option<String>,
+    /// only offered once this flag has been set
+    requires_flag: Option<String>,
+    /// only offered until this flag has been set
+    unless_flag: Option<String>,
+    sets_flag: Option<String>,
+    gives: Option<String>,
+    /// only offered if the player carries an item with this name, one of
+    /// which it takes
+    takes: Option<String>,
+    /// only offered if the player has this much gold, which it takes
+    costs: Option<i32>,
+    /// heals the player fully and cures any poison
+    #[serde(default)]
+    heals: bool,
+}
+
+impl Choice {
+    fn is_available(&self, game: &Game) -> bool {
+        let has_item = |name: &String| game.inventory.iter().any(|item| &item.name == name);
+        self.requires_item.as_ref().map_or(true, has_item)
+            && self.takes.as_ref().map_or(true, has_item)
+            && self
+                .requires_flag
+                .as_ref()
+                .map_or(true, |flag| game.flags.contains(flag))
+            && self
+                .unless_flag
+                .as_ref()
+                .map_or(true, |flag| !game.flags.contains(flag))
+            && self.costs.map_or(true, |cost| game.gold >= cost)
+    }
+
+    /// set flags, hand over items, take gold and heal for picking this answer
+    fn apply(&self, game: &mut Game, player: &mut Object, speaker: &str) {
+        if let Some(cost) = self.costs {
+            game.gold -= cost;
+            game.messages
+                .add(format!("You pay the {} {} gold.", speaker, cost), GREEN);
+        }
+        if self.heals {
+            let max_hp = player.max_hp(game);
+            player.heal(max_hp, game);
+            player.effects.retain(|status| !status.effect.is_harmful());
+            game.messages.add("You wake up rested and well.", GREEN);
+        }
+        if let Some(name) = self.takes.as_ref() {
+            let taken = game.inventory.iter().position(|item| &item.name == name);
+            if let Some(inventory_id) = taken {
+                let item = take_from_inventory(inventory_id, 1, &mut game.inventory);
+                game.messages.add(
+                    format!("You give the {} {}.", speaker, item.a_name(game)),
+                    GREEN,
+                );
+            }
+        }
+        if let Some(flag) = self.sets_flag.as_ref() {
+            game.flags.insert(flag.clone());
+        }
+        if let Some(item) = self.gives.as_ref() {
+            if game.inventory.len() >= 26 {
+                game.messages.add(
+                    format!("Your inventory is full, so the {} keeps it.", speaker),
+                    RED,
+                );
+            } else {
+                let item = make_item(item, 0, 0);
+                game.messages.add(
+                    format!("The {} gives you {}.", speaker, item.a_name(game)),
+                    GREEN,
+                );
+                stow(item, &mut game.inventory);
+            }
+        }
+    }
+}
+
+/// read the dialogue tree with the given name from the data file
+fn load(name: &str) -> Result<Dialogue, String> {
+    let mut dialogues: HashMap<String, Dialogue> =
+        serde_json::from_str(DIALOGUE_DATA).map_err(|e| e.to_string())?;
+    dialogues
+        .remove(name)
+        .ok_or_else(|| format!("no dialogue named {}", name))
+}
+
+/// Hold the conversation `name` with `speaker`, until the player picks an
+/// answer that ends it (or any other key).
+pub fn talk(tcod: &mut Tcod, game: &mut Game, player: &mut Object, name: &str, speaker: &str) {
+    let dialogue = match load(name) {
+        Ok(dialogue) => dialogue,
+        Err(e) => {
+            game.messages
+                .add(format!("The {} has nothing to say ({}).", speaker, e), RED);
+            return;
+        }
+    };
+
+    let mut node_name = dialogue.start.clone();
+    while let Some(node) = dialogue.nodes.get(&node_name) {
+        let choices: Vec<&Choice> = node
+            .choices
+            .iter()
+            .filter(|choice| choice.is_available(game))
+            .collect();
+        let options: Vec<&str> = choices.iter().map(|choice| choice.text.as_str()).collect();
+        let header = format!("{}\n", node.text);
+        let choice = match menu(&header, &options, DIALOGUE_WIDTH, tcod) {
+            Some(index) => choices[index],
+            None => break,
+        };
+        choice.apply(game, player, speaker);
+        match choice.next.as_ref() {
+            Some(next) => node_name = next.clone(),
+            None => break,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::{items, new_game_state, PLAYER};
+
+    #[test]
+    fn every_dialogue_leads_somewhere_and_names_real_items() {
+        for name in ["hermit", "lost adventurer", "wounded knight", "innkeeper"] {
+            let dialogue = load(name).unwrap();
+            assert!(dialogue.nodes.contains_key(&dialogue.start), "{}", name);
+            for choice in dialogue.nodes.values().flat_map(|node| &node.choices) {
+                if let Some(next) = choice.next.as_ref() {
+                    assert!(dialogue.nodes.contains_key(next), "{} has no {}", name, next);
+                }
+                let named = [&choice.requires_item, &choice.gives, &choice.takes];
+                for item in named.into_iter().flatten() {
+                    assert!(items::templates().iter().any(|t| &t.name == item), "{}", item);
+                }
+            }
+        }
+        assert!(load("nobody").is_err());
+    }
+
+    #[test]
+    fn gifts_are_given_once() {
+        let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let carried = game.inventory.len();
+        // ask for something for the road, and thank the hermit
+        let mut tcod = Tcod::pressing("ba");
+        talk(&mut tcod, &mut game, &mut objects[PLAYER], "hermit", "hermit");
+        assert_eq!(game.inventory.len(), carried + 1);
+        assert!(game.flags.contains("hermit_gift"));
+        // the second answer is farewell now
+        let mut tcod = Tcod::pressing("ba");
+        talk(&mut tcod, &mut game, &mut objects[PLAYER], "hermit", "hermit");
+        assert_eq!(game.inventory.len(), carried + 1);
+    }
+}
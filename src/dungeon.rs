@@ -0,0 +1,364 @@
+//! The dungeon as a whole. Besides the main dungeon there are side branches,
+//! each with its own look, layout and inhabitants, reached by stairs from
+//! one level of the main dungeon. A level the player leaves is put away just
+//! as it was, and taken out again when they come back by the stairs; only
+//! levels never visited before are generated.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::{self, GameEvent};
+use crate::colors::Color;
+use crate::companions;
+use crate::entities::Objects;
+use crate::map::{self, is_blocked, Map, MAP_HEIGHT, MAP_WIDTH};
+use crate::town;
+use crate::{Game, Item, Object, DIRECTIONS, PLAYER};
+
+pub const DOWN_STAIRS: &str = "stairs";
+pub const UP_STAIRS: &str = "up stairs";
+
+/// how many levels each side branch goes down, below its entrance
+const BRANCH_LENGTH: u32 = 3;
+/// how deep the main dungeon goes; the crown lies on its last level
+const MAIN_DEPTH: u32 = 16;
+
+/// A part of the dungeon.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub enum Branch {
+    #[default]
+    Main,
+    /// old mine workings: caves, full of orcs
+    Mines,
+    /// a burial vault of narrow chambers, where the dead walk
+    Crypt,
+    /// flooded tunnels, home to things that like the damp
+    Sewer,
+}
+
+/// The colors a branch's walls and floors are drawn in, in the dark and
+/// fully lit.
+pub struct Palette {
+    pub dark_wall: Color,
+    pub light_wall: Color,
+    pub dark_ground: Color,
+    pub light_ground: Color,
+}
+
+impl Branch {
+    pub const SIDE_BRANCHES: [Branch; 3] = [Branch::Mines, Branch::Crypt, Branch::Sewer];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Branch::Main => "the dungeon",
+            Branch::Mines => "the mines",
+            Branch::Crypt => "the crypt",
+            Branch::Sewer => "the sewer",
+        }
+    }
+
+    /// the name shown next to the dungeon level
+    pub fn title(self) -> &'static str {
+        match self {
+            Branch::Main => "Dungeon",
+            Branch::Mines => "Mines",
+            Branch::Crypt => "Crypt",
+            Branch::Sewer => "Sewer",
+        }
+    }
+
+    /// the level of the main dungeon with the stairs into this branch
+    pub fn entrance(self) -> Option<u32> {
+        match self {
+            Branch::Main => None,
+            Branch::Mines => Some(2),
+            Branch::Crypt => Some(3),
+            Branch::Sewer => Some(4),
+        }
+    }
+
+    /// the last level of this branch, with no stairs further down
+    pub fn bottom(self) -> Option<u32> {
+        match self {
+            Branch::Main => Some(MAIN_DEPTH),
+            _ => self.entrance().map(|entrance| entrance + BRANCH_LENGTH),
+        }
+    }
+
+    /// what the stairs into this branch are called
+    pub fn stairs_name(self) -> String {
+        format!("stairs to {}", self.name())
+    }
+
+    /// how big this branch's levels are, in tiles across and down; the
+    /// bigger ones scroll as the player moves about
+    pub fn map_size(self) -> (i32, i32) {
+        match self {
+            Branch::Main | Branch::Crypt => (MAP_WIDTH, MAP_HEIGHT),
+            Branch::Mines => (120, 60),
+            Branch::Sewer => (100, 50),
+        }
+    }
+
+    /// whether this branch is one of those listed, or any branch at all if
+    /// none are
+    pub fn is_among(self, branches: &[Branch]) -> bool {
+        branches.is_empty() || branches.contains(&self)
+    }
+
+    pub fn palette(self) -> Palette {
+        let color = |r, g, b| Color { r, g, b };
+        match self {
+            Branch::Main => Palette {
+                dark_wall: crate::COLOR_DARK_WALL,
+                light_wall: crate::COLOR_LIGHT_WALL,
+                dark_ground: crate::COLOR_DARK_GROUND,
+                light_ground: crate::COLOR_LIGHT_GROUND,
+            },
+            Branch::Mines => Palette {
+                dark_wall: color(50, 35, 20),
+                light_wall: color(140, 100, 60),
+                dark_ground: color(45, 40, 35),
+                light_ground: color(170, 140, 90),
+            },
+            Branch::Crypt => Palette {
+                dark_wall: color(40, 40, 50),
+                light_wall: color(120, 120, 140),
+                dark_ground: color(35, 30, 45),
+                light_ground: color(110, 100, 130),
+            },
+            Branch::Sewer => Palette {
+                dark_wall: color(20, 40, 30),
+                light_wall: color(70, 110, 80),
+                dark_ground: color(25, 45, 35),
+                light_ground: color(90, 130, 90),
+            },
+        }
+    }
+}
+
+/// A level the player isn't on: its map, with everything on it but the player.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Level {
+    map: Map,
+    objects: Vec<Object>,
+}
+
+/// Every level the player has been on, other than the one they're on now,
+/// keyed by branch and depth, and the branch they're in.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Dungeon {
+    levels: BTreeMap<Branch, BTreeMap<u32, Level>>,
+    #[serde(default)]
+    pub branch: Branch,
+}
+
+impl Dungeon {
+    /// the deepest level the player has been on, counting the one they're on
+    pub fn deepest(&self, current: u32) -> u32 {
+        self.levels
+            .values()
+            .flat_map(|levels| levels.keys().copied())
+            .fold(current, u32::max)
+    }
+
+    /// everything on the levels the player isn't on
+    pub fn objects(&self) -> impl Iterator<Item = &Object> {
+        self.levels
+            .values()
+            .flat_map(|levels| levels.values())
+            .flat_map(|level| &level.objects)
+    }
+
+    pub fn objects_mut(&mut self) -> impl Iterator<Item = &mut Object> {
+        self.levels
+            .values_mut()
+            .flat_map(|levels| levels.values_mut())
+            .flat_map(|level| &mut level.objects)
+    }
+
+    fn has_visited(&self, branch: Branch, depth: u32) -> bool {
+        self.levels
+            .get(&branch)
+            .map_or(false, |levels| levels.contains_key(&depth))
+    }
+}
+
+/// the side branch whose stairs the player stands on, if any
+pub fn branch_stairs_here(objects: &Objects) -> Option<Branch> {
+    let player = &objects[PLAYER];
+    Branch::SIDE_BRANCHES.iter().copied().find(|branch| {
+        let name = branch.stairs_name();
+        objects
+            .iter()
+            .any(|object| object.pos() == player.pos() && object.name == name)
+    })
+}
+
+/// Take the stairs down. The FOV map has to be rebuilt for the new map
+/// afterwards.
+pub fn descend(game: &mut Game, objects: &mut Objects) {
+    go_down(game.dungeon.branch, game.dungeon_level + 1, game, objects);
+}
+
+/// Take the stairs from the main dungeon into a side branch. The FOV map has
+/// to be rebuilt for the new map afterwards.
+pub fn enter_branch(branch: Branch, game: &mut Game, objects: &mut Objects) {
+    go_down(branch, game.dungeon_level + 1, game, objects);
+}
+
+/// Go down to the given level. One the player hasn't been on yet is
+/// generated, after a rest; one they have is just as they left it.
+fn go_down(branch: Branch, depth: u32, game: &mut Game, objects: &mut Objects) {
+    if !game.dungeon.has_visited(branch, depth) {
+        let heal_hp = objects[PLAYER].max_hp(game) / 2;
+        objects[PLAYER].heal(heal_hp, game);
+    }
+    change_level(branch, depth, UP_STAIRS, game, objects);
+}
+
+/// Take the stairs back up, to the level as it was left; from the top of a
+/// side branch, that's the main dungeon. Climbing out with the crown wins the
+/// game. The FOV map has to be rebuilt for the map afterwards.
+pub fn ascend(game: &mut Game, objects: &mut Objects) {
+    let branch = game.dungeon.branch;
+    let depth = game.dungeon_level - 1;
+    let has_crown = game.inventory.iter().any(|item| item.item == Some(Item::Crown));
+    if depth == 0 && has_crown {
+        game.won = true;
+    }
+    if branch.entrance() == Some(depth) {
+        change_level(Branch::Main, depth, &branch.stairs_name(), game, objects);
+    } else {
+        change_level(branch, depth, DOWN_STAIRS, game, objects);
+    }
+}
+
+/// put the current level away and go to the one at `depth` in `branch`,
+/// arriving on the stairs with the given name
+fn change_level(
+    branch: Branch,
+    depth: u32,
+    arrive_on: &str,
+    game: &mut Game,
+    objects: &mut Objects,
+) {
+    // whatever happened on the old level is heard about there
+    bus::dispatch(game, objects);
+    // companions close by come along
+    let companions = companions::take_along(game, objects);
+    let left_behind = Level {
+        map: std::mem::replace(&mut game.map, Map::new(1, 1, map::Tile::wall())),
+        objects: objects.set_aside_from(PLAYER + 1),
+    };
+    let dungeon = &mut game.dungeon;
+    dungeon
+        .levels
+        .entry(dungeon.branch)
+        .or_default()
+        .insert(game.dungeon_level, left_behind);
+    dungeon.branch = branch;
+    let up = depth < game.dungeon_level;
+    game.dungeon_level = depth;
+
+    // whatever was going on on the old level stays there
+    game.noises.clear();
+    game.projectiles.clear();
+
+    let stored = game
+        .dungeon
+        .levels
+        .get_mut(&branch)
+        .and_then(|levels| levels.remove(&depth));
+    let first_visit = stored.is_none();
+    match stored {
+        Some(level) => {
+            game.map = level.map;
+            objects.extend(level.objects);
+            let stairs = objects.iter().find(|object| object.name == arrive_on);
+            if let Some((x, y)) = stairs.map(Object::pos) {
+                make_way(x, y, game, objects);
+                objects[PLAYER].set_pos(x, y);
+            }
+        }
+        None if depth == 0 => game.map = town::make_town(objects, &mut game.rng),
+        None => game.map = map::make_map(objects, depth, branch, &mut game.rng),
+    }
+    if depth == 0 {
+        let deepest = game.dungeon.deepest(depth);
+        town::restock(objects, deepest, &mut game.rng);
+    }
+    companions::arrive(companions, game, objects);
+    let event = GameEvent::LevelChanged {
+        branch,
+        depth,
+        up,
+        first_visit,
+    };
+    bus::publish(game, event);
+}
+
+/// anything standing in the way where the player arrives steps aside
+fn make_way(x: i32, y: i32, game: &Game, objects: &mut Objects) {
+    let in_the_way = (PLAYER + 1..objects.len())
+        .find(|&id| objects[id].blocks && objects[id].pos() == (x, y));
+    if let Some(id) = in_the_way {
+        let aside = DIRECTIONS
+            .iter()
+            .find(|&&(dx, dy)| !is_blocked(x + dx, y + dy, &game.map, objects));
+        if let Some(&(dx, dy)) = aside {
+            objects[id].set_pos(x + dx, y + dy);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::new_game_state;
+
+    fn stairs_at(name: &str, objects: &Objects) -> (i32, i32) {
+        objects.iter().find(|object| object.name == name).unwrap().pos()
+    }
+
+    #[test]
+    fn levels_are_kept_as_they_were_left() {
+        let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        descend(&mut game, &mut objects);
+        descend(&mut game, &mut objects);
+        let map = game.map.clone();
+        let count = objects.len();
+        ascend(&mut game, &mut objects);
+        assert_eq!((game.dungeon_level, game.dungeon.deepest(game.dungeon_level)), (1, 2));
+        assert_eq!(objects[PLAYER].pos(), stairs_at(DOWN_STAIRS, &objects));
+        descend(&mut game, &mut objects);
+        assert_eq!(game.dungeon_level, 2);
+        assert_eq!(serde_json::to_value(&game.map).unwrap(), serde_json::to_value(&map).unwrap());
+        assert_eq!(objects.len(), count);
+        assert_eq!(objects[PLAYER].pos(), stairs_at(UP_STAIRS, &objects));
+    }
+
+    #[test]
+    fn side_branches_lead_off_the_main_dungeon_and_back() {
+        let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        descend(&mut game, &mut objects);
+        descend(&mut game, &mut objects);
+        let (x, y) = stairs_at(&Branch::Mines.stairs_name(), &objects);
+        objects[PLAYER].set_pos(x, y);
+        assert_eq!(branch_stairs_here(&objects), Some(Branch::Mines));
+        enter_branch(Branch::Mines, &mut game, &mut objects);
+        assert_eq!((game.dungeon.branch, game.dungeon_level), (Branch::Mines, 3));
+        let (width, height) = Branch::Mines.map_size();
+        assert_eq!((game.map.width(), game.map.height()), (width, height));
+        ascend(&mut game, &mut objects);
+        assert_eq!((game.dungeon.branch, game.dungeon_level), (Branch::Main, 2));
+        assert_eq!(objects[PLAYER].pos(), (x, y));
+    }
+}
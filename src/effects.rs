@@ -0,0 +1,208 @@
+//! Status effects: poison, burning, regeneration, slowness, confusion, being
+//! caught in a web, being spurred on by a pack leader and being charmed. Each lasts a number of turns on whoever has it, the
+//! player or a monster, and does its work once a turn until it wears off.
+//! Snakes poison what they bite, some scrolls and traps burn, slow or
+//! confuse, giant spiders spit webs, pack leaders rally their packs, scrolls
+//! of charming win monsters over, and some corpses heal or sicken whoever
+//! eats them.
+
+use serde::{Deserialize, Serialize};
+
+use crate::bus::{self, GameEvent};
+use crate::colors::{Color, LIGHT_CYAN, LIGHT_GREEN, LIGHT_GREY, ORANGE, RED};
+use crate::entities::Objects;
+use crate::{Game, Object, PLAYER};
+
+/// what poison and burning take from their victim every turn
+const POISON_DAMAGE: i32 = 1;
+const BURN_DAMAGE: i32 = 3;
+/// what regeneration gives back every turn
+const REGENERATION_AMOUNT: i32 = 2;
+/// what being inspired adds to an attack
+pub const INSPIRED_POWER: i32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Effect {
+    Poison,
+    Burn,
+    Regeneration,
+    /// acts half as often
+    Slow,
+    /// acts twice as often
+    Haste,
+    /// stumbles about at random
+    Confusion,
+    /// can fight, but not move
+    Webbed,
+    /// hits harder
+    Inspired,
+    /// fights on the player's side
+    Charmed,
+}
+
+impl Effect {
+    /// how the panel shows the player having it
+    pub fn name(self) -> &'static str {
+        match self {
+            Effect::Poison => "Poisoned",
+            Effect::Burn => "Burning",
+            Effect::Regeneration => "Regenerating",
+            Effect::Slow => "Slowed",
+            Effect::Haste => "Hasted",
+            Effect::Confusion => "Confused",
+            Effect::Webbed => "Webbed",
+            Effect::Inspired => "Inspired",
+            Effect::Charmed => "Charmed",
+        }
+    }
+
+    /// whether it's something the healer will cure
+    pub fn is_harmful(self) -> bool {
+        matches!(self, Effect::Poison | Effect::Burn | Effect::Confusion)
+    }
+
+    /// what's said when the player gets it, when a monster in view does,
+    /// and when it wears off the player
+    fn messages(self) -> (&'static str, &'static str, &'static str, Color) {
+        match self {
+            Effect::Poison => ("You are poisoned!", "is poisoned", "You feel less sick.", RED),
+            Effect::Burn => (
+                "You catch fire!",
+                "catches fire",
+                "The flames on you die out.",
+                ORANGE,
+            ),
+            Effect::Regeneration => (
+                "Your wounds begin to knit together!",
+                "begins to heal before your eyes",
+                "Your wounds stop knitting together.",
+                LIGHT_GREEN,
+            ),
+            Effect::Slow => ("You slow down!", "slows down", "You speed up again.", LIGHT_GREY),
+            Effect::Haste => (
+                "You feel yourself speed up!",
+                "speeds up",
+                "You slow down again.",
+                LIGHT_CYAN,
+            ),
+            Effect::Confusion => (
+                "You feel confused!",
+                "looks vacant, and starts to stumble around",
+                "Your head clears.",
+                LIGHT_GREEN,
+            ),
+            Effect::Webbed => (
+                "You are caught in a web!",
+                "is caught in a web",
+                "You tear free of the web.",
+                LIGHT_GREY,
+            ),
+            Effect::Inspired => (
+                "You feel inspired!",
+                "lets out a war cry",
+                "Your fighting spirit fades.",
+                ORANGE,
+            ),
+            Effect::Charmed => (
+                "You feel strangely fond of your enemies.",
+                "looks at you adoringly",
+                "You come to your senses.",
+                LIGHT_GREEN,
+            ),
+        }
+    }
+}
+
+/// An effect on someone, for a number of turns yet.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatusEffect {
+    pub effect: Effect,
+    pub turns: i32,
+}
+
+impl Object {
+    pub fn has_effect(&self, effect: Effect) -> bool {
+        self.effects.iter().any(|status| status.effect == effect)
+    }
+}
+
+/// Put an effect on someone. If they already have it, it lasts as long as
+/// whichever of the two would last longer.
+pub fn apply(target: &mut Object, status: StatusEffect, game: &mut Game) {
+    match target.effects.iter_mut().find(|s| s.effect == status.effect) {
+        Some(existing) => existing.turns = existing.turns.max(status.turns),
+        None => {
+            target.effects.push(status);
+            let (on_player, on_monster, _, color) = status.effect.messages();
+            if target.name == "player" {
+                game.messages.add(on_player, color);
+            } else if game.map.is_visible(target.x, target.y) {
+                game.messages
+                    .add(format!("The {} {}!", target.name, on_monster), color);
+            }
+        }
+    }
+}
+
+/// Let every effect on everyone do its work for a turn, and wear off those
+/// that have run their course.
+pub fn tick(game: &mut Game, objects: &mut Objects) {
+    for id in 0..objects.len() {
+        if objects[id].effects.is_empty() || !objects[id].alive {
+            continue;
+        }
+        let statuses = objects[id].effects.clone();
+        for status in statuses {
+            match status.effect {
+                Effect::Poison => hurt(id, POISON_DAMAGE, "poison", game, objects),
+                Effect::Burn => hurt(id, BURN_DAMAGE, "burning", game, objects),
+                Effect::Regeneration => objects[id].heal(REGENERATION_AMOUNT, game),
+                Effect::Slow
+                | Effect::Haste
+                | Effect::Confusion
+                | Effect::Webbed
+                | Effect::Inspired
+                | Effect::Charmed => {}
+            }
+        }
+
+        let object = &mut objects[id];
+        for status in object.effects.iter_mut() {
+            status.turns -= 1;
+            if status.turns > 0 || !object.alive {
+                continue;
+            }
+            let (_, _, worn_off, color) = status.effect.messages();
+            if id == PLAYER {
+                game.messages.add(worn_off, color);
+            } else if game.map.is_visible(object.x, object.y) {
+                let name = status.effect.name().to_lowercase();
+                game.messages
+                    .add(format!("The {} is no longer {}!", object.name, name), color);
+            }
+        }
+        object.effects.retain(|status| status.turns > 0);
+    }
+}
+
+/// damage from an effect; a monster it kills is counted as the player's kill
+fn hurt(id: usize, damage: i32, source: &str, game: &mut Game, objects: &mut Objects) {
+    if !objects[id].alive {
+        return;
+    }
+    bus::publish(
+        game,
+        GameEvent::DamageDealt {
+            source: source.into(),
+            target: objects[id].name.clone(),
+            amount: damage,
+        },
+    );
+    if let Some(xp) = objects[id].take_damage(damage, game) {
+        if id != PLAYER {
+            if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+                fighter.xp += xp;
+            }
+        }
+    }
+}
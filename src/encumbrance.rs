@@ -0,0 +1,32 @@
+//! Encumbrance. Everything the player carries weighs something, and how much
+//! they can carry without trouble comes from their strength. Past that they're
+//! burdened, and so slow that monsters get two turns to their one; past half
+//! as much again, they can't pick anything else up.
+
+use crate::items;
+use crate::{Game, Object};
+
+/// how much the player can carry for every point of strength
+const CAPACITY_PER_STRENGTH: i32 = 5;
+/// the most the player can carry at all, in percent of their capacity
+const MAX_LOAD_PERCENT: i32 = 150;
+
+/// the weight of everything the player carries
+pub fn load(game: &Game) -> i32 {
+    game.inventory.iter().map(items::weight).sum()
+}
+
+/// how much the player can carry before they're burdened
+pub fn capacity(player: &Object) -> i32 {
+    player.fighter.map_or(0, |f| f.strength) * CAPACITY_PER_STRENGTH
+}
+
+/// whether the player carries more than they can and is slowed by it
+pub fn is_burdened(game: &Game, player: &Object) -> bool {
+    load(game) > capacity(player)
+}
+
+/// whether the player could pick up an item on top of all they carry
+pub fn can_lift(item: &Object, game: &Game, player: &Object) -> bool {
+    load(game) + items::weight(item) <= capacity(player) * MAX_LOAD_PERCENT / 100
+}
@@ -0,0 +1,500 @@
+//! Entities: everything on the level, and a way to refer to each that lasts.
+//! An object's place in the list of objects changes whenever anything before
+//! it is removed, so anything that needs to keep track of another object
+//! from one turn to the next, like the enemy a monster is after or the
+//! leader of its pack, holds on to its `EntityId` instead.
+//!
+//! The objects are kept in an arena, `Objects`, that hands out an ID to each
+//! as it's put on the level and knows where every ID's object is in the
+//! list, so looking one up takes no searching. An ID is made of a slot and
+//! the generation of that slot: once an object is gone for good its slot
+//! goes to the next one along, a generation on, so an ID held on to for too
+//! long finds nothing rather than the wrong object. Objects put aside with a
+//! level the player has left, or by companions on the stairs, keep their
+//! slots until they're back.
+//!
+//! Parts of an object that only some objects have, and that nothing outside
+//! needs to see, can be kept apart from it in a `Storage`, by ID, like the
+//! routes monsters are following. A new one is added to the arena alone,
+//! without touching every place an object is made.
+
+use std::ops::{Deref, DerefMut};
+
+use serde::{Deserialize, Serialize};
+
+use crate::packs::PackMember;
+use crate::pathfinding::Path;
+use crate::{Game, Object};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct EntityId {
+    index: u32,
+    generation: u32,
+}
+
+/// Where the object holding a slot is.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Place {
+    /// nowhere; the slot's free for the next object
+    #[default]
+    Free,
+    /// at this point in the list
+    Here(usize),
+    /// put aside, off the level, to come back later
+    Away,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+struct Slot {
+    generation: u32,
+    place: Place,
+}
+
+/// The objects on the level, in order, with the player first. They can be
+/// read and changed in place like any list, but only come and go through
+/// the arena's own methods, which keep track of their IDs.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Objects {
+    list: Vec<Object>,
+    slots: Vec<Slot>,
+    /// the slots given up by objects gone for good, to be used again
+    free: Vec<u32>,
+    /// the route each monster is following, if it's chasing something
+    pub paths: Storage<Path>,
+}
+
+impl Objects {
+    /// Take on the given objects, with the IDs they had when they were
+    /// saved, and put aside those of the objects on other levels. Whatever
+    /// has no ID yet, or one that's taken, is given a new one.
+    pub fn restore(list: Vec<Object>, away: impl IntoIterator<Item = EntityId>) -> Self {
+        let mut objects = Objects::default();
+        let mut claim = |id: EntityId, place| {
+            let index = id.index as usize;
+            if objects.slots.len() <= index {
+                objects.slots.resize(index + 1, Slot::default());
+            }
+            let slot = &mut objects.slots[index];
+            if slot.place != Place::Free {
+                return false;
+            }
+            *slot = Slot {
+                generation: id.generation,
+                place,
+            };
+            true
+        };
+        for id in away {
+            claim(id, Place::Away);
+        }
+        let mut unknown = vec![];
+        for (i, object) in list.iter().enumerate() {
+            match object.id {
+                Some(id) if claim(id, Place::Here(i)) => {}
+                _ => unknown.push(i),
+            }
+        }
+        objects.free = (0..objects.slots.len() as u32)
+            .rev()
+            .filter(|&index| objects.slots[index as usize].place == Place::Free)
+            .collect();
+        objects.list = list;
+        for i in unknown {
+            objects.list[i].id = None;
+            objects.seat(i);
+        }
+        objects
+    }
+
+    /// where the object with the given ID is in the list, if it's on the
+    /// level
+    pub fn find(&self, id: EntityId) -> Option<usize> {
+        match self.slots.get(id.index as usize) {
+            Some(&Slot {
+                generation,
+                place: Place::Here(i),
+            }) if generation == id.generation => Some(i),
+            _ => None,
+        }
+    }
+
+    /// whether the object with the given ID is still about, on this level or
+    /// put aside with another
+    pub fn exists(&self, id: EntityId) -> bool {
+        match self.slots.get(id.index as usize) {
+            Some(slot) => slot.generation == id.generation && slot.place != Place::Free,
+            None => false,
+        }
+    }
+
+    /// the ID of the object at the given point in the list
+    pub fn id_of(&self, i: usize) -> EntityId {
+        self.list[i].id.expect("everything on the level has an ID")
+    }
+
+    /// put an object on the level, after everything else
+    pub fn push(&mut self, object: Object) {
+        self.list.push(object);
+        self.seat(self.list.len() - 1);
+    }
+
+    /// take an object off the level for good, putting the last one in its
+    /// place in the list
+    pub fn swap_remove(&mut self, i: usize) -> Object {
+        self.release(i);
+        let mut object = self.list.swap_remove(i);
+        self.reindex(i);
+        object.id = None;
+        object
+    }
+
+    /// put a new object in place of the one at the given point in the list,
+    /// which is gone for good
+    pub fn replace(&mut self, i: usize, object: Object) -> Object {
+        self.release(i);
+        let mut old = std::mem::replace(&mut self.list[i], object);
+        self.seat(i);
+        old.id = None;
+        old
+    }
+
+    /// take everything from the given point in the list on off the level
+    /// for good
+    pub fn truncate(&mut self, len: usize) {
+        for i in len..self.list.len() {
+            self.release(i);
+        }
+        self.list.truncate(len);
+    }
+
+    /// keep only the objects that pass the test; the rest are gone for good
+    pub fn retain(&mut self, mut keep: impl FnMut(&Object) -> bool) {
+        let kept: Vec<bool> = self.list.iter().map(&mut keep).collect();
+        for (i, _) in kept.iter().enumerate().filter(|&(_, &kept)| !kept) {
+            self.release(i);
+        }
+        let mut kept = kept.into_iter();
+        self.list.retain(|_| kept.next().unwrap());
+        self.reindex(0);
+    }
+
+    /// Take an object off the level for now, to come back later, keeping
+    /// its ID; pushing it back on puts it back in its slot.
+    pub fn set_aside(&mut self, i: usize) -> Object {
+        let index = self.id_of(i).index as usize;
+        self.slots[index].place = Place::Away;
+        let object = self.list.remove(i);
+        self.reindex(i);
+        object
+    }
+
+    /// set aside everything from the given point in the list on, as a level
+    /// is when the player leaves it
+    pub fn set_aside_from(&mut self, start: usize) -> Vec<Object> {
+        for i in start..self.list.len() {
+            let index = self.id_of(i).index as usize;
+            self.slots[index].place = Place::Away;
+        }
+        self.list.split_off(start)
+    }
+
+    /// let go of the slot of something set aside that's never coming back
+    pub fn forget(&mut self, id: EntityId) {
+        if self.exists(id) && self.find(id).is_none() {
+            self.free_slot(id.index);
+        }
+    }
+
+    /// Give the object at the given point in the list a slot: back in its
+    /// own if it was set aside, or else a new one.
+    fn seat(&mut self, i: usize) {
+        let returning = self.list[i].id.filter(|&id| {
+            let slot = self.slots.get(id.index as usize);
+            slot.map_or(false, |slot| {
+                slot.generation == id.generation && slot.place == Place::Away
+            })
+        });
+        let index = match returning {
+            Some(id) => id.index,
+            None => match self.free.pop() {
+                Some(index) => index,
+                None => {
+                    self.slots.push(Slot::default());
+                    self.slots.len() as u32 - 1
+                }
+            },
+        };
+        let slot = &mut self.slots[index as usize];
+        slot.place = Place::Here(i);
+        self.list[i].id = Some(EntityId {
+            index,
+            generation: slot.generation,
+        });
+    }
+
+    /// free the slot of the object at the given point in the list, for the
+    /// next object along, a generation on
+    fn release(&mut self, i: usize) {
+        self.free_slot(self.id_of(i).index);
+    }
+
+    fn free_slot(&mut self, index: u32) {
+        let slot = &mut self.slots[index as usize];
+        let id = EntityId {
+            index,
+            generation: slot.generation,
+        };
+        slot.generation += 1;
+        slot.place = Place::Free;
+        self.free.push(index);
+        self.paths.remove(id);
+    }
+
+    /// note where everything from the given point in the list on is now
+    fn reindex(&mut self, start: usize) {
+        for i in start..self.list.len() {
+            let index = self.id_of(i).index as usize;
+            self.slots[index].place = Place::Here(i);
+        }
+    }
+}
+
+impl Deref for Objects {
+    type Target = [Object];
+
+    fn deref(&self) -> &[Object] {
+        &self.list
+    }
+}
+
+impl DerefMut for Objects {
+    fn deref_mut(&mut self) -> &mut [Object] {
+        &mut self.list
+    }
+}
+
+impl<'a> IntoIterator for &'a Objects {
+    type Item = &'a Object;
+    type IntoIter = std::slice::Iter<'a, Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a mut Objects {
+    type Item = &'a mut Object;
+    type IntoIter = std::slice::IterMut<'a, Object>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.list.iter_mut()
+    }
+}
+
+impl Extend<Object> for Objects {
+    fn extend<T: IntoIterator<Item = Object>>(&mut self, objects: T) {
+        for object in objects {
+            self.push(object);
+        }
+    }
+}
+
+impl From<Vec<Object>> for Objects {
+    /// new objects, each given an ID of its own
+    fn from(list: Vec<Object>) -> Self {
+        let mut objects = Objects::default();
+        for mut object in list {
+            object.id = None;
+            objects.push(object);
+        }
+        objects
+    }
+}
+
+/// A part that some objects have, kept apart from them by ID. Whatever was
+/// kept for an object that's gone is never found again, not even by the
+/// next object in its slot.
+#[derive(Clone, Debug)]
+pub struct Storage<T> {
+    /// by slot, what's kept for the object in it, with its generation
+    entries: Vec<Option<(u32, T)>>,
+}
+
+impl<T> Default for Storage<T> {
+    fn default() -> Self {
+        Storage { entries: vec![] }
+    }
+}
+
+impl<T> Storage<T> {
+    /// what's kept for the object with the given ID, if anything
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        match self.entries.get(id.index as usize) {
+            Some(Some((generation, value))) if *generation == id.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        match self.entries.get_mut(id.index as usize) {
+            Some(Some((generation, value))) if *generation == id.generation => Some(value),
+            _ => None,
+        }
+    }
+
+    /// keep the given value for the object with the given ID, handing back
+    /// whatever was kept for it before
+    pub fn insert(&mut self, id: EntityId, value: T) -> Option<T> {
+        let old = self.remove(id);
+        let index = id.index as usize;
+        if self.entries.len() <= index {
+            self.entries.resize_with(index + 1, || None);
+        }
+        self.entries[index] = Some((id.generation, value));
+        old
+    }
+
+    /// stop keeping anything for the object with the given ID, handing back
+    /// what was
+    pub fn remove(&mut self, id: EntityId) -> Option<T> {
+        let entry = self.entries.get_mut(id.index as usize)?;
+        match entry {
+            Some((generation, _)) if *generation == id.generation => {
+                entry.take().map(|(_, value)| value)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// the IDs of everything put aside on the levels the player has left
+pub fn away(game: &Game) -> impl Iterator<Item = EntityId> + '_ {
+    game.dungeon.objects().filter_map(|object| object.id)
+}
+
+/// Forget anything the objects in the game have kept track of that's gone,
+/// as of a save from before it went, or from before there were IDs.
+pub fn forget_the_missing(game: &mut Game, objects: &mut Objects) {
+    fn forget(object: &mut Object, objects: &Objects) {
+        let known = |id: &EntityId| objects.exists(*id);
+        object.target = object.target.filter(known);
+        object.owed = object.owed.filter(known);
+        if let Some(leader) = object.pack.and_then(PackMember::leader) {
+            if !known(&leader) {
+                object.pack = None;
+            }
+        }
+    }
+    let elsewhere = game.inventory.iter_mut().chain(game.dungeon.objects_mut());
+    for object in elsewhere {
+        forget(object, objects);
+    }
+    for i in 0..objects.len() {
+        let mut object = std::mem::take(&mut objects.list[i]);
+        forget(&mut object, objects);
+        objects.list[i] = object;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::test_support::open_field;
+    use crate::{bestiary, dungeon, new_game_state, PLAYER};
+
+    #[test]
+    fn ids_find_objects_wherever_they_end_up() {
+        let (_, mut objects) = open_field(20, 20);
+        let orc = bestiary::template("orc");
+        objects.extend((0..4).map(|x| orc.spawn(x, 0)));
+        let ids: Vec<EntityId> = (0..objects.len()).map(|i| objects.id_of(i)).collect();
+        assert!((1..ids.len()).all(|i| !ids[..i].contains(&ids[i])));
+        let first = objects.swap_remove(1);
+        assert_eq!(first.id, None);
+        assert_eq!(objects.find(ids[4]), Some(1));
+        assert_eq!(objects.find(ids[1]), None);
+        objects.retain(|object| object.x != 1);
+        assert_eq!(objects.find(ids[4]), Some(1));
+        assert_eq!(objects.find(ids[3]), Some(2));
+        assert_eq!(objects.find(ids[2]), None);
+    }
+
+    #[test]
+    fn a_freed_slot_is_used_again_a_generation_on() {
+        let (_, mut objects) = open_field(20, 20);
+        objects.push(bestiary::template("orc").spawn(1, 1));
+        let orc = objects.id_of(PLAYER + 1);
+        objects.truncate(PLAYER + 1);
+        objects.push(bestiary::template("troll").spawn(2, 2));
+        let troll = objects.id_of(PLAYER + 1);
+        assert_eq!(troll.index, orc.index);
+        assert_ne!(troll, orc);
+        assert_eq!(objects.find(orc), None);
+        assert!(!objects.exists(orc));
+        assert_eq!(objects.find(troll), Some(PLAYER + 1));
+        // a copy is something new
+        objects.push(objects[PLAYER + 1].clone());
+        assert_ne!(objects.id_of(PLAYER + 2), troll);
+    }
+
+    #[test]
+    fn what_is_kept_apart_goes_with_its_object() {
+        let (_, mut objects) = open_field(20, 20);
+        objects.push(bestiary::template("orc").spawn(1, 1));
+        let orc = objects.id_of(PLAYER + 1);
+        objects.paths.insert(orc, Path::towards((3, 3)));
+        assert_eq!(objects.paths.get(orc), Some(&Path::towards((3, 3))));
+        assert_eq!(objects.paths.get(objects.id_of(PLAYER)), None);
+        objects.truncate(PLAYER + 1);
+        assert_eq!(objects.paths.get(orc), None);
+        objects.push(bestiary::template("troll").spawn(2, 2));
+        let troll = objects.id_of(PLAYER + 1);
+        assert_eq!(objects.paths.get(troll), None);
+        // an old ID can't reach the new object's either
+        objects.paths.insert(troll, Path::towards((4, 4)));
+        assert_eq!(objects.paths.remove(orc), None);
+        assert_eq!(objects.paths.remove(troll), Some(Path::towards((4, 4))));
+    }
+
+    #[test]
+    fn objects_set_aside_come_back_with_their_ids() {
+        let (_, mut objects) = open_field(20, 20);
+        let orc = bestiary::template("orc");
+        objects.extend((0..3).map(|x| orc.spawn(x, 0)));
+        let ids: Vec<EntityId> = (0..objects.len()).map(|i| objects.id_of(i)).collect();
+        let companion = objects.set_aside(PLAYER + 1);
+        let level = objects.set_aside_from(PLAYER + 1);
+        assert!(ids.iter().skip(1).all(|&id| objects.find(id).is_none() && objects.exists(id)));
+        // nothing new takes their slots while they're away
+        objects.push(orc.spawn(5, 5));
+        assert!(!ids.contains(&objects.id_of(PLAYER + 1)));
+        objects.truncate(PLAYER + 1);
+        objects.push(companion);
+        objects.extend(level.clone());
+        let back: Vec<EntityId> = (0..objects.len()).map(|i| objects.id_of(i)).collect();
+        assert_eq!(back, ids);
+
+        // the put aside are still about after a save, but nothing else is
+        let level = objects.set_aside_from(PLAYER + 2);
+        let away = level.iter().filter_map(|object| object.id);
+        let mut restored = Objects::restore(objects.to_vec(), away);
+        assert_eq!(restored.find(ids[1]), Some(PLAYER + 1));
+        assert!(restored.find(ids[3]).is_none() && restored.exists(ids[3]));
+        restored.push(orc.spawn(5, 5));
+        assert!(!ids.contains(&restored.id_of(PLAYER + 2)));
+    }
+
+    #[test]
+    fn what_is_left_on_other_levels_is_still_about_after_a_save() {
+        let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let armourer = objects.iter().position(|object| object.name == "armourer");
+        let armourer = objects.id_of(armourer.unwrap());
+        dungeon::descend(&mut game, &mut objects);
+        let restored = Objects::restore(objects.to_vec(), away(&game));
+        assert!(restored.find(armourer).is_none() && restored.exists(armourer));
+    }
+}
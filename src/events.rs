@@ -0,0 +1,206 @@
+//! Level-wide events. Every few hundred turns something happens to the whole
+//! level: an earthquake opens up new passages, a band of monsters wanders in,
+//! or the braziers go out.
+
+use rand::Rng;
+
+use crate::colors::{LIGHT_GREY, ORANGE, YELLOW};
+use crate::entities::Objects;
+use crate::map::{self, is_blocked};
+use crate::{Game, DIRECTIONS, PLAYER};
+
+/// turns between one event and the next
+const MIN_EVENT_INTERVAL: u32 = 300;
+const MAX_EVENT_INTERVAL: u32 = 600;
+
+/// how many walls an earthquake knocks through
+const EARTHQUAKE_MIN_WALLS: usize = 3;
+const EARTHQUAKE_MAX_WALLS: usize = 6;
+
+/// how many monsters arrive in a migration, and how far from the player
+const MIGRATION_MIN_MONSTERS: usize = 2;
+const MIGRATION_MAX_MONSTERS: usize = 4;
+const MIGRATION_MIN_DISTANCE: f32 = 15.0;
+
+/// Trigger an event if one is due, and schedule the next one.
+pub fn run_events(game: &mut Game, objects: &mut Objects) {
+    // the town is always peaceful
+    if game.dungeon_level == 0 {
+        return;
+    }
+    if game.next_event_turn == 0 {
+        // nothing scheduled yet (a new game, or a save from before events)
+        schedule_next_event(game);
+        return;
+    }
+    if game.turn < game.next_event_turn {
+        return;
+    }
+    schedule_next_event(game);
+
+    // try the events in a random order until one of them can happen here
+    use Event::*;
+    let mut events = [Earthquake, Migration, Blackout];
+    let first = game.rng.gen_range(0..events.len());
+    events.rotate_left(first);
+    events.iter().any(|event| match event {
+        Earthquake => earthquake(game),
+        Migration => migration(game, objects),
+        Blackout => blackout(game, objects),
+    });
+}
+
+enum Event {
+    Earthquake,
+    Migration,
+    Blackout,
+}
+
+fn schedule_next_event(game: &mut Game) {
+    let interval = game.rng.gen_range(MIN_EVENT_INTERVAL..=MAX_EVENT_INTERVAL);
+    game.next_event_turn = game.turn + interval;
+}
+
+/// knock through a few thin walls between open tiles, making new shortcuts
+fn earthquake(game: &mut Game) -> bool {
+    // a thin wall has open floor on two opposite sides
+    let map = &game.map;
+    let is_open = |x, y| map.get(x, y).map_or(false, |tile| !tile.blocked);
+    let thin_walls: Vec<(i32, i32)> = map
+        .iter()
+        .filter(|&((x, y), tile)| {
+            tile.blocked
+                && ((is_open(x - 1, y) && is_open(x + 1, y))
+                    || (is_open(x, y - 1) && is_open(x, y + 1)))
+        })
+        .map(|(pos, _)| pos)
+        .collect();
+    if thin_walls.is_empty() {
+        return false;
+    }
+
+    let count = game
+        .rng
+        .gen_range(EARTHQUAKE_MIN_WALLS..=EARTHQUAKE_MAX_WALLS)
+        .min(thin_walls.len());
+    for _ in 0..count {
+        let (x, y) = thin_walls[game.rng.gen_range(0..thin_walls.len())];
+        let explored = game.map[(x, y)].explored;
+        game.map[(x, y)] = map::Tile::empty();
+        game.map[(x, y)].explored = explored;
+    }
+    game.messages
+        .add("The ground shakes violently, and walls come crashing down!", ORANGE);
+    true
+}
+
+/// a band of monsters arrives somewhere far from the player
+fn migration(game: &mut Game, objects: &mut Objects) -> bool {
+    let player = &objects[PLAYER];
+    let far_floor: Vec<(i32, i32)> = game
+        .map
+        .iter()
+        .filter(|&((x, y), tile)| {
+            !tile.blocked
+                && !tile.deep_water
+                && !tile.visible
+                && player.distance(x, y) >= MIGRATION_MIN_DISTANCE
+        })
+        .map(|(pos, _)| pos)
+        .collect();
+    if far_floor.is_empty() {
+        return false;
+    }
+
+    // the band gathers around one spot
+    let (center_x, center_y) = far_floor[game.rng.gen_range(0..far_floor.len())];
+    let count = game
+        .rng
+        .gen_range(MIGRATION_MIN_MONSTERS..=MIGRATION_MAX_MONSTERS);
+    let mut arrived = 0;
+    for &(dx, dy) in [(0, 0)].iter().chain(DIRECTIONS.iter()) {
+        if arrived == count {
+            break;
+        }
+        let (x, y) = (center_x + dx, center_y + dy);
+        let free = !is_blocked(x, y, &game.map, objects) && !game.map[(x, y)].deep_water;
+        if free {
+            let (level, branch) = (game.dungeon_level, game.dungeon.branch);
+            let monster = map::random_monster(x, y, level, branch, &mut game.rng);
+            objects.push(monster);
+            arrived += 1;
+        }
+    }
+    game.messages
+        .add("You hear the distant tramp of many feet.", YELLOW);
+    true
+}
+
+/// every brazier on the level goes out
+fn blackout(game: &mut Game, objects: &mut Objects) -> bool {
+    let mut braziers = objects
+        .iter_mut()
+        .filter(|object| object.name == "brazier" && object.light.is_some())
+        .peekable();
+    if braziers.peek().is_none() {
+        return false;
+    }
+    for brazier in braziers {
+        brazier.light = None;
+        brazier.name = "cold brazier".into();
+        brazier.color = LIGHT_GREY;
+    }
+    game.messages
+        .add("An icy wind howls through the level, and the braziers gutter out.", LIGHT_GREY);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::corridor;
+
+    /// a new game a level down, in two rooms with a thin wall between them
+    fn game() -> (Game, Objects) {
+        let (mut game, objects) = corridor(30, 5, Some(15));
+        game.dungeon_level = 1;
+        (game, objects)
+    }
+
+    #[test]
+    fn events_come_when_due_and_never_in_town() {
+        let (mut game, mut objects) = game();
+        run_events(&mut game, &mut objects);
+        let due = game.next_event_turn;
+        assert!((MIN_EVENT_INTERVAL..=MAX_EVENT_INTERVAL).contains(&(due - game.turn)));
+        game.turn = due;
+        run_events(&mut game, &mut objects);
+        assert!(game.next_event_turn > due);
+
+        game.dungeon_level = 0;
+        game.turn = game.next_event_turn;
+        let due = game.next_event_turn;
+        run_events(&mut game, &mut objects);
+        assert_eq!(game.next_event_turn, due);
+    }
+
+    #[test]
+    fn earthquakes_knock_through_thin_walls() {
+        let (mut game, _) = game();
+        assert!(earthquake(&mut game));
+        assert!(!game.map[(15, 2)].blocked);
+        assert!(game.map[(15, 1)].blocked);
+        // with nothing thin left, there's no earthquake
+        assert!(!earthquake(&mut game));
+    }
+
+    #[test]
+    fn migrations_arrive_far_from_the_player() {
+        let (mut game, mut objects) = game();
+        assert!(migration(&mut game, &mut objects));
+        let arrived = &objects[PLAYER + 1..];
+        assert!(arrived.len() >= MIGRATION_MIN_MONSTERS);
+        let player = &objects[PLAYER];
+        assert!(arrived.iter().all(|monster| player.distance_to(monster) >= 14.0));
+    }
+}
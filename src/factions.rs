@@ -0,0 +1,125 @@
+//! Factions: the sides things take in a fight. The player and their
+//! companions are on one side, and NPCs keep out of it until given a reason
+//! not to. The dungeon's monsters are against the player, but not always on
+//! the same side: orcs and the undead hate each other as much as they hate
+//! the player, and fight each other on sight, which a careful player can turn
+//! to their advantage. A charmed monster fights on the player's side for as
+//! long as the charm lasts.
+
+use serde::{Deserialize, Serialize};
+
+use crate::effects::Effect;
+use crate::entities::Objects;
+use crate::{move_towards, mut_two, Game, Object, PLAYER};
+
+/// how far off a monster goes after an enemy other than the player
+const RIVAL_RANGE: f32 = 6.0;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Faction {
+    /// the player and their companions
+    Player,
+    /// everything in the dungeon without a side of its own: beasts, vermin
+    /// and things that lurk
+    #[default]
+    Monsters,
+    /// orcs and goblins
+    Orcs,
+    /// the dead that walk, and those who raise them
+    Undead,
+    /// keeps out of fights
+    Neutral,
+}
+
+/// The pairs of factions that fight each other on sight; any other two leave
+/// each other alone.
+const ENEMIES: &[(Faction, Faction)] = &[
+    (Faction::Player, Faction::Monsters),
+    (Faction::Player, Faction::Orcs),
+    (Faction::Player, Faction::Undead),
+    (Faction::Orcs, Faction::Undead),
+];
+
+impl Faction {
+    /// whether the two sides fight each other
+    pub fn is_enemy_of(self, other: Faction) -> bool {
+        ENEMIES
+            .iter()
+            .any(|&pair| pair == (self, other) || pair == (other, self))
+    }
+}
+
+impl Object {
+    /// the side it's on right now, which for a charmed monster is the
+    /// player's
+    pub fn faction(&self) -> Faction {
+        if self.has_effect(Effect::Charmed) {
+            Faction::Player
+        } else {
+            self.faction
+        }
+    }
+}
+
+/// whether the two fight each other
+pub fn are_enemies(a: &Object, b: &Object) -> bool {
+    a.faction().is_enemy_of(b.faction())
+}
+
+/// The enemy other than the player that a monster or companion goes after,
+/// out of those it can see within `range`: the one it was after before, if
+/// it still can, or else the nearest. It remembers its choice.
+pub fn pick_enemy(id: usize, range: f32, game: &Game, objects: &mut Objects) -> Option<usize> {
+    let fighter = &objects[id];
+    let in_reach = |other: usize| {
+        let object = &objects[other];
+        other != PLAYER
+            && object.alive
+            && object.fighter.is_some()
+            && are_enemies(fighter, object)
+            && fighter.distance_to(object) <= range
+            && fighter.can_see(object.x, object.y, &game.map)
+    };
+    let before = fighter
+        .target
+        .and_then(|target| objects.find(target))
+        .filter(|&other| in_reach(other));
+    let enemy = before.or_else(|| {
+        (0..objects.len()).filter(|&other| in_reach(other)).min_by(|&a, &b| {
+            let a = fighter.distance_to(&objects[a]);
+            let b = fighter.distance_to(&objects[b]);
+            a.partial_cmp(&b).unwrap()
+        })
+    });
+    objects[id].target = enemy.map(|enemy| objects.id_of(enemy));
+    enemy
+}
+
+/// Let a monster go after an enemy it can see that isn't the player: a
+/// monster of a rival faction, or one on the player's side. It leaves them
+/// be if it's already after the player and the player is closer. Returns
+/// whether it did.
+pub fn fight_others(id: usize, game: &mut Game, objects: &mut Objects) -> bool {
+    if objects[id].is_blind() {
+        return false;
+    }
+    let enemy = match pick_enemy(id, RIVAL_RANGE, game, objects) {
+        Some(enemy) => enemy,
+        None => return false,
+    };
+    let monster = &objects[id];
+    let distance = monster.distance_to(&objects[enemy]);
+    if monster.has_noticed_player() && monster.distance_to(&objects[PLAYER]) <= distance {
+        return false;
+    }
+
+    if distance < 2.0 {
+        let (monster, enemy) = mut_two(id, enemy, objects);
+        monster.facing = (enemy.x - monster.x, enemy.y - monster.y);
+        monster.attack(enemy, game);
+    } else {
+        let (x, y) = objects[enemy].pos();
+        move_towards(id, x, y, &mut game.map, objects);
+    }
+    true
+}
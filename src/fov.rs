@@ -0,0 +1,223 @@
+//! Field of view: working out which tiles can be seen from a spot, for the
+//! player's sight and for how far each light reaches. The game window uses
+//! libtcod's FOV, where the game's built with it; anything running without
+//! libtcod, like the headless simulation, uses our own recursive
+//! shadowcasting instead.
+
+#[cfg(feature = "tcod")]
+use tcod::map::{FovAlgorithm, Map as TcodMap};
+
+#[cfg(feature = "tcod")]
+const FOV_ALGO: FovAlgorithm = FovAlgorithm::Basic; // default FOV algorithm
+#[cfg(feature = "tcod")]
+const FOV_LIGHT_WALLS: bool = true; // light walls or not
+
+/// the FOV the game window uses
+#[cfg(feature = "tcod")]
+pub type FovMap = TcodMap;
+#[cfg(not(feature = "tcod"))]
+pub type FovMap = Shadowcasting;
+
+/// Something that can work out what's in sight of a spot, once it's been told
+/// which tiles block sight.
+pub trait Fov {
+    /// set whether sight passes through the tile at the given spot
+    fn set_transparent(&mut self, x: i32, y: i32, transparent: bool);
+
+    /// Work out what can be seen from the given spot, as far as `radius`
+    /// tiles away, or any distance if it's 0. Walls facing the spot are seen.
+    fn compute(&mut self, x: i32, y: i32, radius: i32);
+
+    /// whether the tile at the given spot was in sight, as last computed
+    fn is_in_fov(&self, x: i32, y: i32) -> bool;
+}
+
+#[cfg(feature = "tcod")]
+impl Fov for TcodMap {
+    fn set_transparent(&mut self, x: i32, y: i32, transparent: bool) {
+        self.set(x, y, transparent, transparent);
+    }
+
+    fn compute(&mut self, x: i32, y: i32, radius: i32) {
+        self.compute_fov(x, y, radius, FOV_LIGHT_WALLS, FOV_ALGO);
+    }
+
+    fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        TcodMap::is_in_fov(self, x, y)
+    }
+}
+
+/// how each of the eight octants around the origin is turned, as the
+/// multipliers of the scan's row and column for x and y
+const OCTANTS: [(i32, i32, i32, i32); 8] = [
+    (1, 0, 0, 1),
+    (0, 1, 1, 0),
+    (0, -1, 1, 0),
+    (-1, 0, 0, 1),
+    (-1, 0, 0, -1),
+    (0, -1, -1, 0),
+    (0, 1, -1, 0),
+    (1, 0, 0, -1),
+];
+
+/// Recursive shadowcasting: each octant around the origin is scanned row by
+/// row outwards, and every wall met casts a shadow over the rows behind it.
+pub struct Shadowcasting {
+    width: i32,
+    height: i32,
+    transparent: Vec<bool>,
+    in_fov: Vec<bool>,
+}
+
+impl Shadowcasting {
+    /// an FOV map of the given size, with every tile blocking sight
+    pub fn new(width: i32, height: i32) -> Self {
+        let size = (width * height) as usize;
+        Shadowcasting {
+            width,
+            height,
+            transparent: vec![false; size],
+            in_fov: vec![false; size],
+        }
+    }
+
+    fn index(&self, x: i32, y: i32) -> Option<usize> {
+        if x >= 0 && y >= 0 && x < self.width && y < self.height {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// Scan one octant from `row` on, between the slopes `start` and `end`.
+    /// Where a run of walls starts, the part of the octant beside it is
+    /// scanned on its own, and the scan carries on past the walls.
+    fn cast_light(
+        &mut self,
+        (origin_x, origin_y): (i32, i32),
+        radius: i32,
+        row: i32,
+        mut start: f32,
+        end: f32,
+        (xx, xy, yx, yy): (i32, i32, i32, i32),
+    ) {
+        if start < end {
+            return;
+        }
+        let mut next_start = start;
+        for distance in row..=radius {
+            let dy = -distance;
+            let mut blocked = false;
+            for dx in -distance..=0 {
+                let left_slope = (dx as f32 - 0.5) / (dy as f32 + 0.5);
+                let right_slope = (dx as f32 + 0.5) / (dy as f32 - 0.5);
+                if start < right_slope {
+                    continue;
+                } else if end > left_slope {
+                    break;
+                }
+
+                let (x, y) = (origin_x + dx * xx + dy * xy, origin_y + dx * yx + dy * yy);
+                let index = self.index(x, y);
+                if let Some(index) = index.filter(|_| dx * dx + dy * dy <= radius * radius) {
+                    self.in_fov[index] = true;
+                }
+                // off the map counts as wall
+                let opaque = index.map_or(true, |index| !self.transparent[index]);
+                if blocked {
+                    if opaque {
+                        next_start = right_slope;
+                    } else {
+                        blocked = false;
+                        start = next_start;
+                    }
+                } else if opaque && distance < radius {
+                    blocked = true;
+                    let (origin, octant) = ((origin_x, origin_y), (xx, xy, yx, yy));
+                    self.cast_light(origin, radius, distance + 1, start, left_slope, octant);
+                    next_start = right_slope;
+                }
+            }
+            if blocked {
+                break;
+            }
+        }
+    }
+}
+
+impl Fov for Shadowcasting {
+    fn set_transparent(&mut self, x: i32, y: i32, transparent: bool) {
+        if let Some(index) = self.index(x, y) {
+            self.transparent[index] = transparent;
+        }
+    }
+
+    fn compute(&mut self, x: i32, y: i32, radius: i32) {
+        self.in_fov.iter_mut().for_each(|in_fov| *in_fov = false);
+        let radius = if radius > 0 {
+            radius
+        } else {
+            self.width.max(self.height)
+        };
+        if let Some(index) = self.index(x, y) {
+            self.in_fov[index] = true;
+        }
+        for octant in OCTANTS {
+            self.cast_light((x, y), radius, 1, 1.0, 0.0, octant);
+        }
+    }
+
+    fn is_in_fov(&self, x: i32, y: i32) -> bool {
+        self.index(x, y).map_or(false, |index| self.in_fov[index])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// an open room of the given size, walled all round
+    fn room(width: i32, height: i32) -> Shadowcasting {
+        let mut fov = Shadowcasting::new(width, height);
+        for y in 1..height - 1 {
+            for x in 1..width - 1 {
+                fov.set_transparent(x, y, true);
+            }
+        }
+        fov
+    }
+
+    #[test]
+    fn sees_the_whole_room_and_its_walls() {
+        let mut fov = room(10, 8);
+        fov.compute(3, 3, 0);
+        for y in 0..8 {
+            for x in 0..10 {
+                assert!(fov.is_in_fov(x, y), "({}, {}) should be in view", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn walls_cast_shadows() {
+        let mut fov = room(12, 5);
+        // a wall across the room, with the viewer on its left
+        for y in 1..4 {
+            fov.set_transparent(6, y, false);
+        }
+        fov.compute(2, 2, 0);
+        assert!(fov.is_in_fov(6, 2));
+        assert!(!fov.is_in_fov(8, 2));
+        assert!(!fov.is_in_fov(10, 1));
+    }
+
+    #[test]
+    fn radius_limits_sight() {
+        let mut fov = room(20, 20);
+        fov.compute(10, 10, 3);
+        assert!(fov.is_in_fov(13, 10));
+        assert!(fov.is_in_fov(12, 12));
+        assert!(!fov.is_in_fov(14, 10));
+        assert!(!fov.is_in_fov(13, 13));
+    }
+}
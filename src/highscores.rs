@@ -0,0 +1,58 @@
+//! The high score table: the best runs played on this computer, kept in a
+//! file of their own across games.
+
+use std::fs;
+
+use serde::{Deserialize, Serialize};
+
+/// where the high scores are kept, as a JSON list, best first
+const HIGH_SCORES_FILE: &str = "high_scores.json";
+
+/// how many runs the table keeps
+const HIGH_SCORES_KEPT: usize = 10;
+
+/// How one run ended up.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: i32,
+    pub xp: i32,
+    /// the deepest dungeon level reached
+    pub depth: u32,
+    pub gold: i32,
+    pub turns: u32,
+    /// what killed the player, if anything did
+    pub cause: Option<String>,
+    /// whether the player escaped with the crown
+    #[serde(default)]
+    pub won: bool,
+}
+
+/// the table, best first; empty if there's none yet or it can't be read
+pub fn load() -> Vec<HighScore> {
+    fs::read_to_string(HIGH_SCORES_FILE)
+        .ok()
+        .and_then(|json| serde_json::from_str(&json).ok())
+        .unwrap_or_default()
+}
+
+/// Add a run to the table, if it's good enough to be kept, and return where
+/// it ranks, from 0 for the best.
+pub fn record(entry: HighScore) -> Option<usize> {
+    let mut scores = load();
+    // a tie goes to the run that got there first
+    let rank = scores.iter().position(|s| s.score < entry.score).unwrap_or(scores.len());
+    if rank >= HIGH_SCORES_KEPT {
+        return None;
+    }
+    scores.insert(rank, entry);
+    scores.truncate(HIGH_SCORES_KEPT);
+    let written = serde_json::to_string(&scores)
+        .map_err(|e| e.to_string())
+        .and_then(|json| fs::write(HIGH_SCORES_FILE, json).map_err(|e| e.to_string()));
+    if let Err(e) = written {
+        eprintln!("Could not record the high score: {}", e);
+        return None;
+    }
+    Some(rank)
+}
@@ -0,0 +1,137 @@
+//! Identifying items. Potions and scrolls don't say what they are: at the start
+//! of a run each kind gets a look of its own, a colour for potions and a
+//! nonsense label for scrolls, and goes by it until the player uses one or
+//! reads a scroll of identify on it. From then on every item of that kind is
+//! known by its real name.
+
+use std::collections::BTreeMap;
+
+use rand::prelude::*;
+use serde::Deserialize;
+
+use crate::colors::{LIGHT_CYAN, WHITE};
+use crate::entities::Objects;
+use crate::items;
+use crate::{Chooser, Game, Object, UseResult, PLAYER};
+
+/// what potions look like, one kind to each
+const POTION_COLORS: &[&str] = &[
+    "bubbly blue",
+    "murky green",
+    "fizzy red",
+    "cloudy white",
+    "oily black",
+    "glowing yellow",
+    "smoky grey",
+    "swirling purple",
+    "speckled orange",
+    "milky pink",
+];
+
+/// what's written on scrolls, one kind to each
+const SCROLL_LABELS: &[&str] = &[
+    "XYZZY",
+    "FOOBIE BLETCH",
+    "ZELGO MER",
+    "ELBIB YLOH",
+    "PRATYAVAYAH",
+    "VERR YED HORRE",
+    "JUYED AWK YACC",
+    "KIRJE",
+    "THARR",
+    "NR 9",
+    "DAIYEN FOOELS",
+    "LEP GEX VEN ZEA",
+];
+
+/// What an item looks like before it's identified. Items without one are
+/// known on sight.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+pub enum Looks {
+    Potion,
+    Scroll,
+}
+
+impl Looks {
+    fn choices(self) -> &'static [&'static str] {
+        match self {
+            Looks::Potion => POTION_COLORS,
+            Looks::Scroll => SCROLL_LABELS,
+        }
+    }
+
+    fn describe(self, choice: &str) -> String {
+        match self {
+            Looks::Potion => format!("{} potion", choice),
+            Looks::Scroll => format!("scroll labeled {}", choice),
+        }
+    }
+}
+
+/// Give every kind of potion and scroll a look of its own for a new run, by
+/// the kind's real name. Any kinds left over once the looks run out go known.
+pub fn shuffle_looks(rng: &mut StdRng) -> BTreeMap<String, String> {
+    let mut appearances = BTreeMap::new();
+    for looks in [Looks::Potion, Looks::Scroll] {
+        let mut choices = looks.choices().to_vec();
+        choices.shuffle(rng);
+        let kinds = items::templates()
+            .iter()
+            .filter(|template| template.looks == Some(looks));
+        for (template, choice) in kinds.zip(choices) {
+            appearances.insert(template.name.clone(), looks.describe(choice));
+        }
+    }
+    appearances
+}
+
+/// whether the player knows what kind of item this is
+pub fn is_identified(item: &Object, game: &Game) -> bool {
+    !game.appearances.contains_key(&item.name) || game.identified.contains(&item.name)
+}
+
+/// what the player knows an item as: how it looks, until its kind is identified
+pub fn name(item: &Object, game: &Game) -> String {
+    match game.appearances.get(&item.name) {
+        Some(looks) if !is_identified(item, game) => looks.clone(),
+        _ => item.name.clone(),
+    }
+}
+
+/// learn what kind of item this is, and so every other item of that kind
+pub fn identify(item: &Object, game: &mut Game) {
+    if is_identified(item, game) {
+        return;
+    }
+    let looks = name(item, game);
+    game.identified.insert(item.name.clone());
+    game.messages
+        .add(format!("The {} is a {}.", looks, item.name), LIGHT_CYAN);
+}
+
+/// Read a scroll of identify on something the player carries and doesn't
+/// know yet.
+pub fn read_identify(
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &Objects,
+) -> UseResult {
+    if game.inventory.iter().all(|item| is_identified(item, game)) {
+        game.messages
+            .add("You already know everything you carry.", WHITE);
+        return UseResult::UsedUp;
+    }
+    let header = "Choose an item to identify, or any other to cancel.\n";
+    let inventory_id = match chooser.choose_item(header, game, &objects[PLAYER]) {
+        Some(inventory_id) => inventory_id,
+        None => return UseResult::Cancelled,
+    };
+    let item = game.inventory[inventory_id].clone();
+    if is_identified(&item, game) {
+        game.messages
+            .add(format!("You already know the {}.", item.name), WHITE);
+        return UseResult::UsedUp;
+    }
+    identify(&item, game);
+    UseResult::UsedUp
+}
@@ -0,0 +1,76 @@
+//! Input: the key presses and mouse movements the backends report, in the
+//! game's own terms. Like libtcod, which they're modelled on, a key that
+//! types a character comes as text, with the character, and any other as a
+//! key code.
+
+/// The keys the game tells apart; anything else it's told of is text or
+/// nothing.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum KeyCode {
+    #[default]
+    NoKey,
+    Escape,
+    Backspace,
+    Tab,
+    Enter,
+    Spacebar,
+    Up,
+    Down,
+    Left,
+    Right,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Insert,
+    Delete,
+    NumPad0,
+    NumPad1,
+    NumPad2,
+    NumPad3,
+    NumPad4,
+    NumPad5,
+    NumPad6,
+    NumPad7,
+    NumPad8,
+    NumPad9,
+    NumPadEnter,
+    /// a key for a character, given as `printable`
+    Char,
+    /// a character typed, given as `printable`
+    Text,
+}
+
+/// A key press, or none.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Key {
+    pub code: KeyCode,
+    /// the character the key typed, if any
+    pub printable: char,
+    pub pressed: bool,
+    pub alt: bool,
+    pub ctrl: bool,
+    pub shift: bool,
+}
+
+/// Where the mouse is, and what was just clicked.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Mouse {
+    /// where it is in pixels, where the backend has them
+    pub x: i32,
+    pub y: i32,
+    /// which cell of the screen it's over
+    pub cx: i32,
+    pub cy: i32,
+    pub lbutton_pressed: bool,
+    pub rbutton_pressed: bool,
+    pub mbutton_pressed: bool,
+    pub wheel_up: bool,
+    pub wheel_down: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    Key(Key),
+    Mouse(Mouse),
+}
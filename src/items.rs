@@ -0,0 +1,210 @@
+//! Every kind of item that can be found in the dungeon, as defined in
+//! `data/items.json`: what it looks like, what using it does, the bonuses it
+//! gives when equipped, what it's worth and how often it turns up on each
+//! dungeon level.
+
+use std::sync::OnceLock;
+
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::artifacts::ARTIFACT_VALUE;
+use crate::colors::Color;
+use crate::dungeon::Branch;
+use crate::identify::Looks;
+use crate::map::{from_dungeon_level, Transition};
+use crate::spells::Spell;
+use crate::{Equipment, Item, Object, RangedAttack};
+
+const ITEM_DATA: &str = include_str!("../data/items.json");
+
+/// what an item missing from the data file is worth
+const DEFAULT_VALUE: i32 = 15;
+/// what an item weighs if the data file doesn't say
+const DEFAULT_WEIGHT: i32 = 1;
+/// the chance in percent that equipment found on each dungeon level is cursed
+const CURSE_CHANCE: &[Transition] = &[
+    Transition { level: 1, value: 5 },
+    Transition { level: 3, value: 10 },
+    Transition { level: 6, value: 20 },
+];
+/// the chance in percent that equipment found on each dungeon level is
+/// enchanted, if it isn't cursed
+const ENCHANT_CHANCE: &[Transition] = &[
+    Transition { level: 2, value: 5 },
+    Transition { level: 4, value: 10 },
+    Transition { level: 7, value: 15 },
+];
+/// how much worse than usual cursed equipment is, at most
+const MAX_CURSE_PENALTY: i32 = 2;
+/// how much better than usual enchanted equipment is when found, at most
+const MAX_FOUND_ENCHANTMENT: i32 = 2;
+
+/// How to make one kind of item.
+#[derive(Debug, Deserialize)]
+pub struct ItemTemplate {
+    pub name: String,
+    pub glyph: char,
+    pub color: Color,
+    /// what happens when it's used
+    pub item: Item,
+    #[serde(default)]
+    pub equipment: Option<Equipment>,
+    /// what merchants sell it for
+    pub value: i32,
+    /// how likely this item is to be picked for a random item, against the
+    /// others, from each dungeon level on; never, if empty
+    #[serde(default)]
+    pub spawn: Vec<Transition>,
+    /// the branches of the dungeon it turns up in; all of them, if empty
+    #[serde(default)]
+    pub branches: Vec<Branch>,
+    /// whether it can be found in vaults
+    #[serde(default)]
+    pub vault: bool,
+    /// the spell reading it teaches, for a spellbook
+    #[serde(default)]
+    pub teaches: Option<Spell>,
+    /// how far and how hard it shoots, for a bow or crossbow
+    #[serde(default)]
+    pub ranged: Option<RangedAttack>,
+    /// what one of it weighs, unless it's equipment, which has its own
+    #[serde(default = "default_weight")]
+    pub weight: i32,
+    /// how many come together when one is found, for things that stack, like
+    /// potions and arrows; those without one don't stack
+    #[serde(default)]
+    pub count: Option<i32>,
+    /// what it looks like until it's identified; known on sight, if none
+    #[serde(default)]
+    pub looks: Option<Looks>,
+}
+
+fn default_weight() -> i32 {
+    DEFAULT_WEIGHT
+}
+
+impl ItemTemplate {
+    /// an item of this kind at the given spot
+    pub fn spawn(&self, x: i32, y: i32) -> Object {
+        let mut object = Object::new(x, y, self.glyph, &self.name, self.color, false);
+        object.item = Some(self.item);
+        object.equipment = self.equipment;
+        object.ranged = self.ranged;
+        object.count = self.count;
+        object
+    }
+}
+
+/// read kinds of item from JSON laid out like `data/items.json`, or say
+/// what's wrong with it
+pub fn parse(json: &str) -> Result<Vec<ItemTemplate>, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+/// All kinds of item, read from the data file the first time they're
+/// needed, or what's wrong with it.
+pub fn load() -> Result<&'static [ItemTemplate], String> {
+    static TEMPLATES: OnceLock<Result<Vec<ItemTemplate>, String>> = OnceLock::new();
+    TEMPLATES
+        .get_or_init(|| parse(ITEM_DATA).map_err(|e| format!("data/items.json is invalid: {}", e)))
+        .as_deref()
+        .map_err(Clone::clone)
+}
+
+/// all kinds of item; `run` has made sure the data file reads before
+/// anything needs them
+pub fn templates() -> &'static [ItemTemplate] {
+    load().unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// the kind of item with the given name; the data file must define it
+pub fn template(name: &str) -> &'static ItemTemplate {
+    templates()
+        .iter()
+        .find(|template| template.name == name)
+        .unwrap_or_else(|| panic!("no item named {} in data/items.json", name))
+}
+
+/// How much an item is worth in gold, which is what merchants charge for it.
+/// Items that aren't in the data file go cheap, a stack is worth its share
+/// of what a full one is, and an artifact is worth a good deal more.
+pub fn item_value(item: &Object) -> i32 {
+    let value = match item.item {
+        Some(_) => templates()
+            .iter()
+            .find(|template| template.name == item.name)
+            .map_or(DEFAULT_VALUE, |template| match (item.count, template.count) {
+                (Some(count), Some(full)) => (template.value * count / full).max(1),
+                _ => template.value,
+            }),
+        None => 0,
+    };
+    match item.artifact {
+        Some(_) => value + ARTIFACT_VALUE,
+        None => value,
+    }
+}
+
+/// what an item weighs, or a whole stack of it
+pub fn weight(item: &Object) -> i32 {
+    let each = match item.equipment {
+        Some(equipment) => equipment.weight,
+        None => templates()
+            .iter()
+            .find(|template| template.name == item.name)
+            .map_or(DEFAULT_WEIGHT, |template| template.weight),
+    };
+    each * item.quantity()
+}
+
+/// whether several of this item go in a single inventory slot
+pub fn stacks(item: &Object) -> bool {
+    templates()
+        .iter()
+        .find(|template| template.name == item.name)
+        .map_or(false, |template| template.count.is_some())
+}
+
+/// Maybe make a piece of equipment found on the given dungeon level out of the
+/// ordinary, the more likely the deeper it is: cursed, so that it's worse
+/// than usual and won't come off, or else enchanted, so that it's better.
+pub fn roll_enchantment(item: &mut Object, level: u32, rng: &mut impl Rng) {
+    let equipment = match item.equipment.as_mut() {
+        Some(equipment) if !equipment.cursed => equipment,
+        _ => return,
+    };
+    let roll = rng.gen_range(0..100);
+    let curse_chance = from_dungeon_level(CURSE_CHANCE, level);
+    if roll < curse_chance {
+        equipment.cursed = true;
+        equipment.enchantment -= rng.gen_range(1..=MAX_CURSE_PENALTY);
+    } else if roll < curse_chance + from_dungeon_level(ENCHANT_CHANCE, level) {
+        equipment.enchantment += rng.gen_range(1..=MAX_FOUND_ENCHANTMENT);
+    }
+}
+
+/// the spell a spellbook teaches; None if it's not one, or not in the data file
+pub fn teaches(item: &Object) -> Option<Spell> {
+    templates()
+        .iter()
+        .find(|template| template.name == item.name)
+        .and_then(|template| template.teaches)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacks_are_worth_their_share() {
+        let mut arrows = template("arrows").spawn(0, 0);
+        let full = item_value(&arrows);
+        arrows.count = Some(6);
+        assert_eq!(item_value(&arrows), full / 2);
+        arrows.count = Some(1);
+        assert_eq!(item_value(&arrows), 1);
+        arrows.name = "no such item".into();
+        assert_eq!(item_value(&arrows), DEFAULT_VALUE);
+    }
+}
@@ -0,0 +1,265 @@
+//! Key bindings: which keys do what. Every action can be bound to any number
+//! of keys, and the player can rebind them from the options screen; their
+//! choices are kept in a file of their own across games. Out of the box the
+//! arrow keys, the numpad and vi-keys all move the player. Holding control
+//! while moving attacks whoever's that way, whatever key moves there.
+
+use std::collections::BTreeMap;
+use std::fs;
+
+use crate::abilities::Ability;
+use crate::actions::Action;
+use crate::input::{Key, KeyCode};
+use crate::states::InventoryAction;
+use crate::{menu, show_menu, Tcod};
+
+/// where the player's bindings are kept, as a JSON map from each action's
+/// name to the names of its keys
+const KEYBINDINGS_FILE: &str = "keybindings.json";
+/// how wide the options screen is
+const OPTIONS_WIDTH: i32 = 50;
+
+/// Every action a key can be bound to: the name the file and the options
+/// screen know it by, and the keys it has until the player says otherwise.
+const BINDABLE: &[(&str, Action, &[&str])] = &[
+    ("north", Action::Step { dx: 0, dy: -1 }, &["Up", "NumPad8", "k"]),
+    ("south", Action::Step { dx: 0, dy: 1 }, &["Down", "NumPad2", "j"]),
+    ("west", Action::Step { dx: -1, dy: 0 }, &["Left", "NumPad4", "h"]),
+    ("east", Action::Step { dx: 1, dy: 0 }, &["Right", "NumPad6", "l"]),
+    ("north-west", Action::Step { dx: -1, dy: -1 }, &["Home", "NumPad7", "y"]),
+    ("north-east", Action::Step { dx: 1, dy: -1 }, &["PageUp", "NumPad9", "u"]),
+    ("south-west", Action::Step { dx: -1, dy: 1 }, &["End", "NumPad1", "b"]),
+    ("south-east", Action::Step { dx: 1, dy: 1 }, &["PageDown", "NumPad3", "n"]),
+    ("wait", Action::Wait, &[".", "NumPad5"]),
+    ("pick up", Action::PickUp, &["g"]),
+    ("eat", Action::Eat, &["e"]),
+    ("power strike", Action::UseAbility(Ability::PowerStrike), &["1"]),
+    ("dash", Action::UseAbility(Ability::Dash), &["2"]),
+    ("shield bash", Action::UseAbility(Ability::ShieldBash), &["3"]),
+    ("cast a spell", Action::Cast, &["z"]),
+    ("fire", Action::Fire, &["f"]),
+    ("use an item", Action::Inventory(InventoryAction::Use), &["i"]),
+    ("drop an item", Action::Inventory(InventoryAction::Drop), &["d"]),
+    ("throw an item", Action::Inventory(InventoryAction::Throw), &["t"]),
+    ("give orders", Action::Orders, &["o"]),
+    ("bestiary", Action::ShowBestiary, &["B"]),
+    ("character", Action::ShowCharacter, &["c"]),
+    ("message log", Action::ShowMessages, &["M"]),
+    ("look", Action::Look, &["x"]),
+    ("map", Action::ShowMap, &["Tab"]),
+    ("close a door", Action::CloseDoor, &["C"]),
+    ("rest", Action::Rest, &["R"]),
+    ("search", Action::Search, &["s"]),
+    ("sneak", Action::ToggleSneak, &["S"]),
+    ("go down", Action::Descend, &["<"]),
+    ("go up", Action::Ascend, &[">"]),
+];
+
+/// how many of the bindable actions are movement, listed first
+const MOVEMENT_ACTIONS: usize = 9;
+
+/// the keys that don't type a character which can be bound, by name
+const NAMED_KEYS: &[(&str, KeyCode)] = &[
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Insert", KeyCode::Insert),
+    ("Delete", KeyCode::Delete),
+    ("Tab", KeyCode::Tab),
+    ("NumPad0", KeyCode::NumPad0),
+    ("NumPad1", KeyCode::NumPad1),
+    ("NumPad2", KeyCode::NumPad2),
+    ("NumPad3", KeyCode::NumPad3),
+    ("NumPad4", KeyCode::NumPad4),
+    ("NumPad5", KeyCode::NumPad5),
+    ("NumPad6", KeyCode::NumPad6),
+    ("NumPad7", KeyCode::NumPad7),
+    ("NumPad8", KeyCode::NumPad8),
+    ("NumPad9", KeyCode::NumPad9),
+];
+
+/// The keys bound to each action, by the action's name.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keybindings {
+    keys: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for Keybindings {
+    fn default() -> Self {
+        let keys = BINDABLE
+            .iter()
+            .map(|&(name, _, keys)| {
+                let keys = keys.iter().map(|&key| key.to_string()).collect();
+                (name.to_string(), keys)
+            })
+            .collect();
+        Keybindings { keys }
+    }
+}
+
+impl Keybindings {
+    /// The player's bindings, or the defaults for any action the file
+    /// doesn't mention, or if there's no file or it can't be read.
+    pub fn load() -> Self {
+        let json = match fs::read_to_string(KEYBINDINGS_FILE) {
+            Ok(json) => json,
+            Err(_) => return Keybindings::default(),
+        };
+        Keybindings::parse(&json).unwrap_or_else(|e| {
+            eprintln!("Ignoring the key bindings in {}: {}", KEYBINDINGS_FILE, e);
+            Keybindings::default()
+        })
+    }
+
+    /// the bindings saved as JSON, with the defaults for any action it
+    /// doesn't mention, or what's wrong with it
+    pub fn parse(json: &str) -> Result<Self, String> {
+        let mut bindings = Keybindings::default();
+        let saved: BTreeMap<String, Vec<String>> =
+            serde_json::from_str(json).map_err(|e| e.to_string())?;
+        for (name, keys) in saved {
+            if let Some(bound) = bindings.keys.get_mut(&name) {
+                *bound = keys;
+            }
+        }
+        Ok(bindings)
+    }
+
+    fn save(&self) {
+        let written = serde_json::to_string_pretty(&self.keys)
+            .map_err(|e| e.to_string())
+            .and_then(|json| fs::write(KEYBINDINGS_FILE, json).map_err(|e| e.to_string()));
+        if let Err(e) = written {
+            eprintln!("Could not save the key bindings: {}", e);
+        }
+    }
+
+    /// the action the key is bound to, if any
+    pub fn action_for(&self, key: Key) -> Option<Action> {
+        let name = key_name(key)?;
+        let action = BINDABLE
+            .iter()
+            .find(|(action, ..)| self.keys_of(action).contains(&name))
+            .map(|&(_, action, _)| action)?;
+        match action {
+            Action::Step { dx, dy } if key.ctrl => Some(Action::MoveOrAttack { dx, dy }),
+            action => Some(action),
+        }
+    }
+
+    /// how to ask for the action, to tell the player: its first key in
+    /// quotes, like 't', or where to get one if it has none
+    pub fn key_for(&self, action: Action) -> String {
+        let name = BINDABLE
+            .iter()
+            .find(|&&(_, bound, _)| bound == action)
+            .map(|&(name, ..)| name);
+        match name.and_then(|name| self.keys_of(name).first()) {
+            Some(key) => format!("'{}'", key),
+            None => "the key you bind to it in the options".into(),
+        }
+    }
+
+    /// the keys bound to the named action
+    fn keys_of(&self, action: &str) -> &[String] {
+        self.keys.get(action).map_or(&[], Vec::as_slice)
+    }
+
+    /// bind the key to the named action, and to nothing else
+    fn bind(&mut self, action: &str, key: String) {
+        for keys in self.keys.values_mut() {
+            keys.retain(|bound| *bound != key);
+        }
+        self.keys.entry(action.into()).or_default().push(key);
+    }
+}
+
+/// the name a key is bound by, if it can be bound at all
+fn key_name(key: Key) -> Option<String> {
+    if key.code == KeyCode::Text {
+        return Some(key.printable.to_string()).filter(|_| !key.printable.is_control());
+    }
+    NAMED_KEYS
+        .iter()
+        .find(|&&(_, code)| code == key.code)
+        .map(|&(name, _)| name.into())
+}
+
+/// Let the player look over which keys do what and rebind them, saving their
+/// choices for every game after.
+pub fn options_screen(tcod: &mut Tcod) {
+    loop {
+        let choices = ["Movement", "Everything else", "Reset to defaults"];
+        match menu("Key bindings\n\n", &choices, OPTIONS_WIDTH, tcod) {
+            Some(0) => rebind(&BINDABLE[..MOVEMENT_ACTIONS], tcod),
+            Some(1) => rebind(&BINDABLE[MOVEMENT_ACTIONS..], tcod),
+            Some(2) => {
+                tcod.keys = Keybindings::default();
+                tcod.keys.save();
+            }
+            _ => return,
+        }
+    }
+}
+
+/// pick one of the actions and the key to bind it to, as many times over as
+/// the player likes
+fn rebind(actions: &[(&str, Action, &[&str])], tcod: &mut Tcod) {
+    loop {
+        let options: Vec<String> = actions
+            .iter()
+            .map(|(name, ..)| format!("{}: {}", name, tcod.keys.keys_of(name).join(", ")))
+            .collect();
+        let header = "Press the key next to an action to rebind it, or any other to go back.\n";
+        let (name, ..) = match menu(header, &options, OPTIONS_WIDTH, tcod) {
+            Some(choice) => actions[choice],
+            None => return,
+        };
+        let header = format!(
+            "Press the key for {}, Backspace to unbind its keys, or Escape to cancel.",
+            name
+        );
+        let no_options: &[&str] = &[];
+        show_menu(&header, no_options, OPTIONS_WIDTH, tcod);
+        let key = tcod.backend.wait_for_keypress();
+        match key.code {
+            KeyCode::Escape => continue,
+            KeyCode::Backspace => {
+                tcod.keys.keys.insert(name.into(), vec![]);
+            }
+            _ => match key_name(key) {
+                Some(key) => tcod.keys.bind(name, key),
+                None => continue,
+            },
+        }
+        tcod.keys.save();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_name_the_key_bound_now() {
+        let mut keys = Keybindings::default();
+        assert_eq!(keys.key_for(Action::Fire), "'f'");
+        keys.keys.insert("fire".into(), vec!["F".into()]);
+        assert_eq!(keys.key_for(Action::Fire), "'F'");
+        keys.keys.insert("fire".into(), vec![]);
+        assert_eq!(keys.key_for(Action::Fire), "the key you bind to it in the options");
+    }
+
+    #[test]
+    fn saved_bindings_keep_the_defaults_for_the_rest() {
+        let keys = Keybindings::parse(r#"{"fire": ["F"], "no such action": ["x"]}"#).unwrap();
+        assert_eq!(keys.key_for(Action::Fire), "'F'");
+        assert_eq!(keys.keys_of("throw an item"), ["t"]);
+        assert!(Keybindings::parse(r#"{"fire": "F"}"#).is_err());
+    }
+}
@@ -0,0 +1,259 @@
+//! Opt-in online leaderboard. When a leaderboard URL is given on the command
+//! line, every finished run is posted to it, and the daily challenge screen
+//! lists the best scores anyone has posted for the day's seed.
+//!
+//! The server is expected to take a run as a JSON `Entry` in a POST to the
+//! URL, and to answer a GET to the URL with `?seed=N` appended with a JSON
+//! array of the entries for that seed, best first. Only plain `http://` URLs
+//! are supported.
+//!
+//! The server is only ever asked on a thread of its own, through a
+//! `Request`, so a slow or missing one never holds up the game.
+
+use std::io::{Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+/// give up on the server after this long, rather than hang the game
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// responses larger than this are cut off
+const MAX_RESPONSE_BYTES: u64 = 1 << 20;
+
+/// One finished run, as posted to the server.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Entry {
+    pub seed: u64,
+    pub daily: bool,
+    pub score: i32,
+    pub depth: u32,
+    pub turns: u32,
+    pub cause: Option<String>,
+}
+
+/// Where to post results to.
+#[derive(Clone, Debug)]
+pub struct Leaderboard {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Leaderboard {
+    /// parse a URL of the form `http://host[:port][/path]`
+    pub fn new(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or("the leaderboard URL must start with http://")?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => {
+                let port = port.parse().map_err(|_| format!("bad port in {}", url))?;
+                (host, port)
+            }
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("no host in {}", url));
+        }
+        Ok(Leaderboard {
+            host: host.into(),
+            port,
+            path: path.into(),
+        })
+    }
+
+    /// start posting a finished run
+    pub fn post(&self, entry: Entry) -> Request<()> {
+        let leaderboard = self.clone();
+        Request::start(move || leaderboard.submit(&entry))
+    }
+
+    /// start fetching the best entries posted for `seed`
+    pub fn fetch_top_scores(&self, seed: u64) -> Request<Vec<Entry>> {
+        let leaderboard = self.clone();
+        Request::start(move || leaderboard.top_scores(seed))
+    }
+
+    fn submit(&self, entry: &Entry) -> Result<(), String> {
+        let body = serde_json::to_string(entry).map_err(|e| e.to_string())?;
+        self.request("POST", &self.path, &body).map(|_| ())
+    }
+
+    fn top_scores(&self, seed: u64) -> Result<Vec<Entry>, String> {
+        let separator = if self.path.contains('?') { '&' } else { '?' };
+        let path = format!("{}{}seed={}", self.path, separator, seed);
+        let body = self.request("GET", &path, "")?;
+        parse_scores(&body)
+    }
+
+    /// make an HTTP/1.0 request, so the server sends the body as it is and
+    /// closes the connection after it; returns the body of a 2xx response
+    fn request(&self, method: &str, path: &str, body: &str) -> Result<String, String> {
+        let address = (self.host.as_str(), self.port)
+            .to_socket_addrs()
+            .map_err(|e| e.to_string())?
+            .next()
+            .ok_or_else(|| format!("could not resolve {}", self.host))?;
+        let mut stream = TcpStream::connect_timeout(&address, TIMEOUT).map_err(|e| e.to_string())?;
+        stream.set_read_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(TIMEOUT)).map_err(|e| e.to_string())?;
+
+        let request = format!(
+            "{} {} HTTP/1.0\r\nHost: {}\r\nContent-Type: application/json\r\n\
+             Content-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            self.host,
+            body.len(),
+            body
+        );
+        stream
+            .write_all(request.as_bytes())
+            .map_err(|e| e.to_string())?;
+        let mut response = vec![];
+        stream
+            .take(MAX_RESPONSE_BYTES)
+            .read_to_end(&mut response)
+            .map_err(|e| e.to_string())?;
+        parse_response(&response)
+    }
+}
+
+/// Something asked of the server, on a thread of its own, and the answer
+/// once it comes. A copy of one, as a copy of the game has, asks nothing.
+pub struct Request<T> {
+    pending: Option<JoinHandle<Result<T, String>>>,
+}
+
+impl<T: Send + 'static> Request<T> {
+    fn start(ask: impl FnOnce() -> Result<T, String> + Send + 'static) -> Self {
+        Request {
+            pending: Some(thread::spawn(ask)),
+        }
+    }
+}
+
+impl<T> Request<T> {
+    /// whether the answer is still to come
+    pub fn is_waiting(&self) -> bool {
+        self.pending
+            .as_ref()
+            .map_or(false, |handle| !handle.is_finished())
+    }
+
+    /// the answer, if it's come since last asked; none while it's still
+    /// being waited on, or once it's been collected
+    pub fn poll(&mut self) -> Option<Result<T, String>> {
+        if self.is_waiting() {
+            return None;
+        }
+        let handle = self.pending.take()?;
+        Some(
+            handle
+                .join()
+                .unwrap_or_else(|_| Err("the leaderboard thread panicked".into())),
+        )
+    }
+}
+
+impl<T> Default for Request<T> {
+    fn default() -> Self {
+        Request { pending: None }
+    }
+}
+
+impl<T> Clone for Request<T> {
+    fn clone(&self) -> Self {
+        Request::default()
+    }
+}
+
+/// check the status of a raw HTTP response, and return its body
+pub fn parse_response(response: &[u8]) -> Result<String, String> {
+    let response = std::str::from_utf8(response).map_err(|e| e.to_string())?;
+    let (head, body) = response
+        .split_once("\r\n\r\n")
+        .ok_or("the response has no body")?;
+    let status_line = head.lines().next().unwrap_or_default();
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .ok_or_else(|| format!("bad status line: {}", status_line))?;
+    if !status.starts_with('2') {
+        return Err(format!("the server answered {}", status_line));
+    }
+    Ok(body.into())
+}
+
+pub fn parse_scores(body: &str) -> Result<Vec<Entry>, String> {
+    serde_json::from_str(body).map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn urls_give_the_host_port_and_path() {
+        let leaderboard = Leaderboard::new("http://scores.example.com").unwrap();
+        assert_eq!(leaderboard.host, "scores.example.com");
+        assert_eq!((leaderboard.port, leaderboard.path.as_str()), (80, "/"));
+        let leaderboard = Leaderboard::new("http://localhost:8080/api/runs?game=rl").unwrap();
+        assert_eq!(leaderboard.host, "localhost");
+        assert_eq!((leaderboard.port, leaderboard.path.as_str()), (8080, "/api/runs?game=rl"));
+        assert!(Leaderboard::new("https://scores.example.com").is_err());
+        assert!(Leaderboard::new("http://localhost:port/").is_err());
+        assert!(Leaderboard::new("http://:8080/").is_err());
+    }
+
+    #[test]
+    fn responses_give_their_body_if_the_server_was_happy() {
+        let ok = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n[]";
+        assert_eq!(parse_response(ok), Ok("[]".to_string()));
+        let created = b"HTTP/1.1 201 Created\r\n\r\n";
+        assert_eq!(parse_response(created), Ok(String::new()));
+        let missing = b"HTTP/1.0 404 Not Found\r\n\r\nno such page";
+        let refused = Err("the server answered HTTP/1.0 404 Not Found".to_string());
+        assert_eq!(parse_response(missing), refused);
+        assert!(parse_response(b"HTTP/1.0 200 OK\r\n").is_err());
+        assert!(parse_response(b"\r\n\r\n[]").is_err());
+        assert!(parse_response(b"HTTP/1.0 200 OK\r\n\r\n\xff").is_err());
+        assert!(parse_scores("{\"seed\": 1}").is_err());
+    }
+
+    #[test]
+    fn the_server_is_asked_in_the_background() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 512];
+            let read = stream.read(&mut request).unwrap();
+            let body = r#"[{"seed": 7, "daily": true, "score": 90, "depth": 3,
+                "turns": 400, "cause": "orc"}]"#;
+            write!(stream, "HTTP/1.0 200 OK\r\n\r\n{}", body).unwrap();
+            String::from_utf8_lossy(&request[..read]).into_owned()
+        });
+        let leaderboard = Leaderboard::new(&format!("http://127.0.0.1:{}/runs", port)).unwrap();
+        let mut request = leaderboard.fetch_top_scores(7);
+        let scores = loop {
+            match request.poll() {
+                Some(scores) => break scores.unwrap(),
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        assert_eq!((scores.len(), scores[0].score), (1, 90));
+        assert!(server.join().unwrap().starts_with("GET /runs?seed=7 HTTP/1.0"));
+        // once collected, there's nothing more to come
+        assert!(!request.is_waiting() && request.poll().is_none());
+    }
+}
@@ -0,0 +1,5308 @@
+// without libtcod's window, some of what the windows share goes unused
+#![cfg_attr(not(feature = "tcod"), allow(dead_code))]
+
+mod abilities;
+mod actions;
+mod archery;
+mod artifacts;
+mod backend;
+mod bestiary;
+mod bus;
+mod character;
+mod classes;
+mod colors;
+mod companions;
+mod console;
+mod daily;
+mod daylight;
+mod dialogue;
+mod dungeon;
+mod effects;
+mod encumbrance;
+mod entities;
+mod events;
+mod factions;
+mod fov;
+mod highscores;
+mod identify;
+mod input;
+mod items;
+mod keybindings;
+mod leaderboard;
+mod light;
+mod look;
+mod map;
+mod message_log;
+mod morgue;
+mod noise;
+mod overview;
+mod packs;
+mod pathfinding;
+mod powers;
+mod prefabs;
+mod resting;
+mod projectile;
+mod save;
+mod script;
+pub mod sim;
+mod speed;
+mod spells;
+mod states;
+mod targeting;
+mod telemetry;
+#[cfg(feature = "terminal")]
+mod terminal;
+mod throwing;
+#[cfg(feature = "tiles")]
+mod tiles;
+mod town;
+mod trade;
+mod traps;
+mod tutorial;
+#[cfg(test)]
+mod test_support;
+mod weather;
+
+use rand::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::cmp;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::default::Default;
+use std::path::PathBuf;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+use colors::*;
+use console::*;
+use input::{Event, Key, Mouse};
+use backend::Backend;
+use map::{Door, Map};
+use crate::map::{is_blocked, Transition, MAP_HEIGHT, MAP_WIDTH};
+use bestiary::Bestiary;
+use bus::GameEvent;
+use abilities::{Cooldown, MAX_STAMINA};
+use actions::Action;
+use classes::{Class, CLASSES};
+use companions::Order;
+use dungeon::Dungeon;
+use effects::{Effect, StatusEffect};
+use entities::{EntityId, Objects};
+use factions::Faction;
+use fov::{Fov, FovMap};
+use keybindings::Keybindings;
+use leaderboard::Leaderboard;
+use morgue::RunStats;
+use light::LightSource;
+use noise::Noise;
+use packs::PackMember;
+use pathfinding::DijkstraMap;
+use powers::Power;
+use projectile::Projectile;
+use resting::Activity;
+use save::LoadError;
+use spells::Spell;
+use states::{GameState, InventoryAction, Session};
+use targeting::HOW_TO_AIM;
+use telemetry::{Record, Telemetry};
+use trade::Trader;
+use traps::Trap;
+use tutorial::Tutorial;
+
+// actual size of the window
+const SCREEN_WIDTH: i32 = 80;
+const SCREEN_HEIGHT: i32 = 50;
+
+// sizes and coordinates relevant for the GUI
+const BAR_WIDTH: i32 = 20;
+const PANEL_HEIGHT: i32 = 9;
+const PANEL_Y: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+
+// the part of the map shown at a time, above the panel; bigger maps scroll
+const VIEW_WIDTH: i32 = SCREEN_WIDTH;
+const VIEW_HEIGHT: i32 = SCREEN_HEIGHT - PANEL_HEIGHT;
+
+const MSG_X: i32 = BAR_WIDTH + 2;
+const MSG_WIDTH: i32 = SCREEN_WIDTH - BAR_WIDTH - 2;
+const MSG_HEIGHT: usize = PANEL_HEIGHT as usize - 1;
+/// how many messages the log keeps, for the history screen
+const MESSAGE_LOG_SIZE: usize = 1000;
+const ENEMY_LIST_HEIGHT: usize = PANEL_HEIGHT as usize - 7;
+
+const INVENTORY_WIDTH: i32 = 50;
+const LEVEL_SCREEN_WIDTH: i32 = 44;
+const CHARACTER_SCREEN_WIDTH: i32 = 40;
+const SLOT_MENU_WIDTH: i32 = 70;
+const MAX_NAME_LENGTH: usize = 20;
+const HIGH_SCORES_WIDTH: i32 = 60;
+const VICTORY_WIDTH: i32 = 50;
+
+/// how many turns pass between autosaves, on top of those on a new level
+const AUTOSAVE_TURNS: u32 = 100;
+const DIALOGUE_WIDTH: i32 = 50;
+const BESTIARY_WIDTH: i32 = 60;
+const DAILY_RESULTS_WIDTH: i32 = 40;
+/// how many of the day's best daily challenge results are listed
+const DAILY_RESULTS_SHOWN: usize = 10;
+
+const LIMIT_FPS: i32 = 20; // 20 frames-per-second maximum
+const WINDOW_TITLE: &str = "Rust/libtcod tutorial";
+
+const TORCH_RADIUS: i32 = 10; // how far the player's torch lights up
+
+const HEAL_AMOUNT: i32 = 40;
+
+const LIGHTNING_DAMAGE: i32 = 40;
+const LIGHTNING_RANGE: i32 = 5;
+
+/// a magic missile does more damage for every level of the mage casting it
+const MAGIC_MISSILE_DAMAGE: i32 = 8;
+const MAGIC_MISSILE_LEVEL_DAMAGE: i32 = 2;
+const MAGIC_MISSILE_RANGE: i32 = 6;
+
+const CONFUSE_RANGE: i32 = 8;
+const CONFUSE_NUM_TURNS: i32 = 10;
+/// how likely a confused player is to stumble off the wrong way
+const CONFUSED_STUMBLE_CHANCE: f64 = 0.5;
+
+const CHARM_RANGE: i32 = 6;
+const CHARM_NUM_TURNS: i32 = 15;
+
+const FIREBALL_RADIUS: i32 = 3;
+const FIREBALL_DAMAGE: i32 = 25;
+const FIREBALL_SMOKE_RADIUS: i32 = 2;
+const FIREBALL_SMOKE_TURNS: i32 = 6;
+/// how long those a fireball doesn't kill go on burning
+const BURN_NUM_TURNS: i32 = 3;
+
+const SLOW_RANGE: i32 = 8;
+const SLOW_NUM_TURNS: i32 = 12;
+const HASTE_NUM_TURNS: i32 = 10;
+
+const FLASH_RADIUS: i32 = 6;
+const FLASH_NUM_TURNS: i32 = 8;
+
+const BLINDNESS_NUM_TURNS: i32 = 15;
+
+const TELEPATHY_NUM_TURNS: i32 = 30;
+
+/// the most a scroll can enchant a piece of equipment
+const MAX_ENCHANTMENT: i32 = 5;
+
+const NIGHT_VISION_NUM_TURNS: i32 = 40;
+const NIGHT_VISION_BONUS: i32 = 5;
+
+const CLAIRVOYANCE_RADIUS: i32 = 6;
+
+// stealth
+const BASE_STEALTH: i32 = 10;
+/// monsters see whatever is within this many degrees either side of their facing
+const VISION_CONE_HALF_ANGLE: f32 = 60.0;
+/// how far a monster sees, unless its species says otherwise
+const DEFAULT_SIGHT_RADIUS: i32 = 8;
+/// chance per turn that a monster which hasn't noticed the player looks around
+const LOOK_AROUND_CHANCE: f64 = 0.25;
+const SNEAK_STEALTH_BONUS: i32 = 5;
+const SNEAK_ATTACK_MULTIPLIER: i32 = 2;
+/// monsters with a ranged attack back away from a player closer than this
+const RANGED_KEEP_DISTANCE: f32 = 3.0;
+/// seeing another monster die within this distance shakes a monster's nerve,
+/// as if it were hurt this much worse, in percent of its max HP
+const FRIGHT_RADIUS: f32 = 6.0;
+const FRIGHT_MORALE_PENALTY: i32 = 25;
+/// chance per turn that a fleeing monster cries out, rousing those in earshot
+const CRY_FOR_HELP_CHANCE: f64 = 0.3;
+
+// swimming
+/// equipment at least this heavy drags a swimmer under
+const HEAVY_GEAR_WEIGHT: i32 = 5;
+/// turns a sinking swimmer can hold their breath before drowning
+const MAX_BREATH: i32 = 6;
+const DROWNING_DAMAGE: i32 = 10;
+
+// breeding monsters
+/// chance each turn that a breeder out of the player's sight multiplies
+const BREED_CHANCE: f64 = 0.02;
+/// breeders stop multiplying once there are this many of their species on the level
+const MAX_BREEDERS_PER_LEVEL: usize = 12;
+
+// wandering monsters
+/// the chance each turn, in tenths of a percent, that a monster wanders in
+/// from somewhere out of sight, from each dungeon level on
+const WANDERER_CHANCE: &[Transition] = &[
+    Transition { level: 1, value: 4 },
+    Transition { level: 4, value: 6 },
+    Transition { level: 7, value: 10 },
+];
+/// none wander in once there are this many monsters on the level
+const MAX_MONSTERS_PER_LEVEL: usize = 40;
+/// how close to the player a monster may wander in, at the nearest
+const WANDERER_MIN_DISTANCE: f32 = 10.0;
+
+/// how far the player looks when searching for traps
+const SEARCH_RADIUS: i32 = 3;
+/// what a search has to beat to find a hidden passage right next to the
+/// player, with a d20 plus their perception
+const PASSAGE_DIFFICULTY: i32 = 15;
+
+// eating corpses
+/// corpses older than this many turns have gone bad
+const CORPSE_SPOIL_TURNS: u32 = 100;
+/// and these have rotted away to nothing
+const CORPSE_DECAY_TURNS: u32 = 400;
+const REGENERATION_NUM_TURNS: i32 = 20;
+const POISON_NUM_TURNS: i32 = 10;
+/// chance that an orc corpse disagrees with the player
+const ORC_POISON_CHANCE: f64 = 0.25;
+
+// hunger
+/// how full the player starts out, and the fullest they can get
+const MAX_SATIATION: i32 = 2000;
+/// below these, the player is hungry, and then weak with hunger
+const HUNGRY_SATIATION: i32 = 300;
+const WEAK_SATIATION: i32 = 100;
+/// what a player with nothing left in them loses every turn
+const STARVATION_DAMAGE: i32 = 1;
+const RATION_NOURISHMENT: i32 = 800;
+const CORPSE_NOURISHMENT: i32 = 300;
+
+// experience and level-ups
+const LEVEL_UP_BASE: i32 = 200;
+const LEVEL_UP_FACTOR: i32 = 150;
+
+/// score for every dungeon level descended
+const SCORE_PER_DUNGEON_LEVEL: i32 = 250;
+/// score for making it back out with the crown
+const VICTORY_BONUS: i32 = 5000;
+
+// monsters sensed through telepathy rather than seen
+const COLOR_SENSED: Color = Color {
+    r: 190,
+    g: 90,
+    b: 255,
+};
+
+const COLOR_DARK_WATER: Color = Color { r: 0, g: 20, b: 70 };
+const COLOR_LIGHT_WATER: Color = Color {
+    r: 40,
+    g: 90,
+    b: 200,
+};
+
+const COLOR_DARK_VAULT_DOOR: Color = Color { r: 60, g: 40, b: 0 };
+const COLOR_LIGHT_VAULT_DOOR: Color = Color {
+    r: 200,
+    g: 150,
+    b: 30,
+};
+
+const COLOR_TELEPORTER: Color = Color {
+    r: 230,
+    g: 60,
+    b: 230,
+};
+
+const COLOR_DOOR: Color = Color {
+    r: 160,
+    g: 100,
+    b: 40,
+};
+
+const COLOR_SMOKE: Color = Color {
+    r: 110,
+    g: 110,
+    b: 110,
+};
+
+/// how bright remembered items and features are drawn, compared to when seen
+const REMEMBERED_BRIGHTNESS: f32 = 0.4;
+
+const COLOR_DARK_WALL: Color = Color { r: 0, g: 0, b: 100 };
+const COLOR_LIGHT_WALL: Color = Color {
+    r: 130,
+    g: 110,
+    b: 50,
+};
+const COLOR_DARK_GROUND: Color = Color {
+    r: 50,
+    g: 50,
+    b: 100,
+};
+const COLOR_LIGHT_GROUND: Color = Color {
+    r: 200,
+    g: 180,
+    b: 50,
+};
+
+/// where the bestiary is kept between games
+const BESTIARY_FILE: &str = "bestiary";
+
+// player will always be the first object
+const PLAYER: usize = 0;
+
+/// the eight directions anything can move or face in
+const DIRECTIONS: [(i32, i32); 8] = [
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+    (-1, 0),
+    (1, 0),
+    (-1, 1),
+    (0, 1),
+    (1, 1),
+];
+
+struct Tcod {
+    backend: Box<dyn Backend>,
+    /// everything is drawn here, and the backend shows it
+    screen: Offscreen,
+    /// which cells of the screen show the map, rather than text
+    map_cells: Vec<bool>,
+    con: Offscreen,
+    panel: Offscreen,
+    fov: FovMap,
+    key: Key,
+    /// which keys do what, as the player has them
+    keys: Keybindings,
+    mouse: Mouse,
+    camera: Camera,
+    /// how many frames have been shown, for anything drawn moving
+    frame: u32,
+}
+
+impl Tcod {
+    /// the screens with no window, for tests, where the player presses the
+    /// given keys as `backend::Presses` has them
+    #[cfg(test)]
+    fn pressing(keys: &str) -> Self {
+        Tcod {
+            backend: Box::new(backend::Presses(keys.chars().collect())),
+            screen: Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+            map_cells: vec![false; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+            con: Offscreen::new(VIEW_WIDTH, VIEW_HEIGHT),
+            panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
+            fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+            key: Default::default(),
+            keys: Keybindings::default(),
+            mouse: Default::default(),
+            camera: Camera::default(),
+            frame: 0,
+        }
+    }
+
+    /// show everything drawn on the screen so far
+    fn flush(&mut self) {
+        self.backend.present(&self.screen, &self.map_cells);
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// mark the cells of the screen in the given rect as showing the map,
+    /// or not
+    fn mark_map(&mut self, (x, y): (i32, i32), (width, height): (i32, i32), on_map: bool) {
+        for y in y.max(0)..(y + height).min(SCREEN_HEIGHT) {
+            for x in x.max(0)..(x + width).min(SCREEN_WIDTH) {
+                self.map_cells[(y * SCREEN_WIDTH + x) as usize] = on_map;
+            }
+        }
+    }
+
+    /// the spot on the map under the mouse
+    fn mouse_on_map(&self) -> (i32, i32) {
+        self.camera.to_map(self.mouse.cx, self.mouse.cy)
+    }
+}
+
+/// Which part of the map is on screen, given by the spot on the map shown at
+/// the top left.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+struct Camera {
+    x: i32,
+    y: i32,
+}
+
+impl Camera {
+    /// A view with the given spot in the middle, as far as the edges of the
+    /// map allow. A map smaller than the view is shown from its top left.
+    fn centered_on((x, y): (i32, i32), map: &Map) -> Self {
+        let along = |pos: i32, view: i32, size: i32| (pos - view / 2).min(size - view).max(0);
+        Camera {
+            x: along(x, VIEW_WIDTH, map.width()),
+            y: along(y, VIEW_HEIGHT, map.height()),
+        }
+    }
+
+    /// where a spot on the map is drawn on screen, if it's in view
+    fn to_screen(self, x: i32, y: i32) -> Option<(i32, i32)> {
+        let (screen_x, screen_y) = (x - self.x, y - self.y);
+        let in_view = (0..VIEW_WIDTH).contains(&screen_x) && (0..VIEW_HEIGHT).contains(&screen_y);
+        if in_view {
+            Some((screen_x, screen_y))
+        } else {
+            None
+        }
+    }
+
+    /// the spot on the map drawn at a place on screen
+    fn to_map(self, screen_x: i32, screen_y: i32) -> (i32, i32) {
+        (screen_x + self.x, screen_y + self.y)
+    }
+}
+
+/// What a message is about, for the message log to pick out.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Category {
+    /// attacks, wounds and deaths
+    Combat,
+    /// picking things up, using and wearing them
+    Items,
+    /// everything else
+    #[default]
+    System,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Message {
+    text: String,
+    color: Color,
+    #[serde(default)]
+    category: Category,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Messages {
+    /// the latest `MESSAGE_LOG_SIZE` messages, oldest first
+    messages: VecDeque<Message>,
+}
+
+impl Messages {
+    pub fn new() -> Self {
+        Self {
+            messages: VecDeque::new(),
+        }
+    }
+
+    /// add the new message, with the text and the color
+    pub fn add<T: Into<String>>(&mut self, message: T, color: Color) {
+        self.add_in(Category::System, message, color);
+    }
+
+    /// add the new message, about the given category of thing; the oldest is
+    /// forgotten once the log is full
+    pub fn add_in<T: Into<String>>(&mut self, category: Category, message: T, color: Color) {
+        if self.messages.len() >= MESSAGE_LOG_SIZE {
+            self.messages.pop_front();
+        }
+        self.messages.push_back(Message {
+            text: message.into(),
+            color,
+            category,
+        });
+    }
+
+    /// Create a `DoubleEndedIterator` over the messages
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Message> {
+        self.messages.iter()
+    }
+}
+
+// combat-related properties and methods (monster, player, NPC).
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct Fighter {
+    hp: i32,
+    base_max_hp: i32,
+    base_defense: i32,
+    base_power: i32,
+    /// how good this fighter is at noticing others sneaking around
+    #[serde(default)]
+    perception: i32,
+    /// how far this fighter can see, given some light to see by
+    #[serde(default = "default_sight_radius")]
+    sight_radius: i32,
+    /// turns of blindness this fighter's hits inflict
+    #[serde(default)]
+    blinding: i32,
+    /// turns until this fighter can see again
+    #[serde(default)]
+    blind_turns: i32,
+    /// what this fighter's hits leave on whoever they hit, like a snake's poison
+    #[serde(default)]
+    inflicts: Option<StatusEffect>,
+    /// what this fighter's spells draw on; only the player casts any
+    #[serde(default)]
+    mana: i32,
+    #[serde(default)]
+    max_mana: i32,
+    /// how much this fighter can carry; only matters for the player
+    #[serde(default = "default_strength")]
+    strength: i32,
+    /// how hurt this fighter has to be, in percent of its max HP, before it
+    /// runs away; 0 for never
+    #[serde(default)]
+    morale: i32,
+    /// how quickly this fighter acts: one action a turn at `NORMAL_SPEED`,
+    /// two at twice that
+    #[serde(default = "default_speed")]
+    speed: i32,
+    xp: i32,
+    on_death: DeathCallback,
+}
+
+impl Default for Fighter {
+    /// a fighter with nothing to it yet, but that sees and moves like most do
+    fn default() -> Self {
+        Fighter {
+            hp: 0,
+            base_max_hp: 0,
+            base_defense: 0,
+            base_power: 0,
+            perception: 0,
+            sight_radius: DEFAULT_SIGHT_RADIUS,
+            blinding: 0,
+            blind_turns: 0,
+            inflicts: None,
+            mana: 0,
+            max_mana: 0,
+            strength: 0,
+            morale: 0,
+            speed: speed::NORMAL_SPEED,
+            xp: 0,
+            on_death: DeathCallback::Monster,
+        }
+    }
+}
+
+fn default_sight_radius() -> i32 {
+    DEFAULT_SIGHT_RADIUS
+}
+
+fn default_speed() -> i32 {
+    speed::NORMAL_SPEED
+}
+
+/// the strength of a player saved before there was any, who was a warrior
+fn default_strength() -> i32 {
+    Class::default().fighter().strength
+}
+
+/// A monster's bow, sling or spit: how far it reaches and how hard it hits.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+struct RangedAttack {
+    range: i32,
+    power: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+enum Ai {
+    /// fast asleep, until noise or getting hurt wakes it
+    Asleep,
+    /// hasn't noticed the player yet, and stays put until it does
+    Unaware,
+    /// hasn't noticed the player yet, and keeps close to its pack's leader
+    Follow,
+    Basic,
+    /// keeps its distance and shoots at the player
+    Ranged,
+    /// too badly hurt to fight, and running from the player
+    Flee,
+    /// the player's companion, fighting at their side
+    Ally,
+}
+
+/// How an NPC or monster feels about the player.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+enum Attitude {
+    /// out to kill them
+    #[default]
+    Hostile,
+    /// minds its own business, unless there's trade to be done
+    Neutral,
+    /// glad to talk
+    Friendly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Item {
+    Heal,
+    Lightning,
+    Confuse,
+    Fireball,
+    Sword,
+    Shield,
+    Flash,
+    Blindness,
+    Telepathy,
+    Amulet,
+    Clairvoyance,
+    Lantern,
+    RingOfDimness,
+    NightVision,
+    Key,
+    Retraining,
+    /// equipment that does nothing but give its bonuses
+    Gear,
+    Food,
+    Slow,
+    /// teaches the player a spell
+    Spellbook,
+    /// good for nothing but throwing
+    Rock,
+    /// shot from a bow or crossbow
+    Arrows,
+    /// tells the player what an item they carry is
+    Identify,
+    /// lets cursed equipment come off
+    RemoveCurse,
+    EnchantWeapon,
+    EnchantArmor,
+    /// what the player came down for, to be carried back up to the surface
+    Crown,
+    /// calls up a companion
+    Summon,
+    /// wins a monster over to the player's side for a while
+    Charm,
+    /// speeds the player up for a while
+    Haste,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+enum DeathCallback {
+    Player,
+    #[default]
+    Monster,
+}
+
+impl DeathCallback {
+    fn callback(self, object: &mut Object, game: &mut Game) {
+        use DeathCallback::*;
+        let callback: fn(&mut Object, &mut Game) = match self {
+            Player => player_death,
+            Monster => monster_death,
+        };
+        callback(object, game);
+    }
+}
+
+fn player_death(player: &mut Object, game: &mut Game) {
+    // the game ended!
+    bus::publish(
+        game,
+        GameEvent::Died {
+            name: player.name.clone(),
+            x: player.x,
+            y: player.y,
+            xp: 0,
+            ally: false,
+        },
+    );
+
+    // for added effect, transform the player into a corpse!
+    player.char = '%';
+    player.color = DARK_RED;
+}
+
+/// The remains of a slain monster, which can be eaten until they rot, and
+/// which rot away altogether after a while.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+struct Corpse {
+    species: String,
+    died_on_turn: u32,
+    /// whether it's gone bad, and been renamed to say so
+    #[serde(default)]
+    spoiled: bool,
+}
+
+fn monster_death(monster: &mut Object, game: &mut Game) {
+    let ally = monster.ai == Some(Ai::Ally);
+    if !ally {
+        game.bestiary.killed(monster);
+        game.stats.killed(monster);
+    }
+    bus::publish(
+        game,
+        GameEvent::Died {
+            name: monster.name.clone(),
+            x: monster.x,
+            y: monster.y,
+            xp: monster.fighter.map_or(0, |f| f.xp),
+            ally,
+        },
+    );
+    // transform it into a nasty corpse! it doesn't block, can't be
+    // attacked and doesn't move
+    monster.char = '%';
+    monster.color = DARK_RED;
+    monster.blocks = false;
+    monster.fighter = None;
+    monster.ai = None;
+    monster.effects.clear();
+    monster.corpse = Some(Corpse {
+        species: monster.name.clone(),
+        died_on_turn: game.turn,
+        spoiled: false,
+    });
+    let loot = bestiary::roll_loot(&monster.name, &mut game.rng);
+    for name in monster.drops.take().into_iter().chain(loot) {
+        game.messages.add_in(
+            Category::Combat,
+            format!("The {} drops a {}.", monster.name, name),
+            LIGHT_GREY,
+        );
+        game.dropped.push(map::make_item(&name, monster.x, monster.y));
+    }
+    if let Some(amount) = monster.gold.take() {
+        game.messages.add_in(
+            Category::Combat,
+            format!("The {} drops {} gold.", monster.name, amount),
+            LIGHT_GREY,
+        );
+        game.dropped.push(map::make_gold(amount, monster.x, monster.y));
+    }
+    if monster.boss {
+        game.messages
+            .add_in(Category::Combat, "You hear the seal on the stairs down break.", LIGHT_VIOLET);
+    }
+    monster.name = format!("remains of {}", monster.name);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+/// An object that can be equipped, yielding bonuses.
+struct Equipment {
+    slot: Slot,
+    #[serde(default)]
+    equipped: bool,
+    max_hp_bonus: i32,
+    defense_bonus: i32,
+    power_bonus: i32,
+    /// heavy gear makes it harder to move quietly
+    #[serde(default)]
+    weight: i32,
+    /// lets the wearer sense every monster on the level
+    #[serde(default)]
+    telepathic: bool,
+    /// added to the radius of the wearer's light
+    #[serde(default)]
+    light_bonus: i32,
+    /// takes both hands, so it goes in the right hand and leaves the left empty
+    #[serde(default)]
+    two_handed: bool,
+    /// won't come off once it's on
+    #[serde(default)]
+    cursed: bool,
+    /// how much better than usual it is, or worse if negative: added to a
+    /// weapon's power, or to anything else's defense
+    #[serde(default)]
+    enchantment: i32,
+    /// extra damage its wielder's hits do by burning
+    #[serde(default)]
+    fire_damage: i32,
+}
+
+impl Equipment {
+    /// where it's worn, as the inventory names it
+    fn worn_on(&self) -> String {
+        if self.two_handed {
+            "both hands".into()
+        } else {
+            self.slot.to_string()
+        }
+    }
+
+    /// whether it's a weapon, which enchantment makes hit harder rather than
+    /// protect better
+    fn is_weapon(&self) -> bool {
+        self.slot.is_hand() && self.power_bonus > 0
+    }
+
+    /// the power it gives, with its enchantment if it's a weapon
+    fn power(&self) -> i32 {
+        match self.is_weapon() {
+            true => self.power_bonus + self.enchantment,
+            false => self.power_bonus,
+        }
+    }
+
+    /// the defense it gives, with its enchantment unless it's a weapon
+    fn defense(&self) -> i32 {
+        match self.is_weapon() {
+            true => self.defense_bonus,
+            false => self.defense_bonus + self.enchantment,
+        }
+    }
+
+    /// whether it's in the way of equipping `other`: in the same slot, or
+    /// holding a hand that a two-handed weapon needs
+    fn blocks(&self, other: &Equipment) -> bool {
+        let both_hands = self.two_handed || other.two_handed;
+        self.slot == other.slot || (both_hands && self.slot.is_hand() && other.slot.is_hand())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Slot {
+    LeftHand,
+    RightHand,
+    Head,
+    Body,
+    Feet,
+    Neck,
+    /// a ring on the left hand; a ring can go on either
+    LeftRing,
+    RightRing,
+    Belt,
+    /// a bow or crossbow, for shooting arrows
+    Ranged,
+}
+
+/// every equipment slot, in the order the character screen lists them
+const EQUIPMENT_SLOTS: [Slot; 10] = [
+    Slot::Head,
+    Slot::Neck,
+    Slot::Body,
+    Slot::RightHand,
+    Slot::LeftHand,
+    Slot::RightRing,
+    Slot::LeftRing,
+    Slot::Belt,
+    Slot::Feet,
+    Slot::Ranged,
+];
+
+impl Slot {
+    fn is_hand(self) -> bool {
+        matches!(self, Slot::LeftHand | Slot::RightHand)
+    }
+
+    /// the ring slot on the other hand, for a ring slot
+    fn other_ring(self) -> Option<Slot> {
+        match self {
+            Slot::LeftRing => Some(Slot::RightRing),
+            Slot::RightRing => Some(Slot::LeftRing),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for Slot {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Slot::LeftHand => write!(f, "left hand"),
+            Slot::RightHand => write!(f, "right hand"),
+            Slot::Head => write!(f, "head"),
+            Slot::Body => write!(f, "body"),
+            Slot::Feet => write!(f, "feet"),
+            Slot::Neck => write!(f, "neck"),
+            Slot::LeftRing => write!(f, "left finger"),
+            Slot::RightRing => write!(f, "right finger"),
+            Slot::Belt => write!(f, "belt"),
+            Slot::Ranged => write!(f, "ranged"),
+        }
+    }
+}
+
+/// This is a generic object: the player, a monster, an item, the stairs...
+/// It's always represented by a character on screen. What it is comes down
+/// to which of its parts are filled in: a fighter, an AI, an item and so on.
+/// Each part is empty by default, so a new one only needs adding here.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+struct Object {
+    /// what anything keeping track of this object knows it by, given it
+    /// when it's put on the level; none while it's anywhere else, like in
+    /// the player's pack
+    #[serde(default)]
+    id: Option<EntityId>,
+    x: i32,
+    y: i32,
+    char: char,
+    color: Color,
+    name: String,
+    blocks: bool,
+    alive: bool,
+    fighter: Option<Fighter>,
+    ai: Option<Ai>,
+    item: Option<Item>,
+    level: i32,
+    equipment: Option<Equipment>,
+    light: Option<LightSource>,
+    /// the direction this object last moved or looked in; (0, 0) for none,
+    /// in which case it sees all around it
+    #[serde(default)]
+    facing: (i32, i32),
+    /// whether this monster will cross deep water; the player always can
+    #[serde(default)]
+    swims: bool,
+    /// whether this monster multiplies when left alone
+    #[serde(default)]
+    breeds: bool,
+    /// whether this monster never leaves its spot, like lichen
+    #[serde(default)]
+    rooted: bool,
+    /// what this monster can do besides fight, like heal or spit webs
+    #[serde(default)]
+    powers: Vec<Power>,
+    /// whether this is a level's boss, which keeps the stairs down sealed
+    /// until it dies
+    #[serde(default)]
+    boss: bool,
+    /// the pack this monster goes about with, if any
+    #[serde(default)]
+    pack: Option<PackMember>,
+    /// whether it means the player harm; anything that doesn't turns on the
+    /// player once they hurt it
+    #[serde(default)]
+    attitude: Attitude,
+    /// the side it takes in a fight, unless charmed
+    #[serde(default)]
+    faction: Faction,
+    #[serde(default)]
+    corpse: Option<Corpse>,
+    /// the name of this NPC's conversation in the dialogue data
+    #[serde(default)]
+    dialogue: Option<String>,
+    #[serde(default)]
+    trader: Option<Trader>,
+    #[serde(default)]
+    ranged: Option<RangedAttack>,
+    #[serde(default)]
+    trap: Option<Trap>,
+    /// the item this monster leaves behind when it dies, by name
+    #[serde(default)]
+    drops: Option<String>,
+    /// the gold in this pile, or carried by this monster and dropped when it
+    /// dies
+    #[serde(default)]
+    gold: Option<i32>,
+    /// how many there are in this stack of arrows or the like; None for
+    /// items that don't stack
+    #[serde(default)]
+    count: Option<i32>,
+    /// the name this item goes by if it's an artifact, one of a kind
+    #[serde(default)]
+    artifact: Option<String>,
+    /// the shopkeeper this item was taken from and is still to be paid for
+    #[serde(default)]
+    owed: Option<EntityId>,
+    /// whether this item was carried out of a shop without being paid for
+    #[serde(default)]
+    stolen: bool,
+    /// the status effects on this player or monster
+    #[serde(default)]
+    effects: Vec<StatusEffect>,
+    /// how near this player or monster is to its next action; see `speed`
+    #[serde(default)]
+    energy: i32,
+    /// the enemy other than the player this monster or companion is after
+    #[serde(default)]
+    target: Option<EntityId>,
+}
+
+impl Object {
+    pub fn new(x: i32, y: i32, char: char, name: &str, color: Color, blocks: bool) -> Self {
+        Object {
+            x,
+            y,
+            char,
+            color,
+            name: name.into(),
+            blocks,
+            level: 1,
+            ..Default::default()
+        }
+    }
+
+    /// set the color and then draw the character that represents this object
+    /// where its position is on screen, if it's in view
+    pub fn draw(&self, con: &mut Offscreen, camera: Camera) {
+        if let Some((x, y)) = camera.to_screen(self.x, self.y) {
+            con.set_default_foreground(self.color);
+            con.put_char(x, y, self.char, BackgroundFlag::None);
+        }
+    }
+
+    pub fn pos(&self) -> (i32, i32) {
+        (self.x, self.y)
+    }
+
+    /// whether this is a trap the player hasn't found yet
+    pub fn is_hidden(&self) -> bool {
+        self.trap.map_or(false, |trap| trap.hidden)
+    }
+
+    /// whether the player can pick this up: an item, or a pile of gold
+    pub fn can_pick_up(&self) -> bool {
+        self.item.is_some() || (self.gold.is_some() && self.fighter.is_none())
+    }
+
+    /// whether this is cursed equipment that's been put on, and won't come off
+    pub fn is_stuck(&self) -> bool {
+        self.equipment.map_or(false, |e| e.equipped && e.cursed)
+    }
+
+    /// how many of this item there are, in a stack or on its own
+    pub fn quantity(&self) -> i32 {
+        self.count.unwrap_or(1)
+    }
+
+    /// the name of this item as the player knows it, with how many there
+    /// are, like "arrows x12"
+    pub fn display_name(&self, game: &Game) -> String {
+        let name = match &self.artifact {
+            Some(artifact) => artifact.clone(),
+            None => identify::name(self, game),
+        };
+        let name = match self.equipment {
+            // with its enchantment, like "+2 sword"
+            Some(equipment) if equipment.enchantment != 0 => {
+                format!("{:+} {}", equipment.enchantment, name)
+            }
+            _ => name,
+        };
+        let name = match self.quantity() {
+            1 => name,
+            quantity => format!("{} x{}", name, quantity),
+        };
+        if self.owed.is_some() {
+            format!("{} (unpaid)", name)
+        } else if self.stolen {
+            format!("{} (stolen)", name)
+        } else {
+            name
+        }
+    }
+
+    /// "a dagger", or "healing potion x3" for a stack
+    pub fn a_name(&self, game: &Game) -> String {
+        match self.quantity() {
+            1 => format!("a {}", self.display_name(game)),
+            _ => self.display_name(game),
+        }
+    }
+
+    pub fn set_pos(&mut self, x: i32, y: i32) {
+        self.x = x;
+        self.y = y;
+    }
+
+    /// return the distance to another object
+    pub fn distance_to(&self, other: &Object) -> f32 {
+        let dx = other.x - self.x;
+        let dy = other.y - self.y;
+        ((dx.pow(2) + dy.pow(2)) as f32).sqrt()
+    }
+
+    /// return the distance to some coordinates
+    pub fn distance(&self, x: i32, y: i32) -> f32 {
+        (((x - self.x).pow(2) + (y - self.y).pow(2)) as f32).sqrt()
+    }
+
+    /// returns true if the given coordinates are within this object's vision
+    /// cone, ignoring walls and light
+    pub fn faces(&self, x: i32, y: i32) -> bool {
+        let (facing_x, facing_y) = self.facing;
+        let (dx, dy) = (x - self.x, y - self.y);
+        if (facing_x, facing_y) == (0, 0) || (dx, dy) == (0, 0) {
+            return true;
+        }
+        let dot = (facing_x * dx + facing_y * dy) as f32;
+        let lengths = self.distance(x, y) * ((facing_x.pow(2) + facing_y.pow(2)) as f32).sqrt();
+        dot >= lengths * VISION_CONE_HALF_ANGLE.to_radians().cos()
+    }
+
+    /// Returns true if this object can see the given spot: it's within its
+    /// sight radius, nothing blocks the line between them, and it's lit. Right
+    /// next to it, it can always tell what's there. Unlike the player's FOV,
+    /// this doesn't care whether the object itself stands in the light.
+    pub fn can_see(&self, x: i32, y: i32, map: &Map) -> bool {
+        let distance = self.distance(x, y);
+        if distance < 2.0 {
+            return true;
+        }
+        let sight_radius = self.fighter.map_or(0, |f| f.sight_radius);
+        distance <= sight_radius as f32
+            && map.get(x, y).map_or(false, |tile| tile.light > 0.0)
+            && projectile::line(self.pos(), (x, y))
+                .into_iter()
+                .take_while(|&pos| pos != (x, y))
+                .all(|(x, y)| map.is_transparent(x, y))
+    }
+
+    pub fn take_damage(&mut self, damage: i32, game: &mut Game) -> Option<i32> {
+        // apply damage if possible
+        if let Some(fighter) = self.fighter.as_mut() {
+            if damage > 0 {
+                fighter.hp -= damage;
+            }
+        }
+        self.provoke(game);
+        // getting hurt is a sure way to notice someone's there
+        if matches!(self.ai, Some(Ai::Asleep | Ai::Unaware | Ai::Follow)) {
+            self.ai = Some(self.alerted_ai());
+        }
+        // check for death, call the death function
+        if let Some(fighter) = self.fighter {
+            if fighter.hp <= 0 {
+                self.alive = false;
+                fighter.on_death.callback(self, game);
+                return Some(fighter.xp);
+            }
+        }
+        // or it may just have had enough
+        if matches!(self.ai, Some(Ai::Basic | Ai::Ranged)) && self.loses_nerve(0) {
+            game.messages
+                .add_in(Category::Combat, format!("The {} turns to flee!", self.name), ORANGE);
+            self.ai = Some(Ai::Flee);
+        }
+        None
+    }
+
+    /// Take damage from another fighter, which gets the experience if it's a
+    /// kill. Only the player's side does: when monsters fight among
+    /// themselves, the experience goes to nobody.
+    pub fn take_damage_from(&mut self, attacker: &mut Object, damage: i32, game: &mut Game) {
+        let for_nobody = attacker.faction() != Faction::Player && self.ai.is_some();
+        let xp = match self.fighter.as_mut() {
+            Some(fighter) if for_nobody => std::mem::take(&mut fighter.xp),
+            _ => 0,
+        };
+        match self.take_damage(damage, game) {
+            // yield experience to the attacker
+            Some(gained) => attacker.fighter.as_mut().unwrap().xp += gained,
+            None => {
+                if let Some(fighter) = self.fighter.as_mut() {
+                    fighter.xp += xp;
+                }
+            }
+        }
+    }
+
+    /// whether this fighter is hurt badly enough to run, with its nerve
+    /// shaken by `penalty` on top of its wounds
+    pub fn loses_nerve(&self, penalty: i32) -> bool {
+        self.fighter.map_or(false, |f| {
+            f.morale > 0 && f.hp * 100 < (f.morale + penalty) * f.base_max_hp
+        })
+    }
+
+    /// the AI this monster takes up once it has noticed the player
+    pub fn alerted_ai(&self) -> Ai {
+        if self.ranged.is_some() {
+            Ai::Ranged
+        } else {
+            Ai::Basic
+        }
+    }
+
+    /// fire at a target out of reach, with this object's ranged attack
+    /// what this object shoots with: a monster's own bow or spit, or the
+    /// launcher the player has equipped
+    pub fn ranged_attack(&self, game: &Game) -> Option<RangedAttack> {
+        if self.name == "player" {
+            let launcher = get_equipped_in_slot(Slot::Ranged, &game.inventory)?;
+            game.inventory[launcher].ranged
+        } else {
+            self.ranged
+        }
+    }
+
+    pub fn shoot(&mut self, target: &mut Object, game: &mut Game) {
+        let power = self.ranged_attack(game).map_or(0, |ranged| ranged.power);
+        let damage = power - target.defense(game);
+        game.noises.push(Noise {
+            x: target.x,
+            y: target.y,
+            loudness: noise::COMBAT_NOISE,
+        });
+        bus::publish(
+            game,
+            GameEvent::Attacked {
+                attacker: self.name.clone(),
+                target: target.name.clone(),
+                damage,
+                shot: true,
+                sneak: false,
+            },
+        );
+        if damage > 0 {
+            bus::publish(
+                game,
+                GameEvent::DamageDealt {
+                    source: self.name.clone(),
+                    target: target.name.clone(),
+                    amount: damage,
+                },
+            );
+            target.take_damage_from(self, damage, game);
+        }
+    }
+
+    pub fn attack(&mut self, target: &mut Object, game: &mut Game) {
+        self.strike(target, 1, game);
+    }
+
+    /// an attack with `multiplier` times this object's power behind it
+    pub fn strike(&mut self, target: &mut Object, multiplier: i32, game: &mut Game) {
+        // a simple formula for attack damage
+        let mut damage = multiplier * self.power(game) - target.defense(game);
+        game.noises.push(Noise {
+            x: target.x,
+            y: target.y,
+            loudness: noise::COMBAT_NOISE,
+        });
+        if damage > 0 {
+            // flaming weapons burn whatever they get through to
+            damage += self.fire_damage(game);
+        }
+        // the target never saw it coming
+        let sneak = damage > 0 && matches!(target.ai, Some(Ai::Asleep | Ai::Unaware | Ai::Follow));
+        if sneak {
+            damage *= SNEAK_ATTACK_MULTIPLIER;
+        }
+        bus::publish(
+            game,
+            GameEvent::Attacked {
+                attacker: self.name.clone(),
+                target: target.name.clone(),
+                damage,
+                shot: false,
+                sneak,
+            },
+        );
+        if damage > 0 {
+            // make the target take some damage
+            bus::publish(
+                game,
+                GameEvent::DamageDealt {
+                    source: self.name.clone(),
+                    target: target.name.clone(),
+                    amount: damage,
+                },
+            );
+            target.take_damage_from(self, damage, game);
+            let blinding = self.fighter.map_or(0, |f| f.blinding);
+            if blinding > 0 && target.alive {
+                game.messages
+                    .add_in(Category::Combat, format!("{} is blinded!", target.name), LIGHT_VIOLET);
+                target.blind(blinding);
+            }
+            if let Some(status) = self.fighter.and_then(|f| f.inflicts) {
+                if target.alive {
+                    effects::apply(target, status, game);
+                }
+            }
+        } else {
+            target.provoke(game);
+        }
+    }
+
+    /// Turn an NPC that didn't mean the player any harm against them, for
+    /// good, and straight after them.
+    fn provoke(&mut self, game: &mut Game) {
+        if self.attitude == Attitude::Hostile {
+            return;
+        }
+        self.attitude = Attitude::Hostile;
+        self.faction = Faction::Monsters;
+        if self.ai.is_some() {
+            self.ai = Some(self.alerted_ai());
+        }
+        game.messages
+            .add_in(Category::Combat, format!("The {} turns on you!", self.name), ORANGE);
+    }
+
+    /// heal by the given amount, without going over the maximum
+    pub fn heal(&mut self, amount: i32, game: &Game) {
+        let max_hp = self.max_hp(game);
+        if let Some(ref mut fighter) = self.fighter {
+            fighter.hp += amount;
+            if fighter.hp > max_hp {
+                fighter.hp = max_hp;
+            }
+        }
+    }
+
+    /// Equip object and show a message about it
+    pub fn equip(&mut self, messages: &mut Messages) {
+        if self.item.is_none() {
+            messages.add_in(
+                Category::Items,
+                format!("Can't equip {:?} because it's not an Item.", self),
+                RED,
+            );
+            return;
+        }
+        if let Some(ref mut equipment) = self.equipment {
+            if !equipment.equipped {
+                equipment.equipped = true;
+                messages.add_in(
+                    Category::Items,
+                    format!("Equipped {} on {}.", self.name, equipment.worn_on()),
+                    LIGHT_GREEN,
+                );
+                if equipment.cursed {
+                    messages.add_in(
+                        Category::Items,
+                        format!("The {} is cursed, and clings to you!", self.name),
+                        RED,
+                    );
+                }
+            }
+        } else {
+            messages.add_in(
+                Category::Items,
+                format!("Can't equip {:?} because it's not an Equipment.", self),
+                RED,
+            );
+        }
+    }
+
+    /// Equip object and show a message about it
+    pub fn dequip(&mut self, messages: &mut Messages) {
+        if self.item.is_none() {
+            messages.add_in(
+                Category::Items,
+                format!("Can't dequip {:?} because it's not an Item.", self),
+                RED,
+            );
+            return;
+        }
+        if let Some(ref mut equipment) = self.equipment {
+            if equipment.equipped {
+                equipment.equipped = false;
+                messages.add_in(
+                    Category::Items,
+                    format!("Dequipped {} from {}.", self.name, equipment.worn_on()),
+                    LIGHT_YELLOW,
+                );
+            }
+        } else {
+            messages.add_in(
+                Category::Items,
+                format!("Can't dequip {:?} because it's not an Equipment.", self),
+                RED,
+            );
+        }
+    }
+
+    pub fn max_hp(&self, game: &Game) -> i32 {
+        let base_max_hp = self.fighter.map_or(0, |f| f.base_max_hp);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.max_hp_bonus)
+            .sum();
+        base_max_hp + bonus
+    }
+
+    pub fn power(&self, game: &Game) -> i32 {
+        let base_power = self.fighter.map_or(0, |f| f.base_power);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.power())
+            .sum();
+        let inspired = if self.has_effect(Effect::Inspired) {
+            effects::INSPIRED_POWER
+        } else {
+            0
+        };
+        base_power + bonus + inspired
+    }
+
+    pub fn defense(&self, game: &Game) -> i32 {
+        let base_defense = self.fighter.map_or(0, |f| f.base_defense);
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.defense())
+            .sum();
+        base_defense + bonus
+    }
+
+    /// the extra damage this object's hits do by burning, from what it wields
+    pub fn fire_damage(&self, game: &Game) -> i32 {
+        self.get_all_equipped(game).iter().map(|e| e.fire_damage).sum()
+    }
+
+    /// the total weight of everything this object has equipped
+    pub fn equipped_weight(&self, game: &Game) -> i32 {
+        self.get_all_equipped(game).iter().map(|e| e.weight).sum()
+    }
+
+    /// How hard this object is to notice: agility helps, heavy equipment
+    /// hurts, and sneaking helps a lot. Only the player sneaks, and only the
+    /// player has a class, which may help too.
+    pub fn stealth(&self, game: &Game) -> i32 {
+        let agility = self.fighter.map_or(0, |f| f.base_defense);
+        let weight = self.equipped_weight(game);
+        let sneak_bonus = if self.name == "player" && game.sneaking {
+            SNEAK_STEALTH_BONUS
+        } else {
+            0
+        };
+        let class_bonus = if self.name == "player" {
+            game.class.stealth_bonus()
+        } else {
+            0
+        };
+        BASE_STEALTH + 2 * agility - weight / 2 + sneak_bonus + class_bonus
+    }
+
+    pub fn is_blind(&self) -> bool {
+        self.fighter.map_or(false, |f| f.blind_turns > 0)
+    }
+
+    /// blind this fighter for at least the given number of turns
+    pub fn blind(&mut self, turns: i32) {
+        if let Some(fighter) = self.fighter.as_mut() {
+            fighter.blind_turns = cmp::max(fighter.blind_turns, turns);
+        }
+    }
+
+    /// how far this object's light reaches this turn, after equipment and
+    /// (for the player) potions; 0 if it gives off no light
+    pub fn light_radius(&self, game: &Game) -> i32 {
+        let base_radius = match self.light {
+            Some(_) if weather::douses_torches(game) => 0,
+            Some(light) => light.radius,
+            None => return 0,
+        };
+        let bonus: i32 = self
+            .get_all_equipped(game)
+            .iter()
+            .map(|e| e.light_bonus)
+            .sum();
+        let night_vision = if self.name == "player" && game.night_vision_turns > 0 {
+            NIGHT_VISION_BONUS
+        } else {
+            0
+        };
+        cmp::max(base_radius + bonus + night_vision, 0)
+    }
+
+    /// whether this object can sense monsters it can't see, from a potion
+    /// (only the player drinks those) or from something it wears
+    pub fn is_telepathic(&self, game: &Game) -> bool {
+        (self.name == "player" && game.telepathy_turns > 0)
+            || self.get_all_equipped(game).iter().any(|e| e.telepathic)
+    }
+
+    /// whether this monster knows the player is around
+    pub fn has_noticed_player(&self) -> bool {
+        !matches!(self.ai, None | Some(Ai::Asleep | Ai::Unaware | Ai::Follow))
+    }
+
+    /// returns a list of equipped items
+    pub fn get_all_equipped(&self, game: &Game) -> Vec<Equipment> {
+        if self.name == "player" {
+            game.inventory
+                .iter()
+                .filter(|item| item.equipment.map_or(false, |e| e.equipped))
+                .map(|item| item.equipment.unwrap())
+                .collect()
+        } else {
+            vec![] // other objects have no equipment
+        }
+    }
+}
+
+/// move by the given amount, if the destination is not blocked (or deep water
+/// the object won't swim in); walking into a closed door opens it instead
+fn move_by(id: usize, dx: i32, dy: i32, map: &mut Map, objects: &mut Objects) {
+    if objects[id].rooted {
+        return;
+    }
+    let (x, y) = objects[id].pos();
+    if map.get(x + dx, y + dy).map_or(false, |tile| tile.opens()) {
+        map[(x + dx, y + dy)].open_door();
+        objects[id].facing = (dx, dy);
+        return;
+    }
+    let into_water = map.get(x + dx, y + dy).map_or(false, |tile| tile.deep_water);
+    let refuses = into_water && id != PLAYER && !objects[id].swims;
+    if !is_blocked(x + dx, y + dy, map, objects) && !refuses {
+        objects[id].set_pos(x + dx, y + dy);
+        if (dx, dy) != (0, 0) {
+            objects[id].facing = (dx, dy);
+        }
+        // stepping onto a teleporter sends you to its twin, unless that's occupied
+        if let Some((to_x, to_y)) = map[(x + dx, y + dy)].teleport {
+            if (dx, dy) != (0, 0) && !is_blocked(to_x, to_y, map, objects) {
+                objects[id].set_pos(to_x, to_y);
+            }
+        }
+    }
+}
+
+/// a step onto an adjacent teleporter that lands closer to the target than walking would
+fn teleporter_shortcut(
+    id: usize,
+    (target_x, target_y): (i32, i32),
+    map: &Map,
+    objects: &Objects,
+) -> Option<(i32, i32)> {
+    let (x, y) = objects[id].pos();
+    let here = objects[id].distance(target_x, target_y);
+    DIRECTIONS.iter().copied().find(|&(dx, dy)| {
+        let to = map.get(x + dx, y + dy).and_then(|tile| tile.teleport);
+        to.map_or(false, |(to_x, to_y)| {
+            let there = (((to_x - target_x).pow(2) + (to_y - target_y).pow(2)) as f32).sqrt();
+            there + 1.0 < here
+                && !is_blocked(x + dx, y + dy, map, objects)
+                && !is_blocked(to_x, to_y, map, objects)
+        })
+    })
+}
+
+fn move_towards(id: usize, target_x: i32, target_y: i32, map: &mut Map, objects: &mut Objects) {
+    //vector from this object to the target, and distance
+    let dx = target_x - objects[id].x;
+    let dy = target_y - objects[id].y;
+    let distance = ((dx.pow(2) + dy.pow(2)) as f32).sqrt();
+
+    // normalize it to length 1 (preserving direction), then round it and
+    // convert to integer so the movement is restricted to the map grid
+    let dx = (dx as f32 / distance).round() as i32;
+    let dy = (dy as f32 / distance).round() as i32;
+    move_by(id, dx, dy, map, objects);
+}
+
+/// Mutably borrow two *separate* elements from the given slice.
+/// Panics when the indexes are equal or out of bounds.
+fn mut_two<T>(first_index: usize, second_index: usize, items: &mut [T]) -> (&mut T, &mut T) {
+    assert_ne!(first_index, second_index);
+    let split_at_index = cmp::max(first_index, second_index);
+    let (first_slice, second_slice) = items.split_at_mut(split_at_index);
+    if first_index < second_index {
+        (&mut first_slice[first_index], &mut second_slice[0])
+    } else {
+        (&mut second_slice[0], &mut first_slice[second_index])
+    }
+}
+
+fn ai_take_turn(monster_id: usize, game: &mut Game, objects: &mut Objects) {
+    use Ai::*;
+    if objects[monster_id].has_effect(Effect::Confusion) {
+        // move in a random direction, whatever it had in mind
+        let dx = game.rng.gen_range(-1..2);
+        let dy = game.rng.gen_range(-1..2);
+        move_by(monster_id, dx, dy, &mut game.map, objects);
+        return;
+    }
+    if objects[monster_id].has_effect(Effect::Charmed) {
+        // it fights for the player while the charm lasts, whatever it had in
+        // mind before
+        companions::fight_for_player(monster_id, game, objects);
+        return;
+    }
+    let npc = objects[monster_id].attitude != Attitude::Hostile
+        && objects[monster_id].ai != Some(Ai::Ally);
+    if npc {
+        // NPCs keep to themselves until they're given a reason not to
+        return;
+    }
+    if powers::use_powers(monster_id, game, objects) {
+        return;
+    }
+    let awake = !matches!(objects[monster_id].ai, None | Some(Asleep | Flee | Ally));
+    if awake && factions::fight_others(monster_id, game, objects) {
+        return;
+    }
+    if let Some(ai) = objects[monster_id].ai.take() {
+        let new_ai = match ai {
+            Asleep => Asleep,
+            Unaware => ai_unaware(monster_id, game, objects),
+            Follow => packs::ai_follow(monster_id, game, objects),
+            Basic => ai_basic(monster_id, game, objects),
+            Ranged => ai_ranged(monster_id, game, objects),
+            Flee => ai_flee(monster_id, game, objects),
+            Ally => companions::ai_ally(monster_id, game, objects),
+        };
+        objects[monster_id].ai = Some(new_ai);
+    }
+}
+
+/// An unaware monster stays put, now and then looking around, but every turn
+/// it can see the player within its vision cone it gets a perception check
+/// against the player's stealth: a d20 plus its perception, less half the
+/// distance between them.
+fn ai_unaware(monster_id: usize, game: &mut Game, objects: &mut Objects) -> Ai {
+    if game.rng.gen_bool(LOOK_AROUND_CHANCE) {
+        objects[monster_id].facing = *DIRECTIONS.choose(&mut game.rng).unwrap();
+    }
+
+    let (player_x, player_y) = objects[PLAYER].pos();
+    if !objects[monster_id].can_see(player_x, player_y, &game.map)
+        || !objects[monster_id].faces(player_x, player_y)
+        || objects[monster_id].is_blind()
+        || !objects[PLAYER].alive
+    {
+        return Ai::Unaware;
+    }
+    let monster = &objects[monster_id];
+    let perception = monster.fighter.map_or(0, |f| f.perception);
+    let distance = monster.distance_to(&objects[PLAYER]) as i32;
+    let roll = game.rng.gen_range(1..=20) + perception - distance / 2;
+    if roll > objects[PLAYER].stealth(game) {
+        game.messages
+            .add(format!("The {} notices you!", monster.name), ORANGE);
+        monster.alerted_ai()
+    } else {
+        Ai::Unaware
+    }
+}
+
+fn ai_basic(monster_id: usize, game: &mut Game, objects: &mut Objects) -> Ai {
+    // a basic monster takes its turn, chasing the player while it can see them
+    let (player_x, player_y) = objects[PLAYER].pos();
+    if objects[monster_id].is_blind() {
+        // lash out if the player is right there, otherwise blunder around
+        if objects[monster_id].distance_to(&objects[PLAYER]) < 2.0
+            && objects[PLAYER].fighter.map_or(false, |f| f.hp > 0)
+        {
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.attack(player, game);
+        } else {
+            let dx = game.rng.gen_range(-1..2);
+            let dy = game.rng.gen_range(-1..2);
+            move_by(monster_id, dx, dy, &mut game.map, objects);
+        }
+    } else if objects[monster_id].can_see(player_x, player_y, &game.map) {
+        if objects[monster_id].distance_to(&objects[PLAYER]) >= 2.0 {
+            // move towards player if far away, taking a teleporter if it's a
+            // shortcut, and finding a way around anything in between
+            let target = (player_x, player_y);
+            let step = teleporter_shortcut(monster_id, target, &game.map, objects).or_else(|| {
+                pathfinding::next_step(monster_id, target, &game.to_player, &game.map, objects)
+            });
+            match step {
+                Some((dx, dy)) => move_by(monster_id, dx, dy, &mut game.map, objects),
+                None => move_towards(monster_id, player_x, player_y, &mut game.map, objects),
+            }
+        } else if objects[PLAYER].fighter.map_or(false, |f| f.hp > 0) {
+            // close enough, attack! (if the player is still alive.)
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.facing = (player.x - monster.x, player.y - monster.y);
+            monster.attack(player, game);
+        }
+    } else if let Some((dx, dy)) = pathfinding::follow_path(monster_id, &game.map, objects) {
+        // out of sight, so head for where the player was last seen
+        move_by(monster_id, dx, dy, &mut game.map, objects);
+    }
+    Ai::Basic
+}
+
+/// A ranged monster backs away from the player to shoot from a distance, and
+/// only closes in, or fights hand to hand, when it has no clear shot.
+fn ai_ranged(monster_id: usize, game: &mut Game, objects: &mut Objects) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let aiming = objects[monster_id].can_see(player_x, player_y, &game.map)
+        && !objects[monster_id].is_blind()
+        && objects[PLAYER].fighter.map_or(false, |f| f.hp > 0);
+    if aiming {
+        let distance = objects[monster_id].distance_to(&objects[PLAYER]);
+        if distance < RANGED_KEEP_DISTANCE {
+            if let Some((dx, dy)) = game.to_player.uphill(monster_id, &game.map, objects) {
+                move_by(monster_id, dx, dy, &mut game.map, objects);
+                return Ai::Ranged;
+            }
+        }
+        let range = objects[monster_id].ranged.map_or(0, |ranged| ranged.range);
+        let from = (monster_x, monster_y);
+        let to = (player_x, player_y);
+        if distance >= 2.0
+            && distance <= range as f32
+            && projectile::clear_shot(from, to, &game.map, objects)
+        {
+            game.projectiles.push(Projectile {
+                path: projectile::line(from, to),
+                char: projectile::arrow((player_x - monster_x, player_y - monster_y)),
+                color: WHITE,
+            });
+            let (monster, player) = mut_two(monster_id, PLAYER, objects);
+            monster.facing = (player.x - monster.x, player.y - monster.y);
+            monster.shoot(player, game);
+            return Ai::Ranged;
+        }
+    }
+    // otherwise it gets closer, or fights, like any other monster
+    ai_basic(monster_id, game, objects);
+    Ai::Ranged
+}
+
+/// A fleeing monster runs as far from the player as it can, now and then
+/// crying out and rousing the monsters around it, until it's cornered and has
+/// to fight again.
+fn ai_flee(monster_id: usize, game: &mut Game, objects: &mut Objects) -> Ai {
+    let (monster_x, monster_y) = objects[monster_id].pos();
+    let in_view = game.map.is_in_view(monster_x, monster_y);
+    if game.rng.gen_bool(CRY_FOR_HELP_CHANCE) {
+        if in_view {
+            game.messages.add(
+                format!("The {} cries out for help!", objects[monster_id].name),
+                ORANGE,
+            );
+        }
+        game.noises.push(Noise {
+            x: monster_x,
+            y: monster_y,
+            loudness: noise::CRY_FOR_HELP_NOISE,
+        });
+    }
+
+    if objects[monster_id].is_blind() {
+        // run blindly
+        let dx = game.rng.gen_range(-1..2);
+        let dy = game.rng.gen_range(-1..2);
+        move_by(monster_id, dx, dy, &mut game.map, objects);
+        return Ai::Flee;
+    }
+    match game.to_player.uphill(monster_id, &game.map, objects) {
+        Some((dx, dy)) => {
+            move_by(monster_id, dx, dy, &mut game.map, objects);
+            Ai::Flee
+        }
+        None => {
+            // nowhere left to run
+            if in_view {
+                game.messages.add(
+                    format!("The {} turns to fight!", objects[monster_id].name),
+                    ORANGE,
+                );
+            }
+            ai_basic(monster_id, game, objects);
+            objects[monster_id].alerted_ai()
+        }
+    }
+}
+
+fn player_move_or_attack(dx: i32, dy: i32, game: &mut Game, objects: &mut Objects) {
+    // a confused player doesn't always go where they meant to
+    let (dx, dy) = if objects[PLAYER].has_effect(Effect::Confusion)
+        && game.rng.gen_bool(CONFUSED_STUMBLE_CHANCE)
+    {
+        game.messages.add("You stumble around in confusion.", LIGHT_GREY);
+        loop {
+            let (dx, dy) = (game.rng.gen_range(-1..2), game.rng.gen_range(-1..2));
+            if (dx, dy) != (0, 0) {
+                break (dx, dy);
+            }
+        }
+    } else {
+        (dx, dy)
+    };
+    // the coordinates the player is moving to/attacking
+    let x = objects[PLAYER].x + dx;
+    let y = objects[PLAYER].y + dy;
+
+    // try to find an attackable object there
+    let target_id = objects
+        .iter()
+        .position(|object| object.fighter.is_some() && object.pos() == (x, y));
+
+    // a locked door opens if the player has the key
+    if game.map.get(x, y).map_or(false, |tile| tile.locked) {
+        unlock(x, y, game);
+        return;
+    }
+
+    // attack target if found, move otherwise
+    match target_id {
+        Some(target_id) => {
+            let (player, target) = mut_two(PLAYER, target_id, objects);
+            player.attack(target, game);
+        }
+        None if objects[PLAYER].has_effect(Effect::Webbed) => {
+            game.messages
+                .add("You struggle against the web holding you.", LIGHT_GREY);
+        }
+        None => {
+            let (x, y) = objects[PLAYER].pos();
+            move_by(PLAYER, dx, dy, &mut game.map, objects);
+            if objects[PLAYER].distance(x, y) > 1.5 {
+                let msg = "The floor flares violet, and you are somewhere else!";
+                game.messages.add(msg, COLOR_TELEPORTER);
+            }
+            if objects[PLAYER].pos() != (x, y) {
+                traps::spring(game, objects);
+            }
+        }
+    }
+}
+
+/// walk, attack, or talk or trade with whoever stands in the way, if they
+/// don't mean the player harm, or swap places with a companion
+fn player_step(
+    dx: i32,
+    dy: i32,
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> PlayerAction {
+    let (x, y) = (objects[PLAYER].x + dx, objects[PLAYER].y + dy);
+    let trader = objects.iter_mut().find(|object| {
+        object.pos() == (x, y) && object.trader.is_some() && object.attitude != Attitude::Hostile
+    });
+    if let Some(trader) = trader {
+        if daylight::on_surface(game) && !daylight::shops_open(game.turn) {
+            let msg = format!("The {} has shut up shop for the night.", trader.name);
+            game.messages.add(msg, LIGHT_GREY);
+            return PlayerAction::DidntTakeTurn;
+        }
+        trade::trade(tcod, game, trader);
+        return PlayerAction::DidntTakeTurn;
+    }
+    let npc = objects.iter().find(|object| {
+        object.pos() == (x, y) && object.dialogue.is_some() && object.attitude != Attitude::Hostile
+    });
+    if let Some(npc) = npc {
+        let (name, speaker) = (npc.dialogue.clone().unwrap(), npc.name.clone());
+        dialogue::talk(tcod, game, &mut objects[PLAYER], &name, &speaker);
+        return PlayerAction::DidntTakeTurn;
+    }
+    // companions make way
+    let companion = objects
+        .iter()
+        .position(|object| object.pos() == (x, y) && companions::is_companion(object));
+    if let Some(companion) = companion {
+        if objects[PLAYER].has_effect(Effect::Webbed) {
+            game.messages
+                .add("You struggle against the web holding you.", LIGHT_GREY);
+        } else {
+            let (player_x, player_y) = objects[PLAYER].pos();
+            objects[companion].set_pos(player_x, player_y);
+            objects[PLAYER].set_pos(x, y);
+            traps::spring(game, objects);
+        }
+        return PlayerAction::TookTurn;
+    }
+    player_move_or_attack(dx, dy, game, objects);
+    PlayerAction::TookTurn
+}
+
+/// open the locked door at the given spot with its key, if the player has it
+fn unlock(x: i32, y: i32, game: &mut Game) {
+    if game.map[(x, y)].door.is_some() {
+        let key = game
+            .inventory
+            .iter()
+            .position(|item| item.item == Some(Item::Key) && item.name == map::DOOR_KEY);
+        match key {
+            Some(key) => {
+                game.inventory.remove(key);
+                game.map[(x, y)].open_door();
+                game.messages
+                    .add("You unlock the door with the key.", LIGHT_GREEN);
+            }
+            None => game.messages.add("The door is locked.", WHITE),
+        }
+        return;
+    }
+
+    // otherwise it's the vault door
+    let key = game
+        .inventory
+        .iter()
+        .position(|item| item.item == Some(Item::Key) && item.name != map::DOOR_KEY);
+    match key {
+        Some(key) => {
+            game.inventory.remove(key);
+            let vault_door = |tile: &map::Tile| tile.locked && tile.door.is_none();
+            for ((x, y), tile) in game.map.iter_mut().filter(|(_, tile)| vault_door(tile)) {
+                *tile = map::Tile::empty();
+                tile.explored = true;
+                // the heavy door grinds open
+                game.noises.push(Noise {
+                    x,
+                    y,
+                    loudness: noise::DOOR_NOISE,
+                });
+            }
+            game.messages
+                .add("You unlock the vault door with the key.", LIGHT_GREEN);
+        }
+        None => game.messages.add("The vault door is locked.", WHITE),
+    }
+}
+
+/// Look for hidden passages in the walls around the player. Each one found
+/// turns out to be a door. Returns whether any were.
+fn search_for_passages(game: &mut Game, objects: &Objects) -> bool {
+    let (x, y) = objects[PLAYER].pos();
+    let perception = objects[PLAYER].fighter.map_or(0, |f| f.perception);
+    let mut found = false;
+    for &(dx, dy) in DIRECTIONS.iter() {
+        let secret = game.map.get(x + dx, y + dy).map_or(false, |tile| tile.secret);
+        if secret && game.rng.gen_range(1..=20) + perception >= PASSAGE_DIFFICULTY {
+            game.map[(x + dx, y + dy)] = map::Tile::door();
+            game.map[(x + dx, y + dy)].explored = true;
+            game.messages.add("You find a hidden passage!", LIGHT_CYAN);
+            found = true;
+        }
+    }
+    found
+}
+
+/// Where the choices some items ask the player for come from: the mouse and
+/// menus in the game, or a script when playing headless.
+trait Chooser {
+    /// a spot on the map to use an item on, as allowed by `is_valid_target`,
+    /// or None if the player cancels; `blast` is the radius of whatever goes
+    /// off there, if anything does
+    fn choose_tile(
+        &mut self,
+        game: &mut Game,
+        objects: &Objects,
+        max_range: Option<f32>,
+        in_fov_only: bool,
+        blast: Option<i32>,
+    ) -> Option<(i32, i32)>;
+
+    /// which stat to raise: 0 is constitution, 1 strength, 2 agility and
+    /// 3 perception
+    fn choose_stat(&mut self, header: &str, fighter: &Fighter) -> usize;
+
+    /// an item from the inventory, or None if the player cancels
+    fn choose_item(&mut self, header: &str, game: &Game, player: &Object) -> Option<usize>;
+
+    /// the key for the action, to tell the player about, as
+    /// `Keybindings::key_for` has it
+    fn key_for(&self, action: Action) -> String {
+        Keybindings::default().key_for(action)
+    }
+}
+
+impl Chooser for Tcod {
+    fn choose_tile(
+        &mut self,
+        game: &mut Game,
+        objects: &Objects,
+        max_range: Option<f32>,
+        in_fov_only: bool,
+        blast: Option<i32>,
+    ) -> Option<(i32, i32)> {
+        targeting::target_tile(self, game, objects, max_range, in_fov_only, blast)
+    }
+
+    fn choose_stat(&mut self, header: &str, fighter: &Fighter) -> usize {
+        choose_stat(header, fighter, self)
+    }
+
+    fn choose_item(&mut self, header: &str, game: &Game, player: &Object) -> Option<usize> {
+        inventory_menu(game, player, header, self)
+    }
+
+    fn key_for(&self, action: Action) -> String {
+        self.keys.key_for(action)
+    }
+}
+
+/// whether an item can be used on the given spot: one in the player's FOV
+/// (or anywhere on the map unless `in_fov_only`), and in range if there is one
+fn is_valid_target(
+    pos: (i32, i32),
+    game: &Game,
+    objects: &Objects,
+    max_range: Option<f32>,
+    in_fov_only: bool,
+) -> bool {
+    target_problem(pos, game, objects, max_range, in_fov_only).is_none()
+}
+
+/// why an item can't be used on the given spot, if it can't
+fn target_problem(
+    (x, y): (i32, i32),
+    game: &Game,
+    objects: &Objects,
+    max_range: Option<f32>,
+    in_fov_only: bool,
+) -> Option<&'static str> {
+    let in_fov = game.map.is_visible(x, y) || (!in_fov_only && game.map.in_bounds(x, y));
+    let in_range = max_range.map_or(true, |range| objects[PLAYER].distance(x, y) <= range);
+    if !in_fov {
+        Some("You can't see there.")
+    } else if !in_range {
+        Some("That's out of range.")
+    } else {
+        None
+    }
+}
+
+/// Ask for a spot in the player's FOV, up to a range, for something shot or
+/// thrown there, or that hits everything within `blast` of where it lands,
+/// like a fireball or a thrown potion.
+fn target_area(
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &Objects,
+    what: &str,
+    max_range: Option<f32>,
+    blast: Option<i32>,
+) -> Option<(i32, i32)> {
+    game.messages.add(
+        format!("Pick a target tile for {}. {}", what, HOW_TO_AIM),
+        LIGHT_CYAN,
+    );
+    chooser.choose_tile(game, objects, max_range, true, blast)
+}
+
+/// the ids of everything that fights within `radius` of the given spot
+fn fighters_within(objects: &Objects, (x, y): (i32, i32), radius: i32) -> Vec<usize> {
+    objects
+        .iter()
+        .enumerate()
+        .filter(|(_, obj)| obj.fighter.is_some() && obj.distance(x, y) <= radius as f32)
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// returns a chosen monster inside FOV up to a range, or None if cancelled
+fn target_monster(
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &Objects,
+    max_range: Option<f32>,
+) -> Option<usize> {
+    loop {
+        match chooser.choose_tile(game, objects, max_range, true, None) {
+            Some((x, y)) => {
+                // return the first chosen monster, otherwise continue looping
+                for (id, obj) in objects.iter().enumerate() {
+                    if obj.pos() == (x, y) && obj.fighter.is_some() && id != PLAYER {
+                        return Some(id);
+                    }
+                }
+            }
+            None => return None,
+        }
+    }
+}
+
+/// add to the player's inventory (or purse) and remove from the map
+fn pick_item_up(object_id: usize, game: &mut Game, objects: &mut Objects) {
+    let was_burdened = encumbrance::is_burdened(game, &objects[PLAYER]);
+    if let Some(amount) = objects[object_id].gold {
+        objects.swap_remove(object_id);
+        game.gold += amount;
+        bus::publish(game, GameEvent::GoldPickedUp { amount });
+    } else if !encumbrance::can_lift(&objects[object_id], game, &objects[PLAYER]) {
+        game.messages.add_in(
+            Category::Items,
+            format!("The {} is too heavy to carry with all you have.", objects[object_id].name),
+            RED,
+        );
+    } else if carried_stack(&objects[object_id], &game.inventory).is_some() {
+        // it goes onto the stack of the same thing already carried
+        let item = objects.swap_remove(object_id);
+        let (name, shown_as) = (item.name.clone(), item.a_name(game));
+        bus::publish(game, GameEvent::ItemPickedUp { item: name, shown_as });
+        stow(item, &mut game.inventory);
+    } else if game.inventory.len() >= 26 {
+        game.messages.add_in(
+            Category::Items,
+            format!(
+                "Your inventory is full, cannot pick up {}.",
+                objects[object_id].name
+            ),
+            RED,
+        );
+    } else {
+        let item = objects.swap_remove(object_id);
+        let (name, shown_as) = (item.name.clone(), item.a_name(game));
+        bus::publish(game, GameEvent::ItemPickedUp { item: name, shown_as });
+        if item.item == Some(Item::Crown) {
+            game.messages
+                .add_in(Category::Items, "At last! Now to get it out of here alive.", LIGHT_YELLOW);
+        }
+        let index = game.inventory.len();
+        let is_equipment = item.equipment.is_some();
+        game.inventory.push(item);
+
+        // automatically equip, if the corresponding equipment slot is unused
+        if is_equipment {
+            fit_ring(index, &mut game.inventory);
+        }
+        if is_equipment && equipped_in_the_way(index, &game.inventory).is_empty() {
+            game.inventory[index].equip(&mut game.messages);
+        }
+    }
+    if !was_burdened && encumbrance::is_burdened(game, &objects[PLAYER]) {
+        game.messages
+            .add_in(Category::Items, "You are burdened by all you carry.", ORANGE);
+    }
+}
+
+/// where in the inventory there's already a stack the given item can go onto
+fn carried_stack(item: &Object, inventory: &[Object]) -> Option<usize> {
+    if !items::stacks(item) {
+        return None;
+    }
+    inventory.iter().position(|carried| {
+        carried.name == item.name && carried.owed == item.owed && carried.stolen == item.stolen
+    })
+}
+
+/// Put an item in the inventory, onto the stack of the same thing if there's
+/// already one.
+fn stow(item: Object, inventory: &mut Vec<Object>) {
+    match carried_stack(&item, inventory) {
+        Some(stack_id) => {
+            let stack = &mut inventory[stack_id];
+            stack.count = Some(stack.quantity() + item.quantity());
+        }
+        None => inventory.push(item),
+    }
+}
+
+/// Take some of a stack out of the inventory, or the whole of it (or of an
+/// item that doesn't stack) if that's all there is.
+fn take_from_inventory(inventory_id: usize, amount: i32, inventory: &mut Vec<Object>) -> Object {
+    let stack = &mut inventory[inventory_id];
+    if amount >= stack.quantity() {
+        return inventory.remove(inventory_id);
+    }
+    stack.count = Some(stack.quantity() - amount);
+    let mut taken = stack.clone();
+    taken.count = Some(amount);
+    // the rest of the stack keeps its ID
+    taken.id = None;
+    taken
+}
+
+enum UseResult {
+    UsedUp,
+    UsedAndKept,
+    Cancelled,
+}
+
+fn use_item(
+    inventory_id: usize,
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) {
+    use Item::*;
+    // just call the "use_function" if it is defined
+    if let Some(item) = game.inventory[inventory_id].item {
+        let result = match item {
+            Heal => cast_heal(game, objects),
+            Lightning => cast_lightning(game, objects),
+            Confuse => cast_confuse(chooser, game, objects),
+            Fireball => cast_fireball(chooser, game, objects),
+            Sword => toggle_equipment(inventory_id, game),
+            Shield => toggle_equipment(inventory_id, game),
+            Flash => cast_flash(game, objects),
+            Blindness => cast_blindness(game, objects),
+            Telepathy => cast_telepathy(game),
+            Amulet => toggle_equipment(inventory_id, game),
+            Clairvoyance => cast_clairvoyance(chooser, game, objects),
+            Lantern => toggle_equipment(inventory_id, game),
+            RingOfDimness => toggle_equipment(inventory_id, game),
+            Gear => toggle_equipment(inventory_id, game),
+            NightVision => cast_night_vision(game),
+            Retraining => cast_retraining(chooser, game, objects),
+            Food => eat_food(game),
+            Slow => cast_slow(chooser, game, objects),
+            Spellbook => {
+                let spell = items::teaches(&game.inventory[inventory_id]);
+                spells::learn(spell, game)
+            }
+            Key => {
+                game.messages
+                    .add_in(Category::Items, "Walk into a locked door to unlock it.", WHITE);
+                UseResult::Cancelled
+            }
+            Rock => {
+                let key = chooser.key_for(Action::Inventory(InventoryAction::Throw));
+                let msg = format!("Press {} to throw it at something.", key);
+                game.messages.add_in(Category::Items, msg, WHITE);
+                UseResult::Cancelled
+            }
+            Arrows => {
+                let key = chooser.key_for(Action::Fire);
+                let msg = format!("Equip a bow and press {} to fire them.", key);
+                game.messages.add_in(Category::Items, msg, WHITE);
+                UseResult::Cancelled
+            }
+            Identify => identify::read_identify(chooser, game, objects),
+            RemoveCurse => cast_remove_curse(game),
+            EnchantWeapon => cast_enchant_weapon(game),
+            EnchantArmor => cast_enchant_armor(game),
+            Crown => {
+                game.messages
+                    .add_in(
+                        Category::Items,
+                        "Carry it back up to daylight, and the kings' crown is yours.",
+                        WHITE,
+                    );
+                UseResult::Cancelled
+            }
+            Summon if companions::summon(game, objects) => UseResult::UsedUp,
+            Summon => UseResult::Cancelled,
+            Charm => cast_charm(chooser, game, objects),
+            Haste => cast_haste(game, objects),
+        };
+        finish_item_use(inventory_id, result, game);
+    } else {
+        game.messages.add_in(
+            Category::Items,
+            format!("The {} cannot be used.", game.inventory[inventory_id].name),
+            WHITE,
+        );
+    }
+}
+
+/// destroy or keep an item after it was used, depending on the outcome
+fn finish_item_use(inventory_id: usize, result: UseResult, game: &mut Game) {
+    if !matches!(result, UseResult::Cancelled) {
+        game.telemetry.record(&Record::ItemUsed {
+            turn: game.turn,
+            item: &game.inventory[inventory_id].name,
+        });
+        // using an item shows what it is
+        let item = game.inventory[inventory_id].clone();
+        identify::identify(&item, game);
+    }
+    match result {
+        UseResult::UsedUp => {
+            // destroy after use, unless it was cancelled for some reason
+            take_from_inventory(inventory_id, 1, &mut game.inventory);
+        }
+        UseResult::UsedAndKept => {} // do nothing
+        UseResult::Cancelled => {
+            game.messages.add_in(Category::Items, "Cancelled", WHITE);
+        }
+    }
+}
+
+/// eat the corpse with the given id: what happens depends on what it was,
+/// and how long it's been lying around
+fn eat_corpse(corpse_id: usize, game: &mut Game, objects: &mut Objects) {
+    let object = objects.swap_remove(corpse_id);
+    let corpse = match object.corpse {
+        Some(corpse) => corpse,
+        None => return,
+    };
+    game.messages
+        .add_in(Category::Items, format!("You eat the {}.", object.name), WHITE);
+
+    if game.turn - corpse.died_on_turn >= CORPSE_SPOIL_TURNS {
+        game.messages.add_in(Category::Items, "Ugh, it had gone bad! You feel sick.", RED);
+        let poison = StatusEffect {
+            effect: Effect::Poison,
+            turns: 2 * POISON_NUM_TURNS,
+        };
+        effects::apply(&mut objects[PLAYER], poison, game);
+        return;
+    }
+    nourish(CORPSE_NOURISHMENT, game);
+    match corpse.species.as_str() {
+        "troll" => {
+            let regeneration = StatusEffect {
+                effect: Effect::Regeneration,
+                turns: REGENERATION_NUM_TURNS,
+            };
+            effects::apply(&mut objects[PLAYER], regeneration, game);
+        }
+        "gloom bat" => {
+            game.messages
+                .add_in(Category::Items, "The shadows seem a little less deep.", LIGHT_VIOLET);
+            game.night_vision_turns = cmp::max(game.night_vision_turns, REGENERATION_NUM_TURNS);
+        }
+        "orc" if game.rng.gen_bool(ORC_POISON_CHANCE) => {
+            game.messages.add_in(Category::Items, "That orc meat didn't agree with you.", RED);
+            let poison = StatusEffect {
+                effect: Effect::Poison,
+                turns: POISON_NUM_TURNS,
+            };
+            effects::apply(&mut objects[PLAYER], poison, game);
+        }
+        _ => {
+            game.messages.add_in(Category::Items, "That hit the spot.", WHITE);
+        }
+    }
+}
+
+/// drop `amount` of a stack, or all of it, or an item that doesn't stack
+fn drop_item(inventory_id: usize, amount: i32, game: &mut Game, objects: &mut Objects) {
+    if game.inventory[inventory_id].is_stuck() {
+        let msg = format!("The {} won't come off!", game.inventory[inventory_id].name);
+        game.messages.add_in(Category::Items, msg, RED);
+        return;
+    }
+    let mut item = take_from_inventory(inventory_id, amount, &mut game.inventory);
+    if item.equipment.is_some() {
+        item.dequip(&mut game.messages);
+    }
+    let (x, y) = objects[PLAYER].pos();
+    if game.map[(x, y)].deep_water {
+        // gone for good
+        game.messages
+            .add_in(Category::Items, format!("The {} sinks out of sight.", item.name), YELLOW);
+        return;
+    }
+    item.set_pos(x, y);
+    game.messages
+        .add_in(Category::Items, format!("You dropped {}.", item.a_name(game)), YELLOW);
+    objects.push(item);
+}
+
+fn cast_heal(game: &mut Game, objects: &mut Objects) -> UseResult {
+    // heal the player
+    let player = &mut objects[PLAYER];
+    if let Some(fighter) = player.fighter {
+        if fighter.hp == player.max_hp(game) {
+            game.messages.add("You are already at full health.", RED);
+            return UseResult::Cancelled;
+        }
+        game.messages
+            .add("Your wounds start to feel better!", LIGHT_VIOLET);
+        player.heal(HEAL_AMOUNT, game);
+        return UseResult::UsedUp;
+    }
+    UseResult::Cancelled
+}
+
+fn cast_lightning(game: &mut Game, objects: &mut Objects) -> UseResult {
+    // find closest enemy (inside a maximum range) and damage it
+    let monster_id = closest_monster(&game.map, objects, LIGHTNING_RANGE);
+    if let Some(monster_id) = monster_id {
+        // zap it!
+        game.messages.add_in(
+            Category::Combat,
+            format!(
+                "A lightning bolt strikes the {} with a loud thunder! \
+            The damage is {} hit points.",
+                objects[monster_id].name, LIGHTNING_DAMAGE
+            ),
+            LIGHT_BLUE,
+        );
+        bus::publish(
+            game,
+            GameEvent::DamageDealt {
+                source: "lightning bolt".into(),
+                target: objects[monster_id].name.clone(),
+                amount: LIGHTNING_DAMAGE,
+            },
+        );
+        game.noises.push(Noise {
+            x: objects[monster_id].x,
+            y: objects[monster_id].y,
+            loudness: noise::SPELL_NOISE,
+        });
+        if let Some(xp) = objects[monster_id].take_damage(LIGHTNING_DAMAGE, game) {
+            objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+        }
+        UseResult::UsedUp
+    } else {
+        // no enemy found within maximum range
+        game.messages
+            .add_in(Category::Combat, "No enemy is close enough to strike.", RED);
+        UseResult::Cancelled
+    }
+}
+
+fn cast_magic_missile(game: &mut Game, objects: &mut Objects) -> UseResult {
+    let monster_id = match closest_monster(&game.map, objects, MAGIC_MISSILE_RANGE) {
+        Some(monster_id) => monster_id,
+        None => {
+            game.messages
+                .add_in(Category::Combat, "No enemy is close enough to strike.", RED);
+            return UseResult::Cancelled;
+        }
+    };
+    let damage = MAGIC_MISSILE_DAMAGE + objects[PLAYER].level * MAGIC_MISSILE_LEVEL_DAMAGE;
+    game.messages.add_in(
+        Category::Combat,
+        format!(
+            "A magic missile streaks into the {} for {} hit points.",
+            objects[monster_id].name, damage
+        ),
+        LIGHT_BLUE,
+    );
+    bus::publish(
+        game,
+        GameEvent::DamageDealt {
+            source: "magic missile".into(),
+            target: objects[monster_id].name.clone(),
+            amount: damage,
+        },
+    );
+    game.noises.push(Noise {
+        x: objects[monster_id].x,
+        y: objects[monster_id].y,
+        loudness: noise::SPELL_NOISE,
+    });
+    if let Some(xp) = objects[monster_id].take_damage(damage, game) {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+    }
+    UseResult::UsedUp
+}
+
+fn cast_confuse(
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> UseResult {
+    // ask the player for a target to confuse
+    game.messages.add_in(
+        Category::Combat,
+        format!("Pick an enemy to confuse. {}", HOW_TO_AIM),
+        LIGHT_CYAN,
+    );
+    let monster_id = target_monster(chooser, game, objects, Some(CONFUSE_RANGE as f32));
+    if let Some(monster_id) = monster_id {
+        let confusion = StatusEffect {
+            effect: Effect::Confusion,
+            turns: CONFUSE_NUM_TURNS,
+        };
+        effects::apply(&mut objects[monster_id], confusion, game);
+        UseResult::UsedUp
+    } else {
+        // no enemy found within maximum range
+        game.messages
+            .add_in(Category::Combat, "No enemy is close enough to strike.", RED);
+        UseResult::Cancelled
+    }
+}
+
+/// Win a monster over to the player's side for a while. Bosses are too
+/// strong-willed, and the scroll is wasted on them.
+fn cast_charm(chooser: &mut impl Chooser, game: &mut Game, objects: &mut Objects) -> UseResult {
+    game.messages.add_in(
+        Category::Combat,
+        format!("Pick an enemy to charm. {}", HOW_TO_AIM),
+        LIGHT_CYAN,
+    );
+    match target_monster(chooser, game, objects, Some(CHARM_RANGE as f32)) {
+        Some(monster_id) if objects[monster_id].boss => {
+            game.messages.add_in(
+                Category::Combat,
+                format!("The {} shrugs off the charm.", objects[monster_id].name),
+                ORANGE,
+            );
+            UseResult::UsedUp
+        }
+        Some(monster_id) => {
+            let charm = StatusEffect {
+                effect: Effect::Charmed,
+                turns: CHARM_NUM_TURNS,
+            };
+            effects::apply(&mut objects[monster_id], charm, game);
+            UseResult::UsedUp
+        }
+        None => {
+            game.messages
+                .add_in(Category::Combat, "No enemy is close enough to strike.", RED);
+            UseResult::Cancelled
+        }
+    }
+}
+
+fn cast_slow(chooser: &mut impl Chooser, game: &mut Game, objects: &mut Objects) -> UseResult {
+    game.messages.add_in(
+        Category::Combat,
+        format!("Pick an enemy to slow. {}", HOW_TO_AIM),
+        LIGHT_CYAN,
+    );
+    match target_monster(chooser, game, objects, Some(SLOW_RANGE as f32)) {
+        Some(monster_id) => {
+            let slow = StatusEffect {
+                effect: Effect::Slow,
+                turns: SLOW_NUM_TURNS,
+            };
+            effects::apply(&mut objects[monster_id], slow, game);
+            UseResult::UsedUp
+        }
+        None => {
+            game.messages
+                .add_in(Category::Combat, "No enemy is close enough to strike.", RED);
+            UseResult::Cancelled
+        }
+    }
+}
+
+fn cast_fireball(
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> UseResult {
+    // ask the player for a target tile to throw a fireball at
+    let blast = Some(FIREBALL_RADIUS);
+    let (x, y) = match target_area(chooser, game, objects, "the fireball", None, blast) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+    game.messages.add_in(
+        Category::Combat,
+        format!(
+            "The fireball explodes, burning everything within {} tiles!",
+            FIREBALL_RADIUS
+        ),
+        ORANGE,
+    );
+    game.noises.push(Noise {
+        x,
+        y,
+        loudness: noise::SPELL_NOISE,
+    });
+
+    let mut xp_to_gain = 0;
+    for id in fighters_within(objects, (x, y), FIREBALL_RADIUS) {
+        let obj = &mut objects[id];
+        game.messages.add_in(
+            Category::Combat,
+            format!(
+                "The {} gets burned for {} hit points.",
+                obj.name, FIREBALL_DAMAGE
+            ),
+            ORANGE,
+        );
+        bus::publish(
+            game,
+            GameEvent::DamageDealt {
+                source: "fireball".into(),
+                target: obj.name.clone(),
+                amount: FIREBALL_DAMAGE,
+            },
+        );
+        if let Some(xp) = obj.take_damage(FIREBALL_DAMAGE, game) {
+            if id != PLAYER {
+                // Don't reward the player for burning themself!
+                xp_to_gain += xp;
+            }
+        } else if obj.alive {
+            let burn = StatusEffect {
+                effect: Effect::Burn,
+                turns: BURN_NUM_TURNS,
+            };
+            effects::apply(obj, burn, game);
+        }
+    }
+    objects[PLAYER].fighter.as_mut().unwrap().xp += xp_to_gain;
+
+    // the flames leave a pall of smoke behind
+    game.map.add_smoke(x, y, FIREBALL_SMOKE_RADIUS, FIREBALL_SMOKE_TURNS);
+
+    UseResult::UsedUp
+}
+
+fn cast_flash(game: &mut Game, objects: &mut Objects) -> UseResult {
+    // blind every monster close enough to see the flash; the player shields their eyes
+    game.messages
+        .add("A blinding flash of light fills the room!", WHITE);
+    let (player_x, player_y) = objects[PLAYER].pos();
+    for (id, obj) in objects.iter_mut().enumerate() {
+        if id != PLAYER
+            && obj.fighter.is_some()
+            && obj.distance(player_x, player_y) <= FLASH_RADIUS as f32
+            && game.map.is_in_view(obj.x, obj.y)
+        {
+            game.messages
+                .add(format!("The {} is blinded!", obj.name), LIGHT_GREEN);
+            obj.blind(FLASH_NUM_TURNS);
+        }
+    }
+    UseResult::UsedUp
+}
+
+fn cast_blindness(game: &mut Game, objects: &mut Objects) -> UseResult {
+    game.messages
+        .add("Darkness falls over your eyes!", LIGHT_VIOLET);
+    objects[PLAYER].blind(BLINDNESS_NUM_TURNS);
+    UseResult::UsedUp
+}
+
+fn cast_telepathy(game: &mut Game) -> UseResult {
+    game.messages
+        .add("Your mind expands to sense the creatures around you.", LIGHT_VIOLET);
+    game.telepathy_turns = cmp::max(game.telepathy_turns, TELEPATHY_NUM_TURNS);
+    UseResult::UsedUp
+}
+
+/// forget every stat raised on levelling up, and choose them all again
+fn cast_retraining(
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> UseResult {
+    if game.stat_choices.is_empty() {
+        game.messages.add("You have nothing to unlearn yet.", RED);
+        return UseResult::Cancelled;
+    }
+    let fighter = objects[PLAYER].fighter.as_mut().unwrap();
+    for &choice in game.stat_choices.iter().rev() {
+        lower_stat(fighter, choice);
+    }
+    game.messages.add(
+        "Your training fades from memory, and you are free to learn anew.",
+        LIGHT_VIOLET,
+    );
+
+    let count = game.stat_choices.len();
+    game.stat_choices.clear();
+    for number in 1..=count {
+        let header = format!("Retraining ({} of {}): choose a stat to raise: \n", number, count);
+        let choice = chooser.choose_stat(&header, fighter);
+        raise_stat(fighter, choice);
+        game.stat_choices.push(choice);
+    }
+    UseResult::UsedUp
+}
+
+fn eat_food(game: &mut Game) -> UseResult {
+    game.messages.add_in(Category::Items, "You eat a ration. That was filling.", WHITE);
+    nourish(RATION_NOURISHMENT, game);
+    UseResult::UsedUp
+}
+
+/// how full the player is in a game saved before they could get hungry
+fn well_fed() -> i32 {
+    MAX_SATIATION
+}
+
+fn nourish(amount: i32, game: &mut Game) {
+    game.satiation = cmp::min(game.satiation + amount, MAX_SATIATION);
+}
+
+/// how hungry the player is, for the panel; none if they aren't
+fn hunger(satiation: i32) -> Option<&'static str> {
+    if satiation <= 0 {
+        Some("Starving")
+    } else if satiation < WEAK_SATIATION {
+        Some("Weak")
+    } else if satiation < HUNGRY_SATIATION {
+        Some("Hungry")
+    } else {
+        None
+    }
+}
+
+/// The player gets hungrier every turn, and once there's nothing left in
+/// them, starts to starve.
+fn get_hungrier(game: &mut Game, player: &mut Object) {
+    if !player.alive {
+        return;
+    }
+    let before = hunger(game.satiation);
+    game.satiation = cmp::max(game.satiation - 1, 0);
+    let after = hunger(game.satiation);
+    if after != before {
+        match after {
+            Some("Hungry") => game.messages.add("You are getting hungry.", LIGHT_YELLOW),
+            Some("Weak") => game.messages.add("You feel weak with hunger.", ORANGE),
+            Some(_) => game.messages.add("You are starving!", RED),
+            None => {}
+        }
+    }
+    if game.satiation == 0 {
+        bus::publish(
+            game,
+            GameEvent::DamageDealt {
+                source: "starvation".into(),
+                target: player.name.clone(),
+                amount: STARVATION_DAMAGE,
+            },
+        );
+        player.take_damage(STARVATION_DAMAGE, game);
+    }
+}
+
+/// lift the curse from everything the player carries, so it can come off
+fn cast_remove_curse(game: &mut Game) -> UseResult {
+    let mut stuck = false;
+    for item in game.inventory.iter_mut() {
+        stuck |= item.is_stuck();
+        if let Some(equipment) = item.equipment.as_mut() {
+            equipment.cursed = false;
+        }
+    }
+    if stuck {
+        game.messages
+            .add_in(Category::Items, "You feel as if someone is helping you.", LIGHT_VIOLET);
+    } else {
+        game.messages
+            .add_in(Category::Items, "You feel as if you need some help.", LIGHT_VIOLET);
+    }
+    UseResult::UsedUp
+}
+
+/// make the weapon the player wields better
+fn cast_enchant_weapon(game: &mut Game) -> UseResult {
+    let weapon = game.inventory.iter().position(|item| {
+        item.equipment.map_or(false, |e| e.equipped && e.is_weapon())
+    });
+    match weapon {
+        Some(weapon) => enchant(weapon, game),
+        None => game.messages.add_in(Category::Items, "Your hands twitch.", LIGHT_VIOLET),
+    }
+    UseResult::UsedUp
+}
+
+/// make a piece of the armor the player wears better, picked at random
+fn cast_enchant_armor(game: &mut Game) -> UseResult {
+    let worn: Vec<usize> = (0..game.inventory.len())
+        .filter(|&id| {
+            game.inventory[id]
+                .equipment
+                .map_or(false, |e| e.equipped && !e.is_weapon() && e.defense_bonus > 0)
+        })
+        .collect();
+    match worn.choose(&mut game.rng) {
+        Some(&armor) => enchant(armor, game),
+        None => game.messages.add_in(Category::Items, "Your skin glows then fades.", LIGHT_VIOLET),
+    }
+    UseResult::UsedUp
+}
+
+/// Enchant a piece of equipment once more, which lifts any curse on it too,
+/// unless it's already as enchanted as it can get.
+fn enchant(inventory_id: usize, game: &mut Game) {
+    let item = &mut game.inventory[inventory_id];
+    let equipment = item.equipment.as_mut().unwrap();
+    if equipment.enchantment >= MAX_ENCHANTMENT {
+        let msg = format!("Your {} shudders, but holds no more magic.", item.name);
+        game.messages.add_in(Category::Items, msg, LIGHT_VIOLET);
+        return;
+    }
+    equipment.enchantment += 1;
+    equipment.cursed = false;
+    let msg = format!("Your {} glows blue for a moment.", item.name);
+    game.messages.add_in(Category::Items, msg, LIGHT_VIOLET);
+}
+
+fn cast_haste(game: &mut Game, objects: &mut Objects) -> UseResult {
+    let haste = StatusEffect {
+        effect: Effect::Haste,
+        turns: HASTE_NUM_TURNS,
+    };
+    effects::apply(&mut objects[PLAYER], haste, game);
+    UseResult::UsedUp
+}
+
+fn cast_night_vision(game: &mut Game) -> UseResult {
+    game.messages
+        .add_in(Category::Items, "Your eyes adjust, and the darkness recedes.", LIGHT_VIOLET);
+    game.night_vision_turns = cmp::max(game.night_vision_turns, NIGHT_VISION_NUM_TURNS);
+    UseResult::UsedUp
+}
+
+fn cast_clairvoyance(
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> UseResult {
+    // ask the player for any spot on the map to look at from afar
+    game.messages.add_in(
+        Category::Items,
+        format!("Pick anywhere on the map to look there. {}", HOW_TO_AIM),
+        LIGHT_CYAN,
+    );
+    let (x, y) = match chooser.choose_tile(game, objects, None, false, None) {
+        Some(tile_pos) => tile_pos,
+        None => return UseResult::Cancelled,
+    };
+    game.messages
+        .add_in(Category::Items, "A vision of a distant place forms in your mind.", LIGHT_VIOLET);
+
+    // reveal the layout around it, walls and all, and the items lying there
+    let radius = CLAIRVOYANCE_RADIUS;
+    let in_vision = |(tile_x, tile_y): (i32, i32)| {
+        (tile_x - x).pow(2) + (tile_y - y).pow(2) <= radius.pow(2)
+    };
+    for (pos, tile) in game.map.iter_mut() {
+        if in_vision(pos) {
+            tile.explored = true;
+        }
+    }
+    remember_objects(&mut game.map, objects, |pos, _| in_vision(pos));
+    UseResult::UsedUp
+}
+
+fn toggle_equipment(inventory_id: usize, game: &mut Game) -> UseResult {
+    let equipment = match game.inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return UseResult::Cancelled,
+    };
+    if equipment.equipped {
+        if equipment.cursed {
+            let msg = format!("The {} won't come off!", game.inventory[inventory_id].name);
+            game.messages.add_in(Category::Items, msg, RED);
+            return UseResult::Cancelled;
+        }
+        game.inventory[inventory_id].dequip(&mut game.messages);
+    } else {
+        // if the slot is already being used, dequip whatever is there first,
+        // unless it won't come off
+        fit_ring(inventory_id, &mut game.inventory);
+        let in_the_way = equipped_in_the_way(inventory_id, &game.inventory);
+        if let Some(&stuck) = in_the_way.iter().find(|&&id| game.inventory[id].is_stuck()) {
+            let msg = format!(
+                "You can't equip the {} while the {} won't come off.",
+                game.inventory[inventory_id].name, game.inventory[stuck].name
+            );
+            game.messages.add_in(Category::Items, msg, RED);
+            return UseResult::Cancelled;
+        }
+        for current in in_the_way {
+            game.inventory[current].dequip(&mut game.messages);
+        }
+        game.inventory[inventory_id].equip(&mut game.messages);
+    }
+    UseResult::UsedAndKept
+}
+
+/// Put a ring that's about to go on, on whichever hand has a finger free.
+fn fit_ring(inventory_id: usize, inventory: &mut [Object]) {
+    let slot = match inventory[inventory_id].equipment {
+        Some(equipment) => equipment.slot,
+        None => return,
+    };
+    if let Some(other) = slot.other_ring() {
+        let taken = get_equipped_in_slot(slot, inventory).is_some();
+        if taken && get_equipped_in_slot(other, inventory).is_none() {
+            inventory[inventory_id].equipment.as_mut().unwrap().slot = other;
+        }
+    }
+}
+
+/// the equipped items that would have to come off before the given one can
+/// go on: whatever is in its slot, and both hands for a two-handed weapon
+fn equipped_in_the_way(inventory_id: usize, inventory: &[Object]) -> Vec<usize> {
+    let equipment = match inventory[inventory_id].equipment {
+        Some(equipment) => equipment,
+        None => return vec![],
+    };
+    inventory
+        .iter()
+        .enumerate()
+        .filter(|&(id, item)| {
+            id != inventory_id
+                && item
+                    .equipment
+                    .map_or(false, |e| e.equipped && e.blocks(&equipment))
+        })
+        .map(|(id, _)| id)
+        .collect()
+}
+
+/// what's worn in each slot, a line each, for the character screen
+fn describe_equipment(game: &Game) -> String {
+    let lines: Vec<String> = EQUIPMENT_SLOTS
+        .iter()
+        .map(|&slot| {
+            // a two-handed weapon fills both hands
+            let worn = game.inventory.iter().find(|item| {
+                item.equipment.map_or(false, |e| {
+                    e.equipped && (e.slot == slot || (e.two_handed && slot.is_hand()))
+                })
+            });
+            let slot = slot.to_string();
+            let slot = slot[..1].to_uppercase() + &slot[1..];
+            let worn = worn.map_or("-".into(), |item| item.display_name(game));
+            format!("{}: {}", slot, worn)
+        })
+        .collect();
+    lines.join("\n")
+}
+
+fn get_equipped_in_slot(slot: Slot, inventory: &[Object]) -> Option<usize> {
+    for (inventory_id, item) in inventory.iter().enumerate() {
+        if item
+            .equipment
+            .as_ref()
+            .map_or(false, |e| e.equipped && e.slot == slot)
+        {
+            return Some(inventory_id);
+        }
+    }
+    None
+}
+
+/// ask which stat to raise, until the player picks one
+fn choose_stat(header: &str, fighter: &Fighter, tcod: &mut Tcod) -> usize {
+    let mut choice = None;
+    while choice.is_none() {
+        // keep asking until a choice is made
+        choice = menu(
+            header,
+            &[
+                format!("Constitution (+20 HP, from {})", fighter.base_max_hp),
+                format!("Strength (+1 attack and load, from {})", fighter.base_power),
+                format!("Agility (+1 defense, from {})", fighter.base_defense),
+                format!("Perception (+2 perception, from {})", fighter.perception),
+            ],
+            LEVEL_SCREEN_WIDTH,
+            tcod,
+        );
+    }
+    choice.unwrap()
+}
+
+/// if the player's experience is enough, raise their level, with whatever
+/// their class gains from that, and return true; the caller then picks the
+/// stat to raise with `raise_stat`
+fn gain_level(player: &mut Object, class: Class, messages: &mut Messages) -> bool {
+    let level_up_xp = LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR;
+    // see if the player's experience is enough to level-up
+    if player.fighter.as_ref().map_or(0, |f| f.xp) >= level_up_xp {
+        // it is! level up
+        player.level += 1;
+        messages.add(
+            format!(
+                "Your battle skills grow stronger! You reached level {}!",
+                player.level
+            ),
+            YELLOW,
+        );
+        let fighter = player.fighter.as_mut().unwrap();
+        fighter.xp -= level_up_xp;
+        class.level_up(fighter);
+        true
+    } else {
+        false
+    }
+}
+
+/// apply a level-up choice: 0 is constitution, 1 strength, 2 agility and
+/// 3 perception
+fn raise_stat(fighter: &mut Fighter, choice: usize) {
+    match choice {
+        0 => {
+            fighter.base_max_hp += 20;
+            fighter.hp += 20;
+        }
+        1 => {
+            fighter.base_power += 1;
+            fighter.strength += 1;
+        }
+        2 => {
+            fighter.base_defense += 1;
+        }
+        3 => {
+            fighter.perception += 2;
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// undo a level-up choice made with `raise_stat`
+fn lower_stat(fighter: &mut Fighter, choice: usize) {
+    match choice {
+        0 => {
+            fighter.base_max_hp -= 20;
+            fighter.hp = cmp::max(fighter.hp - 20, 1);
+        }
+        1 => {
+            fighter.base_power -= 1;
+            fighter.strength -= 1;
+        }
+        2 => {
+            fighter.base_defense -= 1;
+        }
+        3 => {
+            fighter.perception -= 2;
+        }
+        _ => unreachable!(),
+    }
+}
+
+/// find closest enemy, up to a maximum range, that the player can see
+fn closest_monster(map: &Map, objects: &Objects, max_range: i32) -> Option<usize> {
+    let mut closest_enemy = None;
+    let mut closest_dist = (max_range + 1) as f32; // start with (slightly more than) maximum range
+
+    for (id, object) in objects.iter().enumerate() {
+        if (id != PLAYER)
+            && object.fighter.is_some()
+            && object.ai.is_some()
+            && factions::are_enemies(&objects[PLAYER], object)
+            && map.is_visible(object.x, object.y)
+        {
+            // calculate distance between this object and the player
+            let dist = objects[PLAYER].distance_to(object);
+            if dist < closest_dist {
+                // it's closer, so remember it
+                closest_enemy = Some(id);
+                closest_dist = dist;
+            }
+        }
+    }
+    closest_enemy
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Game {
+    /// what the player called their character
+    #[serde(default = "unnamed")]
+    character_name: String,
+    #[serde(default)]
+    class: Class,
+    map: Map,
+    messages: Messages,
+    inventory: Vec<Object>,
+    dungeon_level: u32,
+    /// the levels the player has been on and left
+    #[serde(default)]
+    dungeon: Dungeon,
+    #[serde(default)]
+    turn: u32,
+    /// the player is moving slowly and quietly
+    #[serde(default)]
+    sneaking: bool,
+    /// turns left of the player sensing every monster on the level
+    #[serde(default)]
+    telepathy_turns: i32,
+    /// the spells the player knows
+    #[serde(default)]
+    spells: Vec<Spell>,
+    /// what the player's abilities draw on
+    #[serde(default = "abilities::rested")]
+    stamina: i32,
+    /// the abilities the player has to wait to use again
+    #[serde(default)]
+    cooldowns: Vec<Cooldown>,
+    /// turns left of the player seeing further in the dark
+    #[serde(default)]
+    night_vision_turns: i32,
+    /// turns the player has been held under by heavy gear while swimming
+    #[serde(default)]
+    breath_held: i32,
+    /// how much the player has in them; they starve once it runs out
+    #[serde(default = "well_fed")]
+    satiation: i32,
+    /// the turn on which the next level-wide event happens; 0 if not scheduled yet
+    #[serde(default)]
+    next_event_turn: u32,
+    /// the stat the player raised on each level-up so far, as given to `raise_stat`
+    #[serde(default)]
+    stat_choices: Vec<usize>,
+    /// story flags set by conversations
+    #[serde(default)]
+    flags: BTreeSet<String>,
+    /// what the player's companions have been told to do
+    #[serde(default)]
+    order: Order,
+    /// whether the player has climbed back out with the crown
+    #[serde(default)]
+    won: bool,
+    #[serde(default)]
+    gold: i32,
+    /// what the town thinks of the player: nothing much to start with, and
+    /// less for every theft; its shops charge more the lower it is
+    #[serde(default)]
+    reputation: i32,
+    /// the day this daily challenge run was seeded with, if it is one
+    #[serde(default)]
+    daily: Option<u64>,
+    /// the seed the game started from
+    #[serde(default)]
+    seed: u64,
+    /// what each kind of potion and scroll looks like this run, by its real
+    /// name; kinds not in here are known on sight
+    #[serde(default)]
+    appearances: BTreeMap<String, String>,
+    /// the kinds of item the player has learned the names of
+    #[serde(default)]
+    identified: BTreeSet<String>,
+    /// what's happened over the run, for its morgue file
+    #[serde(default)]
+    stats: RunStats,
+    /// every random roll made during the game comes from here, so that a game
+    /// can be replayed from its seed. Loaded games are reseeded.
+    #[serde(skip, default = "StdRng::from_entropy")]
+    rng: StdRng,
+    #[serde(skip)]
+    telemetry: Telemetry,
+    /// where to post the result of the run, if anywhere
+    #[serde(skip)]
+    leaderboard: Option<Leaderboard>,
+    /// the run being posted to the leaderboard, while it's on its way
+    #[serde(skip)]
+    posting: leaderboard::Request<()>,
+    #[serde(skip)]
+    bestiary: Bestiary,
+    /// set while playing the tutorial, which is never saved
+    #[serde(skip)]
+    tutorial: Option<Tutorial>,
+    /// the slot the game is saved in; none for a game that isn't saved
+    #[serde(skip)]
+    save_slot: Option<usize>,
+    /// how far every tile is from the player, for monsters to find their way
+    #[serde(skip)]
+    to_player: DijkstraMap,
+    /// shots fired during the monsters' turn, still to be shown
+    #[serde(skip)]
+    projectiles: Vec<Projectile>,
+    /// what's happened since the last turn began or ended, still to be told
+    /// to whatever listens
+    #[serde(skip)]
+    events: Vec<GameEvent>,
+    /// noises made since the monsters last acted, still to be heard
+    #[serde(skip)]
+    noises: Vec<Noise>,
+    /// items dropped by monsters that died since the monsters last acted,
+    /// still to be put on the map
+    #[serde(skip)]
+    dropped: Vec<Object>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum PlayerAction {
+    TookTurn,
+    DidntTakeTurn,
+    /// open the inventory, to do something with an item
+    Inventory(InventoryAction),
+    /// keep resting or searching, turn after turn
+    Busy(Activity),
+    Exit,
+}
+
+fn get_names_under_mouse((x, y): (i32, i32), objects: &Objects, game: &Game) -> String {
+    // create a list with names of all objects at the mouse's coordinates and in FOV
+    let names = objects
+        .iter()
+        .filter(|obj| obj.pos() == (x, y) && game.map.is_visible(obj.x, obj.y))
+        .map(|obj| match direction_name(obj.facing) {
+            // monsters show which way they're looking
+            Some(direction) if obj.ai.is_some() => {
+                format!("{} (facing {})", obj.name, direction)
+            }
+            _ => obj.display_name(game),
+        })
+        .collect::<Vec<_>>();
+
+    names.join(", ") // join the names, separated by commas
+}
+
+fn direction_name((dx, dy): (i32, i32)) -> Option<&'static str> {
+    match (dx.signum(), dy.signum()) {
+        (0, -1) => Some("north"),
+        (1, -1) => Some("north-east"),
+        (1, 0) => Some("east"),
+        (1, 1) => Some("south-east"),
+        (0, 1) => Some("south"),
+        (-1, 1) => Some("south-west"),
+        (-1, 0) => Some("west"),
+        (-1, -1) => Some("north-west"),
+        _ => None,
+    }
+}
+
+fn menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, tcod: &mut Tcod) -> Option<usize> {
+    show_menu(header, options, width, tcod);
+    let key = tcod.backend.wait_for_keypress();
+
+    // convert the ASCII code to an index; if it corresponds to an option, return it
+    if key.printable.is_alphabetic() {
+        let index = key.printable.to_ascii_lowercase() as usize - 'a' as usize;
+        if index < options.len() {
+            Some(index)
+        } else {
+            None
+        }
+    } else {
+        None
+    }
+}
+
+/// draw a menu's window over the screen and present it, without waiting for a choice
+fn show_menu<T: AsRef<str>>(header: &str, options: &[T], width: i32, tcod: &mut Tcod) {
+    assert!(
+        options.len() <= 26,
+        "Cannot have a menu with more than 26 options."
+    );
+
+    // calculate total height for the header (after auto-wrap) and one line per option
+    let header_height = if header.is_empty() {
+        0
+    } else {
+        tcod.screen.get_height_rect(0, 0, width, SCREEN_HEIGHT, header)
+    };
+    let height = options.len() as i32 + header_height;
+
+    // create an off-screen console that represents the menu's window
+    let mut window = Offscreen::new(width, height);
+
+    // print the header, with auto-wrap
+    window.set_default_foreground(WHITE);
+    window.print_rect_ex(
+        0,
+        0,
+        width,
+        height,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        header,
+    );
+
+    // print all the options
+    for (index, option_text) in options.iter().enumerate() {
+        let menu_letter = (b'a' + index as u8) as char;
+        let text = format!("({}) {}", menu_letter, option_text.as_ref());
+        window.print_ex(
+            0,
+            header_height + index as i32,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            text,
+        );
+    }
+
+    // blit the contents of "window" to the screen
+    let x = SCREEN_WIDTH / 2 - width / 2;
+    let y = SCREEN_HEIGHT / 2 - height / 2;
+    blit(&window, (0, 0), (width, height), &mut tcod.screen, (x, y), 1.0, 0.7);
+    tcod.mark_map((x, y), (width, height), false);
+
+    // present the screen to the player
+    tcod.flush();
+}
+
+fn inventory_menu(game: &Game, player: &Object, header: &str, tcod: &mut Tcod) -> Option<usize> {
+    let inventory = &game.inventory;
+    // under the header, how much the player carries and can carry
+    let header = format!(
+        "{}Load: {} of {}\n",
+        header,
+        encumbrance::load(game),
+        encumbrance::capacity(player)
+    );
+
+    // show a menu with each item of the inventory as an option
+    let options = if inventory.is_empty() {
+        vec!["Inventory is empty.".into()]
+    } else {
+        inventory
+            .iter()
+            .map(|item| {
+                // show additional information, in case it's equipped
+                match item.equipment {
+                    Some(equipment) if equipment.equipped => {
+                        format!("{} (on {})", item.display_name(game), equipment.worn_on())
+                    }
+                    _ => item.display_name(game),
+                }
+            })
+            .collect()
+    };
+
+    let inventory_index = menu(&header, &options, INVENTORY_WIDTH, tcod);
+
+    // if an item was chosen, return it
+    if !inventory.is_empty() {
+        inventory_index
+    } else {
+        None
+    }
+}
+
+/// what the player does with the key they pressed
+fn handle_keys(tcod: &mut Tcod, game: &mut Game, objects: &mut Objects) -> PlayerAction {
+    match actions::from_key(tcod.key, &tcod.keys) {
+        Some(action) => actions::perform(action, tcod, game, objects),
+        None => PlayerAction::DidntTakeTurn,
+    }
+}
+
+fn player_on_stairs(objects: &Objects) -> bool {
+    player_on(dungeon::DOWN_STAIRS, objects)
+}
+
+/// whether the player stands on an object with the given name
+fn player_on(name: &str, objects: &Objects) -> bool {
+    objects
+        .iter()
+        .any(|object| object.pos() == objects[PLAYER].pos() && object.name == name)
+}
+
+/// Advance to the next level, unless its boss still lives. The FOV map has to
+/// be rebuilt for the new map afterwards.
+fn next_level(game: &mut Game, objects: &mut Objects) {
+    if let Some(boss) = objects.iter().find(|object| object.boss && object.alive) {
+        game.messages.add(
+            format!("The stairs are sealed while the {} lives.", boss.name),
+            RED,
+        );
+        return;
+    }
+    dungeon::descend(game, objects);
+}
+
+fn render_bar(
+    panel: &mut Offscreen,
+    x: i32,
+    y: i32,
+    total_width: i32,
+    name: &str,
+    value: i32,
+    maximum: i32,
+    bar_color: Color,
+    back_color: Color,
+) {
+    // render a bar (HP, experience, etc). First calculate the width of the bar
+    let bar_width = (value as f32 / maximum as f32 * total_width as f32) as i32;
+    // a value over the maximum, like a load too heavy, fills it and no more
+    let bar_width = cmp::min(bar_width, total_width);
+
+    // render the background first
+    panel.set_default_background(back_color);
+    panel.rect(x, y, total_width, 1, false, BackgroundFlag::Screen);
+
+    // now render the bar on top
+    panel.set_default_background(bar_color);
+    if bar_width > 0 {
+        panel.rect(x, y, bar_width, 1, false, BackgroundFlag::Screen);
+    }
+
+    // finally, some centered text with teh values
+    panel.set_default_foreground(WHITE);
+    panel.print_ex(
+        x + total_width / 2,
+        y,
+        BackgroundFlag::None,
+        TextAlignment::Center,
+        &format!("{}: {}/{}", name, value, maximum),
+    );
+}
+
+/// where the player is, as the panel names it
+fn place_name(game: &Game) -> String {
+    if daylight::on_surface(game) {
+        let time = daylight::time_of_day(game.turn).name();
+        match weather::around(game).describe() {
+            Some(weather) => format!("Town, {}, {}", time, weather),
+            None => format!("Town, {}", time),
+        }
+    } else if game.dungeon_level == 0 {
+        "Town".to_string()
+    } else {
+        format!("{} level: {}", game.dungeon.branch.title(), game.dungeon_level)
+    }
+}
+
+fn render_all(tcod: &mut Tcod, game: &mut Game, objects: &Objects, fov_recompute: bool) {
+    if fov_recompute {
+        // recompute FOV if needed (the player moved or something)
+        compute_visibility(&mut tcod.fov, game, objects);
+    }
+
+    // show the part of the map around the player
+    tcod.camera = Camera::centered_on(objects[PLAYER].pos(), &game.map);
+    render_objects_to_console(tcod, game, objects);
+    render_map(tcod, game);
+    weather::draw(&mut tcod.con, game, tcod.camera, tcod.frame);
+
+    blit(
+        &tcod.con,
+        (0, 0),
+        (VIEW_WIDTH, VIEW_HEIGHT),
+        &mut tcod.screen,
+        (0, 0),
+        1.0,
+        1.0,
+    );
+    tcod.mark_map((0, 0), (VIEW_WIDTH, VIEW_HEIGHT), true);
+
+    render_panel(tcod, game, objects);
+
+    blit(
+        &tcod.panel,
+        (0, 0),
+        (SCREEN_WIDTH, PANEL_HEIGHT),
+        &mut tcod.screen,
+        (0, PANEL_Y),
+        1.0,
+        1.0,
+    );
+}
+
+fn render_objects_to_console(tcod: &mut Tcod, game: &mut Game, objects: &Objects) {
+    let camera = tcod.camera;
+    let on_screen = |((x, y), tile)| camera.to_screen(x, y).map(|pos| (pos, tile));
+
+    // teleporters the player has come across
+    for ((x, y), tile) in game.map.iter().filter_map(on_screen) {
+        if tile.explored && tile.teleport.is_some() {
+            let color = if tile.visible {
+                COLOR_TELEPORTER
+            } else {
+                lerp(BLACK, COLOR_TELEPORTER, REMEMBERED_BRIGHTNESS)
+            };
+            tcod.con.set_default_foreground(color);
+            tcod.con.put_char(x, y, '^', BackgroundFlag::None);
+        }
+    }
+
+    for ((x, y), tile) in game.map.iter().filter_map(on_screen) {
+        if let Some(door) = tile.door.filter(|_| tile.explored) {
+            let color = if tile.visible {
+                COLOR_DOOR
+            } else {
+                lerp(BLACK, COLOR_DOOR, REMEMBERED_BRIGHTNESS)
+            };
+            let char = if door == Door::Open { '\'' } else { '+' };
+            tcod.con.set_default_foreground(color);
+            tcod.con.put_char(x, y, char, BackgroundFlag::None);
+        }
+    }
+
+    // items and features seen before are drawn dimmed where they were last seen
+    for ((x, y), tile) in game.map.iter().filter_map(on_screen) {
+        if let Some((char, color)) = tile.remembered {
+            if !tile.visible {
+                tcod.con
+                    .set_default_foreground(lerp(BLACK, color, REMEMBERED_BRIGHTNESS));
+                tcod.con.put_char(x, y, char, BackgroundFlag::None);
+            }
+        }
+    }
+
+    let mut to_draw: Vec<_> = objects
+        .iter()
+        .filter(|o| game.map.is_visible(o.x, o.y) && !o.is_hidden())
+        .collect();
+    // sort so that non-blocking objects come first
+    to_draw.sort_by(|o1, o2| o1.blocks.cmp(&o2.blocks));
+    // draw the objects in the list
+    for object in &to_draw {
+        object.draw(&mut tcod.con, camera);
+    }
+
+    // monsters out of sight but sensed through telepathy; the terrain around
+    // them stays hidden
+    if objects[PLAYER].is_telepathic(game) {
+        for object in objects {
+            let sensed = object.ai.is_some()
+                && object.fighter.is_some()
+                && !game.map.is_visible(object.x, object.y);
+            if let Some((x, y)) = camera.to_screen(object.x, object.y).filter(|_| sensed) {
+                tcod.con.set_default_foreground(COLOR_SENSED);
+                tcod.con.put_char(x, y, object.char, BackgroundFlag::None);
+            }
+        }
+    }
+}
+
+fn render_map(tcod: &mut Tcod, game: &Game) {
+    let palette = game.dungeon.branch.palette();
+    let view = (0..VIEW_HEIGHT).flat_map(|y| (0..VIEW_WIDTH).map(move |x| (x, y)));
+    for (x, y) in view {
+        let (map_x, map_y) = tcod.camera.to_map(x, y);
+        let tile = match game.map.get(map_x, map_y) {
+            Some(tile) => tile,
+            None => continue,
+        };
+        let (dark, light) = if tile.locked {
+            (COLOR_DARK_VAULT_DOOR, COLOR_LIGHT_VAULT_DOOR)
+        } else if tile.block_sight {
+            (palette.dark_wall, palette.light_wall)
+        } else if tile.deep_water {
+            (COLOR_DARK_WATER, COLOR_LIGHT_WATER)
+        } else {
+            (palette.dark_ground, palette.light_ground)
+        };
+        let color = if tile.visible {
+            // inside fov, brighter the closer it is to a light
+            lerp(dark, light, tile.light)
+        } else {
+            dark
+        };
+        // smoke greys out whatever's seen through it
+        let color = if tile.visible && tile.smoke > 0 {
+            lerp(color, COLOR_SMOKE, 0.6)
+        } else {
+            color
+        };
+        if tile.explored {
+            tcod.con
+                .set_char_background(x, y, color, BackgroundFlag::Set);
+        }
+    }
+}
+
+/// what the player is up to, or suffering from, as the panel and the
+/// character screen list it
+fn player_status(game: &Game, player: &Object) -> Vec<String> {
+    let mut status: Vec<String> = vec![];
+    if game.sneaking {
+        status.push("Sneaking".into());
+    }
+    if player.is_blind() {
+        status.push("Blind".into());
+    }
+    if player.is_telepathic(game) {
+        status.push("Telepathic".into());
+    }
+    if game.night_vision_turns > 0 {
+        status.push("Night vision".into());
+    }
+    for effect in player.effects.iter() {
+        status.push(effect.effect.name().into());
+    }
+    if let Some(hunger) = hunger(game.satiation) {
+        status.push(hunger.into());
+    }
+    if encumbrance::is_burdened(game, player) {
+        status.push("Burdened".into());
+    }
+    if game.breath_held > 0 {
+        let breath = cmp::max(MAX_BREATH - game.breath_held, 0);
+        status.push(format!("Breath: {}", breath));
+    }
+    for cooldown in game.cooldowns.iter() {
+        status.push(format!("{}: {}", cooldown.ability.name(), cooldown.turns));
+    }
+    status
+}
+
+fn render_panel(tcod: &mut Tcod, game: &mut Game, objects: &Objects) {
+    // prepare to render the GUI panel
+    tcod.panel.set_default_background(BLACK);
+    tcod.panel.clear();
+
+    // show the player's stats
+    let hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+    let max_hp = objects[PLAYER].max_hp(game);
+    render_bar(
+        &mut tcod.panel,
+        1,
+        1,
+        BAR_WIDTH,
+        "HP",
+        hp,
+        max_hp,
+        LIGHT_RED,
+        DARKER_RED,
+    );
+    render_bar(
+        &mut tcod.panel,
+        1,
+        2,
+        BAR_WIDTH,
+        "Stamina",
+        game.stamina,
+        MAX_STAMINA,
+        LIGHT_GREEN,
+        DARKER_GREEN,
+    );
+    let (mana, max_mana) = objects[PLAYER].fighter.map_or((0, 0), |f| (f.mana, f.max_mana));
+    render_bar(
+        &mut tcod.panel,
+        1,
+        3,
+        BAR_WIDTH,
+        "Mana",
+        mana,
+        max_mana,
+        LIGHT_BLUE,
+        DARKER_BLUE,
+    );
+    render_bar(
+        &mut tcod.panel,
+        1,
+        4,
+        BAR_WIDTH,
+        "Load",
+        encumbrance::load(game),
+        encumbrance::capacity(&objects[PLAYER]),
+        LIGHT_SEPIA,
+        DARKER_SEPIA,
+    );
+
+    tcod.panel.print_ex(
+        1,
+        6,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        place_name(game),
+    );
+
+    // show what the player is up to, or suffering from
+    let status = player_status(game, &objects[PLAYER]);
+    tcod.panel.set_default_foreground(LIGHT_GREY);
+    tcod.panel.print_ex(
+        1,
+        5,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        status.join(", "),
+    );
+
+    // list the nearest monsters in view, marking those that have noticed the player
+    let mut enemies: Vec<_> = objects
+        .iter()
+        .filter(|o| {
+            o.ai.is_some()
+                && o.fighter.is_some()
+                && factions::are_enemies(&objects[PLAYER], o)
+                && game.map.is_visible(o.x, o.y)
+        })
+        .collect();
+    enemies.sort_by(|a, b| {
+        let a = objects[PLAYER].distance_to(a);
+        let b = objects[PLAYER].distance_to(b);
+        a.partial_cmp(&b).unwrap()
+    });
+    for (line, enemy) in enemies.iter().take(ENEMY_LIST_HEIGHT).enumerate() {
+        let (marker, color) = if enemy.has_noticed_player() {
+            ('!', LIGHT_RED)
+        } else if enemy.ai == Some(Ai::Asleep) {
+            ('z', DARK_GREY)
+        } else {
+            ('?', LIGHT_GREY)
+        };
+        tcod.panel.set_default_foreground(color);
+        tcod.panel.print_ex(
+            1,
+            7 + line as i32,
+            BackgroundFlag::None,
+            TextAlignment::Left,
+            format!("{} {}", marker, enemy.name),
+        );
+    }
+
+    // print the game messages, one line at a time
+    let mut y = MSG_HEIGHT as i32;
+    for message in game.messages.iter().rev() {
+        let msg = &message.text;
+        let msg_height = tcod.panel.get_height_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+        y -= msg_height;
+        if y < 0 {
+            break;
+        }
+        tcod.panel.set_default_foreground(message.color);
+        tcod.panel.print_rect(MSG_X, y, MSG_WIDTH, 0, msg);
+    }
+
+    // display names of objects under the mouse
+    tcod.panel.set_default_foreground(LIGHT_GREY);
+    tcod.panel.print_ex(
+        1,
+        0,
+        BackgroundFlag::None,
+        TextAlignment::Left,
+        get_names_under_mouse(tcod.mouse_on_map(), objects, game),
+    );
+}
+
+fn new_game(class: Class, tcod: &mut Tcod) -> (Game, Objects) {
+    let seed = StdRng::from_entropy().gen();
+    let (mut game, objects) = new_game_state(StdRng::seed_from_u64(seed), class);
+    game.seed = seed;
+    initialise_fov(tcod, &game.map);
+    (game, objects)
+}
+
+/// Create a player of the given class and the first level, using `rng` for
+/// everything random that happens in the game from then on.
+fn new_game_state(mut rng: StdRng, class: Class) -> (Game, Objects) {
+    // create object representing the player
+    let mut player = Object::new(0, 0, '@', "player", WHITE, true);
+    player.alive = true;
+    player.faction = Faction::Player;
+    player.light = Some(LightSource {
+        radius: TORCH_RADIUS,
+    });
+    player.fighter = Some(class.fighter());
+
+    // the list of objects with just the player
+    let mut objects = Objects::from(vec![player]);
+
+    let mut game = Game {
+        character_name: unnamed(),
+        class,
+        // the game starts in town (the map is not drawn to the screen yet)
+        map: town::make_town(&mut objects, &mut rng),
+        messages: Messages::new(),
+        inventory: vec![],
+        dungeon_level: 0,
+        dungeon: Dungeon::default(),
+        turn: 0,
+        sneaking: false,
+        telepathy_turns: 0,
+        spells: class.starting_spells(),
+        stamina: MAX_STAMINA,
+        cooldowns: vec![],
+        night_vision_turns: 0,
+        breath_held: 0,
+        satiation: MAX_SATIATION,
+        next_event_turn: 0,
+        stat_choices: vec![],
+        flags: BTreeSet::new(),
+        order: Order::Follow,
+        won: false,
+        gold: 0,
+        reputation: 0,
+        daily: None,
+        seed: 0,
+        appearances: BTreeMap::new(),
+        identified: BTreeSet::new(),
+        stats: RunStats::default(),
+        rng,
+        telemetry: Telemetry::default(),
+        leaderboard: None,
+        posting: Default::default(),
+        bestiary: Bestiary::default(),
+        tutorial: None,
+        save_slot: None,
+        to_player: DijkstraMap::default(),
+        projectiles: vec![],
+        events: vec![],
+        noises: vec![],
+        dropped: vec![],
+    };
+
+    game.inventory = class.starting_items();
+    game.appearances = identify::shuffle_looks(&mut game.rng);
+
+    // a warm welcoming message!
+    game.messages.add(
+        "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.",
+        RED,
+    );
+
+    (game, objects)
+}
+
+fn initialise_fov(tcod: &mut Tcod, map: &Map) {
+    tcod.fov = FovMap::new(map.width(), map.height());
+    set_fov_map(&mut tcod.fov, map);
+
+    // unexplored areas start black (which is the default background color)
+    tcod.con.clear();
+}
+
+/// Recompute what the player can see: light from every light source, then the
+/// player's line of sight, which only reveals lit tiles, and is unlimited
+/// except out in the dark. A blind player only makes out the tiles right next
+/// to them. Everything seen becomes explored.
+fn compute_visibility(fov: &mut impl Fov, game: &mut Game, objects: &Objects) {
+    // smoke comes and goes, so what blocks sight has to be refreshed first
+    set_fov_map(fov, &game.map);
+    if daylight::on_surface(game) {
+        game.map.ambient_light = daylight::ambient_light(game.turn);
+    }
+    let lights: Vec<_> = objects
+        .iter()
+        .map(|object| (object.pos(), object.light_radius(game)))
+        .collect();
+    // the dark and the weather both cut how far the player can see
+    let sight = [daylight::sight_radius(game), weather::sight_radius(game)]
+        .into_iter()
+        .filter(|&radius| radius > 0)
+        .min()
+        .unwrap_or(0);
+    let map = &mut game.map;
+    light::compute_lighting(fov, map, &lights);
+    let player = &objects[PLAYER];
+    let blind = player.is_blind();
+    fov.compute(player.x, player.y, sight);
+    for ((x, y), tile) in map.iter_mut() {
+        tile.in_view = fov.is_in_fov(x, y) && tile.light > 0.0;
+        tile.visible = if blind {
+            (x - player.x).abs() <= 1 && (y - player.y).abs() <= 1
+        } else {
+            tile.in_view
+        };
+        if tile.visible {
+            tile.explored = true;
+        }
+    }
+    remember_objects(map, objects, |_, tile| tile.visible);
+
+    let seen_monsters = objects
+        .iter()
+        .filter(|o| o.fighter.is_some() && o.ai.is_some() && map.is_visible(o.x, o.y));
+    for monster in seen_monsters {
+        game.bestiary.saw(monster);
+    }
+}
+
+/// Update what the player remembers lying on every tile for which `seen`
+/// returns true: the item or feature there, if any. Monsters move about, so
+/// they aren't remembered.
+fn remember_objects(
+    map: &mut Map,
+    objects: &Objects,
+    seen: impl Fn((i32, i32), &map::Tile) -> bool,
+) {
+    for (pos, tile) in map.iter_mut() {
+        if seen(pos, tile) {
+            tile.remembered = None;
+        }
+    }
+    for object in objects.iter().filter(|o| o.fighter.is_none() && !o.is_hidden()) {
+        let seen = map.get(object.x, object.y).map_or(false, |tile| seen(object.pos(), tile));
+        if seen {
+            map[(object.x, object.y)].remembered = Some((object.char, object.color));
+        }
+    }
+}
+
+/// create the FOV map, according to the generated map and the smoke on it
+fn set_fov_map(fov: &mut impl Fov, map: &Map) {
+    for ((x, y), _) in map.iter() {
+        fov.set_transparent(x, y, map.is_transparent(x, y));
+    }
+}
+
+/// everything that happens in a turn, once the player has acted: the
+/// monsters act and the turn is recorded
+fn end_player_turn(game: &mut Game, objects: &mut Objects) {
+    objects.extend(game.dropped.drain(..));
+    game.to_player = DijkstraMap::new(objects[PLAYER].pos(), &game.map);
+    bus::dispatch(game, objects);
+    trade::catch_thieves(game, objects);
+    traps::notice(game, objects);
+    noise::spread(game, objects);
+    speed::monsters_act(game, objects);
+    swim(game, objects);
+    get_hungrier(game, &mut objects[PLAYER]);
+    tick_status_effects(game, objects);
+    rot_corpses(game, objects);
+    breed(game, objects);
+    spawn_wanderer(game, objects);
+    game.map.thin_smoke();
+    events::run_events(game, objects);
+    bus::dispatch(game, objects);
+    game.turn += 1;
+    daylight::pass_time(game);
+    weather::pass_time(game, &objects[PLAYER]);
+    record_turn(game, &objects[PLAYER]);
+    if !objects[PLAYER].alive {
+        record_run_ended(game, &objects[PLAYER], "died");
+    }
+}
+
+/// Swimming is free, unless the player's gear is heavy enough to drag them
+/// under: then they hold their breath for a few turns, and start drowning.
+fn swim(game: &mut Game, objects: &mut Objects) {
+    let player = &mut objects[PLAYER];
+    let sinking = player.alive
+        && game.map[(player.x, player.y)].deep_water
+        && player.equipped_weight(game) >= HEAVY_GEAR_WEIGHT;
+    if !sinking {
+        if game.breath_held > 0 {
+            game.messages.add("You catch your breath.", LIGHT_BLUE);
+        }
+        game.breath_held = 0;
+        return;
+    }
+
+    game.breath_held += 1;
+    if game.breath_held == 1 {
+        game.messages
+            .add("Your heavy gear drags you under the water!", LIGHT_BLUE);
+    }
+    if game.breath_held > MAX_BREATH {
+        game.messages.add("You are drowning!", RED);
+        bus::publish(
+            game,
+            GameEvent::DamageDealt {
+                source: "drowning".into(),
+                target: player.name.clone(),
+                amount: DROWNING_DAMAGE,
+            },
+        );
+        player.take_damage(DROWNING_DAMAGE, game);
+    }
+}
+
+/// corpses that have just gone bad look (and smell) it, and those that have
+/// lain around long enough rot away to nothing
+fn rot_corpses(game: &mut Game, objects: &mut Objects) {
+    for object in objects.iter_mut() {
+        // turns can pass without this running, so it goes by age, not the
+        // exact turn
+        let corpse = match object.corpse.as_mut() {
+            Some(corpse) if !corpse.spoiled => corpse,
+            _ => continue,
+        };
+        if game.turn - corpse.died_on_turn >= CORPSE_SPOIL_TURNS {
+            corpse.spoiled = true;
+            object.name = format!("rotten {}", object.name);
+            object.color = DARKER_RED;
+        }
+    }
+
+    let turn = game.turn;
+    let age = |object: &Object| object.corpse.as_ref().map_or(0, |c| turn - c.died_on_turn);
+    for object in objects.iter().filter(|&object| age(object) >= CORPSE_DECAY_TURNS) {
+        if game.map.is_in_view(object.x, object.y) {
+            game.messages
+                .add(format!("The {} rot away.", object.name), DARKER_RED);
+        }
+    }
+    objects.retain(|object| age(object) < CORPSE_DECAY_TURNS);
+}
+
+/// Breeders the player can't see may spawn a copy of themselves next to them,
+/// until their species fills up the level.
+fn breed(game: &mut Game, objects: &mut Objects) {
+    for id in 0..objects.len() {
+        let parent = &objects[id];
+        if !parent.breeds || !parent.alive || game.map.is_in_view(parent.x, parent.y) {
+            continue;
+        }
+        let kin = objects
+            .iter()
+            .filter(|object| object.alive && object.name == parent.name)
+            .count();
+        if kin >= MAX_BREEDERS_PER_LEVEL || !game.rng.gen_bool(BREED_CHANCE) {
+            continue;
+        }
+
+        let free: Vec<(i32, i32)> = DIRECTIONS
+            .iter()
+            .map(|&(dx, dy)| (parent.x + dx, parent.y + dy))
+            .filter(|&(x, y)| {
+                !is_blocked(x, y, &game.map, objects)
+                    && (parent.swims || !game.map[(x, y)].deep_water)
+            })
+            .collect();
+        if let Some(&(x, y)) = free.choose(&mut game.rng) {
+            let mut child = parent.clone();
+            child.id = None;
+            child.target = None;
+            child.set_pos(x, y);
+            child.ai = Some(Ai::Unaware);
+            if let Some(fighter) = child.fighter.as_mut() {
+                fighter.hp = fighter.base_max_hp;
+                fighter.blind_turns = 0;
+            }
+            objects.push(child);
+        }
+    }
+}
+
+/// Now and then, the more often the deeper the level, a monster wanders in
+/// somewhere the player has explored but can't see right now, and comes
+/// looking for them. Staying put too long only brings more of them. In
+/// town, they only come by night.
+fn spawn_wanderer(game: &mut Game, objects: &mut Objects) {
+    let level = game.dungeon_level;
+    if game.tutorial.is_some() {
+        return;
+    }
+    let chance = match level {
+        0 => daylight::wanderer_chance(game.turn),
+        _ => map::from_dungeon_level(WANDERER_CHANCE, level),
+    };
+    if game.rng.gen_range(0..1000) >= chance {
+        return;
+    }
+    let monsters = objects.iter().filter(|o| o.alive && o.ai.is_some()).count();
+    if monsters >= MAX_MONSTERS_PER_LEVEL {
+        return;
+    }
+
+    let player = &objects[PLAYER];
+    let spots: Vec<(i32, i32)> = game
+        .map
+        .iter()
+        .filter(|&((x, y), tile)| {
+            tile.explored
+                && !tile.blocked
+                && !tile.deep_water
+                && !tile.in_view
+                && player.distance(x, y) >= WANDERER_MIN_DISTANCE
+        })
+        .map(|(pos, _)| pos)
+        .filter(|&(x, y)| !is_blocked(x, y, &game.map, objects))
+        .collect();
+    let (x, y) = match spots.choose(&mut game.rng) {
+        Some(&spot) => spot,
+        None => return,
+    };
+    let mut monster = match level {
+        0 => daylight::wanderer(x, y, &mut game.rng),
+        _ => map::random_monster(x, y, level, game.dungeon.branch, &mut game.rng),
+    };
+    monster.ai = Some(Ai::Basic);
+    let towards_player = pathfinding::Path::towards(player.pos());
+    objects.push(monster);
+    let monster = objects.id_of(objects.len() - 1);
+    objects.paths.insert(monster, towards_player);
+}
+
+/// count down every fighter's blindness and the player's telepathy and night vision
+fn tick_status_effects(game: &mut Game, objects: &mut Objects) {
+    abilities::tick(game);
+    spells::tick(game, &mut objects[PLAYER]);
+    if game.telepathy_turns > 0 {
+        game.telepathy_turns -= 1;
+        if game.telepathy_turns == 0 {
+            game.messages
+                .add("You no longer sense the creatures around you.", LIGHT_VIOLET);
+        }
+    }
+    if game.night_vision_turns > 0 {
+        game.night_vision_turns -= 1;
+        if game.night_vision_turns == 0 {
+            game.messages
+                .add("The darkness closes in around you again.", LIGHT_VIOLET);
+        }
+    }
+    effects::tick(game, objects);
+
+    for (id, object) in objects.iter_mut().enumerate() {
+        if let Some(fighter) = object.fighter.as_mut() {
+            if fighter.blind_turns > 0 {
+                fighter.blind_turns -= 1;
+                if fighter.blind_turns == 0 {
+                    if id == PLAYER {
+                        game.messages.add("You can see again.", LIGHT_GREEN);
+                    } else if game.map.is_visible(object.x, object.y) {
+                        game.messages
+                            .add(format!("The {} can see again.", object.name), RED);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// append the player's end-of-turn state to the telemetry log
+fn record_turn(game: &mut Game, player: &Object) {
+    if !game.telemetry.is_enabled() {
+        return;
+    }
+    let max_hp = player.max_hp(game);
+    game.telemetry.record(&Record::Turn {
+        turn: game.turn,
+        dungeon_level: game.dungeon_level,
+        hp: player.fighter.map_or(0, |f| f.hp),
+        max_hp,
+        xp: player.fighter.map_or(0, |f| f.xp),
+        level: player.level,
+    });
+}
+
+/// The player made it out with the crown: tell the story, score the run and
+/// put its save away, since there's no more playing it.
+fn win_game(tcod: &mut Tcod, game: &mut Game, objects: &Objects) {
+    record_run_ended(game, &objects[PLAYER], "won");
+    let story = match morgue::write(game, &objects[PLAYER]) {
+        Ok(path) => format!("The story of your run is told in {}.", path.display()),
+        Err(e) => format!("Could not write the morgue file: {}", e),
+    };
+    let msg = format!(
+        "\nYou step out into the daylight with the crown of the ancient kings.\n\n\
+         The tombs are behind you, and their treasure is yours.\n\n\
+         You win, with a score of {}!\n\n{}\n{}",
+        score(game, &objects[PLAYER]),
+        story,
+        if game.posting.is_waiting() {
+            "\nYour run is being posted to the leaderboard.\n"
+        } else {
+            ""
+        }
+    );
+    msgbox(&msg, VICTORY_WIDTH, tcod);
+    record_high_score(game, &objects[PLAYER], tcod);
+    if let Some(slot) = game.save_slot {
+        if let Err(e) = save::delete(slot) {
+            msgbox(&format!("\nCould not delete the save: {}.\n", e), 50, tcod);
+        }
+    }
+}
+
+/// a run's score: all the experience the player earned and the gold they
+/// carry, plus a bonus for every level descended, down to the deepest, and a
+/// big one for winning
+fn score(game: &Game, player: &Object) -> i32 {
+    let spent_on_levels: i32 = (1..player.level)
+        .map(|level| LEVEL_UP_BASE + level * LEVEL_UP_FACTOR)
+        .sum();
+    let xp = player.fighter.map_or(0, |f| f.xp);
+    let deepest = game.dungeon.deepest(game.dungeon_level);
+    let depth_bonus = deepest.saturating_sub(1) as i32 * SCORE_PER_DUNGEON_LEVEL;
+    let victory_bonus = if game.won { VICTORY_BONUS } else { 0 };
+    spent_on_levels + xp + game.gold + depth_bonus + victory_bonus
+}
+
+/// put a finished run in the high score table, and show the table
+fn record_high_score(game: &Game, player: &Object, tcod: &mut Tcod) {
+    let rank = highscores::record(highscores::HighScore {
+        name: game.character_name.clone(),
+        score: score(game, player),
+        xp: player.fighter.map_or(0, |f| f.xp),
+        depth: game.dungeon.deepest(game.dungeon_level),
+        gold: game.gold,
+        turns: game.turn,
+        cause: game
+            .telemetry
+            .last_damage_to_player()
+            .filter(|_| !game.won)
+            .map(String::from),
+        won: game.won,
+    });
+    let footer = if rank.is_none() {
+        "\nThis run didn't make the table.\n"
+    } else {
+        ""
+    };
+    show_high_scores(rank, footer, tcod);
+}
+
+/// show the high score table, marking the run at `highlight` if there is one
+fn show_high_scores(highlight: Option<usize>, footer: &str, tcod: &mut Tcod) {
+    let scores = highscores::load();
+    let mut msg = "\nHigh scores\n\n".to_string();
+    if scores.is_empty() {
+        msg += "No runs have ended yet.\n";
+    } else {
+        msg += "     Name                  Score     XP Depth  Gold  Turns\n";
+    }
+    for (rank, entry) in scores.iter().enumerate() {
+        let marker = if highlight == Some(rank) { '>' } else { ' ' };
+        msg += &format!(
+            "{}{:>2}. {:<20} {:>6} {:>6} {:>5} {:>5} {:>6}\n",
+            marker,
+            rank + 1,
+            entry.name,
+            entry.score,
+            entry.xp,
+            entry.depth,
+            entry.gold,
+            entry.turns
+        );
+        if let Some(cause) = &entry.cause {
+            msg += &format!("     killed by {}\n", cause);
+        }
+        if entry.won {
+            msg += "     escaped with the crown\n";
+        }
+    }
+    msg += footer;
+    msgbox(&msg, HIGH_SCORES_WIDTH, tcod);
+}
+
+fn record_run_ended(game: &mut Game, player: &Object, outcome: &str) {
+    let cause = game.telemetry.last_damage_to_player().map(String::from);
+    game.telemetry.record(&Record::RunEnded {
+        turn: game.turn,
+        dungeon_level: game.dungeon_level,
+        outcome,
+        cause: cause.as_deref(),
+    });
+    game.telemetry.flush();
+
+    // a quit run isn't over yet, so only deaths and wins are scored
+    if outcome == "quit" {
+        return;
+    }
+    if let Some(leaderboard) = game.leaderboard.as_ref() {
+        let entry = leaderboard::Entry {
+            seed: game.seed,
+            daily: game.daily.is_some(),
+            score: score(game, player),
+            depth: game.dungeon.deepest(game.dungeon_level),
+            turns: game.turn,
+            cause: cause.clone(),
+        };
+        // posted in the background, and reported on once the server answers
+        game.posting = leaderboard.post(entry);
+    }
+    // daily runs are also kept apart locally
+    if let Some(day) = game.daily {
+        daily::record_result(&daily::DailyResult {
+            day,
+            dungeon_level: game.dungeon.deepest(game.dungeon_level),
+            turn: game.turn,
+            outcome: outcome.into(),
+        });
+    }
+}
+
+/// Resets the last stored key/mouse event and replaces it with the next
+fn process_event(tcod: &mut Tcod) {
+    tcod.key = Default::default();
+    tcod.mouse = Default::default();
+
+    match tcod.backend.check_for_event() {
+        Some(Event::Mouse(m)) => tcod.mouse = m,
+        Some(Event::Key(k)) => tcod.key = k,
+        None => {}
+    }
+}
+
+/// show the main menu, and start whatever the player picks from it
+fn main_menu(tcod: &mut Tcod, options: &Options) -> GameState {
+    // show the background image, at twice the regular console resolution,
+    // where there's libtcod to draw it; a blank screen where there isn't
+    tcod.screen.set_default_background(BLACK);
+    tcod.screen.clear();
+    #[cfg(feature = "tcod")]
+    backend::draw_image("menu_background.png", &mut tcod.screen)
+        .expect("Background image not found");
+    tcod.mark_map((0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), false);
+
+    tcod.screen.set_default_foreground(LIGHT_YELLOW);
+    tcod.screen.print_ex(
+        SCREEN_WIDTH / 2,
+        SCREEN_HEIGHT / 2 - 4,
+        BackgroundFlag::None,
+        TextAlignment::Center,
+        "TOMBS OF THE ANCIENT KINGS",
+    );
+    tcod.screen.print_ex(
+        SCREEN_WIDTH / 2,
+        SCREEN_HEIGHT - 2,
+        BackgroundFlag::None,
+        TextAlignment::Center,
+        "By Yours Truly",
+    );
+
+    // show options and wait for the player's choice
+    let choices = &[
+        "Play a new game",
+        "Load a saved game",
+        "Play the daily challenge",
+        "Play the tutorial",
+        "High scores",
+        "Key bindings",
+        "Quit",
+    ];
+    let choice = menu("", choices, 30, tcod);
+
+    match choice {
+        Some(0) => {
+            // new game
+            let (slot, name) = match choose_new_slot(tcod) {
+                Some(chosen) => chosen,
+                None => return GameState::MainMenu,
+            };
+            let class = match choose_class(tcod) {
+                Some(class) => class,
+                None => return GameState::MainMenu,
+            };
+            let (mut game, objects) = new_game(class, tcod);
+            claim_slot(&mut game, &objects, slot, name, tcod);
+            start_telemetry(&mut game, options);
+            game.leaderboard = options.leaderboard.clone();
+            GameState::Playing(Session::new(game, objects))
+        }
+        Some(1) => {
+            // load game
+            match load_menu(tcod) {
+                Some((mut game, objects)) => {
+                    initialise_fov(tcod, &game.map);
+                    start_telemetry(&mut game, options);
+                    game.leaderboard = options.leaderboard.clone();
+                    GameState::Playing(Session::new(game, objects))
+                }
+                None => GameState::MainMenu,
+            }
+        }
+        Some(2) => {
+            // everyone gets the same dungeon today
+            let day = daily::today();
+            show_daily_results(day, options, tcod);
+            let (slot, name) = match choose_new_slot(tcod) {
+                Some(chosen) => chosen,
+                None => return GameState::MainMenu,
+            };
+            let class = daily::class_of(day);
+            let (mut game, objects) = new_game_state(StdRng::seed_from_u64(day), class);
+            game.daily = Some(day);
+            game.seed = day;
+            claim_slot(&mut game, &objects, slot, name, tcod);
+            initialise_fov(tcod, &game.map);
+            start_telemetry(&mut game, options);
+            game.leaderboard = options.leaderboard.clone();
+            GameState::Playing(Session::new(game, objects))
+        }
+        Some(3) => {
+            let (game, objects) = tutorial::new_tutorial();
+            initialise_fov(tcod, &game.map);
+            GameState::Playing(Session::new(game, objects))
+        }
+        Some(4) => {
+            show_high_scores(None, "", tcod);
+            GameState::MainMenu
+        }
+        Some(5) => {
+            keybindings::options_screen(tcod);
+            GameState::MainMenu
+        }
+        Some(6) => GameState::Quit,
+        _ => GameState::MainMenu,
+    }
+}
+
+/// show how earlier attempts at the day's challenge went
+fn show_daily_results(day: u64, options: &Options, tcod: &mut Tcod) {
+    let mut msg = format!(
+        "\nDaily challenge for {}, as a {}\n\n",
+        daily::date_of(day),
+        daily::class_of(day).name()
+    );
+    let results = daily::results_for(day);
+    if results.is_empty() {
+        msg += "No attempts yet today.\n";
+    }
+    for result in results.iter().take(DAILY_RESULTS_SHOWN) {
+        msg += &format!(
+            "Reached level {} in {} turns\n",
+            result.dungeon_level, result.turn
+        );
+    }
+    if let Some(leaderboard) = options.leaderboard.as_ref() {
+        msg += "\nBest scores worldwide\n\n";
+        // the scores are fetched in the background, and the player can leave
+        // without waiting for them
+        let mut fetch = leaderboard.fetch_top_scores(day);
+        let entries = loop {
+            if let Some(entries) = fetch.poll() {
+                break entries;
+            }
+            let options: &[&str] = &[];
+            let fetching = format!("{}Fetching...\n", msg);
+            show_menu(&fetching, options, DAILY_RESULTS_WIDTH, tcod);
+            if tcod.backend.is_closed() {
+                return;
+            }
+            if let Some(Event::Key(_)) = tcod.backend.check_for_event() {
+                return;
+            }
+            thread::sleep(Duration::from_millis(50));
+        };
+        match entries {
+            Ok(entries) => {
+                for entry in entries.iter().take(DAILY_RESULTS_SHOWN) {
+                    msg += &format!("{} points, level {}\n", entry.score, entry.depth);
+                }
+            }
+            Err(e) => msg += &format!("The leaderboard is unavailable: {}\n", e),
+        }
+    }
+    msgbox(&msg, DAILY_RESULTS_WIDTH, tcod);
+}
+
+/// turn on telemetry for this game if it was requested on the command line
+fn start_telemetry(game: &mut Game, options: &Options) {
+    if let Some(path) = options.telemetry.as_ref() {
+        match Telemetry::open(path) {
+            Ok(telemetry) => game.telemetry = telemetry,
+            Err(e) => game.messages.add(
+                format!("Could not open telemetry file {}: {}", path.display(), e),
+                RED,
+            ),
+        }
+    }
+}
+
+fn msgbox(text: &str, width: i32, tcod: &mut Tcod) {
+    let options: &[&str] = &[];
+    menu(text, options, width, tcod);
+}
+
+/// ask the player a yes-or-no question
+fn confirm(question: &str, tcod: &mut Tcod) -> bool {
+    menu(question, &["Yes", "No"], 40, tcod) == Some(0)
+}
+
+/// Let the player type a line of up to `max_length` characters, finished
+/// with Enter. None if they give up with Escape.
+fn ask_text(header: &str, max_length: usize, tcod: &mut Tcod) -> Option<String> {
+    use input::KeyCode::*;
+
+    let options: &[&str] = &[];
+    let mut text = String::new();
+    while !tcod.backend.is_closed() {
+        show_menu(&format!("{}\n\n{}_", header, text), options, 40, tcod);
+        let key = tcod.backend.wait_for_keypress();
+        match key.code {
+            Enter | NumPadEnter => return Some(text),
+            Escape => return None,
+            Backspace => {
+                text.pop();
+            }
+            Text if !key.printable.is_control() && text.chars().count() < max_length => {
+                text.push(key.printable);
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// one line of the slot menus
+fn describe_slot(number: usize, slot: &save::Slot) -> String {
+    let summary = match slot {
+        save::Slot::Empty => "(empty)".to_string(),
+        save::Slot::Saved {
+            name,
+            level,
+            place,
+            saved_at,
+        } => {
+            let time = saved_at % (24 * 60 * 60);
+            format!(
+                "{}, level {}, {}, saved {} {:02}:{:02}",
+                name,
+                level,
+                place,
+                daily::date_of(saved_at / (24 * 60 * 60)),
+                time / (60 * 60),
+                time % (60 * 60) / 60
+            )
+        }
+        save::Slot::Broken(_) => "(can't be loaded)".to_string(),
+    };
+    format!("Slot {}: {}", number + 1, summary)
+}
+
+/// what a character the player didn't name is called
+fn unnamed() -> String {
+    "Nameless".to_string()
+}
+
+/// Ask where a new game should be saved, making sure before a game already
+/// there is overwritten, and what the player's character is called.
+fn choose_new_slot(tcod: &mut Tcod) -> Option<(usize, String)> {
+    let slots = save::slots();
+    let options: Vec<String> =
+        slots.iter().enumerate().map(|(i, s)| describe_slot(i, s)).collect();
+    let slot = menu("Save the new game in which slot?\n", &options, SLOT_MENU_WIDTH, tcod)?;
+    if let save::Slot::Saved { name, .. } = &slots[slot] {
+        let question = format!("Overwrite {}'s game? It will be lost for good.\n", name);
+        if !confirm(&question, tcod) {
+            return None;
+        }
+    }
+    let name = ask_text("What is your name?", MAX_NAME_LENGTH, tcod)?;
+    let name = match name.trim() {
+        "" => unnamed(),
+        name => name.to_string(),
+    };
+    Some((slot, name))
+}
+
+/// ask what class the new game's character is; None if the player backs out
+fn choose_class(tcod: &mut Tcod) -> Option<Class> {
+    let options: Vec<&str> = CLASSES.iter().map(|class| class.description()).collect();
+    let choice = menu("Choose your class:\n", &options, SLOT_MENU_WIDTH, tcod)?;
+    Some(CLASSES[choice])
+}
+
+/// Name a new game's character and save the game in its slot straight away,
+/// so the slot is taken even if the game is never saved again.
+fn claim_slot(game: &mut Game, objects: &Objects, slot: usize, name: String, tcod: &mut Tcod) {
+    game.character_name = name;
+    game.save_slot = Some(slot);
+    if let Err(e) = save::write(slot, game, objects) {
+        msgbox(&format!("\nCould not save the new game: {}.\n", e), 50, tcod);
+    }
+}
+
+/// Show the saved games, to load one or delete some. None if the player
+/// backs out without loading anything.
+fn load_menu(tcod: &mut Tcod) -> Option<(Game, Objects)> {
+    while !tcod.backend.is_closed() {
+        let slots = save::slots();
+        let options: Vec<String> =
+            slots.iter().enumerate().map(|(i, s)| describe_slot(i, s)).collect();
+        let slot = menu("Load which game?\n", &options, SLOT_MENU_WIDTH, tcod)?;
+        let (header, name) = match &slots[slot] {
+            save::Slot::Empty => {
+                msgbox("\nThat slot is empty.\n", 24, tcod);
+                continue;
+            }
+            save::Slot::Saved { name, .. } => (format!("{}\n", options[slot]), name.clone()),
+            save::Slot::Broken(e) => (
+                format!("Slot {} can't be loaded: {}.\n", slot + 1, e),
+                format!("slot {}", slot + 1),
+            ),
+        };
+        let load = !matches!(slots[slot], save::Slot::Broken(_));
+        let choices: &[&str] = if load {
+            &["Continue this game", "Delete this save", "Back"]
+        } else {
+            &["Delete this save", "Back"]
+        };
+        match menu(&header, choices, SLOT_MENU_WIDTH, tcod).map(|choice| choices[choice]) {
+            Some("Continue this game") => match load_game(slot) {
+                Ok(save) => return Some(save),
+                Err(e) => {
+                    msgbox(&format!("\nCould not load the saved game: {}.\n", e), 50, tcod);
+                }
+            },
+            Some("Delete this save") => {
+                let question = format!("Delete {}'s game for good?\n", name);
+                if confirm(&question, tcod) {
+                    if let Err(e) = save::delete(slot) {
+                        msgbox(&format!("\nCould not delete the save: {}.\n", e), 50, tcod);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+/// Serializes and writes saves on a background thread, so that saving doesn't
+/// stall the game loop on slow disks. Only one save is in flight at a time.
+struct BackgroundSaver {
+    pending: Option<JoinHandle<Result<(), String>>>,
+}
+
+impl BackgroundSaver {
+    pub fn new() -> Self {
+        BackgroundSaver { pending: None }
+    }
+
+    /// start saving a snapshot of the current game state, if it has a slot
+    /// and the player's still alive to play it
+    pub fn save(&mut self, game: &Game, objects: &Objects) {
+        // never let two saves write the same file at once
+        if let Err(e) = self.wait() {
+            eprintln!("Saving failed: {}", e);
+        }
+        let slot = match game.save_slot {
+            Some(slot) if objects[PLAYER].alive => slot,
+            _ => return,
+        };
+        let game = game.clone();
+        let objects = objects.to_vec();
+        self.pending = Some(thread::spawn(move || save::write(slot, &game, &objects)));
+    }
+
+    /// returns true while a save is still being written
+    pub fn is_saving(&self) -> bool {
+        self.pending
+            .as_ref()
+            .map_or(false, |handle| !handle.is_finished())
+    }
+
+    /// if the last save has finished, collect it and report any failure
+    pub fn poll(&mut self, messages: &mut Messages) {
+        if self.pending.is_some() && !self.is_saving() {
+            if let Err(e) = self.wait() {
+                messages.add(format!("Saving failed: {}", e), RED);
+            }
+        }
+    }
+
+    /// block until the last save (if any) has been written
+    pub fn wait(&mut self) -> Result<(), String> {
+        match self.pending.take() {
+            Some(handle) => handle
+                .join()
+                .unwrap_or_else(|_| Err("the save thread panicked".into())),
+            None => Ok(()),
+        }
+    }
+}
+
+fn load_game(slot: usize) -> Result<(Game, Objects), LoadError> {
+    let (game, objects) = save::read(slot)?;
+    let (mut game, objects) = check_save(game, objects).map_err(LoadError::Damaged)?;
+    game.save_slot = Some(slot);
+    Ok((game, objects))
+}
+
+fn parse_save(json_save_state: &str) -> Result<(Game, Objects), LoadError> {
+    let (game, objects) = save::parse(json_save_state)?;
+    check_save(game, objects).map_err(LoadError::Damaged)
+}
+
+/// Check that a save describes a playable game, so that a damaged or
+/// hand-edited file is reported as an error instead of crashing later.
+fn check_save(game: Game, objects: Objects) -> Result<(Game, Objects), String> {
+    let size = (game.map.width(), game.map.height());
+    if !game.map.is_consistent() || size != game.dungeon.branch.map_size() {
+        return Err("the saved map has the wrong size".into());
+    }
+    let player = objects.get(PLAYER).ok_or("the save has no player")?;
+    if player.fighter.is_none() {
+        return Err("the saved player has no combat stats".into());
+    }
+    if let Some(object) = objects.iter().find(|o| !game.map.in_bounds(o.x, o.y)) {
+        return Err(format!("{} is outside the map", object.name));
+    }
+    if let Some(object) = objects.iter().find(|o| o.ai.is_some() && o.fighter.is_none()) {
+        return Err(format!("{} has an AI but no combat stats", object.name));
+    }
+    if game.inventory.len() > 26 {
+        return Err("the saved inventory has too many items".into());
+    }
+    Ok((game, objects))
+}
+
+/// Entry points for the fuzz targets in `fuzz/`.
+#[doc(hidden)]
+pub mod fuzzing {
+    use std::error::Error;
+
+    /// parse a save file the same way "Continue last game" does, discarding the result
+    pub fn load_save(data: &[u8]) -> Result<(), Box<dyn Error>> {
+        let json_save_state = std::str::from_utf8(data)?;
+        super::parse_save(json_save_state)?;
+        Ok(())
+    }
+
+    /// parse a headless game's script the same way `--script` does
+    pub fn script(data: &[u8]) -> Result<(), String> {
+        let text = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::script::parse(text).map(|_| ())
+    }
+
+    /// parse a leaderboard server's answer the same way the daily challenge screen does
+    pub fn leaderboard_response(data: &[u8]) -> Result<(), String> {
+        let body = super::leaderboard::parse_response(data)?;
+        super::leaderboard::parse_scores(&body).map(|_| ())
+    }
+
+    /// parse monster species the same way `data/monsters.json` is
+    pub fn monsters(data: &[u8]) -> Result<(), String> {
+        let json = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::bestiary::parse(json).map(|_| ())
+    }
+
+    /// parse kinds of item the same way `data/items.json` is
+    pub fn items(data: &[u8]) -> Result<(), String> {
+        let json = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::items::parse(json).map(|_| ())
+    }
+
+    /// parse kinds of pack the same way `data/packs.json` is
+    pub fn packs(data: &[u8]) -> Result<(), String> {
+        let json = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::packs::parse(json).map(|_| ())
+    }
+
+    /// parse prefabs the same way `data/prefabs.json` is
+    pub fn prefabs(data: &[u8]) -> Result<(), String> {
+        let json = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::prefabs::parse(json).map(|_| ())
+    }
+
+    /// parse a town map the same way `data/town.txt` is
+    pub fn town(data: &[u8]) -> Result<(), String> {
+        let text = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::town::parse(text).map(|_| ())
+    }
+
+    /// parse a tileset layout the same way `data/tiles.json` is
+    #[cfg(feature = "tiles")]
+    pub fn tiles(data: &[u8]) -> Result<(), String> {
+        let json = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::tiles::parse(json)
+    }
+
+    /// parse saved key bindings the same way the options screen's file is
+    pub fn keybindings(data: &[u8]) -> Result<(), String> {
+        let json = std::str::from_utf8(data).map_err(|e| e.to_string())?;
+        super::keybindings::Keybindings::parse(json).map(|_| ())
+    }
+}
+
+/// Options given on the command line
+#[derive(Debug, Default)]
+pub struct Options {
+    /// append balance telemetry to this file
+    telemetry: Option<PathBuf>,
+    /// post finished runs to this leaderboard
+    leaderboard: Option<Leaderboard>,
+    /// play in the terminal instead of libtcod's window
+    terminal: bool,
+    /// play in a window drawn from this tileset, instead of libtcod's
+    tiles: Option<PathBuf>,
+    /// play without a window, with the bot or `script` choosing what to do
+    headless: bool,
+    /// the commands for a headless game
+    script: Option<PathBuf>,
+    /// the seed for a headless game, instead of a random one
+    seed: Option<u64>,
+}
+
+impl Options {
+    pub fn from_args(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut options = Options::default();
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--telemetry" => {
+                    let path = args.next().ok_or("--telemetry needs a file name")?;
+                    options.telemetry = Some(path.into());
+                }
+                "--leaderboard" => {
+                    let url = args.next().ok_or("--leaderboard needs a URL")?;
+                    options.leaderboard = Some(Leaderboard::new(&url)?);
+                }
+                "--terminal" if cfg!(feature = "terminal") => options.terminal = true,
+                "--terminal" => {
+                    return Err("--terminal needs the game built with the terminal feature".into())
+                }
+                "--tiles" if cfg!(feature = "tiles") => {
+                    let path = args.next().ok_or("--tiles needs a tileset image")?;
+                    options.tiles = Some(path.into());
+                }
+                "--tiles" => {
+                    return Err("--tiles needs the game built with the tiles feature".into())
+                }
+                "--headless" => options.headless = true,
+                "--script" => {
+                    let path = args.next().ok_or("--script needs a file name")?;
+                    options.script = Some(path.into());
+                }
+                "--seed" => {
+                    let seed = args.next().ok_or("--seed needs a number")?;
+                    let seed = seed.parse().map_err(|_| format!("not a seed: {}", seed))?;
+                    options.seed = Some(seed);
+                }
+                _ => return Err(format!("unknown argument: {}", arg)),
+            }
+        }
+        if !options.headless && (options.script.is_some() || options.seed.is_some()) {
+            return Err("--script and --seed only go with --headless".into());
+        }
+        Ok(options)
+    }
+}
+
+/// Open the game window and run the main menu until the player quits, or
+/// play a headless game if asked to. Only a headless game can fail, or a
+/// window this build can't open.
+pub fn run(options: &Options) -> Result<(), String> {
+    // read the data files now, so a bad one is reported before anything
+    // needs it
+    bestiary::load()?;
+    items::load()?;
+    packs::load()?;
+    prefabs::load()?;
+    town::load()?;
+    if options.headless {
+        return run_headless(options);
+    }
+
+    let mut tcod = Tcod {
+        backend: open_backend(options)?,
+        screen: Offscreen::new(SCREEN_WIDTH, SCREEN_HEIGHT),
+        map_cells: vec![false; (SCREEN_WIDTH * SCREEN_HEIGHT) as usize],
+        con: Offscreen::new(VIEW_WIDTH, VIEW_HEIGHT),
+        panel: Offscreen::new(SCREEN_WIDTH, PANEL_HEIGHT),
+        fov: FovMap::new(MAP_WIDTH, MAP_HEIGHT),
+        key: Default::default(),
+        keys: Keybindings::load(),
+        mouse: Default::default(),
+        camera: Camera::default(),
+        frame: 0,
+    };
+
+    states::run(&mut tcod, options);
+    Ok(())
+}
+
+/// play a game without a window, and say how it went
+fn run_headless(options: &Options) -> Result<(), String> {
+    let script = match &options.script {
+        Some(path) => Some(
+            std::fs::read_to_string(path)
+                .map_err(|e| format!("can't read {}: {}", path.display(), e))?,
+        ),
+        None => None,
+    };
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let summary = sim::run_headless(seed, script.as_deref())
+        .map_err(|e| format!("seed {}: {}", seed, e))?;
+    println!("seed {}: {}", seed, summary);
+    Ok(())
+}
+
+/// the terminal or a tileset, if asked for on the command line, or else
+/// libtcod's window, where the game's built with it
+#[cfg_attr(
+    not(any(feature = "terminal", feature = "tiles")),
+    allow(unused_variables)
+)]
+fn open_backend(options: &Options) -> Result<Box<dyn Backend>, String> {
+    #[cfg(feature = "terminal")]
+    if options.terminal {
+        let terminal = terminal::Terminal::open(LIMIT_FPS)
+            .unwrap_or_else(|e| panic!("could not take over the terminal: {}", e));
+        return Ok(Box::new(terminal));
+    }
+    #[cfg(feature = "tiles")]
+    if let Some(path) = &options.tiles {
+        let tileset = tiles::Tileset::open(path, SCREEN_WIDTH, SCREEN_HEIGHT, WINDOW_TITLE)
+            .unwrap_or_else(|e| panic!("could not open the tileset window: {}", e));
+        return Ok(Box::new(tileset));
+    }
+
+    #[cfg(feature = "tcod")]
+    {
+        let window = backend::Window::open(SCREEN_WIDTH, SCREEN_HEIGHT, WINDOW_TITLE, LIMIT_FPS);
+        Ok(Box::new(window))
+    }
+    #[cfg(not(feature = "tcod"))]
+    {
+        Err("this build has no libtcod window; play with --terminal or --tiles".into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::map::Tile;
+
+    #[test]
+    fn data_files_read() {
+        assert_eq!(bestiary::load().err(), None);
+        assert_eq!(items::load().err(), None);
+        assert_eq!(packs::load().err(), None);
+        assert_eq!(prefabs::load().err(), None);
+        assert_eq!(town::load().err(), None);
+        assert!(town::parse("#.#\n#X#").is_err());
+        let pack = r#"[{"name": "pack", "leader": "no such monster", "followers": [],
+            "min_followers": 0, "max_followers": 0}]"#;
+        let problem = packs::parse(pack).err();
+        assert_eq!(problem.as_deref(), Some("the pack has unknown monster no such monster"));
+    }
+
+    #[test]
+    fn hunger_is_announced_once_and_starving_hurts() {
+        let (mut game, mut objects) = game();
+        let hp = objects[PLAYER].fighter.unwrap().hp;
+        game.satiation = HUNGRY_SATIATION;
+        get_hungrier(&mut game, &mut objects[PLAYER]);
+        get_hungrier(&mut game, &mut objects[PLAYER]);
+        let hungry = game.messages.iter().filter(|m| m.text == "You are getting hungry.");
+        assert_eq!(hungry.count(), 1);
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, hp);
+
+        game.satiation = 1;
+        get_hungrier(&mut game, &mut objects[PLAYER]);
+        assert_eq!(hunger(game.satiation), Some("Starving"));
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, hp - STARVATION_DAMAGE);
+        nourish(RATION_NOURISHMENT, &mut game);
+        assert_eq!(hunger(game.satiation), None);
+    }
+
+    #[test]
+    fn sneaking_helps_and_heavy_armor_hurts_stealth() {
+        let (mut game, objects) = game();
+        let plain = objects[PLAYER].stealth(&game);
+        game.sneaking = true;
+        assert_eq!(objects[PLAYER].stealth(&game), plain + SNEAK_STEALTH_BONUS);
+        game.sneaking = false;
+        wear("chain mail", &mut game);
+        assert_eq!(objects[PLAYER].stealth(&game), plain - 5);
+    }
+
+    #[test]
+    fn monsters_see_only_what_they_face() {
+        let mut orc = bestiary::template("orc").spawn(10, 10);
+        orc.facing = (1, 0);
+        assert!(orc.faces(14, 11));
+        assert!(!orc.faces(6, 10));
+        assert!(!orc.faces(10, 14));
+        orc.facing = (0, 0);
+        assert!(orc.faces(6, 10));
+    }
+
+    #[test]
+    fn monsters_see_no_further_than_their_sight_or_the_light() {
+        let mut map = Map::new(30, 3, Tile::empty());
+        for (_, tile) in map.iter_mut() {
+            tile.light = 1.0;
+        }
+        let orc = bestiary::template("orc").spawn(1, 1);
+        let sight = orc.fighter.unwrap().sight_radius;
+        assert!(orc.can_see(1 + sight, 1, &map));
+        assert!(!orc.can_see(2 + sight, 1, &map));
+        map[(3, 1)].light = 0.0;
+        map[(2, 1)].light = 0.0;
+        assert!(!orc.can_see(3, 1, &map));
+        assert!(orc.can_see(2, 1, &map));
+        map[(3, 1)] = Tile::wall();
+        assert!(!orc.can_see(4, 1, &map));
+    }
+
+    /// what the player sees in town now, worked out afresh
+    fn look_around(game: &mut Game, objects: &Objects) {
+        let mut fov = fov::Shadowcasting::new(game.map.width(), game.map.height());
+        compute_visibility(&mut fov, game, objects);
+    }
+
+    #[test]
+    fn the_blind_only_make_out_whats_next_to_them() {
+        let (mut game, mut objects) = game();
+        let (x, y) = objects[PLAYER].pos();
+        look_around(&mut game, &objects);
+        assert!(game.map.is_visible(x + 3, y));
+        objects[PLAYER].blind(5);
+        look_around(&mut game, &objects);
+        assert!(game.map.is_visible(x + 1, y + 1));
+        assert!(!game.map.is_visible(x + 3, y));
+        assert!(game.map[(x + 3, y)].in_view);
+    }
+
+    #[test]
+    fn telepathy_comes_from_the_spell_or_the_amulet() {
+        let (mut game, objects) = game();
+        assert!(!objects[PLAYER].is_telepathic(&game));
+        cast_telepathy(&mut game);
+        assert!(objects[PLAYER].is_telepathic(&game));
+        game.telepathy_turns = 0;
+        wear("amulet of ESP", &mut game);
+        assert!(objects[PLAYER].is_telepathic(&game));
+    }
+
+    #[test]
+    fn items_seen_are_remembered_out_of_sight() {
+        let (mut game, mut objects) = game();
+        let (x, y) = objects[PLAYER].pos();
+        objects.push(map::make_item("healing potion", x + 2, y));
+        look_around(&mut game, &objects);
+        let remembered = game.map[(x + 2, y)].remembered;
+        assert_eq!(remembered.map(|(char, _)| char), Some('!'));
+        objects[PLAYER].blind(5);
+        look_around(&mut game, &objects);
+        assert_eq!(game.map[(x + 2, y)].remembered, remembered);
+        // once it's seen to be gone, it's forgotten
+        objects.truncate(objects.len() - 1);
+        objects[PLAYER].fighter.as_mut().unwrap().blind_turns = 0;
+        look_around(&mut game, &objects);
+        assert_eq!(game.map[(x + 2, y)].remembered, None);
+    }
+
+    /// Answers the questions an item asks by picking the same spot, and
+    /// the first of anything else.
+    struct Pick((i32, i32));
+
+    impl Chooser for Pick {
+        fn choose_tile(
+            &mut self,
+            _game: &mut Game,
+            _objects: &Objects,
+            _max_range: Option<f32>,
+            _in_fov_only: bool,
+            _blast: Option<i32>,
+        ) -> Option<(i32, i32)> {
+            Some(self.0)
+        }
+
+        fn choose_stat(&mut self, _header: &str, _fighter: &Fighter) -> usize {
+            0
+        }
+
+        fn choose_item(&mut self, _header: &str, _game: &Game, _player: &Object) -> Option<usize> {
+            Some(0)
+        }
+    }
+
+    #[test]
+    fn clairvoyance_reveals_the_spot_picked() {
+        let (mut game, mut objects) = game();
+        for (_, tile) in game.map.iter_mut() {
+            tile.explored = false;
+        }
+        objects.push(map::make_item("healing potion", 12, 10));
+        let used = cast_clairvoyance(&mut Pick((10, 10)), &mut game, &mut objects);
+        assert!(matches!(used, UseResult::UsedUp));
+        assert!(game.map[(10 + CLAIRVOYANCE_RADIUS, 10)].explored);
+        assert!(!game.map[(11 + CLAIRVOYANCE_RADIUS, 10)].explored);
+        assert!(game.map[(12, 10)].remembered.is_some());
+        assert!(!game.map.is_visible(12, 10));
+    }
+
+    #[test]
+    fn gear_and_night_vision_change_how_far_light_reaches() {
+        let (mut game, objects) = game();
+        let torch = objects[PLAYER].light_radius(&game);
+        let lantern = wear("lantern", &mut game);
+        assert_eq!(objects[PLAYER].light_radius(&game), torch + 3);
+        game.inventory.remove(lantern);
+        wear("ring of dimness", &mut game);
+        assert_eq!(objects[PLAYER].light_radius(&game), torch - 2);
+        game.night_vision_turns = 1;
+        assert_eq!(objects[PLAYER].light_radius(&game), torch - 2 + NIGHT_VISION_BONUS);
+    }
+
+    /// a new game on an open, empty field, the player at (5, 5) and alone
+    fn field() -> (Game, Objects) {
+        let (mut game, objects) = test_support::open_field(20, 20);
+        game.inventory.clear();
+        (game, objects)
+    }
+
+    #[test]
+    fn heavy_gear_drowns_the_player_once_their_breath_runs_out() {
+        let (mut game, mut objects) = field();
+        game.map[(5, 5)].deep_water = true;
+        swim(&mut game, &mut objects);
+        assert_eq!(game.breath_held, 0);
+        wear("chain mail", &mut game);
+        let hp = objects[PLAYER].fighter.unwrap().hp;
+        for _ in 0..MAX_BREATH {
+            swim(&mut game, &mut objects);
+        }
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, hp);
+        swim(&mut game, &mut objects);
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, hp - DROWNING_DAMAGE);
+        game.map[(5, 5)].deep_water = false;
+        swim(&mut game, &mut objects);
+        assert_eq!(game.breath_held, 0);
+    }
+
+    #[test]
+    fn stepping_on_a_teleporter_lands_on_its_twin() {
+        let (mut game, mut objects) = field();
+        game.map[(6, 5)].teleport = Some((15, 15));
+        game.map[(15, 15)].teleport = Some((6, 5));
+        move_by(PLAYER, 1, 0, &mut game.map, &mut objects);
+        assert_eq!(objects[PLAYER].pos(), (15, 15));
+        // standing still on one goes nowhere
+        move_by(PLAYER, 0, 0, &mut game.map, &mut objects);
+        assert_eq!(objects[PLAYER].pos(), (15, 15));
+    }
+
+    #[test]
+    fn breeders_out_of_sight_multiply_up_to_a_limit() {
+        let (mut game, mut objects) = field();
+        objects.push(bestiary::template("giant rat").spawn(12, 12));
+        for _ in 0..2000 {
+            breed(&mut game, &mut objects);
+        }
+        let rats = objects.iter().filter(|o| o.name == "giant rat").count();
+        assert_eq!(rats, MAX_BREEDERS_PER_LEVEL);
+    }
+
+    #[test]
+    fn walking_into_a_closed_door_opens_it() {
+        let (mut game, mut objects) = field();
+        game.map[(6, 5)] = Tile::door();
+        assert!(!game.map.is_transparent(6, 5));
+        move_by(PLAYER, 1, 0, &mut game.map, &mut objects);
+        assert_eq!(objects[PLAYER].pos(), (5, 5));
+        assert!(game.map.is_transparent(6, 5));
+        move_by(PLAYER, 1, 0, &mut game.map, &mut objects);
+        assert_eq!(objects[PLAYER].pos(), (6, 5));
+        game.map[(7, 5)] = Tile::door();
+        game.map[(7, 5)].locked = true;
+        move_by(PLAYER, 1, 0, &mut game.map, &mut objects);
+        assert!(game.map[(7, 5)].blocked);
+    }
+
+    #[test]
+    fn searching_finds_hidden_passages() {
+        let (mut game, objects) = field();
+        game.map[(6, 6)] = Tile::secret_passage();
+        while !search_for_passages(&mut game, &objects) {}
+        assert_eq!(game.map[(6, 6)].door, Some(Door::Closed));
+        assert!(!game.map[(6, 6)].secret && game.map[(6, 6)].explored);
+    }
+
+    /// the remains of a monster of the given species, lying at the end of
+    /// the objects, killed this turn
+    fn corpse(species: &str, game: &mut Game, objects: &mut Objects) -> usize {
+        let mut monster = bestiary::template(species).spawn(1, 1);
+        monster_death(&mut monster, game);
+        objects.push(monster);
+        objects.len() - 1
+    }
+
+    #[test]
+    fn fresh_troll_is_filling_and_heals() {
+        let (mut game, mut objects) = game();
+        game.satiation = 0;
+        let troll = corpse("troll", &mut game, &mut objects);
+        eat_corpse(troll, &mut game, &mut objects);
+        assert_eq!(game.satiation, CORPSE_NOURISHMENT);
+        assert!(objects[PLAYER].has_effect(Effect::Regeneration));
+        assert!(objects.iter().all(|object| object.corpse.is_none()));
+    }
+
+    #[test]
+    fn spoiled_corpses_make_the_player_sick() {
+        let (mut game, mut objects) = game();
+        game.satiation = 0;
+        let troll = corpse("troll", &mut game, &mut objects);
+        game.turn += CORPSE_SPOIL_TURNS;
+        eat_corpse(troll, &mut game, &mut objects);
+        assert_eq!(game.satiation, 0);
+        assert!(objects[PLAYER].has_effect(Effect::Poison));
+        assert!(!objects[PLAYER].has_effect(Effect::Regeneration));
+    }
+
+    #[test]
+    fn corpses_spoil_once_even_if_turns_are_skipped() {
+        let (mut game, mut objects) = game();
+        corpse("orc", &mut game, &mut objects);
+        game.turn += CORPSE_SPOIL_TURNS + 3;
+        rot_corpses(&mut game, &mut objects);
+        rot_corpses(&mut game, &mut objects);
+        let corpse = objects.last().unwrap();
+        assert_eq!(corpse.name, "rotten remains of orc");
+        assert!(corpse.corpse.as_ref().unwrap().spoiled);
+    }
+
+    /// a new game with nothing in the player's pack
+    fn game() -> (Game, Objects) {
+        let (mut game, objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        game.inventory.clear();
+        (game, objects)
+    }
+
+    /// put the named item on the player, returning where it is in the pack
+    fn wear(name: &str, game: &mut Game) -> usize {
+        let template = items::templates().iter().find(|t| t.name == name).unwrap();
+        let mut item = template.spawn(0, 0);
+        item.equipment.as_mut().unwrap().equipped = true;
+        game.inventory.push(item);
+        game.inventory.len() - 1
+    }
+
+    /// how much one blow from a troll takes off the player
+    fn troll_blow(game: &mut Game, objects: &mut Objects) -> i32 {
+        let template = bestiary::templates().iter().find(|t| t.name == "troll").unwrap();
+        let mut troll = template.spawn(1, 0);
+        let before = objects[PLAYER].fighter.unwrap().hp;
+        troll.attack(&mut objects[PLAYER], game);
+        before - objects[PLAYER].fighter.unwrap().hp
+    }
+
+    #[test]
+    fn worn_armor_lowers_damage() {
+        let (mut game, mut objects) = game();
+        let bare = troll_blow(&mut game, &mut objects);
+        wear("leather armor", &mut game);
+        let armored = troll_blow(&mut game, &mut objects);
+        assert!(bare > 0);
+        assert_eq!(armored, bare - 1);
+    }
+
+    #[test]
+    fn enchanted_armor_lowers_damage() {
+        let (mut game, mut objects) = game();
+        let armor = wear("leather armor", &mut game);
+        let plain = troll_blow(&mut game, &mut objects);
+        enchant(armor, &mut game);
+        let enchanted = troll_blow(&mut game, &mut objects);
+        assert_eq!(enchanted, plain - 1);
+    }
+
+    #[test]
+    fn artifact_defense_lowers_damage() {
+        let (mut game, mut objects) = game();
+        let armor = wear("leather armor", &mut game);
+        let plain = troll_blow(&mut game, &mut objects);
+        // roll until the armor comes out Sturdy, of the Turtle or both
+        let mut rng = StdRng::seed_from_u64(1);
+        let base = game.inventory[armor].equipment.unwrap().defense_bonus;
+        let mut artifact = game.inventory[armor].clone();
+        while artifact.equipment.unwrap().defense_bonus == base {
+            artifact = game.inventory[armor].clone();
+            artifacts::maybe_make_artifact(&mut artifact, 10, &mut rng);
+        }
+        let bonus = artifact.equipment.unwrap().defense_bonus - base;
+        game.inventory[armor] = artifact;
+        let affixed = troll_blow(&mut game, &mut objects);
+        assert_eq!(affixed, plain - bonus);
+    }
+
+    #[test]
+    fn wanderers_turn_up_out_of_sight_and_come_looking() {
+        let (mut game, mut objects) = field();
+        for (_, tile) in game.map.iter_mut() {
+            tile.explored = true;
+        }
+        for _ in 0..10_000 {
+            spawn_wanderer(&mut game, &mut objects);
+        }
+        // none in town by day
+        assert_eq!(objects.len(), PLAYER + 1);
+        // but some by night
+        game.turn = daylight::DAY_LENGTH / 2;
+        while objects.len() == PLAYER + 1 {
+            spawn_wanderer(&mut game, &mut objects);
+        }
+        objects.truncate(PLAYER + 1);
+        game.dungeon_level = 5;
+        while objects.len() == PLAYER + 1 {
+            spawn_wanderer(&mut game, &mut objects);
+        }
+        let wanderer = &objects[PLAYER + 1];
+        assert!(objects[PLAYER].distance_to(wanderer) >= WANDERER_MIN_DISTANCE);
+        assert_eq!(wanderer.ai, Some(Ai::Basic));
+        assert!(objects.paths.get(objects.id_of(PLAYER + 1)).is_some());
+        for _ in 0..100_000 {
+            spawn_wanderer(&mut game, &mut objects);
+        }
+        assert_eq!(objects.len(), PLAYER + 1 + MAX_MONSTERS_PER_LEVEL);
+    }
+
+    #[test]
+    fn the_town_shops_shut_at_night() {
+        let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let shop = objects.iter().position(|o| o.trader.is_some()).unwrap();
+        let (x, y) = objects[shop].pos();
+        objects[PLAYER].set_pos(x - 1, y);
+        game.turn = daylight::DAY_LENGTH / 2;
+        assert!(!daylight::shops_open(game.turn));
+        let mut tcod = Tcod::pressing("");
+        player_step(1, 0, &mut tcod, &mut game, &mut objects);
+        let last = game.messages.iter().last().unwrap();
+        assert!(last.text.ends_with("has shut up shop for the night."));
+    }
+
+    #[test]
+    fn archers_shoot_from_afar_and_back_off_up_close() {
+        let (mut game, mut objects) = field();
+        for (_, tile) in game.map.iter_mut() {
+            tile.light = 1.0;
+        }
+        game.to_player = DijkstraMap::new(objects[PLAYER].pos(), &game.map);
+        let mut archer = bestiary::template("goblin archer").spawn(10, 5);
+        archer.ai = Some(Ai::Ranged);
+        objects.push(archer);
+        let archer = PLAYER + 1;
+        ai_take_turn(archer, &mut game, &mut objects);
+        assert_eq!(objects[archer].pos(), (10, 5));
+        assert_eq!(game.projectiles.len(), 1);
+        objects[archer].set_pos(7, 5);
+        ai_take_turn(archer, &mut game, &mut objects);
+        assert!(objects[archer].distance_to(&objects[PLAYER]) > 2.0);
+        assert_eq!(game.projectiles.len(), 1);
+    }
+
+    #[test]
+    fn badly_hurt_monsters_run_from_the_player() {
+        let (mut game, mut objects) = field();
+        game.to_player = DijkstraMap::new(objects[PLAYER].pos(), &game.map);
+        let mut orc = bestiary::template("orc").spawn(7, 5);
+        orc.ai = Some(Ai::Basic);
+        objects.push(orc);
+        let orc = PLAYER + 1;
+        // an orc's nerve holds until it's down to a fifth of its HP
+        let max_hp = objects[orc].fighter.unwrap().base_max_hp;
+        objects[orc].take_damage(max_hp - max_hp / 5, &mut game);
+        assert_eq!(objects[orc].ai, Some(Ai::Basic));
+        objects[orc].take_damage(1, &mut game);
+        assert_eq!(objects[orc].ai, Some(Ai::Flee));
+        let distance = objects[orc].distance_to(&objects[PLAYER]);
+        ai_take_turn(orc, &mut game, &mut objects);
+        assert!(objects[orc].distance_to(&objects[PLAYER]) > distance);
+        assert_eq!(objects[orc].ai, Some(Ai::Flee));
+    }
+
+    #[test]
+    fn retraining_makes_the_level_up_choices_over_again() {
+        let (mut game, mut objects) = game();
+        let mut tcod = Tcod::pressing("aa");
+        let used = cast_retraining(&mut tcod, &mut game, &mut objects);
+        assert!(matches!(used, UseResult::Cancelled));
+        let before = objects[PLAYER].fighter.unwrap();
+        for _ in 0..2 {
+            raise_stat(objects[PLAYER].fighter.as_mut().unwrap(), 1);
+            game.stat_choices.push(1);
+        }
+        let used = cast_retraining(&mut tcod, &mut game, &mut objects);
+        assert!(matches!(used, UseResult::UsedUp));
+        let after = objects[PLAYER].fighter.unwrap();
+        assert_eq!(after.base_power, before.base_power);
+        assert_eq!(after.base_max_hp, before.base_max_hp + 40);
+        assert_eq!(game.stat_choices, [0, 0]);
+    }
+}
@@ -0,0 +1,84 @@
+//! Lighting. Every object carrying a `LightSource` (the player's torch,
+//! braziers, glowing fungus) lights up the tiles in its line of sight within
+//! its radius, which equipment and potions can change from turn to turn. The
+//! player only sees tiles that are both in view and lit.
+
+use serde::{Deserialize, Serialize};
+
+use crate::fov::Fov;
+use crate::map::Map;
+
+/// tiles at the very edge of a light's radius are still this bright
+const MIN_LIGHT: f32 = 0.25;
+
+/// Light given off by an object, before any bonuses.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct LightSource {
+    pub radius: i32,
+}
+
+/// Recompute the light level of every tile from `lights`, given as the
+/// position and current radius of each. Uses `fov` as scratch space, so the
+/// player's FOV has to be computed again afterwards.
+pub fn compute_lighting(fov: &mut impl Fov, map: &mut Map, lights: &[((i32, i32), i32)]) {
+    let ambient_light = map.ambient_light;
+    for (_, tile) in map.iter_mut() {
+        tile.light = ambient_light;
+    }
+
+    for &((light_x, light_y), radius) in lights {
+        if radius <= 0 {
+            continue;
+        }
+        fov.compute(light_x, light_y, radius);
+        for ((x, y), tile) in map.iter_mut() {
+            if fov.is_in_fov(x, y) {
+                // brightest at the source, fading out towards the edge
+                let distance = (((x - light_x).pow(2) + (y - light_y).pow(2)) as f32).sqrt();
+                let light = (1.0 - distance / (radius + 1) as f32).max(MIN_LIGHT);
+                tile.light = tile.light.max(light);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fov::Shadowcasting;
+    use crate::map::Tile;
+
+    /// an open room walled all round, with a wall across it at x = 8
+    fn room() -> (Map, Shadowcasting) {
+        let mut map = Map::new(14, 7, Tile::wall());
+        let mut fov = Shadowcasting::new(14, 7);
+        for y in 1..6 {
+            for x in 1..13 {
+                if x != 8 {
+                    map[(x, y)] = Tile::empty();
+                    fov.set_transparent(x, y, true);
+                }
+            }
+        }
+        (map, fov)
+    }
+
+    #[test]
+    fn light_fades_with_distance_and_stops_at_walls() {
+        let (mut map, mut fov) = room();
+        map.ambient_light = 0.1;
+        compute_lighting(&mut fov, &mut map, &[((3, 3), 4)]);
+        assert_eq!(map[(3, 3)].light, 1.0);
+        assert!(map[(4, 3)].light > map[(6, 3)].light);
+        assert!(map[(7, 3)].light >= MIN_LIGHT);
+        assert_eq!(map[(10, 3)].light, 0.1);
+    }
+
+    #[test]
+    fn the_brightest_light_wins() {
+        let (mut map, mut fov) = room();
+        compute_lighting(&mut fov, &mut map, &[((2, 3), 5), ((6, 3), 2), ((11, 3), 0)]);
+        assert_eq!(map[(6, 3)].light, 1.0);
+        assert_eq!(map[(11, 3)].light, 0.0);
+    }
+}
@@ -0,0 +1,194 @@
+//! Look mode: a cursor the player moves about the map with the movement
+//! keys, saying what's under it: the tile, anything lying there, and any
+//! monster standing there, with its health and what's ailing it. Everything
+//! the mouse tells about, and more, without needing the mouse.
+
+use crate::actions::Action;
+use crate::colors::{Color, BLACK, DARKER_RED, LIGHT_GREY, LIGHT_RED, LIGHT_YELLOW, WHITE};
+use crate::console::*;
+use crate::entities::Objects;
+use crate::factions;
+use crate::map::{Door, Tile};
+use crate::{direction_name, render_all, render_bar, Ai, Game, Object, Tcod, PLAYER, VIEW_HEIGHT};
+
+/// how wide the box describing the spot under the cursor is
+const LOOK_WIDTH: i32 = 50;
+/// the color the spot under the cursor is picked out with
+const COLOR_CURSOR: Color = LIGHT_YELLOW;
+
+/// One line of the description: some text, or a monster's health as a bar.
+enum Line {
+    Text(String, Color),
+    Health(i32, i32),
+}
+
+/// Let the player move a cursor around the map, starting on themselves, and
+/// see what's there, until they press a key that isn't for moving.
+pub fn look(tcod: &mut Tcod, game: &mut Game, objects: &Objects) {
+    let mut cursor = objects[PLAYER].pos();
+    while !tcod.backend.is_closed() {
+        render_all(tcod, game, objects, false);
+        let (screen_x, screen_y) = match tcod.camera.to_screen(cursor.0, cursor.1) {
+            Some(pos) => pos,
+            None => return,
+        };
+        tcod.screen
+            .set_char_background(screen_x, screen_y, COLOR_CURSOR, BackgroundFlag::Set);
+        // the description goes at the other end of the view from the cursor
+        let lines = describe(cursor, game, objects);
+        let top = if screen_y < VIEW_HEIGHT / 2 {
+            VIEW_HEIGHT - lines.len() as i32 - 2
+        } else {
+            0
+        };
+        draw(tcod, &lines, top);
+        tcod.flush();
+
+        let key = tcod.backend.wait_for_keypress();
+        let (dx, dy) = match tcod.keys.action_for(key) {
+            Some(Action::Step { dx, dy } | Action::MoveOrAttack { dx, dy }) => (dx, dy),
+            _ => return,
+        };
+        // the cursor stays on the part of the map that's on screen
+        let (x, y) = (cursor.0 + dx, cursor.1 + dy);
+        if game.map.in_bounds(x, y) && tcod.camera.to_screen(x, y).is_some() {
+            cursor = (x, y);
+        }
+    }
+}
+
+/// draw the description in a box across the view, from the given line down
+fn draw(tcod: &mut Tcod, lines: &[Line], top: i32) {
+    let height = lines.len() as i32 + 2;
+    let screen = &mut tcod.screen;
+    screen.set_default_background(BLACK);
+    screen.rect(0, top, LOOK_WIDTH, height, true, BackgroundFlag::Set);
+    for (row, line) in lines.iter().enumerate() {
+        let y = top + 1 + row as i32;
+        match *line {
+            Line::Text(ref text, color) => {
+                screen.set_default_foreground(color);
+                screen.print(1, y, text);
+            }
+            Line::Health(hp, max_hp) => {
+                render_bar(screen, 3, y, LOOK_WIDTH - 6, "HP", hp, max_hp, LIGHT_RED, DARKER_RED)
+            }
+        }
+    }
+    screen.set_default_foreground(LIGHT_GREY);
+    screen.print(1, top + height - 1, "Move to look around, any other key to stop.");
+    tcod.mark_map((0, top), (LOOK_WIDTH, height), false);
+}
+
+/// what the player can tell about the spot: the tile, and whatever's there
+/// that they can see or sense
+fn describe((x, y): (i32, i32), game: &Game, objects: &Objects) -> Vec<Line> {
+    let tile = match game.map.get(x, y) {
+        Some(tile) if tile.explored => tile,
+        _ => return vec![Line::Text("You haven't seen this spot.".into(), LIGHT_GREY)],
+    };
+    let mut lines = vec![];
+    if tile.visible {
+        lines.push(Line::Text(format!("You see {}.", tile_name(tile)), WHITE));
+        if tile.smoke > 0 {
+            lines.push(Line::Text("Smoke hangs in the air.".into(), LIGHT_GREY));
+        }
+    } else {
+        lines.push(Line::Text(format!("You remember {}.", tile_name(tile)), LIGHT_GREY));
+        if tile.remembered.is_some() {
+            lines.push(Line::Text("Something was lying here.".into(), LIGHT_GREY));
+        }
+    }
+
+    let telepathic = objects[PLAYER].is_telepathic(game);
+    for (id, object) in objects.iter().enumerate() {
+        if object.pos() != (x, y) || object.is_hidden() {
+            continue;
+        }
+        let is_monster = object.ai.is_some() && object.fighter.is_some();
+        if id == PLAYER && tile.visible {
+            lines.push(Line::Text("You are standing here.".into(), WHITE));
+        } else if is_monster && tile.visible {
+            lines.extend(describe_monster(object, game, objects));
+        } else if is_monster && telepathic {
+            lines.push(Line::Text(format!("You sense a {} here.", object.name), WHITE));
+        } else if tile.visible && object.fighter.is_none() {
+            lines.push(Line::Text(format!("There is {} here.", object.a_name(game)), WHITE));
+        }
+    }
+    lines
+}
+
+/// what the tile itself is, like "a closed door" or "deep water"
+fn tile_name(tile: &Tile) -> &'static str {
+    match tile.door {
+        _ if tile.locked => "a locked vault door",
+        Some(Door::Closed) => "a closed door",
+        Some(Door::Open) => "an open door",
+        None if tile.teleport.is_some() => "a teleporter",
+        None if tile.block_sight => "a wall",
+        None if tile.deep_water => "deep water",
+        None => "the floor",
+    }
+}
+
+/// a monster the player can see: what it is and what it's up to, how hurt it
+/// is, and the status effects on it
+fn describe_monster(monster: &Object, game: &Game, objects: &Objects) -> Vec<Line> {
+    let attitude = if !factions::are_enemies(&objects[PLAYER], monster) {
+        "friendly"
+    } else if monster.has_noticed_player() {
+        "hostile"
+    } else if monster.ai == Some(Ai::Asleep) {
+        "asleep"
+    } else {
+        "unaware of you"
+    };
+    let mut about = format!("A {} ({})", monster.name, attitude);
+    if let Some(direction) = direction_name(monster.facing) {
+        about += &format!(", facing {}", direction);
+    }
+    let mut lines = vec![Line::Text(about, WHITE)];
+    if let Some(fighter) = monster.fighter {
+        lines.push(Line::Health(fighter.hp, monster.max_hp(game)));
+    }
+    let mut status: Vec<&str> = monster.effects.iter().map(|e| e.effect.name()).collect();
+    if monster.is_blind() {
+        status.push("Blind");
+    }
+    if !status.is_empty() {
+        lines.push(Line::Text(status.join(", "), LIGHT_GREY));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bestiary;
+    use crate::test_support::open_field;
+
+    /// the text of the description, health bars left out
+    fn text(lines: Vec<Line>) -> Vec<String> {
+        lines
+            .into_iter()
+            .filter_map(|line| match line {
+                Line::Text(text, _) => Some(text),
+                Line::Health(..) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn look_tells_only_what_the_player_has_seen() {
+        let (mut game, mut objects) = open_field(20, 20);
+        game.map[(6, 5)].visible = false;
+        objects.push(bestiary::template("troll").spawn(6, 5));
+        let look = |game: &Game, objects: &Objects| text(describe((6, 5), game, objects));
+        assert_eq!(look(&game, &objects), ["You haven't seen this spot."]);
+        game.map[(6, 5)].explored = true;
+        assert_eq!(look(&game, &objects), ["You remember the floor."]);
+        game.map[(6, 5)].visible = true;
+        assert_eq!(look(&game, &objects), ["You see the floor.", "A troll (unaware of you)"]);
+    }
+}
@@ -1,101 +1,1350 @@
 use std::cmp;
+use std::ops::{Index, IndexMut};
 
-use rand::{Rng, thread_rng};
+use rand::seq::SliceRandom;
+use rand::Rng;
 use rand::distributions::WeightedIndex;
-use tcod::colors::{LIGHT_YELLOW, SKY, VIOLET, WHITE};
-use tcod::colors;
+use serde::{Deserialize, Serialize};
 
-use crate::{Ai, DeathCallback, Equipment, Fighter, Item, Object, PLAYER, Slot, Tile};
+use crate::artifacts;
+use crate::bestiary::{self, MonsterTemplate};
+use crate::colors::{Color, FLAME, GOLD, LIGHT_GREEN, WHITE};
+use crate::colors;
+use crate::dungeon::{self, Branch};
+use crate::entities::Objects;
+use crate::items;
+use crate::light::LightSource;
+use crate::packs::{self, PackMember, PackTemplate};
+use crate::prefabs;
+use crate::trade::Trader;
+use crate::traps;
+use crate::{Ai, Fighter, Object, DIRECTIONS, PLAYER};
 
 // size of the map
 pub const MAP_WIDTH: i32 = 80;
 pub const MAP_HEIGHT: i32 = 43;
 
 // parameters for dungeon generator
+/// how many times a level is made again if it comes out broken
+const MAP_GENERATION_TRIES: u32 = 10;
 const ROOM_MAX_SIZE: i32 = 10;
 const ROOM_MIN_SIZE: i32 = 6;
 const MAX_ROOMS: i32 = 30;
 
-pub type Map = Vec<Vec<Tile>>;
+// binary space partitioning: parts are never split smaller than this, and
+// once a room or two would fit, they may be left whole with this chance
+const BSP_MIN_AREA: i32 = ROOM_MIN_SIZE + 2;
+const BSP_STOP_CHANCE: f64 = 0.3;
 
-pub fn make_map(objects: &mut Vec<Object>, level: u32) -> Map {
+// caves: how much starts out as rock, how often it's smoothed, how much of the
+// map the cave must cover, and how far apart things are placed in it
+const CAVE_ROCK_CHANCE: i32 = 45;
+const CAVE_SMOOTHING_ROUNDS: i32 = 5;
+const CAVE_MIN_OPEN_PERCENT: i32 = 35;
+const CAVE_AREA_SPACING: i32 = 14;
+
+/// chance in percent that a room (other than the first) is flooded; most of
+/// the sewer is
+const POOL_CHANCE: u32 = 15;
+const SEWER_POOL_CHANCE: u32 = 60;
+
+/// chance that a monster is found asleep
+const ASLEEP_CHANCE: f64 = 0.4;
+
+/// the chance in percent that a room on each dungeon level holds a whole
+/// pack of monsters rather than a few strays
+const PACK_CHANCE: &[Transition] = &[
+    Transition { level: 3, value: 10 },
+    Transition { level: 6, value: 20 },
+];
+/// how many spots in a room to try for each member of a pack
+const PACK_PLACEMENT_TRIES: i32 = 10;
+
+// treasure vaults
+const VAULT_SIZE: i32 = 6;
+const VAULT_PLACEMENT_TRIES: i32 = 30;
+const VAULT_LOOT_COUNT: usize = 3;
+
+// boss rooms
+const BOSS_ROOM_WIDTH: i32 = 16;
+const BOSS_ROOM_HEIGHT: i32 = 10;
+
+// rooms laid out by hand: the chance in percent of one on a level, and how
+// many ways round to try fitting it in
+const PREFAB_CHANCE: u32 = 40;
+const PREFAB_PLACEMENT_TRIES: i32 = 5;
+
+// secret rooms, behind a hidden passage
+const SECRET_ROOM_SIZE: i32 = 5;
+const SECRET_ROOM_ITEMS: usize = 2;
+
+/// the NPCs that may be somewhere on a level, with the chance in percent that
+/// each is, from each dungeon level on
+const NPC_CHANCES: &[(&str, &[Transition])] = &[
+    ("hermit", &[Transition { level: 2, value: 10 }]),
+    ("lost adventurer", &[Transition { level: 1, value: 8 }]),
+    ("wounded knight", &[Transition { level: 3, value: 8 }]),
+];
+
+// goblin peddlers
+const PEDDLER_CHANCE: u32 = 15;
+const PEDDLER_MIN_WARES: usize = 3;
+const PEDDLER_MAX_WARES: usize = 6;
+const PEDDLER_MIN_GOLD: i32 = 40;
+const PEDDLER_MAX_GOLD: i32 = 120;
+
+// gold lying about: the chance in percent of a pile in a room, and how much
+// more a pile can hold for every dungeon level
+const GOLD_CHANCE: u32 = 30;
+const GOLD_PER_LEVEL: i32 = 10;
+
+/// chance in percent that a doorway into a room gets a door
+const DOOR_CHANCE: u32 = 60;
+/// the item that unlocks a locked door
+pub const DOOR_KEY: &str = "door key";
+/// what lies on the last level of the main dungeon, for the player to carry
+/// back out
+pub const CROWN: &str = "crown of the ancient kings";
+
+/// how many tries to find a spot for each of a pair of teleporters
+const TELEPORTER_PLACEMENT_TRIES: i32 = 20;
+
+// light sources
+const BRAZIER_RADIUS: i32 = 8;
+const FUNGUS_RADIUS: i32 = 2;
+
+/// A tile of the map and its properties
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Tile {
+    pub blocked: bool,
+    pub explored: bool,
+    pub block_sight: bool,
+    /// the glyph of the item or feature the player last saw lying here
+    pub remembered: Option<(char, Color)>,
+    /// turns until the smoke hanging here clears; sight doesn't pass through smoke
+    #[serde(default)]
+    pub smoke: i32,
+    /// too deep to wade: only the player and swimming monsters go in
+    #[serde(default)]
+    pub deep_water: bool,
+    /// a locked vault door, which blocks like a wall until opened with a key
+    #[serde(default)]
+    pub locked: bool,
+    /// a teleporter, leading to its twin at the given position
+    #[serde(default)]
+    pub teleport: Option<(i32, i32)>,
+    /// a door, which blocks movement and sight while closed; a locked one
+    /// has to be unlocked with a key first
+    #[serde(default)]
+    pub door: Option<Door>,
+    /// a hidden passage, which passes for wall until the player finds it
+    #[serde(default)]
+    pub secret: bool,
+    /// how brightly the tile is lit, from 0 (dark) to 1
+    #[serde(skip)]
+    pub light: f32,
+    /// whether the tile is lit and in the player's line of sight, so that
+    /// anything standing on it could see the player
+    #[serde(skip)]
+    pub in_view: bool,
+    /// whether the player can currently see the tile: it's in view and the
+    /// player isn't blind
+    #[serde(skip)]
+    pub visible: bool,
+}
+
+impl Tile {
+    pub fn empty() -> Self {
+        Tile {
+            blocked: false,
+            explored: false,
+            block_sight: false,
+            remembered: None,
+            smoke: 0,
+            deep_water: false,
+            locked: false,
+            teleport: None,
+            door: None,
+            secret: false,
+            light: 0.0,
+            in_view: false,
+            visible: false,
+        }
+    }
+
+    pub fn wall() -> Self {
+        Tile {
+            blocked: true,
+            explored: false,
+            block_sight: true,
+            remembered: None,
+            smoke: 0,
+            deep_water: false,
+            locked: false,
+            teleport: None,
+            door: None,
+            secret: false,
+            light: 0.0,
+            in_view: false,
+            visible: false,
+        }
+    }
+
+    /// a closed door
+    pub fn door() -> Self {
+        Tile {
+            door: Some(Door::Closed),
+            ..Tile::wall()
+        }
+    }
+
+    /// a hidden passage, looking just like a wall
+    pub fn secret_passage() -> Self {
+        Tile {
+            secret: true,
+            ..Tile::wall()
+        }
+    }
+
+    /// whether this is a closed door that isn't locked, which anyone walking
+    /// into opens
+    pub fn opens(&self) -> bool {
+        self.door == Some(Door::Closed) && !self.locked
+    }
+
+    pub fn open_door(&mut self) {
+        self.door = Some(Door::Open);
+        self.blocked = false;
+        self.block_sight = false;
+        self.locked = false;
+    }
+
+    pub fn close_door(&mut self) {
+        self.door = Some(Door::Closed);
+        self.blocked = true;
+        self.block_sight = true;
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Door {
+    Open,
+    Closed,
+}
+
+/// The tiles of a level, stored row by row in a single flat `Vec`.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Map {
+    width: i32,
+    height: i32,
+    tiles: Vec<Tile>,
+    /// how brightly every tile is lit without any light source, as under
+    /// the open sky
+    #[serde(default)]
+    pub ambient_light: f32,
+}
+
+impl Map {
+    /// create a map of the given size with every tile set to `tile`
+    pub fn new(width: i32, height: i32, tile: Tile) -> Self {
+        assert!(width > 0 && height > 0, "Map dimensions must be positive.");
+        Map {
+            width,
+            height,
+            tiles: vec![tile; (width * height) as usize],
+            ambient_light: 0.0,
+        }
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    /// returns true if the tile storage matches the dimensions, which may not be
+    /// the case for a map read from a damaged save
+    pub fn is_consistent(&self) -> bool {
+        self.width > 0
+            && self.height > 0
+            && (self.width as usize).checked_mul(self.height as usize) == Some(self.tiles.len())
+    }
+
+    /// returns true if the coordinates are inside the map
+    pub fn in_bounds(&self, x: i32, y: i32) -> bool {
+        x >= 0 && x < self.width && y >= 0 && y < self.height
+    }
+
+    pub fn index_of(&self, x: i32, y: i32) -> Option<usize> {
+        if self.in_bounds(x, y) {
+            Some((y * self.width + x) as usize)
+        } else {
+            None
+        }
+    }
+
+    /// the tile at the given coordinates, or None if they are out of bounds
+    pub fn get(&self, x: i32, y: i32) -> Option<&Tile> {
+        self.index_of(x, y).map(|index| &self.tiles[index])
+    }
+
+    /// returns true if the tile is lit and in the player's line of sight,
+    /// even if the player is blind
+    pub fn is_in_view(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).map_or(false, |tile| tile.in_view)
+    }
+
+    /// returns true if the player can currently see the tile
+    pub fn is_visible(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).map_or(false, |tile| tile.visible)
+    }
+
+    pub fn get_mut(&mut self, x: i32, y: i32) -> Option<&mut Tile> {
+        self.index_of(x, y).map(move |index| &mut self.tiles[index])
+    }
+
+    /// returns true if sight can pass through the tile: it's not a wall, and
+    /// not filled with smoke
+    pub fn is_transparent(&self, x: i32, y: i32) -> bool {
+        self.get(x, y).map_or(false, |tile| !tile.block_sight && tile.smoke == 0)
+    }
+
+    /// fill the open tiles within `radius` of a spot with smoke lasting `turns`
+    pub fn add_smoke(&mut self, x: i32, y: i32, radius: i32, turns: i32) {
+        for ((tile_x, tile_y), tile) in self.iter_mut() {
+            let in_radius = (tile_x - x).pow(2) + (tile_y - y).pow(2) <= radius.pow(2);
+            if in_radius && !tile.blocked {
+                tile.smoke = cmp::max(tile.smoke, turns);
+            }
+        }
+    }
+
+    /// where the open doors are; sight passes through these, so when they
+    /// change what the player sees does too
+    pub fn open_doors(&self) -> Vec<(i32, i32)> {
+        self.iter()
+            .filter(|(_, tile)| tile.door == Some(Door::Open))
+            .map(|(pos, _)| pos)
+            .collect()
+    }
+
+    /// returns true if there's smoke anywhere on the map
+    pub fn has_smoke(&self) -> bool {
+        self.tiles.iter().any(|tile| tile.smoke > 0)
+    }
+
+    /// let a turn's worth of smoke clear away
+    pub fn thin_smoke(&mut self) {
+        for tile in self.tiles.iter_mut() {
+            if tile.smoke > 0 {
+                tile.smoke -= 1;
+            }
+        }
+    }
+
+    /// iterate over every tile together with its coordinates
+    pub fn iter(&self) -> impl Iterator<Item = ((i32, i32), &Tile)> {
+        let width = self.width;
+        self.tiles
+            .iter()
+            .enumerate()
+            .map(move |(index, tile)| ((index as i32 % width, index as i32 / width), tile))
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = ((i32, i32), &mut Tile)> {
+        let width = self.width;
+        self.tiles
+            .iter_mut()
+            .enumerate()
+            .map(move |(index, tile)| ((index as i32 % width, index as i32 / width), tile))
+    }
+}
+
+impl Index<(i32, i32)> for Map {
+    type Output = Tile;
+
+    /// Panics when the coordinates are out of bounds.
+    fn index(&self, (x, y): (i32, i32)) -> &Tile {
+        self.get(x, y)
+            .unwrap_or_else(|| panic!("Tile ({}, {}) is outside the map.", x, y))
+    }
+}
+
+impl IndexMut<(i32, i32)> for Map {
+    fn index_mut(&mut self, (x, y): (i32, i32)) -> &mut Tile {
+        self.get_mut(x, y)
+            .unwrap_or_else(|| panic!("Tile ({}, {}) is outside the map.", x, y))
+    }
+}
+
+/// Spots marked on a hand-made map, by the character they're marked with.
+pub type Marks = Vec<(char, (i32, i32))>;
+
+/// Read a map laid out by hand: `#` is wall, `.` floor, `~` deep water and
+/// `+` a closed door. It's centered on a level-sized map of wall. Any other
+/// character marks a spot to put something; those are returned with where
+/// they are, standing on floor.
+pub fn load_static_map(text: &str) -> Result<(Map, Marks), String> {
+    let rows: Vec<&str> = text.lines().collect();
+    let width = rows.iter().map(|row| row.chars().count()).max().unwrap_or(0) as i32;
+    let height = rows.len() as i32;
+    if width > MAP_WIDTH || height > MAP_HEIGHT {
+        return Err(format!("the map is {}x{}, more than a level holds", width, height));
+    }
+    let (left, top) = ((MAP_WIDTH - width) / 2, (MAP_HEIGHT - height) / 2);
+
+    let mut map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
+    let mut marks = vec![];
+    for (y, row) in rows.iter().enumerate() {
+        for (x, char) in row.chars().enumerate() {
+            let pos = (left + x as i32, top + y as i32);
+            map[pos] = match drawn_tile(char) {
+                Some(tile) => tile,
+                None if char.is_alphanumeric() || "@<>".contains(char) => {
+                    marks.push((char, pos));
+                    Tile::empty()
+                }
+                None => return Err(format!("unknown map character '{}' at row {}", char, y + 1)),
+            };
+        }
+    }
+    Ok((map, marks))
+}
+
+/// the tile a character stands for in a map or prefab laid out by hand, if
+/// it's one of the tiles
+fn drawn_tile(char: char) -> Option<Tile> {
+    match char {
+        '#' => Some(Tile::wall()),
+        '.' => Some(Tile::empty()),
+        '~' => Some(Tile {
+            deep_water: true,
+            ..Tile::empty()
+        }),
+        '+' => Some(Tile::door()),
+        _ => None,
+    }
+}
+
+/// Make a level of the given branch, with the player placed in it. Should a
+/// level come out broken, as `validate` finds, it's made again, up to a few
+/// times; after that the last try is kept anyway.
+pub fn make_map(objects: &mut Objects, level: u32, branch: Branch, rng: &mut impl Rng) -> Map {
+    let mut tries = 1;
+    loop {
+        let map = generate_map(objects, level, branch, rng);
+        if tries == MAP_GENERATION_TRIES || validate(&map, objects, level, branch).is_ok() {
+            return map;
+        }
+        tries += 1;
+    }
+}
+
+/// Check what every generated level must have: the stairs down (unless it's
+/// the bottom of its branch), back up and into any side branch starting on
+/// it, and the crown on the last level of the main dungeon, all reachable
+/// from the player, locked doors and all; and nothing stuck inside a wall.
+pub fn validate(map: &Map, objects: &Objects, level: u32, branch: Branch) -> Result<(), String> {
+    if !map.is_consistent() {
+        return Err("the map's tiles don't match its size".into());
+    }
+    for object in objects {
+        match map.get(object.x, object.y) {
+            None => return Err(format!("{} is off the map", object.name)),
+            Some(tile) if tile.blocked => {
+                return Err(format!("{} is inside a wall at {:?}", object.name, object.pos()));
+            }
+            Some(_) => {}
+        }
+    }
+
+    let mut landmarks = vec![dungeon::UP_STAIRS.to_string()];
+    if branch.bottom() != Some(level) {
+        landmarks.push(dungeon::DOWN_STAIRS.to_string());
+    } else if branch == Branch::Main {
+        landmarks.push(CROWN.to_string());
+    }
+    if branch == Branch::Main {
+        let side_branches = Branch::SIDE_BRANCHES.iter();
+        landmarks.extend(
+            side_branches
+                .filter(|side_branch| side_branch.entrance() == Some(level))
+                .map(|side_branch| side_branch.stairs_name()),
+        );
+    }
+    let reachable = reachable_from(objects[PLAYER].pos(), map, |tile| {
+        !tile.blocked || tile.door.is_some()
+    });
+    for name in landmarks {
+        let found = objects.iter().find(|object| object.name == name);
+        let landmark = found.ok_or_else(|| format!("no {} on the level", name))?;
+        if !reachable[map.index_of(landmark.x, landmark.y).unwrap()] {
+            return Err(format!("the {} can't be reached", name));
+        }
+    }
+    Ok(())
+}
+
+fn generate_map(objects: &mut Objects, level: u32, branch: Branch, rng: &mut impl Rng) -> Map {
     // fill map with "blocked" tiles
-    let mut map = vec![vec![Tile::wall(); MAP_HEIGHT as usize]; MAP_WIDTH as usize];
+    let (width, height) = branch.map_size();
+    let mut map = Map::new(width, height, Tile::wall());
 
     // player is the first element, remove everything else.
     // NOTE: works only when the player is the first object!
     assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
     objects.truncate(1);
 
-    let mut rooms = vec![];
+    // dig out the level, keeping the rooms (or the parts of a cave) that
+    // things are placed in
+    let pool_chance = match branch {
+        Branch::Sewer => SEWER_POOL_CHANCE,
+        _ => POOL_CHANCE,
+    };
+    let rooms = match choose_generator(level, branch, rng) {
+        MapGenerator::ScatteredRooms => {
+            dig_layout(scatter_rooms(width, height, rng), pool_chance, &mut map, rng)
+        }
+        MapGenerator::Bsp => dig_layout(bsp_rooms(width, height, rng), pool_chance, &mut map, rng),
+        MapGenerator::Caves => dig_caves(&mut map, rng),
+    };
 
-    for _ in 0..MAX_ROOMS {
-        // random width and height
-        let w = thread_rng().gen_range(ROOM_MIN_SIZE..(ROOM_MAX_SIZE + 1));
-        let h = thread_rng().gen_range(ROOM_MIN_SIZE..(ROOM_MAX_SIZE + 1));
-        // random position without going out of the boundaries of the map
-        let x = thread_rng().gen_range(0..(MAP_WIDTH - w));
-        let y = thread_rng().gen_range(0..(MAP_HEIGHT - h));
+    // the player starts in the first room. place the player before the
+    // monsters so that none spawns on top of them
+    let (start_x, start_y) = rooms[0].center();
+    objects[PLAYER].set_pos(start_x, start_y);
 
-        let new_room = Rect::new(x, y, w, h);
+    // with the stairs back up under their feet
+    let up_stairs = Object::new(start_x, start_y, '>', dungeon::UP_STAIRS, WHITE, false);
+    objects.push(up_stairs);
 
-        // run through the other rooms and see if they intersect with this one
-        let failed = rooms
-            .iter()
-            .any(|other_room| new_room.intersects_with(other_room));
+    // add some content to each room, such as monsters
+    for &room in &rooms {
+        place_objects(room, &map, objects, level, branch, rng);
+    }
 
-        if !failed {
-            // this means there are no intersections, so this room is valid
+    // create stairs at the center of the last room, unless this is as deep
+    // as the branch goes; on some levels of the main dungeon, a boss guards
+    // them in a room of its own
+    if branch.bottom() != Some(level) {
+        let boss = match branch {
+            Branch::Main => bestiary::boss_of(level),
+            _ => None,
+        };
+        let stairs_room = match boss {
+            Some(boss) => place_boss_room(boss, &rooms, &mut map, objects, rng),
+            None => rooms[rooms.len() - 1],
+        };
+        let (stairs_x, stairs_y) = stairs_room.center();
+        let stairs = Object::new(stairs_x, stairs_y, '<', dungeon::DOWN_STAIRS, WHITE, false);
+        objects.push(stairs);
+    } else if branch == Branch::Main {
+        // at the very bottom, in the last room, is what the player came for
+        let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
+        objects.push(make_item(CROWN, last_room_x, last_room_y));
+    }
+
+    // and the stairs into any side branch starting here, somewhere between
+    let side_branch = Branch::SIDE_BRANCHES
+        .iter()
+        .find(|side_branch| branch == Branch::Main && side_branch.entrance() == Some(level));
+    if let (Some(side_branch), Some(room)) = (side_branch, rooms[1..rooms.len() - 1].choose(rng)) {
+        let (x, y) = room.center();
+        let color = side_branch.palette().light_ground;
+        objects.push(Object::new(x, y, '<', &side_branch.stairs_name(), color, false));
+    }
+
+    // perhaps a room laid out by hand
+    if rng.gen_range(0..100) < PREFAB_CHANCE {
+        place_prefab(&rooms, &mut map, objects, level, branch, rng);
+    }
 
-            // "paint" it to the map's tiles
-            create_room(new_room, &mut map);
+    // there may be a room that's hidden away
+    let secret_room_chance = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 20 },
+            Transition { level: 4, value: 35 },
+        ],
+        level,
+    );
+    if rng.gen_range(0..100) < secret_room_chance {
+        place_secret_room(&rooms, &mut map, objects, level, branch, rng);
+    }
 
-            // add some content to this room, such as monsters
-            place_objects(new_room, &map, objects, level);
+    // deeper down, there may be a vault
+    let vault_chance = from_dungeon_level(
+        &[
+            Transition {
+                level: 3,
+                value: 25,
+            },
+            Transition {
+                level: 6,
+                value: 50,
+            },
+        ],
+        level,
+    );
+    if rng.gen_range(0..100) < vault_chance {
+        place_vault(&rooms, &mut map, objects, rng);
+    }
+
+    // and one of the doors may be locked, with a monster carrying the key
+    let locked_door_chance = from_dungeon_level(&[Transition { level: 2, value: 30 }], level);
+    if rng.gen_range(0..100) < locked_door_chance {
+        lock_door(&mut map, objects, rng);
+    }
+
+    // teleporters go in last, so no tunnel digs through them
+    let teleporter_chance = from_dungeon_level(
+        &[
+            Transition {
+                level: 2,
+                value: 20,
+            },
+            Transition {
+                level: 5,
+                value: 35,
+            },
+        ],
+        level,
+    );
+    if rooms.len() > 2 && rng.gen_range(0..100) < teleporter_chance {
+        place_teleporters(&rooms[1..], &mut map, objects, rng);
+    }
+
+    // now and then, someone down here is willing to talk, or to trade
+    for &(name, chance) in NPC_CHANCES {
+        if rooms.len() > 1 && rng.gen_range(0..100) < from_dungeon_level(chance, level) {
+            let npc = bestiary::template(name).spawn(0, 0);
+            place_npc(npc, &rooms[1..], &map, objects, rng);
+        }
+    }
+    if rooms.len() > 1 && rng.gen_range(0..100) < PEDDLER_CHANCE {
+        let peddler = make_peddler(level, branch, rng);
+        place_npc(peddler, &rooms[1..], &map, objects, rng);
+    }
+
+    map
+}
+
+/// Link two rooms with a pair of teleporters, each leading to the other. They
+/// are kept off the room centers, where the tunnels come in.
+fn place_teleporters(rooms: &[Rect], map: &mut Map, objects: &Objects, rng: &mut impl Rng) {
+    // two different rooms
+    let first = rng.gen_range(0..rooms.len());
+    let second = (first + rng.gen_range(1..rooms.len())) % rooms.len();
+    let mut find_spot = |room: &Rect, map: &Map| {
+        (0..TELEPORTER_PLACEMENT_TRIES).find_map(|_| {
+            let x = rng.gen_range((room.x1 + 1)..room.x2);
+            let y = rng.gen_range((room.y1 + 1)..room.y2);
+            let free = !is_blocked(x, y, map, objects)
+                && !map[(x, y)].deep_water
+                && map[(x, y)].teleport.is_none()
+                && (x, y) != room.center();
+            free.then_some((x, y))
+        })
+    };
+    let spots = (find_spot(&rooms[first], map), find_spot(&rooms[second], map));
+    if let (Some(first), Some(second)) = spots {
+        map[first].teleport = Some(second);
+        map[second].teleport = Some(first);
+    }
+}
+
+/// put an NPC on a free, dry tile in one of the given rooms
+fn place_npc(
+    mut npc: Object,
+    rooms: &[Rect],
+    map: &Map,
+    objects: &mut Objects,
+    rng: &mut impl Rng,
+) {
+    let room = rooms.choose(rng).unwrap();
+    let x = rng.gen_range((room.x1 + 1)..room.x2);
+    let y = rng.gen_range((room.y1 + 1)..room.y2);
+    if !is_blocked(x, y, map, objects) && !map[(x, y)].deep_water {
+        npc.set_pos(x, y);
+        objects.push(npc);
+    }
+}
+
+/// a goblin peddler, carrying a few of the items found at this depth
+fn make_peddler(level: u32, branch: Branch, rng: &mut impl Rng) -> Object {
+    let count = rng.gen_range(PEDDLER_MIN_WARES..=PEDDLER_MAX_WARES);
+    let wares = (0..count).map(|_| random_item(0, 0, level, branch, rng)).collect();
+    let mut peddler = bestiary::template("goblin peddler").spawn(0, 0);
+    peddler.trader = Some(Trader {
+        wares,
+        gold: rng.gen_range(PEDDLER_MIN_GOLD..=PEDDLER_MAX_GOLD),
+        shop: None,
+    });
+    peddler
+}
+
+/// The tutorial level: a row of rooms that teach one thing each, from the
+/// player's start to the stairs. Moving, then picking up and using items,
+/// fighting, targeting a scroll, and finally the stairs.
+pub fn make_tutorial_map(objects: &mut Objects) -> Map {
+    let mut map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
+    assert_eq!(&objects[PLAYER] as *const _, &objects[0] as *const _);
+    objects.truncate(1);
+
+    let rooms: Vec<Rect> = (0..5).map(|i| Rect::new(2 + i * 15, 16, 10, 8)).collect();
+    for (i, &room) in rooms.iter().enumerate() {
+        create_room(room, &mut map);
+        objects.push(make_brazier(room.x1 + 1, room.y1 + 1));
+        if i > 0 {
+            let (prev_x, y) = rooms[i - 1].center();
+            create_h_tunnel(prev_x, room.center().0, y, &mut map);
+        }
+    }
 
-            // center coordinates of the new room, will be useful later
-            let (new_x, new_y) = new_room.center();
+    let (x, y) = rooms[0].center();
+    objects[PLAYER].set_pos(x, y);
 
-            if rooms.is_empty() {
-                // this is the first room, where the player starts at
-                objects[PLAYER].set_pos(new_x, new_y)
+    // something to drink, and something to wield
+    let (x, y) = rooms[1].center();
+    objects.push(make_item("healing potion", x - 2, y - 1));
+    objects.push(make_item("sword", x + 2, y + 1));
+
+    // an orc to fight
+    let (x, y) = rooms[2].center();
+    objects.push(tutorial_orc(x + 2, y));
+
+    // a scroll that needs a target, and another orc to try it on
+    let (x, y) = rooms[3].center();
+    objects.push(make_item("scroll of confusion", x - 2, y));
+    objects.push(tutorial_orc(x + 3, y - 2));
+
+    let (x, y) = rooms[4].center();
+    objects.push(Object::new(x, y, '<', "stairs", WHITE, false));
+
+    map
+}
+
+/// a weaker orc than usual, that goes straight for the player
+fn tutorial_orc(x: i32, y: i32) -> Object {
+    let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
+    orc.fighter = Some(Fighter {
+        base_max_hp: 10,
+        hp: 10,
+        base_defense: 0,
+        base_power: 3,
+        perception: 6,
+        sight_radius: 8,
+        xp: 35,
+        ..Default::default()
+    });
+    orc.ai = Some(Ai::Basic);
+    orc.alive = true;
+    orc
+}
+
+/// The ways the rooms of a level can be laid out.
+enum MapGenerator {
+    /// rooms dropped anywhere they fit, each joined to the one before
+    ScatteredRooms,
+    /// the map split in halves, and those in halves again, with a room in
+    /// each part and every two halves joined
+    Bsp,
+    /// one winding cave, grown by cellular automata
+    Caves,
+}
+
+/// Rooms, and the pairs of points to dig tunnels between. The player starts in
+/// the first room, and the stairs are in the last.
+struct Layout {
+    rooms: Vec<Rect>,
+    tunnels: Vec<((i32, i32), (i32, i32))>,
+}
+
+/// the first level is always scattered rooms; deeper ones are often more
+/// orderly, or natural caves. Side branches are always dug the same way.
+fn choose_generator(level: u32, branch: Branch, rng: &mut impl Rng) -> MapGenerator {
+    match branch {
+        Branch::Main => {}
+        Branch::Mines => return MapGenerator::Caves,
+        Branch::Crypt => return MapGenerator::Bsp,
+        Branch::Sewer => return MapGenerator::ScatteredRooms,
+    }
+    let bsp_chance = from_dungeon_level(
+        &[Transition {
+            level: 2,
+            value: 50,
+        }],
+        level,
+    );
+    let caves_chance = from_dungeon_level(
+        &[
+            Transition {
+                level: 3,
+                value: 20,
+            },
+            Transition {
+                level: 6,
+                value: 40,
+            },
+        ],
+        level,
+    );
+    let generator_dist = WeightedIndex::new([50, bsp_chance, caves_chance]).unwrap();
+    match rng.sample(&generator_dist) {
+        0 => MapGenerator::ScatteredRooms,
+        1 => MapGenerator::Bsp,
+        _ => MapGenerator::Caves,
+    }
+}
+
+/// dig the rooms and tunnels of a layout into the map, and return the rooms
+fn dig_layout(layout: Layout, pool_chance: u32, map: &mut Map, rng: &mut impl Rng) -> Vec<Rect> {
+    // "paint" the rooms to the map's tiles
+    for &room in &layout.rooms {
+        create_room(room, map);
+    }
+    // tunnels are dug afterwards, leaving a dry path through the pool
+    for &room in &layout.rooms[1..] {
+        if rng.gen_range(0..100) < pool_chance {
+            create_pool(room, map);
+        }
+    }
+    for &((from_x, from_y), (to_x, to_y)) in &layout.tunnels {
+        // toss a coin (random bool value -- either true or false)
+        if rng.gen() {
+            // first move horizontally, then vertically
+            create_h_tunnel(from_x, to_x, from_y, map);
+            create_v_tunnel(from_y, to_y, to_x, map);
+        } else {
+            create_v_tunnel(from_y, to_y, from_x, map);
+            create_h_tunnel(from_x, to_x, to_y, map);
+        }
+    }
+    for &room in &layout.rooms {
+        place_doors(room, map, rng);
+    }
+    layout.rooms
+}
+
+/// Put doors in some of the places where tunnels broke through a room's
+/// walls: the gaps with wall on either side and open floor in front and
+/// behind.
+fn place_doors(room: Rect, map: &mut Map, rng: &mut impl Rng) {
+    let blocked = |map: &Map, x, y| map.get(x, y).map_or(true, |tile| tile.blocked);
+    for x in room.x1..=room.x2 {
+        for y in room.y1..=room.y2 {
+            let side = x == room.x1 || x == room.x2;
+            let end = y == room.y1 || y == room.y2;
+            if side == end || blocked(map, x, y) {
+                // a corner, the inside of the room, or still wall
+                continue;
+            }
+            let doorway = if side {
+                blocked(map, x, y - 1)
+                    && blocked(map, x, y + 1)
+                    && !blocked(map, x - 1, y)
+                    && !blocked(map, x + 1, y)
+            } else {
+                blocked(map, x - 1, y)
+                    && blocked(map, x + 1, y)
+                    && !blocked(map, x, y - 1)
+                    && !blocked(map, x, y + 1)
+            };
+            if doorway && rng.gen_range(0..100) < DOOR_CHANCE {
+                map[(x, y)] = Tile::door();
+            }
+        }
+    }
+}
+
+/// Dig a small room into solid rock, reached from the nearest room through a
+/// hidden passage, with a few items left in it.
+fn place_secret_room(
+    rooms: &[Rect],
+    map: &mut Map,
+    objects: &mut Objects,
+    level: u32,
+    branch: Branch,
+    rng: &mut impl Rng,
+) {
+    let room = match find_solid_rock(SECRET_ROOM_SIZE, SECRET_ROOM_SIZE, map, rng) {
+        Some(room) => room,
+        None => return,
+    };
+    dig_sealed_room(room, rooms, Tile::secret_passage(), map);
+    for _ in 0..SECRET_ROOM_ITEMS {
+        let x = rng.gen_range((room.x1 + 1)..room.x2);
+        let y = rng.gen_range((room.y1 + 1)..room.y2);
+        if !is_blocked(x, y, map, objects) {
+            objects.push(random_item(x, y, level, branch, rng));
+        }
+    }
+}
+
+/// the room whose center is nearest the given spot
+fn nearest_room((x, y): (i32, i32), rooms: &[Rect]) -> &Rect {
+    rooms
+        .iter()
+        .min_by_key(|room| {
+            let (room_x, room_y) = room.center();
+            (room_x - x).pow(2) + (room_y - y).pow(2)
+        })
+        .unwrap()
+}
+
+/// Stamp a prefab for this level into solid rock, turned and flipped any way,
+/// with a tunnel from one of its doors to the nearest room.
+fn place_prefab(
+    rooms: &[Rect],
+    map: &mut Map,
+    objects: &mut Objects,
+    level: u32,
+    branch: Branch,
+    rng: &mut impl Rng,
+) {
+    let prefab = match prefabs::random_prefab(level, branch, rng) {
+        Some(prefab) => prefab,
+        None => return,
+    };
+    for _ in 0..PREFAB_PLACEMENT_TRIES {
+        let grid = prefab.oriented(rng);
+        let (width, height) = (grid[0].len() as i32, grid.len() as i32);
+        // with a margin of rock all round, for the tunnel to start from
+        let rock = match find_solid_rock(width + 1, height + 1, map, rng) {
+            Some(rock) => rock,
+            None => continue,
+        };
+        let (left, top) = (rock.x1 + 1, rock.y1 + 1);
+        let (center_x, center_y) = (left + width / 2, top + height / 2);
+        let (target_x, target_y) = nearest_room((center_x, center_y), rooms).center();
+
+        // the tunnel leaves from outside a door on the side facing that room
+        let mut doors = grid.iter().enumerate().flat_map(|(y, row)| {
+            row.iter()
+                .enumerate()
+                .filter(|&(_, &char)| char == '+')
+                .map(move |(x, _)| (x as i32, y as i32))
+        });
+        let entrance = doors.find_map(|(x, y)| {
+            let (dx, dy) = match (x, y) {
+                (0, _) => (-1, 0),
+                (_, 0) => (0, -1),
+                _ if x == width - 1 => (1, 0),
+                _ if y == height - 1 => (0, 1),
+                _ => return None,
+            };
+            let (out_x, out_y) = (left + x + dx, top + y + dy);
+            let facing = (dx < 0 && target_x <= out_x)
+                || (dx > 0 && target_x >= out_x)
+                || (dy < 0 && target_y <= out_y)
+                || (dy > 0 && target_y >= out_y);
+            if facing {
+                Some((out_x, out_y, dx != 0))
             } else {
-                // all rooms after the first:
-                // connect it to the previous room with a tunnel
-
-                // center coordinates of the previous room
-                let (prev_x, prev_y) = rooms[rooms.len() - 1].center();
-
-                // toss a coin (random bool value -- either true or false)
-                if rand::random() {
-                    // first move horizontally, then vertically
-                    create_h_tunnel(prev_x, new_x, prev_y, &mut map);
-                    create_v_tunnel(prev_y, new_y, new_x, &mut map);
-                } else {
-                    create_v_tunnel(prev_y, new_y, prev_x, &mut map);
-                    create_h_tunnel(prev_x, new_x, new_y, &mut map);
+                None
+            }
+        });
+        let (out_x, out_y, sideways) = match entrance {
+            Some(entrance) => entrance,
+            None => continue,
+        };
+
+        for (y, row) in grid.iter().enumerate() {
+            for (x, &char) in row.iter().enumerate() {
+                let (x, y) = (left + x as i32, top + y as i32);
+                map[(x, y)] = drawn_tile(char).unwrap_or_else(Tile::empty);
+                match char {
+                    'M' => objects.push(random_monster(x, y, level, branch, rng)),
+                    'I' => objects.push(random_item(x, y, level, branch, rng)),
+                    '$' => objects.push(random_gold(x, y, level, rng)),
+                    '^' => objects.push(traps::random_trap(x, y, level, rng)),
+                    _ => {}
                 }
             }
+        }
+        if sideways {
+            create_h_tunnel(out_x, target_x, out_y, map);
+            create_v_tunnel(out_y, target_y, target_x, map);
+        } else {
+            create_v_tunnel(out_y, target_y, out_x, map);
+            create_h_tunnel(out_x, target_x, target_y, map);
+        }
+        return;
+    }
+}
+
+/// a rectangle of the given size where nothing has been dug yet, if one is
+/// found
+fn find_solid_rock(width: i32, height: i32, map: &Map, rng: &mut impl Rng) -> Option<Rect> {
+    if width >= map.width() || height >= map.height() {
+        return None;
+    }
+    (0..VAULT_PLACEMENT_TRIES)
+        .map(|_| {
+            let x = rng.gen_range(0..(map.width() - width));
+            let y = rng.gen_range(0..(map.height() - height));
+            Rect::new(x, y, width, height)
+        })
+        .find(|room| (room.x1..=room.x2).all(|x| (room.y1..=room.y2).all(|y| map[(x, y)].blocked)))
+}
 
-            // finally, append the new room to the list
-            rooms.push(new_room);
+/// Dig out a room and a tunnel from it to the nearest of `rooms`, then put
+/// `entrance` wherever the tunnel broke through the room's walls.
+fn dig_sealed_room(room: Rect, rooms: &[Rect], entrance: Tile, map: &mut Map) {
+    create_room(room, map);
+    let (x, y) = room.center();
+    let (nearest_x, nearest_y) = nearest_room((x, y), rooms).center();
+    create_h_tunnel(x, nearest_x, y, map);
+    create_v_tunnel(y, nearest_y, nearest_x, map);
+
+    for wall_x in room.x1..=room.x2 {
+        for wall_y in room.y1..=room.y2 {
+            let on_wall = wall_x == room.x1
+                || wall_x == room.x2
+                || wall_y == room.y1
+                || wall_y == room.y2;
+            if on_wall && !map[(wall_x, wall_y)].blocked {
+                map[(wall_x, wall_y)] = entrance;
+            }
         }
     }
+}
 
-    // create stairs at the center of the last room
-    let (last_room_x, last_room_y) = rooms[rooms.len() - 1].center();
-    let mut stairs = Object::new(last_room_x, last_room_y, '<', "stairs", WHITE, false);
-    stairs.always_visible = true;
-    objects.push(stairs);
+/// Lock one of the level's doors, and give its key to a monster the player
+/// can get to without going through it. If no monster can be got to, the
+/// door is left unlocked.
+fn lock_door(map: &mut Map, objects: &mut Objects, rng: &mut impl Rng) {
+    let doors: Vec<_> = map
+        .iter()
+        .filter(|(_, tile)| tile.door.is_some())
+        .map(|(pos, _)| pos)
+        .collect();
+    let door = match doors.choose(rng) {
+        Some(&door) => door,
+        None => return,
+    };
+    map[door].locked = true;
 
-    map
+    let reachable = reachable_from(objects[PLAYER].pos(), map, |tile| {
+        !tile.blocked || tile.opens()
+    });
+    let holders: Vec<_> = (0..objects.len())
+        .filter(|&id| {
+            let object = &objects[id];
+            id != PLAYER
+                && object.ai.is_some()
+                && map.index_of(object.x, object.y).map_or(false, |index| reachable[index])
+        })
+        .collect();
+    match holders.choose(rng) {
+        Some(&holder) => objects[holder].drops = Some(DOOR_KEY.into()),
+        None => map[door].locked = false,
+    }
+}
+
+/// which tiles can be walked to from the given spot, through those tiles
+/// `can_pass` allows, by index into the map
+fn reachable_from(
+    (x, y): (i32, i32),
+    map: &Map,
+    can_pass: impl Fn(&Tile) -> bool,
+) -> Vec<bool> {
+    let mut reachable = vec![false; (map.width() * map.height()) as usize];
+    let passable = |x, y| map.get(x, y).map_or(false, &can_pass);
+    let mut frontier = vec![(x, y)];
+    if let Some(index) = map.index_of(x, y) {
+        reachable[index] = true;
+    }
+    while let Some((x, y)) = frontier.pop() {
+        for &(dx, dy) in DIRECTIONS.iter() {
+            let (next_x, next_y) = (x + dx, y + dy);
+            if let Some(index) = map.index_of(next_x, next_y) {
+                if passable(next_x, next_y) && !reachable[index] {
+                    reachable[index] = true;
+                    frontier.push((next_x, next_y));
+                }
+            }
+        }
+    }
+    reachable
+}
+
+/// Grow a cave: start from random rock, and smooth it over a few rounds so
+/// that tiles become whatever most of their neighbours are. Then only the
+/// largest connected part is kept, so that everything can be reached. Returns
+/// areas spread over the cave, each centered on open floor, to place things in.
+fn dig_caves(map: &mut Map, rng: &mut impl Rng) -> Vec<Rect> {
+    let (width, height) = (map.width(), map.height());
+    let index = |x: i32, y: i32| (y * width + x) as usize;
+    // the edge of the map is always rock
+    let inside = |i: i32| {
+        let (x, y) = (i % width, i / width);
+        x > 0 && y > 0 && x < width - 1 && y < height - 1
+    };
+
+    let open = loop {
+        let mut open: Vec<bool> = (0..width * height)
+            .map(|i| inside(i) && rng.gen_range(0..100) >= CAVE_ROCK_CHANCE)
+            .collect();
+        for _ in 0..CAVE_SMOOTHING_ROUNDS {
+            let mut next = open.clone();
+            for y in 1..(height - 1) {
+                for x in 1..(width - 1) {
+                    // count the rock in the tile and its eight neighbours
+                    let rock = (-1..=1)
+                        .flat_map(|dy| (-1..=1).map(move |dx| (dx, dy)))
+                        .filter(|&(dx, dy)| !open[index(x + dx, y + dy)])
+                        .count();
+                    next[index(x, y)] = rock < 5;
+                }
+            }
+            open = next;
+        }
+
+        let cave = largest_region(&open, width);
+        if cave.len() as i32 >= width * height * CAVE_MIN_OPEN_PERCENT / 100 {
+            break cave;
+        }
+    };
+
+    for &(x, y) in &open {
+        map[(x, y)] = Tile::empty();
+    }
+
+    // one area in every part of a grid laid over the map that has floor in it,
+    // from the top left to the bottom right
+    let mut areas = vec![];
+    for cell_y in (0..height).step_by(CAVE_AREA_SPACING as usize) {
+        for cell_x in (0..width).step_by(CAVE_AREA_SPACING as usize) {
+            let floor: Vec<&(i32, i32)> = open
+                .iter()
+                .filter(|&&(x, y)| {
+                    (cell_x..cell_x + CAVE_AREA_SPACING).contains(&x)
+                        && (cell_y..cell_y + CAVE_AREA_SPACING).contains(&y)
+                })
+                .collect();
+            if let Some(&&(x, y)) = floor.choose(rng) {
+                let half = CAVE_AREA_SPACING / 2;
+                areas.push(Rect::new(x - half, y - half, 2 * half, 2 * half));
+            }
+        }
+    }
+    areas
+}
+
+/// The tiles of the largest group of open tiles joined to each other. The edge
+/// of the map mustn't be open.
+fn largest_region(open: &[bool], width: i32) -> Vec<(i32, i32)> {
+    let mut seen = vec![false; open.len()];
+    let mut largest = vec![];
+    for start in 0..open.len() {
+        if !open[start] || seen[start] {
+            continue;
+        }
+        // flood fill from here
+        seen[start] = true;
+        let mut region = vec![];
+        let mut stack = vec![start];
+        while let Some(i) = stack.pop() {
+            let (x, y) = (i as i32 % width, i as i32 / width);
+            region.push((x, y));
+            for &(dx, dy) in DIRECTIONS.iter() {
+                let next = ((y + dy) * width + x + dx) as usize;
+                if open[next] && !seen[next] {
+                    seen[next] = true;
+                    stack.push(next);
+                }
+            }
+        }
+        if region.len() > largest.len() {
+            largest = region;
+        }
+    }
+    largest
+}
+
+fn scatter_rooms(width: i32, height: i32, rng: &mut impl Rng) -> Layout {
+    let mut layout = Layout {
+        rooms: vec![],
+        tunnels: vec![],
+    };
+
+    // as many tries as fit rooms on a map of the usual size, or more on a
+    // bigger one
+    let max_rooms = MAX_ROOMS * width * height / (MAP_WIDTH * MAP_HEIGHT);
+    for _ in 0..max_rooms {
+        // random width and height
+        let w = rng.gen_range(ROOM_MIN_SIZE..(ROOM_MAX_SIZE + 1));
+        let h = rng.gen_range(ROOM_MIN_SIZE..(ROOM_MAX_SIZE + 1));
+        // random position without going out of the boundaries of the map
+        let x = rng.gen_range(0..(width - w));
+        let y = rng.gen_range(0..(height - h));
+
+        let new_room = Rect::new(x, y, w, h);
+
+        // run through the other rooms and see if they intersect with this one
+        let failed = layout
+            .rooms
+            .iter()
+            .any(|other_room| new_room.intersects_with(other_room));
+
+        if !failed {
+            // all rooms after the first: connect it to the previous room
+            if let Some(prev_room) = layout.rooms.last() {
+                layout.tunnels.push((prev_room.center(), new_room.center()));
+            }
+            layout.rooms.push(new_room);
+        }
+    }
+    layout
+}
+
+fn bsp_rooms(width: i32, height: i32, rng: &mut impl Rng) -> Layout {
+    let mut layout = Layout {
+        rooms: vec![],
+        tunnels: vec![],
+    };
+    let whole_map = Rect::new(0, 0, width - 1, height - 1);
+    split_area(whole_map, rng, &mut layout);
+    layout
+}
+
+/// Split `area` in two, and each half again, until the parts are about room
+/// sized; then put a room in each part. The rooms on either side of every
+/// split are joined with a tunnel. Returns the index of one of the rooms in
+/// `area`, for the split above to connect to.
+fn split_area(area: Rect, rng: &mut impl Rng, layout: &mut Layout) -> usize {
+    let (w, h) = (area.x2 - area.x1, area.y2 - area.y1);
+    let can_split_across = w >= 2 * BSP_MIN_AREA;
+    let can_split_down = h >= 2 * BSP_MIN_AREA;
+    // parts that could hold more than one large room always get split; others
+    // sometimes don't, so that room sizes vary
+    let too_big = w > 2 * ROOM_MAX_SIZE || h > 2 * ROOM_MAX_SIZE;
+    let stop =
+        (!can_split_across && !can_split_down) || (!too_big && rng.gen_bool(BSP_STOP_CHANCE));
+    if stop {
+        let room_w = rng.gen_range(ROOM_MIN_SIZE..=cmp::min(ROOM_MAX_SIZE, w));
+        let room_h = rng.gen_range(ROOM_MIN_SIZE..=cmp::min(ROOM_MAX_SIZE, h));
+        let x = rng.gen_range(area.x1..=(area.x2 - room_w));
+        let y = rng.gen_range(area.y1..=(area.y2 - room_h));
+        layout.rooms.push(Rect::new(x, y, room_w, room_h));
+        return layout.rooms.len() - 1;
+    }
+
+    // split across the longer side, if there's a choice
+    let across = can_split_across && (!can_split_down || w > h);
+    let (first, second) = if across {
+        let cut = rng.gen_range((area.x1 + BSP_MIN_AREA)..=(area.x2 - BSP_MIN_AREA));
+        (Rect { x2: cut, ..area }, Rect { x1: cut, ..area })
+    } else {
+        let cut = rng.gen_range((area.y1 + BSP_MIN_AREA)..=(area.y2 - BSP_MIN_AREA));
+        (Rect { y2: cut, ..area }, Rect { y1: cut, ..area })
+    };
+    let first = split_area(first, rng, layout);
+    let second = split_area(second, rng, layout);
+    let (first_room, second_room) = (layout.rooms[first], layout.rooms[second]);
+    layout.tunnels.push((first_room.center(), second_room.center()));
+    if rng.gen() {
+        first
+    } else {
+        second
+    }
 }
 
 fn create_room(room: Rect, map: &mut Map) {
     // go through the tiles in the rectangle and make them passable
     for x in (room.x1 + 1)..room.x2 {
         for y in (room.y1 + 1)..room.y2 {
-            map[x as usize][y as usize] = Tile::empty();
+            map[(x, y)] = Tile::empty();
+        }
+    }
+}
+
+/// Dig a sealed vault into solid rock, full of loot and watched by a guardian
+/// as strong as the loot is valuable. It's joined to the nearest room by a
+/// tunnel behind a locked door, and the key is left lying in another room.
+fn place_vault(rooms: &[Rect], map: &mut Map, objects: &mut Objects, rng: &mut impl Rng) {
+    let vault = match find_solid_rock(VAULT_SIZE, VAULT_SIZE, map, rng) {
+        Some(vault) => vault,
+        None => return,
+    };
+    let locked_door = Tile {
+        locked: true,
+        ..Tile::wall()
+    };
+    dig_sealed_room(vault, rooms, locked_door, map);
+    let (vault_x, vault_y) = vault.center();
+
+    // fill the vault with loot
+    let loot_choices: Vec<_> = items::templates().iter().filter(|t| t.vault).collect();
+    let mut loot_value = 0;
+    for _ in 0..VAULT_LOOT_COUNT {
+        let x = rng.gen_range((vault.x1 + 1)..vault.x2);
+        let y = rng.gen_range((vault.y1 + 1)..vault.y2);
+        let loot = loot_choices.choose(rng).unwrap();
+        loot_value += loot.value;
+        objects.push(loot.spawn(x, y));
+    }
+
+    // the guardian stands just inside
+    let mut guardian = bestiary::template("vault guardian").spawn(vault_x, vault_y);
+    if let Some(fighter) = guardian.fighter.as_mut() {
+        fighter.base_max_hp += loot_value / 2;
+        fighter.hp = fighter.base_max_hp;
+        fighter.base_defense += loot_value / 50;
+        fighter.base_power += loot_value / 15;
+        fighter.xp += 2 * loot_value;
+    }
+    objects.push(guardian);
+
+    // and the key lies in some other room
+    let key_room = rooms.choose(rng).unwrap();
+    for _ in 0..VAULT_PLACEMENT_TRIES {
+        let x = rng.gen_range((key_room.x1 + 1)..key_room.x2);
+        let y = rng.gen_range((key_room.y1 + 1)..key_room.y2);
+        if !is_blocked(x, y, map, objects) && !map[(x, y)].deep_water {
+            objects.push(make_item("vault key", x, y));
+            break;
+        }
+    }
+}
+
+/// Dig a big room for the level's boss, lit by braziers, where it waits by the
+/// stairs down. If there's no room for one, the boss takes the last room
+/// instead. Returns the room the stairs go in.
+fn place_boss_room(
+    boss: &MonsterTemplate,
+    rooms: &[Rect],
+    map: &mut Map,
+    objects: &mut Objects,
+    rng: &mut impl Rng,
+) -> Rect {
+    let room = match find_solid_rock(BOSS_ROOM_WIDTH, BOSS_ROOM_HEIGHT, map, rng) {
+        Some(room) => {
+            dig_sealed_room(room, rooms, Tile::empty(), map);
+            objects.push(make_brazier(room.x1 + 1, room.y1 + 1));
+            objects.push(make_brazier(room.x2 - 1, room.y2 - 1));
+            room
+        }
+        None => rooms[rooms.len() - 1],
+    };
+    let (x, y) = room.center();
+    let spot = DIRECTIONS
+        .iter()
+        .map(|&(dx, dy)| (x + dx, y + dy))
+        .find(|&(x, y)| !is_blocked(x, y, map, objects));
+    if let Some((x, y)) = spot {
+        objects.push(boss.spawn(x, y));
+    }
+    room
+}
+
+/// flood a room, leaving a dry margin along its walls
+fn create_pool(room: Rect, map: &mut Map) {
+    for x in (room.x1 + 2)..(room.x2 - 1) {
+        for y in (room.y1 + 2)..(room.y2 - 1) {
+            map[(x, y)].deep_water = true;
         }
     }
 }
@@ -103,14 +1352,14 @@ fn create_room(room: Rect, map: &mut Map) {
 fn create_h_tunnel(x1: i32, x2: i32, y: i32, map: &mut Map) {
     // horizontal tunnel. `min()` and `max()` are used in case `x1 > x2`
     for x in cmp::min(x1, x2)..(cmp::max(x1, x2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        map[(x, y)] = Tile::empty();
     }
 }
 
 fn create_v_tunnel(y1: i32, y2: i32, x: i32, map: &mut Map) {
     // vertical tunnel
     for y in cmp::min(y1, y2)..(cmp::max(y1, y2) + 1) {
-        map[x as usize][y as usize] = Tile::empty();
+        map[(x, y)] = Tile::empty();
     }
 }
 
@@ -148,14 +1397,15 @@ impl Rect {
     }
 }
 
-struct Transition {
-    level: u32,
-    value: u32,
+#[derive(Debug, Deserialize)]
+pub struct Transition {
+    pub level: u32,
+    pub value: u32,
 }
 
 /// Returns a value that depends on level. The table specifies what
 /// value occurs after each level, default is 0.
-fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
+pub fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
     table
         .iter()
         .rev()
@@ -163,7 +1413,16 @@ fn from_dungeon_level(table: &[Transition], level: u32) -> u32 {
         .map_or(0, |transition| transition.value)
 }
 
-fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
+fn place_objects(
+    room: Rect,
+    map: &Map,
+    objects: &mut Objects,
+    level: u32,
+    branch: Branch,
+    rng: &mut impl Rng,
+) {
+    place_light_sources(room, map, objects, level, rng);
+
     // maximum number of monsters per room
     let max_monsters = from_dungeon_level(
         &[
@@ -174,74 +1433,29 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
         level,
     );
 
-    // choose random number of monsters
-    let num_monsters = thread_rng().gen_range(0..(max_monsters + 1));
-
-    // monster random table
-    let troll_chance = from_dungeon_level(
-        &[
-            Transition {
-                level: 3,
-                value: 15,
-            },
-            Transition {
-                level: 5,
-                value: 30,
-            },
-            Transition {
-                level: 7,
-                value: 60,
-            },
-        ],
-        level,
-    );
-
-    // monster random table
-    let monster_weights = [80, troll_chance];
-    let monster_choices = ["orc", "troll"];
-    let monster_dist = WeightedIndex::new(monster_weights).unwrap();
-    let mut monster_rng = thread_rng();
+    // sometimes a whole pack instead
+    let pack = if rng.gen_range(0..100) < from_dungeon_level(PACK_CHANCE, level) {
+        packs::random_pack(level, branch, rng)
+    } else {
+        None
+    };
+    let num_monsters = match pack {
+        Some(pack) => {
+            place_pack(pack, room, map, objects, rng);
+            0
+        }
+        // choose random number of monsters
+        None => rng.gen_range(0..(max_monsters + 1)),
+    };
 
     for _ in 0..num_monsters {
         // choose random spot for this monster
-        let x = thread_rng().gen_range((room.x1 + 1)..room.x2);
-        let y = thread_rng().gen_range((room.y1 + 1)..room.y2);
+        let x = rng.gen_range((room.x1 + 1)..room.x2);
+        let y = rng.gen_range((room.y1 + 1)..room.y2);
 
-        // only place it if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            let monster_choice = monster_choices[monster_rng.sample(&monster_dist)];
-            let mut monster = match monster_choice {
-                "orc" => {
-                    // create an orc
-                    let mut orc = Object::new(x, y, 'o', "orc", colors::DESATURATED_GREEN, true);
-                    orc.fighter = Some(Fighter {
-                        base_max_hp: 20,
-                        hp: 20,
-                        base_defense: 0,
-                        base_power: 4,
-                        xp: 35,
-                        on_death: DeathCallback::Monster,
-                    });
-                    orc.ai = Some(Ai::Basic);
-                    orc
-                }
-                "troll" => {
-                    let mut troll = Object::new(x, y, 'T', "troll", colors::DARKER_GREEN, true);
-                    troll.fighter = Some(Fighter {
-                        base_max_hp: 30,
-                        hp: 30,
-                        base_defense: 2,
-                        base_power: 8,
-                        xp: 100,
-                        on_death: DeathCallback::Monster,
-                    });
-                    troll.ai = Some(Ai::Basic);
-                    troll
-                }
-                _ => unreachable!(),
-            };
-
-            monster.alive = true;
+        // only place it if the tile is not blocked, nor under water
+        if !is_blocked(x, y, map, objects) && !map[(x, y)].deep_water {
+            let monster = random_monster(x, y, level, branch, rng);
             objects.push(monster);
         }
     }
@@ -255,128 +1469,234 @@ fn place_objects(room: Rect, map: &Map, objects: &mut Vec<Object>, level: u32) {
         level,
     );
 
-    // item random table
-    let item_weights = [
-        35,
-        from_dungeon_level(
-            &[Transition {
-                level: 4,
-                value: 25,
-            }],
-            level,
-        ),
-        from_dungeon_level(
-            &[Transition {
-                level: 6,
-                value: 25,
-            }],
-            level,
-        ),
-        from_dungeon_level(
-            &[Transition {
-                level: 2,
-                value: 10,
-            }],
-            level,
-        ),
-        from_dungeon_level(&[Transition { level: 4, value: 5 }], level),
-        from_dungeon_level(
-            &[Transition {
-                level: 8,
-                value: 15,
-            }],
-            level,
-        ),
-    ];
-    let item_choices = [
-        Item::Heal,
-        Item::Lightning,
-        Item::Fireball,
-        Item::Confuse,
-        Item::Sword,
-        Item::Shield,
-    ];
-
     // choose random number of items
-    let num_items = thread_rng().gen_range(0..(max_items + 1));
-
-    // monster random table
-    let item_dist = WeightedIndex::new(item_weights).unwrap();
-    let mut item_rng = thread_rng();
+    let num_items = rng.gen_range(0..(max_items + 1));
 
     for _ in 0..num_items {
         // choose random spot for this item
-        let x = thread_rng().gen_range((room.x1 + 1)..room.x2);
-        let y = thread_rng().gen_range((room.y1 + 1)..room.y2);
+        let x = rng.gen_range((room.x1 + 1)..room.x2);
+        let y = rng.gen_range((room.y1 + 1)..room.y2);
 
-        // only place if the tile is not blocked
-        if !is_blocked(x, y, map, objects) {
-            let item_choice = item_choices[item_rng.sample(&item_dist)];
-            let mut item = match item_choice {
-                Item::Heal => {
-                    // create a healing potion (70% chance)
-                    let mut object = Object::new(x, y, '!', "healing potion", VIOLET, false);
-                    object.item = Some(Item::Heal);
-                    object
-                }
-                Item::Lightning => {
-                    // create a lightning bolt scroll (30% chance)
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of lightning bolt", LIGHT_YELLOW, false);
-                    object.item = Some(Item::Lightning);
-                    object
-                }
-                Item::Fireball => {
-                    // create a fireball scroll (10% chance)
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of fireball", LIGHT_YELLOW, false);
-                    object.item = Some(Item::Fireball);
-                    object
-                }
-                Item::Confuse => {
-                    // create a confuse scroll (10% chance)
-                    let mut object =
-                        Object::new(x, y, '#', "scroll of confusion", LIGHT_YELLOW, false);
-                    object.item = Some(Item::Confuse);
-                    object
-                }
-                Item::Sword => {
-                    // create a sword
-                    let mut object = Object::new(x, y, '/', "sword", SKY, false);
-                    object.item = Some(Item::Sword);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::RightHand,
-                        max_hp_bonus: 0,
-                        power_bonus: 3,
-                        defense_bonus: 0,
-                    });
-                    object
-                }
-                Item::Shield => {
-                    // create a sword
-                    let mut object = Object::new(x, y, '[', "shield", SKY, false);
-                    object.item = Some(Item::Shield);
-                    object.equipment = Some(Equipment {
-                        equipped: false,
-                        slot: Slot::LeftHand,
-                        max_hp_bonus: 0,
-                        power_bonus: 0,
-                        defense_bonus: 1,
-                    });
-                    object
-                }
-            };
+        // only place if the tile is not blocked, nor under water
+        if !is_blocked(x, y, map, objects) && !map[(x, y)].deep_water {
+            let item = random_item(x, y, level, branch, rng);
 
-            item.always_visible = true;
             objects.push(item);
         }
     }
+
+    // maybe some gold, more of it the deeper
+    if rng.gen_range(0..100) < GOLD_CHANCE {
+        let x = rng.gen_range((room.x1 + 1)..room.x2);
+        let y = rng.gen_range((room.y1 + 1)..room.y2);
+        if !is_blocked(x, y, map, objects) && !map[(x, y)].deep_water {
+            objects.push(random_gold(x, y, level, rng));
+        }
+    }
+
+    // chance in percent of a hidden trap somewhere in the room
+    let trap_chance = from_dungeon_level(
+        &[
+            Transition { level: 1, value: 10 },
+            Transition { level: 3, value: 20 },
+            Transition { level: 6, value: 30 },
+        ],
+        level,
+    );
+    if rng.gen_range(0..100) < trap_chance {
+        let x = rng.gen_range((room.x1 + 1)..room.x2);
+        let y = rng.gen_range((room.y1 + 1)..room.y2);
+
+        // not under anything else, nor under water or on a teleporter
+        let taken = objects.iter().any(|object| object.pos() == (x, y));
+        let free = map.get(x, y).map_or(false, |tile| {
+            !tile.blocked && !tile.deep_water && tile.teleport.is_none()
+        });
+        if !taken && free {
+            objects.push(traps::random_trap(x, y, level, rng));
+        }
+    }
+}
+
+/// a pile of gold at the given spot
+pub fn make_gold(amount: i32, x: i32, y: i32) -> Object {
+    let mut gold = Object::new(x, y, '$', "gold", GOLD, false);
+    gold.gold = Some(amount);
+    gold
+}
+
+/// a pile of gold at the given spot, holding more the deeper the level
+fn random_gold(x: i32, y: i32, level: u32, rng: &mut impl Rng) -> Object {
+    make_gold(rng.gen_range(1..=GOLD_PER_LEVEL * level as i32), x, y)
+}
+
+/// create the object for the item with the given name, lying at the given spot
+pub fn make_item(name: &str, x: i32, y: i32) -> Object {
+    items::template(name).spawn(x, y)
+}
+
+/// Create an item at the given spot, picked from the items found at the given
+/// dungeon level of the given branch.
+pub fn random_item(x: i32, y: i32, level: u32, branch: Branch, rng: &mut impl Rng) -> Object {
+    // item random table
+    let templates = items::templates();
+    let item_weights: Vec<u32> = templates
+        .iter()
+        .map(|template| {
+            if branch.is_among(&template.branches) {
+                from_dungeon_level(&template.spawn, level)
+            } else {
+                0
+            }
+        })
+        .collect();
+    let item_dist = WeightedIndex::new(item_weights).unwrap();
+
+    let mut item = templates[rng.sample(&item_dist)].spawn(x, y);
+    items::roll_enchantment(&mut item, level, rng);
+    artifacts::maybe_make_artifact(&mut item, level, rng);
+    item
+}
+
+/// Create a monster at the given spot, picked from the monsters found at the
+/// given dungeon level of the given branch.
+pub fn random_monster(x: i32, y: i32, level: u32, branch: Branch, rng: &mut impl Rng) -> Object {
+    // monster random table
+    let templates = bestiary::templates();
+    let monster_weights: Vec<u32> = templates
+        .iter()
+        .map(|template| {
+            if branch.is_among(&template.branches) {
+                from_dungeon_level(&template.spawn, level)
+            } else {
+                0
+            }
+        })
+        .collect();
+    let monster_dist = WeightedIndex::new(monster_weights).unwrap();
+
+    let mut monster = templates[rng.sample(&monster_dist)].spawn(x, y);
+    monster.facing = *DIRECTIONS.choose(rng).unwrap();
+    if monster.ai == Some(Ai::Unaware) && rng.gen_bool(ASLEEP_CHANCE) {
+        monster.ai = Some(Ai::Asleep);
+    }
+    if let Some(gold) = monster.gold {
+        monster.gold = Some(rng.gen_range(1..=gold));
+    }
+    monster
+}
+
+/// Place a pack in a room: its leader first, then its followers about it,
+/// wherever there's space. Followers start out following, awake; without
+/// room for the leader, there's no pack at all.
+fn place_pack(
+    pack: &PackTemplate,
+    room: Rect,
+    map: &Map,
+    objects: &mut Objects,
+    rng: &mut impl Rng,
+) {
+    let mut leader = None;
+    let num_followers = rng.gen_range(pack.min_followers..=pack.max_followers);
+    let mut species = vec![pack.leader.as_str()];
+    for _ in 0..num_followers {
+        species.push(pack.followers.choose(rng).unwrap());
+    }
+
+    for (i, name) in species.into_iter().enumerate() {
+        let spot = (0..PACK_PLACEMENT_TRIES)
+            .map(|_| {
+                let x = rng.gen_range((room.x1 + 1)..room.x2);
+                let y = rng.gen_range((room.y1 + 1)..room.y2);
+                (x, y)
+            })
+            .find(|&(x, y)| !is_blocked(x, y, map, objects) && !map[(x, y)].deep_water);
+        let (x, y) = match spot {
+            Some(spot) => spot,
+            None if i == 0 => return,
+            None => continue,
+        };
+
+        let mut monster = bestiary::template(name).spawn(x, y);
+        monster.facing = *DIRECTIONS.choose(rng).unwrap();
+        monster.pack = Some(match leader {
+            Some(leader) => PackMember::Follower(leader),
+            None => PackMember::Leader,
+        });
+        if i > 0 {
+            monster.ai = Some(Ai::Follow);
+        }
+        if let Some(gold) = monster.gold {
+            monster.gold = Some(rng.gen_range(1..=gold));
+        }
+        objects.push(monster);
+        if i == 0 {
+            leader = Some(objects.id_of(objects.len() - 1));
+        }
+    }
+}
+
+/// Light up some rooms. The deeper the level, the fewer braziers are left
+/// burning and the more it's down to glowing fungus and the player's torch.
+fn place_light_sources(
+    room: Rect,
+    map: &Map,
+    objects: &mut Objects,
+    level: u32,
+    rng: &mut impl Rng,
+) {
+    let brazier_chance = from_dungeon_level(
+        &[
+            Transition {
+                level: 1,
+                value: 60,
+            },
+            Transition {
+                level: 4,
+                value: 35,
+            },
+            Transition {
+                level: 7,
+                value: 15,
+            },
+        ],
+        level,
+    );
+    // braziers stand in a corner, out of the way of the room's center (in
+    // caves, the corner may well be solid rock)
+    let (x, y) = (room.x1 + 1, room.y1 + 1);
+    if rng.gen_range(0..100) < brazier_chance && !is_blocked(x, y, map, objects) {
+        objects.push(make_brazier(x, y));
+    }
+
+    let max_fungus = from_dungeon_level(
+        &[Transition { level: 2, value: 1 }, Transition { level: 5, value: 3 }],
+        level,
+    );
+    for _ in 0..rng.gen_range(0..(max_fungus + 1)) {
+        let x = rng.gen_range((room.x1 + 1)..room.x2);
+        let y = rng.gen_range((room.y1 + 1)..room.y2);
+        if !is_blocked(x, y, map, objects) && !map[(x, y)].deep_water {
+            let mut fungus = Object::new(x, y, ',', "glowing fungus", LIGHT_GREEN, false);
+            fungus.light = Some(LightSource {
+                radius: FUNGUS_RADIUS,
+            });
+            objects.push(fungus);
+        }
+    }
+}
+
+fn make_brazier(x: i32, y: i32) -> Object {
+    let mut brazier = Object::new(x, y, '&', "brazier", FLAME, false);
+    brazier.light = Some(LightSource {
+        radius: BRAZIER_RADIUS,
+    });
+    brazier
 }
 
-pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
-    // first test the map tile
-    if map[x as usize][y as usize].blocked {
+pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &Objects) -> bool {
+    // first test the map tile (anything outside the map counts as blocked)
+    if map.get(x, y).map_or(true, |tile| tile.blocked) {
         return true;
     }
     // now check for any blocking objects
@@ -384,3 +1704,188 @@ pub fn is_blocked(x: i32, y: i32, map: &Map, objects: &[Object]) -> bool {
         .iter()
         .any(|object| object.blocks && object.pos() == (x, y))
 }
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn player() -> Object {
+        Object::new(0, 0, '@', "player", WHITE, true)
+    }
+
+    /// the levels of each branch, from the top of the main dungeon to the
+    /// bottom of every side branch
+    fn every_level() -> Vec<(Branch, u32)> {
+        let mut levels: Vec<_> = (1..=10).map(|level| (Branch::Main, level)).collect();
+        levels.push((Branch::Main, Branch::Main.bottom().unwrap()));
+        for branch in Branch::SIDE_BRANCHES {
+            let (entrance, bottom) = (branch.entrance().unwrap(), branch.bottom().unwrap());
+            levels.extend((entrance + 1..=bottom).map(|level| (branch, level)));
+        }
+        levels
+    }
+
+    /// two rooms with no way between them, the player and the stairs up in
+    /// one and the stairs down in the other
+    fn two_rooms() -> (Map, Objects) {
+        let mut map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
+        create_room(Rect::new(1, 1, 6, 6), &mut map);
+        create_room(Rect::new(20, 1, 6, 6), &mut map);
+        let mut objects = Objects::from(vec![player()]);
+        objects[PLAYER].set_pos(3, 3);
+        objects.push(Object::new(3, 3, '>', dungeon::UP_STAIRS, WHITE, false));
+        objects.push(Object::new(22, 3, '<', dungeon::DOWN_STAIRS, WHITE, false));
+        (map, objects)
+    }
+
+    #[test]
+    fn generated_levels_are_valid() {
+        for seed in 0..50 {
+            for (branch, level) in every_level() {
+                let mut rng = StdRng::seed_from_u64(seed);
+                let mut objects = Objects::from(vec![player()]);
+                let map = generate_map(&mut objects, level, branch, &mut rng);
+                if let Err(e) = validate(&map, &objects, level, branch) {
+                    panic!("seed {}, {:?} level {}: {}", seed, branch, level, e);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn same_seed_makes_the_same_level() {
+        let make = || {
+            let mut objects = Objects::from(vec![player()]);
+            let map = make_map(&mut objects, 4, Branch::Main, &mut StdRng::seed_from_u64(7));
+            let walls: Vec<_> = map.iter().map(|(_, tile)| tile.blocked).collect();
+            let things: Vec<_> = objects.iter().map(|o| (o.name.clone(), o.pos())).collect();
+            (walls, things)
+        };
+        assert_eq!(make(), make());
+    }
+
+    #[test]
+    fn caves_are_one_big_connected_cave() {
+        for seed in 0..10 {
+            let mut map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
+            let areas = dig_caves(&mut map, &mut StdRng::seed_from_u64(seed));
+            let open: Vec<bool> = map.iter().map(|(_, tile)| !tile.blocked).collect();
+            let floor = open.iter().filter(|&&open| open).count() as i32;
+            assert!(floor >= MAP_WIDTH * MAP_HEIGHT * CAVE_MIN_OPEN_PERCENT / 100);
+            assert_eq!(largest_region(&open, MAP_WIDTH).len() as i32, floor, "seed {}", seed);
+            for ((x, y), tile) in map.iter() {
+                let edge = x == 0 || y == 0 || x == MAP_WIDTH - 1 || y == MAP_HEIGHT - 1;
+                assert!(!edge || tile.blocked, "seed {}: ({}, {}) is open", seed, x, y);
+            }
+            assert!(!areas.is_empty());
+            for area in areas {
+                assert!(!map[area.center()].blocked, "seed {}: {:?}", seed, area.center());
+            }
+        }
+    }
+
+    #[test]
+    fn smoke_hides_what_is_behind_it_until_it_clears() {
+        let mut map = Map::new(10, 10, Tile::empty());
+        map[(1, 2)] = Tile::wall();
+        map.add_smoke(2, 2, 1, 2);
+        assert!(!map.is_transparent(2, 3) && !map.is_transparent(3, 2));
+        assert!(map.is_transparent(3, 3));
+        assert_eq!(map[(1, 2)].smoke, 0);
+        map.thin_smoke();
+        assert!(map.has_smoke());
+        map.thin_smoke();
+        assert!(!map.has_smoke() && map.is_transparent(2, 2));
+    }
+
+    #[test]
+    fn boss_levels_have_their_boss() {
+        for seed in 0..20 {
+            let mut objects = Objects::from(vec![player()]);
+            generate_map(&mut objects, 5, Branch::Main, &mut StdRng::seed_from_u64(seed));
+            let bosses: Vec<_> = objects.iter().filter(|o| o.boss).collect();
+            assert_eq!(bosses.len(), 1, "seed {}", seed);
+            assert_eq!(bosses[0].name, "orc warlord");
+        }
+    }
+
+    #[test]
+    fn validate_accepts_connected_rooms() {
+        let (mut map, objects) = two_rooms();
+        create_h_tunnel(6, 21, 3, &mut map);
+        // a locked door is still a way through, for whoever has the key
+        map[(10, 3)] = Tile {
+            locked: true,
+            ..Tile::door()
+        };
+        assert_eq!(validate(&map, &objects, 1, Branch::Main), Ok(()));
+    }
+
+    #[test]
+    fn validate_finds_unreachable_stairs() {
+        let (map, objects) = two_rooms();
+        assert!(validate(&map, &objects, 1, Branch::Main).is_err());
+    }
+
+    #[test]
+    fn validate_finds_missing_stairs() {
+        let (mut map, mut objects) = two_rooms();
+        create_h_tunnel(6, 21, 3, &mut map);
+        objects.truncate(objects.len() - 1);
+        assert!(validate(&map, &objects, 1, Branch::Main).is_err());
+        // unless it's the bottom of a branch
+        let bottom = Branch::Mines.bottom().unwrap();
+        assert_eq!(validate(&map, &objects, bottom, Branch::Mines), Ok(()));
+    }
+
+    #[test]
+    fn validate_finds_objects_in_walls() {
+        let (mut map, mut objects) = two_rooms();
+        create_h_tunnel(6, 21, 3, &mut map);
+        objects.push(make_gold(5, 10, 10));
+        assert!(validate(&map, &objects, 1, Branch::Main).is_err());
+    }
+
+    #[test]
+    fn bsp_rooms_are_apart_and_joined_in_one_tree() {
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..20 {
+            let layout = bsp_rooms(MAP_WIDTH, MAP_HEIGHT, &mut rng);
+            let mut map = Map::new(MAP_WIDTH, MAP_HEIGHT, Tile::wall());
+            for &room in &layout.rooms {
+                assert!(room.x1 >= 0 && room.y1 >= 0);
+                assert!(room.x2 < MAP_WIDTH && room.y2 < MAP_HEIGHT);
+                for x in (room.x1 + 1)..room.x2 {
+                    for y in (room.y1 + 1)..room.y2 {
+                        assert!(map[(x, y)].blocked, "rooms overlap at ({}, {})", x, y);
+                    }
+                }
+                create_room(room, &mut map);
+            }
+            assert!(layout.rooms.len() > 1);
+            assert_eq!(layout.tunnels.len(), layout.rooms.len() - 1);
+        }
+    }
+
+    #[test]
+    fn vaults_are_locked_and_guarded_and_the_key_is_left_outside() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let (mut map, mut objects) = two_rooms();
+        let rooms = [Rect::new(1, 1, 6, 6), Rect::new(20, 1, 6, 6)];
+        place_vault(&rooms, &mut map, &mut objects, &mut rng);
+        let locked: Vec<_> = map.iter().filter(|(_, tile)| tile.locked).collect();
+        assert_eq!(locked.len(), 1);
+        let find = |name: &str| objects.iter().find(|object| object.name == name).unwrap();
+        let (key, guardian) = (find("vault key"), find("vault guardian"));
+        let inside = |room: &Rect, (x, y): (i32, i32)| {
+            x > room.x1 && x < room.x2 && y > room.y1 && y < room.y2
+        };
+        assert!(rooms.iter().all(|room| !inside(room, guardian.pos())));
+        assert!(rooms.iter().any(|room| inside(room, key.pos())));
+        let base = bestiary::template("vault guardian").hp;
+        assert!(guardian.fighter.unwrap().hp > base);
+    }
+}
@@ -0,0 +1,133 @@
+//! The message log: every message of the game so far, or the latest
+//! `MESSAGE_LOG_SIZE` of them, on a screen of its own to scroll back
+//! through. The player can pick out just the fighting, just the items or
+//! just the rest, and look for the messages that mention something.
+
+use crate::colors::{Color, DARK_GREY, LIGHT_GREEN, LIGHT_GREY, LIGHT_RED, WHITE};
+use crate::console::*;
+use crate::input::KeyCode;
+use crate::{ask_text, Category, Message, Messages, Tcod, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// the lines at the top of the screen, above the messages
+const HEADER_HEIGHT: i32 = 3;
+/// how many messages Page Up and Page Down scroll by
+const PAGE: usize = 10;
+
+/// The ways the log can be narrowed down: a name for the header, and the
+/// category shown, or none for all of them.
+const FILTERS: [(&str, Option<Category>); 4] = [
+    ("All", None),
+    ("Combat", Some(Category::Combat)),
+    ("Items", Some(Category::Items)),
+    ("System", Some(Category::System)),
+];
+
+impl Category {
+    /// the color the log marks messages of this category with
+    fn color(self) -> Color {
+        match self {
+            Category::Combat => LIGHT_RED,
+            Category::Items => LIGHT_GREEN,
+            Category::System => LIGHT_GREY,
+        }
+    }
+}
+
+/// Show the message log until the player leaves it. The arrow keys and Page
+/// Up and Down scroll, Tab picks which messages to show and `/` looks for
+/// some text.
+pub fn show(tcod: &mut Tcod, messages: &Messages) {
+    let mut filter = 0;
+    let mut search: Option<String> = None;
+    // how many of the latest messages are scrolled out of sight below
+    let mut scroll = 0;
+    while !tcod.backend.is_closed() {
+        let shown: Vec<&Message> = messages
+            .iter()
+            .filter(|message| FILTERS[filter].1.map_or(true, |c| c == message.category))
+            .filter(|message| {
+                search.as_ref().map_or(true, |text| {
+                    message.text.to_lowercase().contains(&text.to_lowercase())
+                })
+            })
+            .collect();
+        scroll = scroll.min(shown.len().saturating_sub(1));
+        draw(tcod, &shown, filter, search.as_deref(), scroll);
+
+        let key = tcod.backend.wait_for_keypress();
+        match key.code {
+            KeyCode::Up | KeyCode::NumPad8 => scroll += 1,
+            KeyCode::Down | KeyCode::NumPad2 => scroll = scroll.saturating_sub(1),
+            KeyCode::PageUp | KeyCode::NumPad9 => scroll += PAGE,
+            KeyCode::PageDown | KeyCode::NumPad3 => scroll = scroll.saturating_sub(PAGE),
+            KeyCode::Home | KeyCode::NumPad7 => scroll = shown.len(),
+            KeyCode::End | KeyCode::NumPad1 => scroll = 0,
+            KeyCode::Tab => {
+                filter = (filter + 1) % FILTERS.len();
+                scroll = 0;
+            }
+            KeyCode::Text if key.printable == '/' => {
+                search = ask_text("Show the messages that mention:", 30, tcod)
+                    .filter(|text| !text.is_empty());
+                scroll = 0;
+            }
+            _ => return,
+        }
+    }
+}
+
+/// draw the log, newest messages at the bottom, with what's being shown
+/// above them
+fn draw(tcod: &mut Tcod, shown: &[&Message], filter: usize, search: Option<&str>, scroll: usize) {
+    let screen = &mut tcod.screen;
+    screen.set_default_background(Color::new(0, 0, 0));
+    screen.clear();
+
+    // the filters across the top, the one in use picked out
+    screen.set_default_foreground(WHITE);
+    screen.print(1, 0, "Message log");
+    let mut x = 14;
+    for (index, &(name, category)) in FILTERS.iter().enumerate() {
+        let color = match category {
+            _ if index != filter => DARK_GREY,
+            Some(category) => category.color(),
+            None => WHITE,
+        };
+        screen.set_default_foreground(color);
+        let label = if index == filter {
+            format!("[{}]", name)
+        } else {
+            format!(" {} ", name)
+        };
+        screen.print(x, 0, &label);
+        x += label.len() as i32 + 1;
+    }
+    screen.set_default_foreground(LIGHT_GREY);
+    let help = match search {
+        Some(text) => format!("Mentioning \"{}\". Tab: show others, /: find, Esc: back", text),
+        None => "Arrows, PgUp, PgDn: scroll. Tab: show others, /: find, Esc: back".into(),
+    };
+    screen.print(1, 1, &help);
+
+    // then the messages, from the newest shown up, each marked with the
+    // color of what it's about
+    let mut y = SCREEN_HEIGHT;
+    for message in shown.iter().rev().skip(scroll) {
+        let height = screen.get_height_rect(3, 0, SCREEN_WIDTH - 4, 0, &message.text);
+        y -= height;
+        if y < HEADER_HEIGHT {
+            break;
+        }
+        screen.set_default_foreground(message.category.color());
+        screen.put_char(1, y, '\u{7}', BackgroundFlag::None);
+        screen.set_default_foreground(message.color);
+        screen.print_rect(3, y, SCREEN_WIDTH - 4, 0, &message.text);
+    }
+    if shown.is_empty() {
+        screen.set_default_foreground(LIGHT_GREY);
+        screen.print(1, HEADER_HEIGHT, "No messages to show.");
+    }
+
+    tcod.mark_map((0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), false);
+    tcod.flush();
+}
@@ -0,0 +1,149 @@
+//! Morgue files: when the player dies, or wins, the story of the run is
+//! written to a text file of its own in `morgue/`, to look back on once the
+//! game's over.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{daily, place_name, Game, Object, LEVEL_UP_BASE, LEVEL_UP_FACTOR};
+
+/// where morgue files are written
+const MORGUE_DIR: &str = "morgue";
+
+/// What happened over a run, kept for its morgue file.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RunStats {
+    /// how many of each species died
+    pub kills: BTreeMap<String, u32>,
+}
+
+impl RunStats {
+    pub fn killed(&mut self, monster: &Object) {
+        *self.kills.entry(monster.name.clone()).or_insert(0) += 1;
+    }
+}
+
+/// Write the dead (or victorious) player's morgue file, and say where it went.
+pub fn write(game: &Game, player: &Object) -> Result<PathBuf, String> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let name: String = game
+        .character_name
+        .chars()
+        .map(|c| if c.is_alphanumeric() { c } else { '_' })
+        .collect();
+    let path = PathBuf::from(MORGUE_DIR).join(format!("{}-{}.txt", name, now));
+    fs::create_dir_all(MORGUE_DIR).map_err(|e| e.to_string())?;
+    fs::write(&path, morgue(game, player)).map_err(|e| e.to_string())?;
+    Ok(path)
+}
+
+/// the text of a morgue file
+fn morgue(game: &Game, player: &Object) -> String {
+    let cause = game.telemetry.last_damage_to_player().unwrap_or("something unknown");
+    let ending = if game.won {
+        format!("Escaped the tombs with the crown on turn {}.", game.turn)
+    } else {
+        format!("Killed by {} on turn {}.\nDied in: {}", cause, game.turn, place_name(game))
+    };
+    let run = match game.daily {
+        Some(day) => format!("Daily challenge of {}", daily::date_of(day)),
+        None => format!("Seed: {}", game.seed),
+    };
+    let mut text = format!(
+        "{}, level {} {}
+{}
+Deepest level reached: {}
+{}
+",
+        game.character_name,
+        player.level,
+        game.class.name().to_lowercase(),
+        ending,
+        game.dungeon.deepest(game.dungeon_level),
+        run,
+    );
+
+    if let Some(fighter) = player.fighter {
+        text.push_str(&format!(
+            "
+Character
+
+Experience: {} (next level at {})
+Maximum HP: {}
+Attack: {}
+Defense: {}
+Stealth: {}
+Perception: {}
+Gold: {}
+",
+            fighter.xp,
+            LEVEL_UP_BASE + player.level * LEVEL_UP_FACTOR,
+            player.max_hp(game),
+            player.power(game),
+            player.defense(game),
+            player.stealth(game),
+            fighter.perception,
+            game.gold,
+        ));
+    }
+
+    text.push_str("\nEquipment\n\n");
+    let equipped: Vec<String> = game
+        .inventory
+        .iter()
+        .filter_map(|item| match item.equipment {
+            Some(equipment) if equipment.equipped => {
+                Some(format!("{} (on {})\n", item.name, equipment.worn_on()))
+            }
+            _ => None,
+        })
+        .collect();
+    if equipped.is_empty() {
+        text.push_str("Nothing\n");
+    }
+    text.extend(equipped);
+
+    let total: u32 = game.stats.kills.values().sum();
+    text.push_str(&format!("\nKills: {}\n\n", total));
+    let mut kills: Vec<(&String, &u32)> = game.stats.kills.iter().collect();
+    // the most killed first, then by name
+    kills.sort_by(|a, b| b.1.cmp(a.1).then(a.0.cmp(b.0)));
+    for (species, count) in kills {
+        text.push_str(&format!("{:>5} {}\n", count, species));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::bestiary;
+    use crate::classes::Class;
+    use crate::{new_game_state, PLAYER};
+
+    #[test]
+    fn morgue_files_tell_of_the_gear_and_kills_most_first() {
+        let (mut game, objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let orc = bestiary::template("orc").spawn(0, 0);
+        let troll = bestiary::template("troll").spawn(0, 0);
+        game.stats.killed(&troll);
+        game.stats.killed(&orc);
+        game.stats.killed(&orc);
+        let text = morgue(&game, &objects[PLAYER]);
+        assert!(text.contains("level 1 warrior"));
+        assert!(text.contains("Killed by something unknown"));
+        assert!(text.contains("helmet (on head)"));
+        assert!(text.contains("Kills: 3\n\n    2 orc\n    1 troll\n"));
+        game.won = true;
+        assert!(morgue(&game, &objects[PLAYER]).contains("Escaped the tombs with the crown"));
+    }
+}
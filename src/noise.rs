@@ -0,0 +1,126 @@
+//! Noise. Fights, spells going off, heavy doors and monsters crying for help
+//! make noise, which carries through open tiles but not through walls. A
+//! sleeping monster that hears it wakes up, a monster that's awake but hasn't
+//! noticed the player yet comes to see what's going on, and one that's
+//! already hunting the player heads for it if it has nothing better to go on.
+
+use std::collections::VecDeque;
+
+use crate::colors::ORANGE;
+use crate::entities::Objects;
+use crate::pathfinding::Path;
+use crate::{Ai, Game, DIRECTIONS};
+
+/// how many tiles each kind of noise carries
+pub const COMBAT_NOISE: i32 = 6;
+pub const SPELL_NOISE: i32 = 12;
+pub const DOOR_NOISE: i32 = 10;
+pub const CRY_FOR_HELP_NOISE: i32 = 8;
+
+/// A noise made this turn, still to be heard.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Noise {
+    pub x: i32,
+    pub y: i32,
+    pub loudness: i32,
+}
+
+/// Let every monster within earshot of this turn's noises react to them.
+pub fn spread(game: &mut Game, objects: &mut Objects) {
+    for noise in std::mem::take(&mut game.noises) {
+        let heard = earshot(noise, game);
+        for id in 0..objects.len() {
+            let me = objects.id_of(id);
+            let chasing = objects.paths.get(me).is_some();
+            let object = &mut objects[id];
+            let in_earshot = game
+                .map
+                .index_of(object.x, object.y)
+                .map_or(false, |index| heard[index]);
+            if !in_earshot {
+                continue;
+            }
+            let heads_for_it = match object.ai {
+                Some(Ai::Asleep) => {
+                    if game.map.is_in_view(object.x, object.y) {
+                        game.messages
+                            .add(format!("The {} wakes up!", object.name), ORANGE);
+                    }
+                    object.ai = Some(Ai::Unaware);
+                    false
+                }
+                Some(Ai::Unaware | Ai::Follow) => {
+                    object.ai = Some(object.alerted_ai());
+                    true
+                }
+                Some(Ai::Basic | Ai::Ranged) => !chasing,
+                _ => false,
+            };
+            if heads_for_it {
+                objects.paths.insert(me, Path::towards((noise.x, noise.y)));
+            }
+        }
+    }
+}
+
+/// which tiles the noise carries to, by index into the map
+fn earshot(noise: Noise, game: &Game) -> Vec<bool> {
+    let map = &game.map;
+    let mut heard = vec![false; (map.width() * map.height()) as usize];
+    let open = |x: i32, y: i32| map.get(x, y).map_or(false, |tile| !tile.blocked);
+    let mut frontier = VecDeque::new();
+    if let Some(index) = map.index_of(noise.x, noise.y) {
+        heard[index] = true;
+        frontier.push_back(((noise.x, noise.y), 0));
+    }
+    while let Some(((x, y), distance)) = frontier.pop_front() {
+        if distance >= noise.loudness {
+            continue;
+        }
+        for &(dx, dy) in DIRECTIONS.iter() {
+            let (next_x, next_y) = (x + dx, y + dy);
+            if let Some(index) = map.index_of(next_x, next_y) {
+                if open(next_x, next_y) && !heard[index] {
+                    heard[index] = true;
+                    frontier.push_back(((next_x, next_y), distance + 1));
+                }
+            }
+        }
+    }
+    heard
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bestiary;
+    use crate::test_support::corridor;
+
+    #[test]
+    fn noise_carries_as_far_as_it_is_loud_and_not_through_walls() {
+        let (game, _) = corridor(20, 3, Some(10));
+        let heard = earshot(Noise { x: 2, y: 1, loudness: 5 }, &game);
+        let hears = |x| heard[game.map.index_of(x, 1).unwrap()];
+        assert!(hears(7));
+        assert!(!hears(8));
+        let heard = earshot(Noise { x: 2, y: 1, loudness: 15 }, &game);
+        assert!(!heard[game.map.index_of(11, 1).unwrap()]);
+    }
+
+    #[test]
+    fn sleepers_wake_and_the_unaware_come_to_look() {
+        let (mut game, mut objects) = corridor(20, 3, Some(10));
+        for (x, ai) in [(4, Ai::Asleep), (6, Ai::Unaware), (12, Ai::Asleep)] {
+            let mut orc = bestiary::template("orc").spawn(x, 1);
+            orc.ai = Some(ai);
+            objects.push(orc);
+        }
+        game.noises.push(Noise { x: 2, y: 1, loudness: COMBAT_NOISE });
+        spread(&mut game, &mut objects);
+        assert_eq!(objects[1].ai, Some(Ai::Unaware));
+        assert_eq!(objects[2].ai, Some(objects[2].alerted_ai()));
+        assert_eq!(objects.paths.get(objects.id_of(2)), Some(&Path::towards((2, 1))));
+        assert_eq!(objects[3].ai, Some(Ai::Asleep));
+        assert!(game.noises.is_empty());
+    }
+}
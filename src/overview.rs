@@ -0,0 +1,227 @@
+//! The map screen: the whole of the level the player has explored, at a
+//! glance. A level too big for the screen is shrunk to fit, each cell of the
+//! screen standing for a block of tiles, or can be seen full size and
+//! scrolled about. Stairs, shops and items the player remembers are marked
+//! on it, so they can be found again.
+
+use std::cmp;
+use std::collections::HashMap;
+
+use crate::actions::Action;
+use crate::colors::{Color, BLACK, GOLD, LIGHT_GREY, WHITE};
+use crate::console::*;
+use crate::dungeon::{self, Branch};
+use crate::entities::Objects;
+use crate::{place_name, Game, Object, Tcod, COLOR_DARK_WATER, PLAYER};
+use crate::{SCREEN_HEIGHT, SCREEN_WIDTH};
+
+/// the screen lines the map is drawn on, between the title and the key help
+const AREA_TOP: i32 = 1;
+const AREA_WIDTH: i32 = SCREEN_WIDTH;
+const AREA_HEIGHT: i32 = SCREEN_HEIGHT - 2;
+/// how many tiles a key press scrolls the full-size map by
+const SCROLL_STEP: i32 = 5;
+
+/// Something marked on the map, drawn over the terrain. Where a cell of the
+/// shrunk map covers more than one, the one with the highest priority shows.
+#[derive(Clone, Copy)]
+struct Marker {
+    priority: u8,
+    char: char,
+    color: Color,
+}
+
+/// Show the map of the level until the player leaves it. Space switches a
+/// level too big for the screen between shrunk to fit and full size, which
+/// the movement keys scroll.
+pub fn show(tcod: &mut Tcod, game: &Game, objects: &Objects) {
+    let (width, height) = (game.map.width(), game.map.height());
+    // how many tiles across and down each cell stands for, to fit it all in
+    let fit = cmp::max(div_ceil(width, AREA_WIDTH), div_ceil(height, AREA_HEIGHT)).max(1);
+    let mut scale = fit;
+    let (x, y) = objects[PLAYER].pos();
+    let mut corner = (x - AREA_WIDTH / 2, y - AREA_HEIGHT / 2);
+    let markers = markers(game, objects);
+    while !tcod.backend.is_closed() {
+        // the full-size map scrolls no further than its edges
+        corner = (
+            corner.0.clamp(0, cmp::max(width - AREA_WIDTH, 0)),
+            corner.1.clamp(0, cmp::max(height - AREA_HEIGHT, 0)),
+        );
+        let shown_corner = if scale == 1 { corner } else { (0, 0) };
+        draw(tcod, game, &markers, scale, shown_corner, fit > 1);
+
+        let key = tcod.backend.wait_for_keypress();
+        match tcod.keys.action_for(key) {
+            Some(Action::Step { dx, dy } | Action::MoveOrAttack { dx, dy }) if scale == 1 => {
+                corner = (corner.0 + dx * SCROLL_STEP, corner.1 + dy * SCROLL_STEP);
+                continue;
+            }
+            _ => {}
+        }
+        if key.printable == ' ' && fit > 1 {
+            scale = if scale == 1 { fit } else { 1 };
+        } else {
+            return;
+        }
+    }
+}
+
+fn div_ceil(a: i32, b: i32) -> i32 {
+    (a + b - 1) / b
+}
+
+/// everything marked on the map, by where it is: the player, the stairs and
+/// shops they know of, and the items they remember
+fn markers(game: &Game, objects: &Objects) -> HashMap<(i32, i32), Marker> {
+    let mut markers = HashMap::new();
+    for (pos, tile) in game.map.iter() {
+        if let Some((char, color)) = tile.remembered {
+            let marker = Marker {
+                priority: 1,
+                char,
+                color,
+            };
+            markers.insert(pos, marker);
+        }
+    }
+    for object in objects.iter() {
+        let known = game
+            .map
+            .get(object.x, object.y)
+            .map_or(false, |tile| tile.visible || tile.remembered.is_some());
+        let marker = if object.trader.is_some() && known {
+            Marker {
+                priority: 2,
+                char: '$',
+                color: GOLD,
+            }
+        } else if is_stairs(object) && known {
+            Marker {
+                priority: 3,
+                char: object.char,
+                color: WHITE,
+            }
+        } else {
+            continue;
+        };
+        markers.insert(object.pos(), marker);
+    }
+    let player = Marker {
+        priority: 4,
+        char: '@',
+        color: WHITE,
+    };
+    markers.insert(objects[PLAYER].pos(), player);
+    markers
+}
+
+/// whether this is a way up or down: the stairs, or the stairs into a branch
+fn is_stairs(object: &Object) -> bool {
+    object.name == dungeon::DOWN_STAIRS
+        || object.name == dungeon::UP_STAIRS
+        || Branch::SIDE_BRANCHES
+            .iter()
+            .any(|branch| branch.stairs_name() == object.name)
+}
+
+/// draw the map, each cell standing for `scale` tiles across and down, from
+/// the given tile at the top left
+fn draw(
+    tcod: &mut Tcod,
+    game: &Game,
+    markers: &HashMap<(i32, i32), Marker>,
+    scale: i32,
+    (left, top): (i32, i32),
+    can_zoom: bool,
+) {
+    let palette = game.dungeon.branch.palette();
+    let screen = &mut tcod.screen;
+    screen.set_default_background(BLACK);
+    screen.clear();
+    screen.set_default_foreground(WHITE);
+    screen.print(1, 0, format!("Map of {}", place_name(game)));
+
+    // a map smaller than the screen sits in the middle of it
+    let columns = cmp::min(div_ceil(game.map.width(), scale), AREA_WIDTH);
+    let rows = cmp::min(div_ceil(game.map.height(), scale), AREA_HEIGHT);
+    let (offset_x, offset_y) = ((AREA_WIDTH - columns) / 2, AREA_TOP + (AREA_HEIGHT - rows) / 2);
+    for row in 0..rows {
+        for column in 0..columns {
+            let (x, y) = (left + column * scale, top + row * scale);
+            let block = (y..y + scale).flat_map(|y| (x..x + scale).map(move |x| (x, y)));
+            // the block is floor if any of it the player's seen is, and
+            // shows whatever marked in it matters most
+            let mut seen = None;
+            let mut marker: Option<Marker> = None;
+            for (x, y) in block {
+                let tile = match game.map.get(x, y) {
+                    Some(tile) if tile.explored => tile,
+                    _ => continue,
+                };
+                let color = if !tile.block_sight && tile.deep_water {
+                    COLOR_DARK_WATER
+                } else if !tile.block_sight {
+                    palette.dark_ground
+                } else {
+                    palette.dark_wall
+                };
+                if seen.is_none() || !tile.block_sight {
+                    seen = Some(color);
+                }
+                if let Some(&found) = markers.get(&(x, y)) {
+                    if marker.map_or(true, |marker| found.priority > marker.priority) {
+                        marker = Some(found);
+                    }
+                }
+            }
+            let (screen_x, screen_y) = (offset_x + column, offset_y + row);
+            if let Some(color) = seen {
+                screen.set_char_background(screen_x, screen_y, color, BackgroundFlag::Set);
+            }
+            if let Some(marker) = marker {
+                screen.set_default_foreground(marker.color);
+                screen.put_char(screen_x, screen_y, marker.char, BackgroundFlag::None);
+            }
+        }
+    }
+
+    screen.set_default_foreground(LIGHT_GREY);
+    let help = match (can_zoom, scale) {
+        (false, _) => "@ you, $ shop. Any key to go back.",
+        (true, 1) => "Move to scroll, Space to see it all, any other key to go back.",
+        (true, _) => "@ you, $ shop. Space for full size, any other key to go back.",
+    };
+    screen.print(1, SCREEN_HEIGHT - 1, help);
+    tcod.mark_map((0, 0), (SCREEN_WIDTH, SCREEN_HEIGHT), false);
+    tcod.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::new_game_state;
+
+    #[test]
+    fn only_the_stairs_and_shops_the_player_knows_of_are_marked() {
+        let (mut game, objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let stairs = objects.iter().find(|object| object.name == dungeon::DOWN_STAIRS).unwrap();
+        let shops: Vec<_> = objects.iter().filter(|object| object.trader.is_some()).collect();
+        assert!(is_stairs(stairs) && !shops.is_empty());
+        let unknown = markers(&game, &objects);
+        assert_eq!(unknown.len(), 1);
+        assert_eq!(unknown[&objects[PLAYER].pos()].char, '@');
+
+        game.map[stairs.pos()].remembered = Some((' ', BLACK));
+        for shop in &shops {
+            game.map[shop.pos()].visible = true;
+        }
+        let known = markers(&game, &objects);
+        assert_eq!(known[&stairs.pos()].char, stairs.char);
+        assert!(shops.iter().all(|shop| known[&shop.pos()].char == '$'));
+    }
+}
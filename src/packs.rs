@@ -0,0 +1,174 @@
+//! Packs: monsters that go about in groups, like an orc warband, as defined
+//! in `data/packs.json`. A pack is placed together in one room, around its
+//! leader. The rest keep close to the leader, go after the player as soon as
+//! it does, and fight harder while it's there to rally them.
+
+use std::iter;
+use std::sync::OnceLock;
+
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::bestiary;
+use crate::dungeon::Branch;
+use crate::entities::{EntityId, Objects};
+use crate::map::{from_dungeon_level, Transition};
+use crate::pathfinding::Path;
+use crate::{ai_unaware, move_towards, Ai, Game};
+
+const PACK_DATA: &str = include_str!("../data/packs.json");
+
+/// how far followers let their leader get before they catch up
+const FOLLOW_DISTANCE: f32 = 3.0;
+
+/// How to make one kind of pack.
+#[derive(Debug, Deserialize)]
+pub struct PackTemplate {
+    pub name: String,
+    /// the species of its leader
+    pub leader: String,
+    /// the species its followers are picked from, each as likely as the next
+    pub followers: Vec<String>,
+    pub min_followers: usize,
+    pub max_followers: usize,
+    /// how likely this pack is to be picked for a random pack, against the
+    /// others, from each dungeon level on; never, if empty
+    #[serde(default)]
+    pub spawn: Vec<Transition>,
+    /// the branches of the dungeon it turns up in; all of them, if empty
+    #[serde(default)]
+    pub branches: Vec<Branch>,
+}
+
+/// A monster's place in its pack: at its head, or following the leader with
+/// the given ID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PackMember {
+    Leader,
+    Follower(EntityId),
+}
+
+impl PackMember {
+    /// the leader followed, if it's a follower
+    pub fn leader(self) -> Option<EntityId> {
+        match self {
+            PackMember::Leader => None,
+            PackMember::Follower(leader) => Some(leader),
+        }
+    }
+}
+
+/// Read kinds of pack from JSON laid out like `data/packs.json`, or say
+/// what's wrong with it. Every species they name must be in the bestiary.
+pub fn parse(json: &str) -> Result<Vec<PackTemplate>, String> {
+    let templates: Vec<PackTemplate> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let bestiary = bestiary::load()?;
+    for template in &templates {
+        for species in iter::once(&template.leader).chain(&template.followers) {
+            if !bestiary.iter().any(|monster| &monster.name == species) {
+                return Err(format!("the {} has unknown monster {}", template.name, species));
+            }
+        }
+    }
+    Ok(templates)
+}
+
+/// All kinds of pack, read from the data file the first time they're
+/// needed, or what's wrong with it.
+pub fn load() -> Result<&'static [PackTemplate], String> {
+    static TEMPLATES: OnceLock<Result<Vec<PackTemplate>, String>> = OnceLock::new();
+    TEMPLATES
+        .get_or_init(|| parse(PACK_DATA).map_err(|e| format!("data/packs.json is invalid: {}", e)))
+        .as_deref()
+        .map_err(Clone::clone)
+}
+
+/// all kinds of pack; `run` has made sure the data file reads before
+/// anything needs them
+pub fn templates() -> &'static [PackTemplate] {
+    load().unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// a kind of pack found on the given dungeon level of the given branch;
+/// none if no pack is
+pub fn random_pack(
+    level: u32,
+    branch: Branch,
+    rng: &mut impl Rng,
+) -> Option<&'static PackTemplate> {
+    let templates = templates();
+    let weights = templates.iter().map(|template| {
+        if branch.is_among(&template.branches) {
+            from_dungeon_level(&template.spawn, level)
+        } else {
+            0
+        }
+    });
+    let dist = WeightedIndex::new(weights).ok()?;
+    Some(&templates[rng.sample(&dist)])
+}
+
+/// where the leader the given monster follows is, if it's still alive
+pub fn leader_of(id: usize, objects: &Objects) -> Option<usize> {
+    let leader = objects[id].pack?.leader()?;
+    objects.find(leader).filter(|&leader| objects[leader].alive)
+}
+
+/// A follower that hasn't noticed the player keeps close to its leader,
+/// keeping an eye out for the player as it goes. Once the leader is after
+/// the player, it joins in, heading for wherever the leader is; if the
+/// leader dies, it's left to fend for itself.
+pub fn ai_follow(id: usize, game: &mut Game, objects: &mut Objects) -> Ai {
+    let leader = match leader_of(id, objects) {
+        Some(leader) => leader,
+        None => return Ai::Unaware,
+    };
+    let (leader_x, leader_y) = objects[leader].pos();
+    if objects[leader].has_noticed_player() {
+        let follower = objects.id_of(id);
+        objects.paths.insert(follower, Path::towards((leader_x, leader_y)));
+        return objects[id].alerted_ai();
+    }
+
+    let ai = ai_unaware(id, game, objects);
+    if ai != Ai::Unaware {
+        return ai;
+    }
+    if objects[id].distance(leader_x, leader_y) > FOLLOW_DISTANCE {
+        move_towards(id, leader_x, leader_y, &mut game.map, objects);
+    }
+    Ai::Follow
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn packs_must_be_made_of_known_monsters() {
+        let pack = r#"[{"name": "band", "leader": "orc", "followers": ["%s"],
+            "min_followers": 1, "max_followers": 2}]"#;
+        assert_eq!(parse(&pack.replace("%s", "troll")).unwrap().len(), 1);
+        let unknown = parse(&pack.replace("%s", "dragon"));
+        assert_eq!(unknown.unwrap_err(), "the band has unknown monster dragon");
+    }
+
+    #[test]
+    fn followers_lose_their_leader_when_it_dies() {
+        let orc = bestiary::template("orc");
+        let mut objects = Objects::from((0..3).map(|x| orc.spawn(x, 0)).collect::<Vec<_>>());
+        objects[1].pack = Some(PackMember::Leader);
+        objects[2].pack = Some(PackMember::Leader);
+        objects[0].pack = Some(PackMember::Follower(objects.id_of(2)));
+        assert_eq!(leader_of(0, &objects), Some(2));
+        assert_eq!(leader_of(1, &objects), None);
+        objects[2].alive = false;
+        assert_eq!(leader_of(0, &objects), None);
+        // nor does anything that takes the leader's place once it's gone
+        objects[2].alive = true;
+        let leader = objects.swap_remove(2);
+        objects.push(leader);
+        assert_eq!(leader_of(0, &objects), None);
+    }
+}
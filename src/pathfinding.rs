@@ -0,0 +1,348 @@
+//! Pathfinding for monsters. Monsters chasing the player mostly roll
+//! downhill on a Dijkstra map worked out once a turn from where the player
+//! stands. Where that doesn't help, a monster works out its own route with A*
+//! search, around walls, water it won't swim and other monsters, and keeps it
+//! until it stops being useful, so it isn't searched again every turn. Closed
+//! doors are no obstacle, since walking into one opens it.
+
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::entities::Objects;
+use crate::map::{is_blocked, Map};
+use crate::DIRECTIONS;
+
+/// routes longer than this aren't worth walking; the monster goes straight
+/// for its target instead
+const MAX_PATH_LENGTH: i32 = 40;
+
+/// A route to a goal, as the tiles still to walk through, the goal last.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Path {
+    steps: Vec<(i32, i32)>,
+}
+
+impl Path {
+    /// a route to `goal` that's still to be worked out
+    pub fn towards(goal: (i32, i32)) -> Self {
+        Path { steps: vec![goal] }
+    }
+
+    fn goal(&self) -> Option<(i32, i32)> {
+        self.steps.last().copied()
+    }
+}
+
+/// How many moves every tile is from a goal, walking around walls, deep water
+/// and teleporters but not around monsters or through locked doors; `None`
+/// for tiles it can't be reached from.
+#[derive(Clone, Debug, Default)]
+pub struct DijkstraMap {
+    width: i32,
+    height: i32,
+    distances: Vec<Option<i32>>,
+}
+
+impl DijkstraMap {
+    pub fn new(goal: (i32, i32), map: &Map) -> Self {
+        let (width, height) = (map.width(), map.height());
+        let mut distances = vec![None; (width * height) as usize];
+        let walkable = |(x, y): (i32, i32)| {
+            map.get(x, y).map_or(false, |tile| {
+                (!tile.blocked || tile.opens()) && !tile.deep_water && tile.teleport.is_none()
+            })
+        };
+
+        // every move costs the same, so a breadth-first flood will do
+        let mut frontier = VecDeque::new();
+        if map.in_bounds(goal.0, goal.1) {
+            distances[(goal.1 * width + goal.0) as usize] = Some(0);
+            frontier.push_back((goal, 0));
+        }
+        while let Some(((x, y), distance)) = frontier.pop_front() {
+            for &(dx, dy) in DIRECTIONS.iter() {
+                let next = (x + dx, y + dy);
+                if walkable(next) {
+                    let index = (next.1 * width + next.0) as usize;
+                    if distances[index].is_none() {
+                        distances[index] = Some(distance + 1);
+                        frontier.push_back((next, distance + 1));
+                    }
+                }
+            }
+        }
+        DijkstraMap {
+            width,
+            height,
+            distances,
+        }
+    }
+
+    pub fn distance(&self, x: i32, y: i32) -> Option<i32> {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return None;
+        }
+        self.distances[(y * self.width + x) as usize]
+    }
+
+    /// The move that takes the object `id` closest to the goal. Tiles taken by
+    /// other monsters are skipped, so a crowd spreads out around the goal
+    /// rather than queueing up behind the first of them.
+    pub fn downhill(&self, id: usize, map: &Map, objects: &Objects) -> Option<(i32, i32)> {
+        self.best_step(id, map, objects, |there, here| here - there)
+    }
+
+    /// The move that takes the object `id` furthest from the goal.
+    pub fn uphill(&self, id: usize, map: &Map, objects: &Objects) -> Option<(i32, i32)> {
+        self.best_step(id, map, objects, |there, here| there - here)
+    }
+
+    /// the move to a free tile that scores best by `gain`, if any gains at all
+    fn best_step(
+        &self,
+        id: usize,
+        map: &Map,
+        objects: &Objects,
+        gain: impl Fn(i32, i32) -> i32,
+    ) -> Option<(i32, i32)> {
+        let (x, y) = objects[id].pos();
+        let here = self.distance(x, y)?;
+        DIRECTIONS
+            .iter()
+            .copied()
+            .filter_map(|(dx, dy)| {
+                let gained = gain(self.distance(x + dx, y + dy)?, here);
+                let free = can_enter(x + dx, y + dy, map, objects);
+                (gained > 0 && free).then_some((gained, (dx, dy)))
+            })
+            .max_by_key(|&(gained, _)| gained)
+            .map(|(_, step)| step)
+    }
+}
+
+/// whether there's nothing in the way on the tile, or only a door that opens
+fn can_enter(x: i32, y: i32, map: &Map, objects: &Objects) -> bool {
+    map.get(x, y).map_or(false, |tile| tile.opens()) || !is_blocked(x, y, map, objects)
+}
+
+/// how many moves apart two tiles are, when diagonal moves count as one
+fn moves_between((x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> i32 {
+    (x1 - x2).abs().max((y1 - y2).abs())
+}
+
+/// Find the shortest route for the object `id` to `to`, with A*.
+/// Teleporters and blocking objects (other than whatever is at `to`) are
+/// walked around.
+pub fn find_path(id: usize, to: (i32, i32), map: &Map, objects: &Objects) -> Option<Path> {
+    let from = objects[id].pos();
+    let swims = objects[id].swims;
+    let index = |(x, y): (i32, i32)| (y * map.width() + x) as usize;
+    let size = (map.width() * map.height()) as usize;
+
+    // tiles with something blocking in them
+    let mut occupied = vec![false; size];
+    for object in objects.iter().filter(|object| object.blocks) {
+        if map.in_bounds(object.x, object.y) {
+            occupied[index(object.pos())] = true;
+        }
+    }
+    let passable = |pos: (i32, i32)| {
+        if pos == to {
+            return true;
+        }
+        map.get(pos.0, pos.1).map_or(false, |tile| {
+            (!tile.blocked || tile.opens())
+                && (swims || !tile.deep_water)
+                && tile.teleport.is_none()
+                && !occupied[index(pos)]
+        })
+    };
+
+    // cost of the best route found so far to each tile, and where it came from
+    let mut cost = vec![i32::MAX; size];
+    let mut came_from = vec![None; size];
+    let mut open = BinaryHeap::new();
+    cost[index(from)] = 0;
+    open.push(Reverse((moves_between(from, to), from)));
+
+    while let Some(Reverse((_, pos))) = open.pop() {
+        if pos == to {
+            // walk back to the start
+            let mut steps = vec![];
+            let mut pos = to;
+            while pos != from {
+                steps.push(pos);
+                pos = came_from[index(pos)]?;
+            }
+            steps.reverse();
+            return Some(Path { steps });
+        }
+        let next_cost = cost[index(pos)] + 1;
+        if next_cost > MAX_PATH_LENGTH {
+            continue;
+        }
+        for &(dx, dy) in DIRECTIONS.iter() {
+            let next = (pos.0 + dx, pos.1 + dy);
+            if !passable(next) || next_cost >= cost[index(next)] {
+                continue;
+            }
+            cost[index(next)] = next_cost;
+            came_from[index(next)] = Some(pos);
+            open.push(Reverse((next_cost + moves_between(next, to), next)));
+        }
+    }
+    None
+}
+
+/// The move the object `id` should make to get to `target`, which
+/// `to_target` leads to. Where it doesn't, a route worked out on an earlier
+/// turn is followed while it still leads there (the target may have taken a
+/// step since), and searched again otherwise.
+pub fn next_step(
+    id: usize,
+    target: (i32, i32),
+    to_target: &DijkstraMap,
+    map: &Map,
+    objects: &mut Objects,
+) -> Option<(i32, i32)> {
+    let me = objects.id_of(id);
+    if let Some(step) = to_target.downhill(id, map, objects) {
+        // remember only where it leads, in case the target slips out of sight
+        objects.paths.insert(me, Path::towards(target));
+        return Some(step);
+    }
+
+    let pos = objects[id].pos();
+    let reusable = objects.paths.get(me).map_or(false, |path| {
+        path.goal().map_or(false, |goal| moves_between(goal, target) <= 1)
+            && path.steps.len() as i32 <= MAX_PATH_LENGTH
+            && path.steps.first().map_or(false, |&(x, y)| {
+                moves_between(pos, (x, y)) == 1
+                    && ((x, y) == target || can_enter(x, y, map, objects))
+            })
+    });
+    if reusable {
+        let path = objects.paths.get_mut(me).unwrap();
+        if path.goal() != Some(target) {
+            path.steps.push(target);
+        }
+    } else {
+        match find_path(id, target, map, objects) {
+            Some(path) => objects.paths.insert(me, path),
+            None => objects.paths.remove(me),
+        };
+    }
+    follow_path(id, map, objects)
+}
+
+/// Take the next step along the object's route, if it has one, searching it
+/// again if the object has strayed from it and forgetting it once it's been
+/// walked.
+pub fn follow_path(id: usize, map: &Map, objects: &mut Objects) -> Option<(i32, i32)> {
+    let me = objects.id_of(id);
+    let pos = objects[id].pos();
+    let path = objects.paths.get(me)?;
+    if path.steps.first().map_or(false, |&next| moves_between(pos, next) != 1) {
+        let goal = path.goal()?;
+        match find_path(id, goal, map, objects) {
+            Some(path) => objects.paths.insert(me, path),
+            None => objects.paths.remove(me),
+        };
+    }
+
+    let path = objects.paths.get_mut(me)?;
+    if path.steps.is_empty() {
+        objects.paths.remove(me);
+        return None;
+    }
+    let next = path.steps.remove(0);
+    if path.steps.is_empty() {
+        objects.paths.remove(me);
+    }
+    Some((next.0 - pos.0, next.1 - pos.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colors::WHITE;
+    use crate::map::Tile;
+    use crate::Object;
+
+    /// an open room walled all round, with a wall down the middle at x = 5
+    /// that can only be got round at the bottom
+    fn divided_room() -> Map {
+        let mut map = Map::new(11, 8, Tile::wall());
+        for y in 1..7 {
+            for x in 1..10 {
+                map[(x, y)] = Tile::empty();
+            }
+        }
+        for y in 1..6 {
+            map[(5, y)] = Tile::wall();
+        }
+        map
+    }
+
+    fn monster(x: i32, y: i32) -> Object {
+        Object::new(x, y, 'o', "orc", WHITE, true)
+    }
+
+    #[test]
+    fn routes_go_round_walls() {
+        let map = divided_room();
+        let objects = Objects::from(vec![monster(2, 1)]);
+        let path = find_path(0, (8, 1), &map, &objects).unwrap();
+        assert_eq!(path.goal(), Some((8, 1)));
+        assert!(path.steps.contains(&(5, 6)));
+        assert!(path.steps.iter().all(|&(x, y)| !map[(x, y)].blocked));
+    }
+
+    #[test]
+    fn routes_go_round_other_monsters() {
+        let map = divided_room();
+        let objects = Objects::from(vec![monster(2, 1), monster(5, 6)]);
+        assert_eq!(find_path(0, (8, 1), &map, &objects), None);
+    }
+
+    #[test]
+    fn routes_are_kept_and_walked_step_by_step() {
+        let map = divided_room();
+        let mut objects = Objects::from(vec![monster(2, 1)]);
+        // with no flow field to follow, it works out a route of its own
+        let nowhere = DijkstraMap::default();
+        let (dx, dy) = next_step(0, (8, 1), &nowhere, &map, &mut objects).unwrap();
+        let monster = objects.id_of(0);
+        let left = objects.paths.get(monster).cloned().unwrap();
+        objects[0].set_pos(2 + dx, 1 + dy);
+        next_step(0, (8, 1), &nowhere, &map, &mut objects).unwrap();
+        assert_eq!(objects.paths.get(monster).unwrap().steps, left.steps[1..]);
+    }
+
+    #[test]
+    fn distances_count_moves_round_walls() {
+        let map = divided_room();
+        let to_goal = DijkstraMap::new((8, 1), &map);
+        assert_eq!(to_goal.distance(8, 1), Some(0));
+        assert_eq!(to_goal.distance(6, 1), Some(2));
+        // down to the gap, through it and back up
+        assert_eq!(to_goal.distance(4, 1), Some(10));
+        assert_eq!(to_goal.distance(5, 1), None);
+    }
+
+    #[test]
+    fn crowds_roll_downhill_around_each_other() {
+        let map = divided_room();
+        let to_goal = DijkstraMap::new((8, 3), &map);
+        let mut objects = Objects::from(vec![monster(6, 3), monster(7, 3)]);
+        assert_eq!(to_goal.downhill(1, &map, &objects), Some((1, 0)));
+        // the one in front stands in the way, so it steps round
+        let step = to_goal.downhill(0, &map, &objects).unwrap();
+        assert_ne!(step, (1, 0));
+        assert_eq!(step.0, 1);
+        // and running away takes it further off
+        objects[0].set_pos(7, 5);
+        let (dx, dy) = to_goal.uphill(0, &map, &objects).unwrap();
+        assert_eq!(to_goal.distance(7 + dx, 5 + dy), Some(3));
+    }
+}
@@ -0,0 +1,299 @@
+//! Powers: what some monsters can do besides walk up to the player and hit
+//! them. Each species has a list of them, and every turn a monster looks
+//! through its own: trolls regenerate, orc shamans heal their friends,
+//! giant spiders spit webs, wraiths drain experience with a touch,
+//! necromancers raise the dead and pack leaders rally their packs.
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::bestiary;
+use crate::colors::{LIGHT_GREEN, LIGHT_GREY, LIGHT_VIOLET, VIOLET};
+use crate::effects::{self, Effect, StatusEffect};
+use crate::entities::Objects;
+use crate::map::is_blocked;
+use crate::packs::PackMember;
+use crate::{Ai, Game, Object, PLAYER};
+
+/// what a regenerating monster heals every turn
+const REGENERATE_AMOUNT: i32 = 1;
+/// how far off a shaman can heal another monster it sees, and by how much
+const HEAL_ALLY_RANGE: f32 = 6.0;
+const HEAL_ALLY_AMOUNT: i32 = 8;
+/// how far a web can be spat, and how long it holds whoever it catches
+const WEB_RANGE: f32 = 5.0;
+const WEB_TURNS: i32 = 3;
+/// the experience a wraith's touch takes
+const DRAIN_XP_AMOUNT: i32 = 25;
+/// how far off a necromancer can raise a corpse it sees
+const RAISE_DEAD_RANGE: f32 = 6.0;
+/// what the dead rise as
+const RAISED_SPECIES: &str = "zombie";
+/// how far off a leader's rallying cry carries, and how long it lasts
+const RALLY_RANGE: f32 = 8.0;
+const RALLY_TURNS: i32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Power {
+    /// heals a little every turn, whatever else it does
+    Regenerate,
+    /// heals the most hurt of the other monsters on its side it can see
+    HealAllies,
+    /// spits a web over the player, holding them in place
+    Web,
+    /// touches the player to drain their experience
+    DrainXp,
+    /// raises the nearest corpse it can see as a zombie
+    RaiseDead,
+    /// spurs on the rest of its pack that it can see, whatever else it does
+    Rally,
+}
+
+impl Power {
+    /// what the bestiary calls it
+    pub fn describe(self) -> &'static str {
+        match self {
+            Power::Regenerate => "regenerates",
+            Power::HealAllies => "heals its allies",
+            Power::Web => "spits webs",
+            Power::DrainXp => "drains experience",
+            Power::RaiseDead => "raises the dead",
+            Power::Rally => "rallies its pack",
+        }
+    }
+
+    /// the chance each turn that a monster that's after the player tries it,
+    /// rather than moving or attacking
+    fn chance(self) -> f64 {
+        match self {
+            Power::Regenerate => 1.0,
+            Power::HealAllies => 0.4,
+            Power::Web => 0.25,
+            Power::DrainXp => 0.3,
+            Power::RaiseDead => 0.3,
+            Power::Rally => 1.0,
+        }
+    }
+}
+
+/// Let a monster use its powers for the turn. Regeneration always works,
+/// but the others are only tried by a monster that's after the player, and
+/// use up its turn if they work, except for rallying. Returns whether one
+/// did.
+pub fn use_powers(monster_id: usize, game: &mut Game, objects: &mut Objects) -> bool {
+    let powers = objects[monster_id].powers.clone();
+    let hunting = matches!(objects[monster_id].ai, Some(Ai::Basic | Ai::Ranged));
+    for power in powers {
+        if power == Power::Regenerate {
+            let monster = &mut objects[monster_id];
+            monster.heal(REGENERATE_AMOUNT, game);
+            continue;
+        }
+        if !hunting || !game.rng.gen_bool(power.chance()) {
+            continue;
+        }
+        if power == Power::Rally {
+            rally(monster_id, game, objects);
+            continue;
+        }
+        let used = match power {
+            Power::Regenerate | Power::Rally => false,
+            Power::HealAllies => heal_ally(monster_id, game, objects),
+            Power::Web => spit_web(monster_id, game, objects),
+            Power::DrainXp => drain_xp(monster_id, game, objects),
+            Power::RaiseDead => raise_dead(monster_id, game, objects),
+        };
+        if used {
+            return true;
+        }
+    }
+    false
+}
+
+/// heal the most hurt of the other monsters on its side the healer can see
+/// nearby, if any of them is hurt at all
+fn heal_ally(healer_id: usize, game: &mut Game, objects: &mut Objects) -> bool {
+    let healer = &objects[healer_id];
+    let hurt = |object: &Object| object.fighter.map_or(0, |f| f.base_max_hp - f.hp);
+    let ally_id = (0..objects.len())
+        .filter(|&id| {
+            let object = &objects[id];
+            id != healer_id
+                && id != PLAYER
+                && object.alive
+                && object.ai.is_some()
+                && object.faction() == healer.faction()
+                && hurt(object) > 0
+                && healer.distance_to(object) <= HEAL_ALLY_RANGE
+                && healer.can_see(object.x, object.y, &game.map)
+        })
+        .max_by_key(|&id| hurt(&objects[id]));
+    let ally_id = match ally_id {
+        Some(id) => id,
+        None => return false,
+    };
+
+    if game.map.is_visible(objects[ally_id].x, objects[ally_id].y) {
+        game.messages.add(
+            format!(
+                "The {} chants, and the {}'s wounds close.",
+                objects[healer_id].name, objects[ally_id].name
+            ),
+            LIGHT_GREEN,
+        );
+    }
+    objects[ally_id].heal(HEAL_ALLY_AMOUNT, game);
+    true
+}
+
+/// inspire the rest of the leader's pack that it can see nearby, for a while
+fn rally(leader_id: usize, game: &mut Game, objects: &mut Objects) {
+    if objects[leader_id].pack != Some(PackMember::Leader) {
+        return;
+    }
+    let follower = Some(PackMember::Follower(objects.id_of(leader_id)));
+    for id in 0..objects.len() {
+        let leader = &objects[leader_id];
+        let object = &objects[id];
+        let rallied = object.alive
+            && object.pack == follower
+            && leader.distance_to(object) <= RALLY_RANGE
+            && leader.can_see(object.x, object.y, &game.map);
+        if rallied {
+            let inspired = StatusEffect {
+                effect: Effect::Inspired,
+                turns: RALLY_TURNS,
+            };
+            effects::apply(&mut objects[id], inspired, game);
+        }
+    }
+}
+
+/// spit a web over the player, if they're in sight and not caught already
+fn spit_web(spider_id: usize, game: &mut Game, objects: &mut Objects) -> bool {
+    let (player_x, player_y) = objects[PLAYER].pos();
+    let spider = &objects[spider_id];
+    if objects[PLAYER].has_effect(Effect::Webbed)
+        || spider.distance_to(&objects[PLAYER]) > WEB_RANGE
+        || !spider.can_see(player_x, player_y, &game.map)
+    {
+        return false;
+    }
+    game.messages
+        .add(format!("The {} spits a web at you!", spider.name), LIGHT_GREY);
+    let web = StatusEffect {
+        effect: Effect::Webbed,
+        turns: WEB_TURNS,
+    };
+    effects::apply(&mut objects[PLAYER], web, game);
+    true
+}
+
+/// touch the player, if they're right there, and take some of their
+/// experience
+fn drain_xp(wraith_id: usize, game: &mut Game, objects: &mut Objects) -> bool {
+    if objects[wraith_id].distance_to(&objects[PLAYER]) >= 2.0 || !objects[PLAYER].alive {
+        return false;
+    }
+    let fighter = match objects[PLAYER].fighter.as_mut() {
+        Some(fighter) if fighter.xp > 0 => fighter,
+        _ => return false,
+    };
+    fighter.xp = (fighter.xp - DRAIN_XP_AMOUNT).max(0);
+    game.messages.add(
+        format!("The {} touches you, and you feel your life drain away!", objects[wraith_id].name),
+        VIOLET,
+    );
+    true
+}
+
+/// Raise the nearest corpse the necromancer can see, if it's close enough
+/// and nothing stands on it. The risen are hostile right away, but give no
+/// experience.
+fn raise_dead(necromancer_id: usize, game: &mut Game, objects: &mut Objects) -> bool {
+    let necromancer = &objects[necromancer_id];
+    let corpse_id = objects
+        .iter()
+        .enumerate()
+        .filter(|(_, object)| {
+            object.corpse.is_some()
+                && necromancer.distance(object.x, object.y) <= RAISE_DEAD_RANGE
+                && necromancer.can_see(object.x, object.y, &game.map)
+                && !is_blocked(object.x, object.y, &game.map, objects)
+        })
+        .min_by(|(_, a), (_, b)| {
+            let a = necromancer.distance_to(a);
+            let b = necromancer.distance_to(b);
+            a.partial_cmp(&b).unwrap()
+        })
+        .map(|(id, _)| id);
+    let corpse_id = match corpse_id {
+        Some(id) => id,
+        None => return false,
+    };
+
+    let (x, y) = objects[corpse_id].pos();
+    if game.map.is_in_view(x, y) {
+        game.messages.add(
+            format!(
+                "The {} raises the {} from the dead!",
+                objects[necromancer_id].name, objects[corpse_id].name
+            ),
+            LIGHT_VIOLET,
+        );
+    }
+    let mut risen = bestiary::template(RAISED_SPECIES).spawn(x, y);
+    risen.ai = Some(Ai::Basic);
+    if let Some(fighter) = risen.fighter.as_mut() {
+        fighter.xp = 0;
+    }
+    objects.replace(corpse_id, risen);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::open_field;
+
+    /// the player alone in a lit field, with a monster with the given powers
+    /// at (5, 5)
+    fn field(powers: Vec<Power>) -> (Game, Objects) {
+        let (mut game, mut objects) = open_field(20, 20);
+        for (_, tile) in game.map.iter_mut() {
+            tile.light = 1.0;
+        }
+        objects[PLAYER].set_pos(15, 15);
+        let mut monster = bestiary::template("orc").spawn(5, 5);
+        monster.powers = powers;
+        objects.push(monster);
+        (game, objects)
+    }
+
+    #[test]
+    fn regeneration_works_even_on_monsters_not_hunting() {
+        let (mut game, mut objects) = field(vec![Power::Regenerate, Power::HealAllies]);
+        let monster = PLAYER + 1;
+        objects[monster].ai = Some(Ai::Unaware);
+        objects[monster].fighter.as_mut().unwrap().hp -= 5;
+        let hp = objects[monster].fighter.unwrap().hp;
+        assert!(!use_powers(monster, &mut game, &mut objects));
+        assert_eq!(objects[monster].fighter.unwrap().hp, hp + REGENERATE_AMOUNT);
+    }
+
+    #[test]
+    fn healers_heal_the_most_hurt_ally_in_reach() {
+        let (mut game, mut objects) = field(vec![Power::HealAllies]);
+        let orc = bestiary::template("orc");
+        for (x, hurt) in [(7, 3), (8, 9), (18, 12)] {
+            let mut ally = orc.spawn(x, 5);
+            ally.fighter.as_mut().unwrap().hp -= hurt;
+            objects.push(ally);
+        }
+        assert!(heal_ally(PLAYER + 1, &mut game, &mut objects));
+        let hp = |id: usize| objects[id].fighter.unwrap().hp;
+        assert_eq!(hp(PLAYER + 3), orc.hp - 9 + HEAL_ALLY_AMOUNT);
+        assert_eq!(hp(PLAYER + 2), orc.hp - 3);
+        assert_eq!(hp(PLAYER + 4), orc.hp - 12);
+    }
+}
@@ -0,0 +1,180 @@
+//! Prefabs: rooms laid out by hand in `data/prefabs.json`, stamped into the
+//! rock of a generated level among its other rooms, turned and flipped any
+//! way. Each row of a prefab is drawn with the same characters as the
+//! hand-made maps, plus a few for what to put in it:
+//!
+//! - `M`, a monster and `I`, an item, of those found on the level
+//! - `$`, a pile of gold
+//! - `^`, a hidden trap
+//!
+//! Every prefab needs a door (`+`) on its outer wall, for a tunnel to lead
+//! into it from the rest of the level.
+
+use std::sync::OnceLock;
+
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+use serde::Deserialize;
+
+use crate::dungeon::Branch;
+use crate::map::{from_dungeon_level, Transition};
+
+const PREFAB_DATA: &str = include_str!("../data/prefabs.json");
+
+/// the characters for what goes in a prefab, besides the tiles
+pub const OBJECT_GLYPHS: &str = "MI$^";
+
+/// One room laid out by hand.
+#[derive(Debug, Deserialize)]
+pub struct Prefab {
+    pub name: String,
+    rows: Vec<String>,
+    /// how likely this prefab is to be picked, against the others, from each
+    /// dungeon level on; never, if empty
+    #[serde(default)]
+    spawn: Vec<Transition>,
+    /// the branches of the dungeon it turns up in; all of them, if empty
+    #[serde(default)]
+    branches: Vec<Branch>,
+}
+
+impl Prefab {
+    /// The prefab's rows, given a random quarter turn or two and maybe
+    /// mirrored, as a grid of characters indexed by row, then column.
+    pub fn oriented(&self, rng: &mut impl Rng) -> Vec<Vec<char>> {
+        let mut grid: Vec<Vec<char>> = self.rows.iter().map(|row| row.chars().collect()).collect();
+        if rng.gen() {
+            for row in grid.iter_mut() {
+                row.reverse();
+            }
+        }
+        for _ in 0..rng.gen_range(0..4) {
+            grid = turn(&grid);
+        }
+        grid
+    }
+
+    /// whether the prefab is drawn right, or what's wrong with it
+    fn check(&self) -> Result<(), String> {
+        let width = self.rows.first().map_or(0, |row| row.chars().count());
+        if width == 0 || self.rows.iter().any(|row| row.chars().count() != width) {
+            return Err("its rows must all be the same length".into());
+        }
+        let height = self.rows.len();
+        let mut has_entrance = false;
+        for (y, row) in self.rows.iter().enumerate() {
+            for (x, char) in row.chars().enumerate() {
+                if !"#.~+".contains(char) && !OBJECT_GLYPHS.contains(char) {
+                    return Err(format!("unknown character '{}' at row {}", char, y + 1));
+                }
+                let on_edge = [x == 0, x == width - 1, y == 0, y == height - 1];
+                let edges = on_edge.iter().filter(|&&edge| edge).count();
+                if char == '+' && edges == 1 {
+                    has_entrance = true;
+                } else if edges > 0 && char != '#' && char != '+' {
+                    return Err(format!("its outer wall is open at row {}", y + 1));
+                }
+            }
+        }
+        if !has_entrance {
+            return Err("it has no door in its outer wall".into());
+        }
+        Ok(())
+    }
+}
+
+/// a grid turned a quarter turn clockwise
+fn turn(grid: &[Vec<char>]) -> Vec<Vec<char>> {
+    let width = grid.first().map_or(0, Vec::len);
+    (0..width)
+        .map(|x| grid.iter().rev().map(|row| row[x]).collect())
+        .collect()
+}
+
+/// read prefabs from JSON laid out like `data/prefabs.json`, or say what's
+/// wrong with them
+pub fn parse(json: &str) -> Result<Vec<Prefab>, String> {
+    let prefabs: Vec<Prefab> = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    for prefab in &prefabs {
+        prefab.check().map_err(|e| format!("prefab {}: {}", prefab.name, e))?;
+    }
+    Ok(prefabs)
+}
+
+/// All prefabs, read from the data file the first time they're needed, or
+/// what's wrong with it.
+pub fn load() -> Result<&'static [Prefab], String> {
+    static TEMPLATES: OnceLock<Result<Vec<Prefab>, String>> = OnceLock::new();
+    TEMPLATES
+        .get_or_init(|| {
+            parse(PREFAB_DATA).map_err(|e| format!("data/prefabs.json is invalid: {}", e))
+        })
+        .as_deref()
+        .map_err(Clone::clone)
+}
+
+/// all prefabs; `run` has made sure the data file reads before anything
+/// needs them
+pub fn templates() -> &'static [Prefab] {
+    load().unwrap_or_else(|e| panic!("{}", e))
+}
+
+/// A prefab picked from those found at the given dungeon level of the given
+/// branch, if there are any.
+pub fn random_prefab(level: u32, branch: Branch, rng: &mut impl Rng) -> Option<&'static Prefab> {
+    let templates = templates();
+    let weights = templates.iter().map(|template| {
+        if branch.is_among(&template.branches) {
+            from_dungeon_level(&template.spawn, level)
+        } else {
+            0
+        }
+    });
+    // there may be none this shallow, or in this branch
+    let dist = WeightedIndex::new(weights).ok()?;
+    Some(&templates[rng.sample(&dist)])
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+
+    fn prefab(rows: &[&str]) -> Prefab {
+        Prefab {
+            name: "test".into(),
+            rows: rows.iter().map(|row| row.to_string()).collect(),
+            spawn: vec![],
+            branches: vec![],
+        }
+    }
+
+    #[test]
+    fn prefabs_must_be_walled_in_with_a_door() {
+        assert_eq!(prefab(&["#+##", "#.M#", "####"]).check(), Ok(()));
+        let open = prefab(&["#+##", "..M#", "####"]).check();
+        assert_eq!(open, Err("its outer wall is open at row 2".into()));
+        let shut = prefab(&["####", "#.M#", "####"]).check();
+        assert_eq!(shut, Err("it has no door in its outer wall".into()));
+        // a door in a corner leads nowhere
+        assert!(prefab(&["+###", "#.M#", "####"]).check().is_err());
+        assert!(prefab(&["#+##", "#.M", "####"]).check().is_err());
+        assert!(prefab(&["#+##", "#.X#", "####"]).check().is_err());
+    }
+
+    #[test]
+    fn turning_and_flipping_keeps_what_is_in_it() {
+        let grid = vec![vec!['#', '+'], vec!['.', 'M'], vec!['#', '#']];
+        assert_eq!(turn(&grid), vec![vec!['#', '.', '#'], vec!['#', 'M', '+']]);
+        let prefab = prefab(&["#+##", "#.M#", "####"]);
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..8 {
+            let grid = prefab.oriented(&mut rng);
+            let mut chars: Vec<char> = grid.concat();
+            chars.sort();
+            assert_eq!(chars, "#########+.M".chars().collect::<Vec<_>>());
+        }
+    }
+}
@@ -0,0 +1,100 @@
+//! Projectiles. Monsters with a ranged attack only shoot along a clear line
+//! of sight, and every shot fired during the monsters' turn is queued up to
+//! be shown flying across the screen before the player moves again.
+
+use crate::colors::Color;
+use crate::console::*;
+use crate::entities::Objects;
+use crate::map::{is_blocked, Map};
+use crate::{render_all, Game, Tcod};
+
+/// A shot on its way, to be animated.
+#[derive(Clone, Debug)]
+pub struct Projectile {
+    /// the tiles it flies through, the one it hits last
+    pub path: Vec<(i32, i32)>,
+    pub char: char,
+    pub color: Color,
+}
+
+/// The tiles on a straight line from `from` to `to`, not counting `from`.
+pub fn line((x0, y0): (i32, i32), (x1, y1): (i32, i32)) -> Vec<(i32, i32)> {
+    // Bresenham's line algorithm
+    let (dx, dy) = ((x1 - x0).abs(), -(y1 - y0).abs());
+    let (step_x, step_y) = ((x1 - x0).signum(), (y1 - y0).signum());
+    let mut error = dx + dy;
+    let (mut x, mut y) = (x0, y0);
+    let mut tiles = vec![];
+    while (x, y) != (x1, y1) {
+        let doubled = 2 * error;
+        if doubled >= dy {
+            error += dy;
+            x += step_x;
+        }
+        if doubled <= dx {
+            error += dx;
+            y += step_y;
+        }
+        tiles.push((x, y));
+    }
+    tiles
+}
+
+/// whether a shot from `from` would reach `to` without hitting a wall,
+/// passing through smoke or something standing in the way
+pub fn clear_shot(from: (i32, i32), to: (i32, i32), map: &Map, objects: &Objects) -> bool {
+    line(from, to)
+        .into_iter()
+        .take_while(|&pos| pos != to)
+        .all(|(x, y)| map.is_transparent(x, y) && !is_blocked(x, y, map, objects))
+}
+
+/// The tiles something thrown or shot by the player flies through: towards
+/// the target, but stopping short of a wall and at the first thing in the way
+/// that fights.
+pub fn flight(from: (i32, i32), to: (i32, i32), map: &Map, objects: &Objects) -> Vec<(i32, i32)> {
+    let mut path = vec![];
+    for (x, y) in line(from, to) {
+        if map[(x, y)].blocked {
+            break;
+        }
+        path.push((x, y));
+        if objects.iter().any(|obj| obj.pos() == (x, y) && obj.fighter.is_some()) {
+            break;
+        }
+    }
+    path
+}
+
+/// the character an arrow flying in the given direction is drawn with
+pub fn arrow((dx, dy): (i32, i32)) -> char {
+    if dx == 0 {
+        '|'
+    } else if dy == 0 {
+        '-'
+    } else if (dx > 0) == (dy > 0) {
+        '\\'
+    } else {
+        '/'
+    }
+}
+
+/// Show every queued projectile flying to its target, one after the other,
+/// over the tiles the player can see. Each tile of the flight is a frame, so
+/// the FPS limit sets the pace.
+pub fn animate(tcod: &mut Tcod, game: &mut Game, objects: &Objects) {
+    for projectile in std::mem::take(&mut game.projectiles) {
+        for &(x, y) in &projectile.path {
+            if !game.map.is_visible(x, y) {
+                continue;
+            }
+            tcod.con.clear();
+            render_all(tcod, game, objects, false);
+            if let Some((x, y)) = tcod.camera.to_screen(x, y) {
+                tcod.screen.set_default_foreground(projectile.color);
+                tcod.screen.put_char(x, y, projectile.char, BackgroundFlag::None);
+            }
+            tcod.flush();
+        }
+    }
+}
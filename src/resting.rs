@@ -0,0 +1,163 @@
+//! Resting and searching: things the player keeps at, turn after turn, until
+//! they're done or something interrupts them. Resting waits, healing slowly,
+//! until the player's HP and mana are full; searching looks all around for
+//! traps and hidden passages for a while. Either stops the moment an enemy
+//! comes into view, the player is hurt or they get hungrier, and the longer
+//! the player stays put, the likelier something wanders by to find them.
+
+use crate::colors::LIGHT_GREY;
+use crate::entities::Objects;
+use crate::factions;
+use crate::{hunger, search_for_passages, traps, Game, PLAYER, SEARCH_RADIUS};
+
+/// how many turns of rest it takes to heal a hit point
+const REST_HEAL_TURNS: u32 = 4;
+/// how many turns a search goes on for, if nothing turns up
+const SEARCH_TURNS: u32 = 10;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Activity {
+    Rest,
+    Search,
+}
+
+impl Activity {
+    /// what the player is doing, as messages put it
+    fn doing(self) -> &'static str {
+        match self {
+            Activity::Rest => "resting",
+            Activity::Search => "searching",
+        }
+    }
+}
+
+/// The player's activity, and how it's gone so far.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Busy {
+    activity: Activity,
+    turns: u32,
+    /// the player's HP after their last turn at it
+    hp: i32,
+    /// how hungry the player was when they started
+    hunger: Option<&'static str>,
+    /// whether a search has turned something up
+    found: bool,
+}
+
+impl Busy {
+    /// what the player is doing, as messages put it
+    pub fn doing(&self) -> &'static str {
+        self.activity.doing()
+    }
+
+    pub fn start(activity: Activity, game: &Game, objects: &Objects) -> Self {
+        Busy {
+            activity,
+            turns: 0,
+            hp: objects[PLAYER].fighter.map_or(0, |f| f.hp),
+            hunger: hunger(game.satiation),
+            found: false,
+        }
+    }
+
+    /// Spend another turn on it, unless it's done or has to stop, in which
+    /// case say why. Returns whether the player went on.
+    pub fn go_on(&mut self, game: &mut Game, objects: &mut Objects) -> bool {
+        if let Some(reason) = self.interruption(game, objects) {
+            game.messages.add(reason, LIGHT_GREY);
+            return false;
+        }
+        self.turns += 1;
+        match self.activity {
+            Activity::Rest => {
+                if self.turns.is_multiple_of(REST_HEAL_TURNS) {
+                    objects[PLAYER].heal(1, game);
+                }
+            }
+            Activity::Search => {
+                let found = traps::search(game, objects, SEARCH_RADIUS);
+                self.found = search_for_passages(game, objects) || found;
+            }
+        }
+        self.hp = objects[PLAYER].fighter.map_or(0, |f| f.hp);
+        true
+    }
+
+    /// why the player has to stop, if they do
+    fn interruption(&self, game: &Game, objects: &Objects) -> Option<String> {
+        let player = &objects[PLAYER];
+        let doing = self.activity.doing();
+        let enemy = objects.iter().find(|object| {
+            object.alive
+                && object.ai.is_some()
+                && factions::are_enemies(object, player)
+                && game.map.is_visible(object.x, object.y)
+        });
+        if let Some(enemy) = enemy {
+            return Some(format!("You stop {}: the {} is in view.", doing, enemy.name));
+        }
+        let (hp, mana, max_mana) = player
+            .fighter
+            .map_or((0, 0, 0), |f| (f.hp, f.mana, f.max_mana));
+        if hp < self.hp {
+            return Some(format!("You stop {}: you've been hurt.", doing));
+        }
+        if hunger(game.satiation) != self.hunger {
+            return Some(format!("You stop {}: you're getting hungry.", doing));
+        }
+        match self.activity {
+            Activity::Rest if hp >= player.max_hp(game) && mana >= max_mana => {
+                Some("You feel rested.".into())
+            }
+            Activity::Search if self.found => Some("You stop searching.".into()),
+            Activity::Search if self.turns >= SEARCH_TURNS => {
+                Some("You finish searching.".into())
+            }
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bestiary;
+    use crate::test_support::open_field;
+
+    /// a wounded player alone in a lit field
+    fn field() -> (Game, Objects) {
+        let (game, mut objects) = open_field(20, 20);
+        objects[PLAYER].fighter.as_mut().unwrap().hp -= 2;
+        (game, objects)
+    }
+
+    #[test]
+    fn resting_heals_until_the_player_is_rested() {
+        let (mut game, mut objects) = field();
+        let mut busy = Busy::start(Activity::Rest, &game, &objects);
+        let mut turns = 0;
+        while busy.go_on(&mut game, &mut objects) {
+            turns += 1;
+        }
+        assert_eq!(turns, 2 * REST_HEAL_TURNS);
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, objects[PLAYER].max_hp(&game));
+        assert_eq!(game.messages.iter().last().unwrap().text, "You feel rested.");
+    }
+
+    #[test]
+    fn an_enemy_in_view_or_a_wound_stops_the_player() {
+        let (mut game, mut objects) = field();
+        let mut busy = Busy::start(Activity::Search, &game, &objects);
+        assert!(busy.go_on(&mut game, &mut objects));
+        objects[PLAYER].fighter.as_mut().unwrap().hp -= 1;
+        assert!(!busy.go_on(&mut game, &mut objects));
+        let last = game.messages.iter().last().unwrap();
+        assert_eq!(last.text, "You stop searching: you've been hurt.");
+
+        let mut busy = Busy::start(Activity::Rest, &game, &objects);
+        objects.push(bestiary::template("orc").spawn(9, 5));
+        assert!(!busy.go_on(&mut game, &mut objects));
+        let last = game.messages.iter().last().unwrap();
+        assert_eq!(last.text, "You stop resting: the orc is in view.");
+    }
+}
@@ -0,0 +1,876 @@
+//! Save files, and bringing old ones up to date. Games are saved in a few
+//! numbered slots, each a file of its own. Saves are written to a temporary
+//! file and renamed into place, so one cut short by a crash never replaces a
+//! good one, and the save before is kept to fall back on in case the latest
+//! is damaged anyway. A save records the version of its
+//! format alongside the game; one from an older version goes through every
+//! migration since, as plain JSON, before it's read into the game's structs,
+//! so old saves still load after those structs change.
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{ErrorKind, Write};
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::classes::Class;
+use crate::colors::ORANGE;
+use crate::entities::{self, Objects};
+use crate::{Game, Object};
+
+/// where the slots' files are kept; tests keep theirs out of the way of
+/// the player's
+#[cfg(not(test))]
+const SAVE_DIR: &str = "saves";
+#[cfg(test)]
+const SAVE_DIR: &str = "target/test-saves";
+
+/// where the one save was kept before there were slots
+const OLD_SAVE_FILE: &str = "savegame";
+
+/// how many games can be saved at once
+pub const SLOTS: usize = 5;
+
+/// the version of the save format this game writes
+const VERSION: u64 = 10;
+
+/// A migration takes a save from one version of the format to the next.
+type Migration = fn(Value) -> Result<Value, String>;
+
+/// The migrations, in order: the first takes a save from version 0 to
+/// version 1, and so on. Whenever a change to the saved structs would stop
+/// older saves from loading, add one here and bump `VERSION`.
+const MIGRATIONS: [Migration; VERSION as usize] = [
+    add_version,
+    move_effects,
+    unwrap_confusion,
+    learn_spells,
+    two_ring_slots,
+    npc_attitudes,
+    factions,
+    message_categories,
+    spoiled_corpses,
+    generational_ids,
+];
+
+/// A save, as written.
+#[derive(Serialize)]
+struct SaveFile<'a> {
+    version: u64,
+    game: &'a Game,
+    objects: &'a [Object],
+}
+
+/// A save, as read once it's up to date.
+#[derive(Deserialize)]
+struct Save {
+    game: Game,
+    objects: Vec<Object>,
+}
+
+/// Why a save couldn't be loaded.
+#[derive(Debug)]
+pub enum LoadError {
+    /// there's no save to load
+    Missing,
+    /// the save is there, but doesn't make sense
+    Damaged(String),
+    /// the save was written by a newer version of the game
+    TooNew(u64),
+}
+
+impl std::fmt::Display for LoadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LoadError::Missing => write!(f, "there is no saved game"),
+            LoadError::Damaged(reason) => write!(f, "the save is damaged: {}", reason),
+            LoadError::TooNew(version) => write!(
+                f,
+                "the save is from a newer version of the game (save format {}; \
+                 this version reads up to {})",
+                version, VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+/// What's in a slot, as the load menu shows it.
+pub enum Slot {
+    Empty,
+    Saved {
+        name: String,
+        level: i32,
+        /// where the player is, as the panel names it
+        place: String,
+        /// when the game was saved, in seconds since 1970-01-01 (UTC)
+        saved_at: u64,
+    },
+    /// a save that's there, but can't be loaded
+    Broken(LoadError),
+}
+
+fn slot_path(slot: usize) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("slot{}.json", slot + 1))
+}
+
+/// where the save before the latest in a slot is kept
+fn backup_path(slot: usize) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("slot{}.backup.json", slot + 1))
+}
+
+/// where a save is written before it's renamed into its slot
+fn temporary_path(slot: usize) -> PathBuf {
+    PathBuf::from(SAVE_DIR).join(format!("slot{}.json.tmp", slot + 1))
+}
+
+/// What's in every slot. A game saved before there were slots is moved into
+/// the first, if that's free.
+pub fn slots() -> Vec<Slot> {
+    if fs::metadata(OLD_SAVE_FILE).is_ok() && fs::metadata(slot_path(0)).is_err() {
+        let moved = fs::create_dir_all(SAVE_DIR)
+            .and_then(|_| fs::rename(OLD_SAVE_FILE, slot_path(0)));
+        if let Err(e) = moved {
+            eprintln!("Could not move {} into the first slot: {}", OLD_SAVE_FILE, e);
+        }
+    }
+    (0..SLOTS).map(describe).collect()
+}
+
+fn describe(slot: usize) -> Slot {
+    let (game, objects) = match read(slot) {
+        Ok(save) => save,
+        Err(LoadError::Missing) => return Slot::Empty,
+        Err(e) => return Slot::Broken(e),
+    };
+    let saved_at = fs::metadata(slot_path(slot))
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |since| since.as_secs());
+    Slot::Saved {
+        place: crate::place_name(&game),
+        name: game.character_name,
+        level: objects.first().map_or(1, |player| player.level),
+        saved_at,
+    }
+}
+
+/// Write the game to a slot, in the current format.
+pub fn write(slot: usize, game: &Game, objects: &[Object]) -> Result<(), String> {
+    let save = SaveFile {
+        version: VERSION,
+        game,
+        objects,
+    };
+    let json = serde_json::to_string(&save).map_err(|e| e.to_string())?;
+    write_safely(slot, &json).map_err(|e| e.to_string())
+}
+
+/// Write a save out in full before it takes the slot, keeping the one it
+/// replaces as the backup.
+fn write_safely(slot: usize, json: &str) -> std::io::Result<()> {
+    fs::create_dir_all(SAVE_DIR)?;
+    let mut file = File::create(temporary_path(slot))?;
+    file.write_all(json.as_bytes())?;
+    file.sync_all()?;
+    match fs::rename(slot_path(slot), backup_path(slot)) {
+        Err(e) if e.kind() != ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    fs::rename(temporary_path(slot), slot_path(slot))
+}
+
+/// Read the game from a slot, whichever version of the format it's in. If the
+/// latest save is damaged, or gone, the one before it is loaded instead.
+pub fn read(slot: usize) -> Result<(Game, Objects), LoadError> {
+    match read_file(slot_path(slot)) {
+        Err(LoadError::TooNew(version)) => Err(LoadError::TooNew(version)),
+        Err(e) => {
+            // say why the latest save was passed over, but not why the
+            // backup couldn't be loaded either if it's just not there
+            let (mut game, objects) = read_file(backup_path(slot)).map_err(|backup_error| {
+                match backup_error {
+                    LoadError::Missing => e,
+                    backup_error => backup_error,
+                }
+            })?;
+            game.messages.add(
+                "The last save could not be loaded, so the one before it was.",
+                ORANGE,
+            );
+            Ok((game, objects))
+        }
+        save => save,
+    }
+}
+
+fn read_file(path: PathBuf) -> Result<(Game, Objects), LoadError> {
+    let json = fs::read_to_string(path).map_err(|e| match e.kind() {
+        ErrorKind::NotFound => LoadError::Missing,
+        _ => LoadError::Damaged(e.to_string()),
+    })?;
+    parse(&json)
+}
+
+/// Empty a slot for good, backup and all.
+pub fn delete(slot: usize) -> Result<(), String> {
+    for path in [slot_path(slot), backup_path(slot), temporary_path(slot)] {
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != ErrorKind::NotFound => return Err(e.to_string()),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Read a save, migrating it to the current format first if need be.
+pub fn parse(json: &str) -> Result<(Game, Objects), LoadError> {
+    let save: Value =
+        serde_json::from_str(json).map_err(|e| LoadError::Damaged(e.to_string()))?;
+    let save = migrate(save)?;
+    let save: Save = serde_json::from_value(save).map_err(|e| LoadError::Damaged(e.to_string()))?;
+    let mut game = save.game;
+    let mut objects = Objects::restore(save.objects, entities::away(&game));
+    entities::forget_the_missing(&mut game, &mut objects);
+    Ok((game, objects))
+}
+
+/// Bring a save up to the current version of the format.
+fn migrate(mut save: Value) -> Result<Value, LoadError> {
+    let mut version = version_of(&save)?;
+    if version > VERSION {
+        return Err(LoadError::TooNew(version));
+    }
+    while version < VERSION {
+        save = MIGRATIONS[version as usize](save).map_err(|e| {
+            LoadError::Damaged(format!("could not update it from format {}: {}", version, e))
+        })?;
+        version += 1;
+        if let Some(fields) = save.as_object_mut() {
+            fields.insert("version".into(), version.into());
+        }
+    }
+    Ok(save)
+}
+
+/// the version of the format a save is in
+fn version_of(save: &Value) -> Result<u64, LoadError> {
+    match save {
+        // the first saves were just the game and the objects, in an array
+        Value::Array(_) => Ok(0),
+        Value::Object(fields) => fields
+            .get("version")
+            .and_then(Value::as_u64)
+            .ok_or_else(|| LoadError::Damaged("it has no version".into())),
+        _ => Err(LoadError::Damaged("it isn't a saved game".into())),
+    }
+}
+
+/// version 0 to 1: name the game and the objects, so there's somewhere to
+/// keep the version
+fn add_version(save: Value) -> Result<Value, String> {
+    let (mut game, objects) = match save {
+        Value::Array(parts) if parts.len() == 2 => {
+            let mut parts = parts.into_iter();
+            (parts.next().unwrap(), parts.next().unwrap())
+        }
+        _ => return Err("expected the game and the objects".into()),
+    };
+    if let Some(map) = game.get_mut("map") {
+        flatten_map(map)?;
+    }
+    let mut fields = Map::new();
+    fields.insert("game".into(), game);
+    fields.insert("objects".into(), objects);
+    Ok(Value::Object(fields))
+}
+
+/// The very first saves kept the map as a list of columns of tiles, and
+/// later ones as its size and its tiles row by row; lay out one of the first
+/// like the rest.
+fn flatten_map(map: &mut Value) -> Result<(), String> {
+    let columns = match map {
+        Value::Array(columns) => std::mem::take(columns),
+        _ => return Ok(()),
+    };
+    let mut columns: Vec<Vec<Value>> = columns
+        .into_iter()
+        .map(|column| match column {
+            Value::Array(tiles) => Ok(tiles),
+            _ => Err("expected a column of tiles"),
+        })
+        .collect::<Result<_, _>>()?;
+    let (width, height) = (columns.len(), columns.first().map_or(0, Vec::len));
+    if columns.iter().any(|column| column.len() != height) {
+        return Err("expected the map's columns to be the same height".into());
+    }
+    let mut tiles = Vec::with_capacity(width * height);
+    for y in 0..height {
+        for column in columns.iter_mut() {
+            tiles.push(column[y].take());
+        }
+    }
+    let mut fields = Map::new();
+    fields.insert("width".into(), width.into());
+    fields.insert("height".into(), height.into());
+    fields.insert("tiles".into(), tiles.into());
+    *map = Value::Object(fields);
+    Ok(())
+}
+
+/// version 1 to 2: the player's poisoning and regeneration were counted on
+/// the game, and are now status effects on the player
+fn move_effects(mut save: Value) -> Result<Value, String> {
+    let mut effects = vec![];
+    let game = save
+        .get_mut("game")
+        .and_then(Value::as_object_mut)
+        .ok_or("expected the game")?;
+    for (key, effect) in [("poison_turns", "Poison"), ("regeneration_turns", "Regeneration")] {
+        let turns = game.remove(key).and_then(|turns| turns.as_u64()).unwrap_or(0);
+        if turns > 0 {
+            let mut status = Map::new();
+            status.insert("effect".into(), effect.into());
+            status.insert("turns".into(), turns.into());
+            effects.push(Value::Object(status));
+        }
+    }
+    if effects.is_empty() {
+        return Ok(save);
+    }
+    let player = save
+        .get_mut("objects")
+        .and_then(Value::as_array_mut)
+        .and_then(|objects| objects.first_mut())
+        .and_then(Value::as_object_mut)
+        .ok_or("expected the player")?;
+    player.insert("effects".into(), Value::Array(effects));
+    Ok(save)
+}
+
+/// version 2 to 3: confusion was an AI wrapped around the monster's own, and
+/// is now a status effect
+fn unwrap_confusion(mut save: Value) -> Result<Value, String> {
+    let objects = save
+        .get_mut("objects")
+        .and_then(Value::as_array_mut)
+        .ok_or("expected the objects")?;
+    for object in objects.iter_mut().filter_map(Value::as_object_mut) {
+        let mut turns = 0;
+        if let Some(ai) = object.get_mut("ai") {
+            // {"Confused": {"previous_ai": ..., "num_turns": n}}, confused
+            // until n drops below 0
+            while let Some(confused) = ai.get_mut("Confused") {
+                turns += confused.get("num_turns").and_then(Value::as_u64).unwrap_or(0) + 1;
+                let previous_ai = confused.get_mut("previous_ai").map_or(Value::Null, Value::take);
+                *ai = previous_ai;
+            }
+        }
+        if turns > 0 {
+            let mut status = Map::new();
+            status.insert("effect".into(), "Confusion".into());
+            status.insert("turns".into(), turns.into());
+            let effects = object.entry("effects").or_insert(Value::Array(vec![]));
+            effects
+                .as_array_mut()
+                .ok_or("expected a list of effects")?
+                .push(Value::Object(status));
+        }
+    }
+    Ok(save)
+}
+
+/// version 3 to 4: a mage's spellbook cast magic missiles until it needed a
+/// rest, and is now a spell the player knows, cast for mana
+fn learn_spells(mut save: Value) -> Result<Value, String> {
+    let game = save
+        .get_mut("game")
+        .and_then(Value::as_object_mut)
+        .ok_or("expected the game")?;
+    game.remove("spell_recharge");
+    let class: Class = match game.get("class") {
+        Some(class) => serde_json::from_value(class.clone()).map_err(|e| e.to_string())?,
+        None => Class::default(),
+    };
+    if let Some(inventory) = game.get_mut("inventory").and_then(Value::as_array_mut) {
+        let carried = inventory.len();
+        inventory.retain(|item| item.get("name") != Some(&Value::from("spellbook")));
+        if inventory.len() < carried {
+            game.insert("spells".into(), Value::Array(vec!["MagicMissile".into()]));
+        }
+    }
+
+    let fighter = save
+        .get_mut("objects")
+        .and_then(Value::as_array_mut)
+        .and_then(|objects| objects.first_mut())
+        .and_then(|player| player.get_mut("fighter"))
+        .and_then(Value::as_object_mut);
+    if let Some(fighter) = fighter {
+        let mana = class.fighter().max_mana as u64;
+        fighter.insert("mana".into(), mana.into());
+        fighter.insert("max_mana".into(), mana.into());
+    }
+    Ok(save)
+}
+
+/// version 4 to 5: rings were worn on a single finger slot, and now there's
+/// one on each hand
+fn two_ring_slots(mut save: Value) -> Result<Value, String> {
+    fn move_rings(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                if fields.get("slot") == Some(&Value::from("Finger")) {
+                    fields.insert("slot".into(), "LeftRing".into());
+                }
+                fields.values_mut().for_each(move_rings);
+            }
+            Value::Array(values) => values.iter_mut().for_each(move_rings),
+            _ => {}
+        }
+    }
+    move_rings(&mut save);
+    Ok(save)
+}
+
+/// version 5 to 6: NPCs only have a say in whether they're hostile now, and
+/// those that talk or trade aren't
+fn npc_attitudes(mut save: Value) -> Result<Value, String> {
+    fn calm_npcs(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                let talks = fields.get("dialogue").map_or(false, |d| !d.is_null());
+                let trades = fields.get("trader").map_or(false, |t| !t.is_null());
+                if talks {
+                    fields.insert("attitude".into(), "Friendly".into());
+                } else if trades {
+                    fields.insert("attitude".into(), "Neutral".into());
+                }
+                fields.values_mut().for_each(calm_npcs);
+            }
+            Value::Array(values) => values.iter_mut().for_each(calm_npcs),
+            _ => {}
+        }
+    }
+    calm_npcs(&mut save);
+    Ok(save)
+}
+
+/// version 6 to 7: everything was against the player unless it was an NPC
+/// or a companion, and now it takes a side
+fn factions(mut save: Value) -> Result<Value, String> {
+    fn take_sides(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                let companion = fields.get("ai") == Some(&Value::from("Ally"));
+                let npc = matches!(
+                    fields.get("attitude").and_then(Value::as_str),
+                    Some("Neutral" | "Friendly")
+                );
+                if companion {
+                    fields.insert("faction".into(), "Player".into());
+                } else if npc {
+                    fields.insert("faction".into(), "Neutral".into());
+                }
+                fields.values_mut().for_each(take_sides);
+            }
+            Value::Array(values) => values.iter_mut().for_each(take_sides),
+            _ => {}
+        }
+    }
+    take_sides(&mut save);
+    let player = save
+        .get_mut("objects")
+        .and_then(Value::as_array_mut)
+        .and_then(|objects| objects.first_mut())
+        .and_then(Value::as_object_mut)
+        .ok_or("expected the player")?;
+    player.insert("faction".into(), "Player".into());
+    Ok(save)
+}
+
+/// version 7 to 8: messages were a pair of their text and color, and now
+/// they say what they're about too, which older ones leave to the default
+fn message_categories(mut save: Value) -> Result<Value, String> {
+    let messages = save
+        .pointer_mut("/game/messages/messages")
+        .and_then(Value::as_array_mut);
+    for message in messages.into_iter().flatten() {
+        let (text, color) = match message.as_array().map(Vec::as_slice) {
+            Some([text, color]) => (text.clone(), color.clone()),
+            _ => return Err("expected a message's text and color".into()),
+        };
+        let mut fields = Map::new();
+        fields.insert("text".into(), text);
+        fields.insert("color".into(), color);
+        *message = Value::Object(fields);
+    }
+    Ok(save)
+}
+
+/// version 8 to 9: a corpse that had gone bad was only known by its name,
+/// and now it says so itself
+fn spoiled_corpses(mut save: Value) -> Result<Value, String> {
+    fn mark_spoiled(value: &mut Value) {
+        match value {
+            Value::Object(fields) => {
+                let rotten = fields
+                    .get("name")
+                    .and_then(Value::as_str)
+                    .map_or(false, |name| name.starts_with("rotten "));
+                if let Some(Value::Object(corpse)) = fields.get_mut("corpse") {
+                    corpse.insert("spoiled".into(), rotten.into());
+                }
+                fields.values_mut().for_each(mark_spoiled);
+            }
+            Value::Array(values) => values.iter_mut().for_each(mark_spoiled),
+            _ => {}
+        }
+    }
+    mark_spoiled(&mut save);
+    Ok(save)
+}
+
+/// version 9 to 10: objects were numbered one after another as they turned
+/// up, packs went by a number of their own and unpaid-for items by their
+/// shopkeeper's name; now everything on a level has a slot in the arena,
+/// and packs and items keep the ID of their leader and shopkeeper
+fn generational_ids(mut save: Value) -> Result<Value, String> {
+    if let Some(Value::Object(game)) = save.get_mut("game") {
+        game.remove("last_entity_id");
+    }
+    let mut lists = vec!["/objects".to_string()];
+    let levels = save.pointer("/game/dungeon/levels").and_then(Value::as_object);
+    for (branch, levels) in levels.into_iter().flatten() {
+        for depth in levels.as_object().into_iter().flat_map(Map::keys) {
+            lists.push(format!("/game/dungeon/levels/{}/{}/objects", branch, depth));
+        }
+    }
+    // give everything on every level a slot of its own, noting what it was
+    // known by before
+    let mut renumbered: HashMap<u64, Value> = HashMap::new();
+    let mut shopkeepers: HashMap<String, Value> = HashMap::new();
+    let mut next = 0;
+    for list in &lists {
+        let objects = save
+            .pointer_mut(list)
+            .and_then(Value::as_array_mut)
+            .ok_or("expected a list of objects")?;
+        let mut leaders = HashMap::new();
+        for object in objects.iter_mut().filter_map(Value::as_object_mut) {
+            let mut id = Map::new();
+            id.insert("index".into(), next.into());
+            id.insert("generation".into(), 0.into());
+            let id = Value::Object(id);
+            next += 1;
+            if let Some(old) = object.get("id").and_then(Value::as_u64) {
+                renumbered.insert(old, id.clone());
+            }
+            let trades = object.get("trader").map_or(false, |t| !t.is_null());
+            match object.get("name").and_then(Value::as_str) {
+                Some(name) if trades => {
+                    shopkeepers.insert(name.to_string(), id.clone());
+                }
+                _ => {}
+            }
+            let pack = object.get("pack");
+            if pack.and_then(|p| p.get("leader")) == Some(&Value::Bool(true)) {
+                let number = pack.and_then(|p| p.get("pack")).and_then(Value::as_u64);
+                leaders.insert(number, id.clone());
+            }
+            object.insert("id".into(), id);
+        }
+        for object in objects.iter_mut().filter_map(Value::as_object_mut) {
+            let pack = match object.get("pack") {
+                Some(pack) if pack.get("leader") == Some(&Value::Bool(true)) => {
+                    Value::from("Leader")
+                }
+                Some(pack) => {
+                    let number = pack.get("pack").and_then(Value::as_u64);
+                    match leaders.get(&number) {
+                        Some(leader) => {
+                            let mut follower = Map::new();
+                            follower.insert("Follower".into(), leader.clone());
+                            Value::Object(follower)
+                        }
+                        None => Value::Null,
+                    }
+                }
+                None => continue,
+            };
+            object.insert("pack".into(), pack);
+        }
+    }
+    // and point whatever kept track of another object at its new ID, or at
+    // nothing if it's gone
+    fn point_at_new_ids(
+        value: &mut Value,
+        renumbered: &HashMap<u64, Value>,
+        shopkeepers: &HashMap<String, Value>,
+    ) {
+        match value {
+            Value::Object(fields) => {
+                if let Some(old) = fields.get("target").and_then(Value::as_u64) {
+                    let new = renumbered.get(&old).cloned();
+                    fields.insert("target".into(), new.unwrap_or(Value::Null));
+                }
+                if let Some(name) = fields.get("owed").and_then(Value::as_str) {
+                    let new = shopkeepers.get(name).cloned();
+                    fields.insert("owed".into(), new.unwrap_or(Value::Null));
+                }
+                // nothing off a level, like what's in the player's pack, has one
+                if fields.get("id").map_or(false, Value::is_u64) {
+                    fields.insert("id".into(), Value::Null);
+                }
+                for value in fields.values_mut() {
+                    point_at_new_ids(value, renumbered, shopkeepers);
+                }
+            }
+            Value::Array(values) => {
+                for value in values {
+                    point_at_new_ids(value, renumbered, shopkeepers);
+                }
+            }
+            _ => {}
+        }
+    }
+    point_at_new_ids(&mut save, &renumbered, &shopkeepers);
+    Ok(save)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn json(text: &str) -> Value {
+        serde_json::from_str(text).unwrap()
+    }
+
+    /// the objects in a save brought up to date, without the IDs they've
+    /// been given, one after another
+    fn objects_of(save: &Value) -> Value {
+        let mut objects = save["objects"].clone();
+        for (index, object) in objects.as_array_mut().unwrap().iter_mut().enumerate() {
+            let id = object.as_object_mut().unwrap().remove("id");
+            let given = format!(r#"{{"index": {}, "generation": 0}}"#, index);
+            assert_eq!(id, Some(json(&given)));
+        }
+        objects
+    }
+
+    #[test]
+    fn unversioned_saves_are_brought_up_to_date() {
+        let save = migrate(json(r#"[{"turn": 3}, [{"name": "player"}]]"#)).unwrap();
+        assert_eq!(save.get("version").and_then(Value::as_u64), Some(VERSION));
+        assert_eq!(save.get("game"), Some(&json(r#"{"turn": 3}"#)));
+        let objects = json(r#"[{"name": "player", "faction": "Player"}]"#);
+        assert_eq!(objects_of(&save), objects);
+    }
+
+    #[test]
+    fn maps_kept_column_by_column_are_laid_out_row_by_row() {
+        let save = json(r#"[{"map": [[{"blocked": true}, {"blocked": false}],
+                                     [{"blocked": false}, {"blocked": true}],
+                                     [{"blocked": true}, {"blocked": true}]]},
+                            [{"name": "player"}]]"#);
+        let save = migrate(save).unwrap();
+        let map = json(r#"{"width": 3, "height": 2, "tiles": [
+            {"blocked": true}, {"blocked": false}, {"blocked": true},
+            {"blocked": false}, {"blocked": true}, {"blocked": true}
+        ]}"#);
+        assert_eq!(save.pointer("/game/map"), Some(&map));
+        let ragged = json(r#"[{"map": [[{}, {}], [{}]]}, [{"name": "player"}]]"#);
+        assert!(matches!(migrate(ragged), Err(LoadError::Damaged(_))));
+    }
+
+    #[test]
+    fn poisoning_moves_onto_the_player() {
+        let save = json(r#"{"version": 1, "game": {"poison_turns": 4, "regeneration_turns": 0},
+                            "objects": [{"name": "player"}]}"#);
+        let save = migrate(save).unwrap();
+        assert_eq!(save.get("game"), Some(&json("{}")));
+        let objects = json(r#"[{"name": "player", "faction": "Player",
+                                "effects": [{"effect": "Poison", "turns": 4}]}]"#);
+        assert_eq!(objects_of(&save), objects);
+    }
+
+    #[test]
+    fn confused_monsters_get_their_own_minds_back() {
+        let save = json(r#"{"version": 2, "game": {}, "objects": [
+            {"name": "player", "ai": null},
+            {"name": "orc", "ai": {"Confused": {"previous_ai": "Basic", "num_turns": 3}}}
+        ]}"#);
+        let save = migrate(save).unwrap();
+        let objects = json(r#"[
+            {"name": "player", "ai": null, "faction": "Player"},
+            {"name": "orc", "ai": "Basic", "effects": [{"effect": "Confusion", "turns": 4}]}
+        ]"#);
+        assert_eq!(objects_of(&save), objects);
+    }
+
+    #[test]
+    fn spellbooks_become_spells() {
+        let save = json(r#"{"version": 3, "game": {"class": "Mage", "spell_recharge": 4,
+                            "inventory": [{"name": "dagger"}, {"name": "spellbook"}]},
+                            "objects": [{"name": "player", "fighter": {"hp": 70}}]}"#);
+        let save = migrate(save).unwrap();
+        let game = json(r#"{"class": "Mage", "inventory": [{"name": "dagger"}],
+                            "spells": ["MagicMissile"]}"#);
+        assert_eq!(save.get("game"), Some(&game));
+        let objects = json(r#"[{"name": "player", "faction": "Player",
+                                "fighter": {"hp": 70, "mana": 30, "max_mana": 30}}]"#);
+        assert_eq!(objects_of(&save), objects);
+    }
+
+    #[test]
+    fn rings_go_on_the_left_hand() {
+        let save = json(r#"{"version": 4, "game": {"inventory": [
+            {"name": "ring of dimness", "equipment": {"slot": "Finger"}}
+        ]}, "objects": [{"name": "player"}]}"#);
+        let save = migrate(save).unwrap();
+        let game = json(r#"{"inventory": [
+            {"name": "ring of dimness", "equipment": {"slot": "LeftRing"}}
+        ]}"#);
+        assert_eq!(save.get("game"), Some(&game));
+    }
+
+    #[test]
+    fn npcs_stay_friendly() {
+        let save = json(r#"{"version": 5, "game": {}, "objects": [
+            {"name": "player", "dialogue": null, "trader": null},
+            {"name": "innkeeper", "dialogue": "innkeeper", "trader": null},
+            {"name": "armourer", "dialogue": null, "trader": {"wares": [], "gold": 200}}
+        ]}"#);
+        let save = migrate(save).unwrap();
+        let objects = json(r#"[
+            {"name": "player", "dialogue": null, "trader": null, "faction": "Player"},
+            {"name": "innkeeper", "dialogue": "innkeeper", "trader": null,
+             "attitude": "Friendly", "faction": "Neutral"},
+            {"name": "armourer", "dialogue": null, "trader": {"wares": [], "gold": 200},
+             "attitude": "Neutral", "faction": "Neutral"}
+        ]"#);
+        assert_eq!(objects_of(&save), objects);
+    }
+
+    #[test]
+    fn everyone_takes_a_side() {
+        let save = json(r#"{"version": 6, "game": {}, "objects": [
+            {"name": "player", "ai": null},
+            {"name": "wolf", "ai": "Ally", "attitude": "Friendly"},
+            {"name": "hermit", "ai": "Unaware", "attitude": "Friendly"},
+            {"name": "orc", "ai": "Basic"}
+        ]}"#);
+        let save = migrate(save).unwrap();
+        let objects = json(r#"[
+            {"name": "player", "ai": null, "faction": "Player"},
+            {"name": "wolf", "ai": "Ally", "attitude": "Friendly", "faction": "Player"},
+            {"name": "hermit", "ai": "Unaware", "attitude": "Friendly", "faction": "Neutral"},
+            {"name": "orc", "ai": "Basic"}
+        ]"#);
+        assert_eq!(objects_of(&save), objects);
+    }
+
+    #[test]
+    fn messages_get_a_category() {
+        let save = json(r#"{"version": 7, "game": {"messages": {"messages": [
+            ["Welcome!", [255, 0, 0]]
+        ]}}, "objects": [{"name": "player"}]}"#);
+        let save = migrate(save).unwrap();
+        let game = json(r#"{"messages": {"messages": [
+            {"text": "Welcome!", "color": [255, 0, 0]}
+        ]}}"#);
+        assert_eq!(save.get("game"), Some(&game));
+    }
+
+    #[test]
+    fn rotten_corpses_stay_rotten() {
+        let save = json(r#"{"version": 8, "game": {}, "objects": [{"name": "player"},
+            {"name": "rotten remains of orc", "corpse": {"species": "orc", "died_on_turn": 1}},
+            {"name": "remains of rat", "corpse": {"species": "rat", "died_on_turn": 90}}
+        ]}"#);
+        let save = migrate(save).unwrap();
+        let spoiled = |id: usize| save.pointer(&format!("/objects/{}/corpse/spoiled", id)).cloned();
+        assert_eq!(spoiled(1), Some(Value::Bool(true)));
+        assert_eq!(spoiled(2), Some(Value::Bool(false)));
+    }
+
+    #[test]
+    fn objects_are_given_slots_and_packs_and_shops_their_ids() {
+        let save = json(r#"{"version": 9, "game": {"last_entity_id": 9,
+            "inventory": [{"name": "dagger", "id": 7, "owed": "armourer"}],
+            "dungeon": {"levels": {"Main": {"1": {"map": {}, "objects": [
+                {"name": "orc", "id": 5, "pack": {"pack": 0, "leader": false}},
+                {"name": "orc warlord", "id": 4, "pack": {"pack": 0, "leader": true}}
+            ]}}}}}, "objects": [
+                {"name": "player", "id": 1},
+                {"name": "armourer", "id": 2, "trader": {"wares": []}},
+                {"name": "wolf", "id": 3, "target": 5},
+                {"name": "rat", "target": 8}
+            ]}"#);
+        let save = migrate(save).unwrap();
+        let id = |index: u64| json(&format!(r#"{{"index": {}, "generation": 0}}"#, index));
+        let ids: Vec<_> = (0..4).map(|i| save["objects"][i]["id"].clone()).collect();
+        assert_eq!(ids, (0..4).map(id).collect::<Vec<_>>());
+        assert_eq!(save["objects"][2]["target"], id(4));
+        assert_eq!(save["objects"][3]["target"], Value::Null);
+        let level = &save["game"]["dungeon"]["levels"]["Main"]["1"]["objects"];
+        assert_eq!(level[0]["pack"], json(r#"{"Follower": {"index": 5, "generation": 0}}"#));
+        assert_eq!(level[1]["pack"], json(r#""Leader""#));
+        let dagger = &save["game"]["inventory"][0];
+        assert_eq!((&dagger["id"], &dagger["owed"]), (&Value::Null, &id(1)));
+        assert_eq!(save["game"].get("last_entity_id"), None);
+    }
+
+    #[test]
+    fn saves_from_newer_games_are_refused() {
+        let save = json(&format!(r#"{{"version": {}, "game": {{}}}}"#, VERSION + 1));
+        assert!(matches!(migrate(save), Err(LoadError::TooNew(v)) if v == VERSION + 1));
+    }
+
+    #[test]
+    fn other_json_is_damaged() {
+        for text in [r#"{"game": {}}"#, "[1, 2, 3]", "7"] {
+            assert!(matches!(migrate(json(text)), Err(LoadError::Damaged(_))), "{}", text);
+        }
+    }
+
+    #[test]
+    fn the_first_saves_still_load_and_save_again() {
+        // a new game, as the game saved it before saves had versions
+        let first = include_str!("../tests/saves/first.json");
+        let (game, objects) = parse(first).unwrap();
+        let old = json(first);
+        let columns = old[0]["map"].as_array().unwrap();
+        for (x, column) in columns.iter().enumerate() {
+            for (y, tile) in column.as_array().unwrap().iter().enumerate() {
+                let blocked = game.map[(x as i32, y as i32)].blocked;
+                assert_eq!(Some(blocked), tile["blocked"].as_bool());
+            }
+        }
+        assert_eq!(game.map.width() as usize, columns.len());
+        let player = &old[1][0];
+        let pos = (player["x"].as_i64().unwrap(), player["y"].as_i64().unwrap());
+        assert_eq!(objects[0].pos(), (pos.0 as i32, pos.1 as i32));
+        assert_eq!(objects.len(), old[1].as_array().unwrap().len());
+        assert!(game.inventory[0].equipment.unwrap().equipped);
+        let welcome = "Welcome stranger! Prepare to perish in the Tombs of the Ancient Kings.";
+        assert_eq!(game.messages.iter().last().unwrap().text, welcome);
+
+        let save = |game: &Game, objects: &Objects| {
+            let save = SaveFile {
+                version: VERSION,
+                game,
+                objects,
+            };
+            serde_json::to_string(&save).unwrap()
+        };
+        let saved = save(&game, &objects);
+        let (game, objects) = parse(&saved).unwrap();
+        assert_eq!(save(&game, &objects), saved);
+    }
+}
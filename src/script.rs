@@ -0,0 +1,339 @@
+//! Scripts for headless play: what the player does, one command a line,
+//! and checks on how it turned out. Blank lines and anything after a `#` are
+//! ignored. The commands are:
+//!
+//! - `move <direction> [times]`: walk or attack, `n`, `ne`, `e` and so on
+//! - `wait [times]`
+//! - `rest` and `search`: keep at it until done or interrupted
+//! - `pickup`: pick up what lies where the player stands
+//! - `use <item> [at <dx> <dy>]`: use a carried item, on the spot that far
+//!   from the player if it needs one
+//! - `ability <ability> [at <dx> <dy>]`: use an ability, like `dash at 3 0`
+//! - `cast <spell> [at <dx> <dy>]`: cast a spell the player knows
+//! - `throw <item> at <dx> <dy>`: throw a carried item at that spot
+//! - `fire at <dx> <dy>`: shoot an arrow from the equipped bow at that spot
+//! - `drop <item>`
+//! - `descend` and `ascend`: take the stairs the player stands on
+//! - `spawn <monster> <direction>`: put a monster next to the player
+//! - `give <item> [x<quantity>]`: put an item in the player's inventory, or
+//!   several of it
+//! - `bot <turns>`: let the bot play for a while
+//! - `expect alive`, `expect dead`, `expect carrying <item> [x<quantity>]`,
+//!   `expect identified <item>`, `expect unidentified <item>`, or
+//!   `expect <stat> <comparison> <number or stat>`: stop the script unless
+//!   it's so. The stats are `depth`, `level`, `hp`, `max_hp`, `mana`,
+//!   `power`, `defense`, `xp`, `gold`, `turn` and `load`, and the
+//!   comparisons `=`, `<`, `>`, `<=` and `>=`.
+
+use std::str::FromStr;
+
+use crate::abilities::{Ability, ABILITIES};
+use crate::resting::Activity;
+use crate::spells::{Spell, SPELLS};
+use crate::DIRECTIONS;
+
+/// the names of the eight directions, in the order of `DIRECTIONS`
+const DIRECTION_NAMES: [&str; 8] = ["nw", "n", "ne", "w", "e", "sw", "s", "se"];
+
+/// One thing for the player to do, or to check.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Move { dx: i32, dy: i32, times: u32 },
+    Wait(u32),
+    Busy(Activity),
+    PickUp,
+    Use { item: String, at: Option<(i32, i32)> },
+    Ability { ability: Ability, at: Option<(i32, i32)> },
+    Cast { spell: Spell, at: Option<(i32, i32)> },
+    Throw { item: String, at: (i32, i32) },
+    Fire { at: (i32, i32) },
+    Drop(String),
+    Descend,
+    Ascend,
+    Spawn { monster: String, dx: i32, dy: i32 },
+    Give { item: String, quantity: i32 },
+    Bot(u32),
+    Expect(Expectation),
+}
+
+/// How the game should stand at some point in a script.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Expectation {
+    Alive,
+    Dead,
+    /// carrying an item, and as many of it as given, if a quantity is
+    Carrying(String, Option<i32>),
+    /// carrying an item, whose kind the player knows, or doesn't
+    Identified(String, bool),
+    Compare(Stat, Comparison, i32),
+    /// one stat against another, like `hp < max_hp`
+    CompareStats(Stat, Comparison, Stat),
+}
+
+/// A number about the player, or the game, that a script can check.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Stat {
+    /// the dungeon level
+    Depth,
+    /// the player's experience level
+    Level,
+    Hp,
+    /// the player's maximum HP, with what they have equipped
+    MaxHp,
+    Mana,
+    /// the player's attack, with what they have equipped
+    Power,
+    /// the player's defense, with what they have equipped
+    Defense,
+    Xp,
+    Gold,
+    Turn,
+    /// the weight of everything the player carries
+    Load,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparison {
+    Equal,
+    Less,
+    Greater,
+    AtMost,
+    AtLeast,
+}
+
+/// the names of the stats, as written in scripts
+const STATS: [(&str, Stat); 11] = [
+    ("depth", Stat::Depth),
+    ("level", Stat::Level),
+    ("hp", Stat::Hp),
+    ("max_hp", Stat::MaxHp),
+    ("mana", Stat::Mana),
+    ("power", Stat::Power),
+    ("defense", Stat::Defense),
+    ("xp", Stat::Xp),
+    ("gold", Stat::Gold),
+    ("turn", Stat::Turn),
+    ("load", Stat::Load),
+];
+
+/// the comparisons, as written in scripts
+const COMPARISONS: [(&str, Comparison); 5] = [
+    ("=", Comparison::Equal),
+    ("<", Comparison::Less),
+    (">", Comparison::Greater),
+    ("<=", Comparison::AtMost),
+    (">=", Comparison::AtLeast),
+];
+
+impl Stat {
+    pub fn name(self) -> &'static str {
+        STATS.iter().find(|&&(_, stat)| stat == self).unwrap().0
+    }
+}
+
+impl Comparison {
+    pub fn symbol(self) -> &'static str {
+        COMPARISONS
+            .iter()
+            .find(|&&(_, comparison)| comparison == self)
+            .unwrap()
+            .0
+    }
+
+    pub fn holds(self, value: i32, expected: i32) -> bool {
+        match self {
+            Comparison::Equal => value == expected,
+            Comparison::Less => value < expected,
+            Comparison::Greater => value > expected,
+            Comparison::AtMost => value <= expected,
+            Comparison::AtLeast => value >= expected,
+        }
+    }
+}
+
+/// A command, and the line of the script it's on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Line {
+    pub number: usize,
+    pub command: Command,
+}
+
+/// Read a script, or say what's wrong with its first bad line.
+pub fn parse(text: &str) -> Result<Vec<Line>, String> {
+    let mut lines = vec![];
+    for (index, line) in text.lines().enumerate() {
+        let number = index + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        if line.is_empty() {
+            continue;
+        }
+        let command = parse_command(line).map_err(|e| format!("line {}: {}", number, e))?;
+        lines.push(Line { number, command });
+    }
+    Ok(lines)
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    let (name, rest) = match line.split_once(char::is_whitespace) {
+        Some((name, rest)) => (name, rest.trim()),
+        None => (line, ""),
+    };
+    let words: Vec<&str> = rest.split_whitespace().collect();
+    let no_arguments = |command| {
+        if words.is_empty() {
+            Ok(command)
+        } else {
+            Err(format!("{} takes nothing after it", name))
+        }
+    };
+    match name {
+        "move" => {
+            let (direction, times) = match words[..] {
+                [direction] => (direction, 1),
+                [direction, times] => (direction, number(times)?),
+                _ => return Err("move needs a direction, and maybe how many times".into()),
+            };
+            let (dx, dy) = direction_named(direction)?;
+            Ok(Command::Move { dx, dy, times })
+        }
+        "wait" => match words[..] {
+            [] => Ok(Command::Wait(1)),
+            [times] => Ok(Command::Wait(number(times)?)),
+            _ => Err("wait takes at most how many times".into()),
+        },
+        "rest" => no_arguments(Command::Busy(Activity::Rest)),
+        "search" => no_arguments(Command::Busy(Activity::Search)),
+        "pickup" => no_arguments(Command::PickUp),
+        "use" => {
+            let (item, at) = targeted(&words)?;
+            if item.is_empty() {
+                return Err("use needs an item".into());
+            }
+            Ok(Command::Use { item, at })
+        }
+        "ability" => {
+            let (name, at) = targeted(&words)?;
+            let ability = ABILITIES
+                .iter()
+                .copied()
+                .find(|ability| ability.name().eq_ignore_ascii_case(&name))
+                .ok_or_else(|| format!("unknown ability: {}", name))?;
+            Ok(Command::Ability { ability, at })
+        }
+        "cast" => {
+            let (name, at) = targeted(&words)?;
+            let spell = SPELLS
+                .iter()
+                .copied()
+                .find(|spell| spell.name().eq_ignore_ascii_case(&name))
+                .ok_or_else(|| format!("unknown spell: {}", name))?;
+            Ok(Command::Cast { spell, at })
+        }
+        "throw" => match targeted(&words)? {
+            (item, Some(at)) if !item.is_empty() => Ok(Command::Throw { item, at }),
+            _ => Err("throw needs an item and where to throw it".into()),
+        },
+        "fire" => match words[..] {
+            ["at", dx, dy] => Ok(Command::Fire {
+                at: (number(dx)?, number(dy)?),
+            }),
+            _ => Err("fire needs where to shoot, like fire at 3 0".into()),
+        },
+        "drop" => item_name(&words, name).map(Command::Drop),
+        "give" => match words[..] {
+            [ref item @ .., quantity] if quantity.starts_with('x') && !item.is_empty() => {
+                Ok(Command::Give {
+                    item: item.join(" "),
+                    quantity: number(&quantity[1..])?,
+                })
+            }
+            _ => item_name(&words, name).map(|item| Command::Give { item, quantity: 1 }),
+        },
+        "descend" => no_arguments(Command::Descend),
+        "ascend" => no_arguments(Command::Ascend),
+        "spawn" => match words.split_last() {
+            Some((direction, monster)) if !monster.is_empty() => {
+                let (dx, dy) = direction_named(direction)?;
+                let monster = monster.join(" ");
+                Ok(Command::Spawn { monster, dx, dy })
+            }
+            _ => Err("spawn needs a monster and a direction".into()),
+        },
+        "bot" => match words[..] {
+            [turns] => Ok(Command::Bot(number(turns)?)),
+            _ => Err("bot needs how many turns".into()),
+        },
+        "expect" => parse_expectation(&words).map(Command::Expect),
+        _ => Err(format!("unknown command: {}", name)),
+    }
+}
+
+fn parse_expectation(words: &[&str]) -> Result<Expectation, String> {
+    match words {
+        ["alive"] => Ok(Expectation::Alive),
+        ["dead"] => Ok(Expectation::Dead),
+        ["carrying", item @ .., quantity] if quantity.starts_with('x') && !item.is_empty() => {
+            let quantity = number(&quantity[1..])?;
+            Ok(Expectation::Carrying(item.join(" "), Some(quantity)))
+        }
+        ["carrying", item @ ..] if !item.is_empty() => {
+            Ok(Expectation::Carrying(item.join(" "), None))
+        }
+        ["identified", item @ ..] if !item.is_empty() => {
+            Ok(Expectation::Identified(item.join(" "), true))
+        }
+        ["unidentified", item @ ..] if !item.is_empty() => {
+            Ok(Expectation::Identified(item.join(" "), false))
+        }
+        [stat, comparison, value] => {
+            let stat = stat_named(stat).ok_or_else(|| format!("unknown stat: {}", stat))?;
+            let comparison = COMPARISONS
+                .iter()
+                .find(|&&(symbol, _)| symbol == *comparison)
+                .map(|&(_, comparison)| comparison)
+                .ok_or_else(|| format!("unknown comparison: {}", comparison))?;
+            match stat_named(value) {
+                Some(other) => Ok(Expectation::CompareStats(stat, comparison, other)),
+                None => Ok(Expectation::Compare(stat, comparison, number(value)?)),
+            }
+        }
+        _ => Err("expect needs alive, dead, carrying an item, or a stat to compare".into()),
+    }
+}
+
+fn stat_named(name: &str) -> Option<Stat> {
+    STATS
+        .iter()
+        .find(|&&(stat, _)| stat == name)
+        .map(|&(_, stat)| stat)
+}
+
+/// the name of what's used, and where it's used, if the script says
+fn targeted(words: &[&str]) -> Result<(String, Option<(i32, i32)>), String> {
+    match words[..] {
+        [.., "at", dx, dy] if words.len() > 3 => {
+            Ok((words[..words.len() - 3].join(" "), Some((number(dx)?, number(dy)?))))
+        }
+        _ => Ok((words.join(" "), None)),
+    }
+}
+
+fn item_name(words: &[&str], command: &str) -> Result<String, String> {
+    if words.is_empty() {
+        Err(format!("{} needs an item", command))
+    } else {
+        Ok(words.join(" "))
+    }
+}
+
+fn number<T: FromStr>(word: &str) -> Result<T, String> {
+    word.parse().map_err(|_| format!("not a number: {}", word))
+}
+
+fn direction_named(name: &str) -> Result<(i32, i32), String> {
+    DIRECTION_NAMES
+        .iter()
+        .position(|&direction| direction == name)
+        .map(|index| DIRECTIONS[index])
+        .ok_or_else(|| format!("unknown direction: {}", name))
+}
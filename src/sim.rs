@@ -0,0 +1,626 @@
+//! Headless play: runs the game logic without opening a window, with a simple
+//! bot or a script choosing the player's actions. Used by the playtest
+//! integration tests, and by `--headless` on the command line.
+
+use std::collections::VecDeque;
+
+use rand::prelude::*;
+
+use crate::abilities;
+use crate::actions::{self, Action};
+use crate::archery;
+use crate::classes::Class;
+use crate::dungeon::{self, UP_STAIRS};
+use crate::encumbrance;
+use crate::entities::Objects;
+use crate::factions;
+use crate::fov::Shadowcasting;
+use crate::identify;
+use crate::map::is_blocked;
+use crate::resting::Busy;
+use crate::script::{self, Command, Expectation, Line, Stat};
+use crate::speed;
+use crate::spells;
+use crate::throwing;
+use crate::{
+    bestiary, cast_heal, compute_visibility, drop_item, eat_food, finish_item_use, gain_level,
+    is_valid_target, items, new_game_state, player_on, player_on_stairs, raise_stat, set_fov_map,
+    stow, use_item, Chooser, Fighter, Game, Item, Object, DIRECTIONS, EQUIPMENT_SLOTS,
+    HUNGRY_SATIATION, MAX_SATIATION, PLAYER,
+};
+
+/// the bot drinks a healing potion when its HP falls below this fraction of the maximum
+const BOT_HEAL_THRESHOLD: f32 = 0.4;
+
+/// how many turns the bot gets when playing headless without a script
+const HEADLESS_BOT_TURNS: u32 = 5000;
+
+/// Play a game without a window: the given script, or the bot if there's
+/// none. Returns how the game went, or what went wrong.
+pub fn run_headless(seed: u64, script: Option<&str>) -> Result<String, String> {
+    let script = script.map(script::parse).transpose()?;
+    let mut sim = Simulation::new(seed);
+    sim.check_invariants()?;
+    match script {
+        Some(script) => sim.run_script(&script)?,
+        None => sim.run_bot(HEADLESS_BOT_TURNS)?,
+    }
+    Ok(sim.summary())
+}
+
+/// The choices items ask for, when a script uses them: the spot it gave, if
+/// there's anything there to use it on, and always constitution to raise.
+struct ScriptChoices {
+    at: Option<(i32, i32)>,
+}
+
+impl Chooser for ScriptChoices {
+    fn choose_tile(
+        &mut self,
+        game: &mut Game,
+        objects: &Objects,
+        max_range: Option<f32>,
+        in_fov_only: bool,
+        _blast: Option<i32>,
+    ) -> Option<(i32, i32)> {
+        // asked once only, so a spot without a monster on it cancels
+        self.at
+            .take()
+            .filter(|&pos| is_valid_target(pos, game, objects, max_range, in_fov_only))
+    }
+
+    fn choose_stat(&mut self, _header: &str, _fighter: &Fighter) -> usize {
+        0
+    }
+
+    fn choose_item(&mut self, _header: &str, game: &Game, _player: &Object) -> Option<usize> {
+        // only a scroll of identify asks, so pick the first item still unknown
+        game.inventory
+            .iter()
+            .position(|item| !identify::is_identified(item, game))
+    }
+}
+
+/// A game played by the bot, one turn at a time.
+pub struct Simulation {
+    game: Game,
+    objects: Objects,
+    fov: Shadowcasting,
+    /// tiles the bot has stood on this level, so it doesn't keep returning to
+    /// an edge of the explored area that standing there won't reveal
+    visited: Vec<bool>,
+}
+
+impl Simulation {
+    /// start a new game; the same seed always plays out the same way
+    pub fn new(seed: u64) -> Self {
+        let (game, objects) = new_game_state(StdRng::seed_from_u64(seed), Class::Warrior);
+        let mut sim = Simulation {
+            game,
+            objects,
+            fov: Shadowcasting::new(1, 1),
+            visited: vec![],
+        };
+        sim.enter_level();
+        sim
+    }
+
+    pub fn turn(&self) -> u32 {
+        self.game.turn
+    }
+
+    pub fn dungeon_level(&self) -> u32 {
+        self.game.dungeon_level
+    }
+
+    pub fn player_alive(&self) -> bool {
+        self.objects[PLAYER].alive
+    }
+
+    pub fn player_hp(&self) -> i32 {
+        self.objects[PLAYER].fighter.map_or(0, |f| f.hp)
+    }
+
+    /// where the game stands, in a line
+    pub fn summary(&self) -> String {
+        let player = &self.objects[PLAYER];
+        let place = match self.game.dungeon_level {
+            0 => "in town".to_string(),
+            depth => format!("on level {} of {}", depth, self.game.dungeon.branch.name()),
+        };
+        format!(
+            "{} {} after {} turns, at experience level {} with {}/{} HP and {} gold",
+            if player.alive { "alive" } else { "died" },
+            place,
+            self.game.turn,
+            player.level,
+            self.player_hp(),
+            player.max_hp(&self.game),
+            self.game.gold,
+        )
+    }
+
+    /// Let the bot play for up to the given number of turns, checking the
+    /// invariants after each one.
+    pub fn run_bot(&mut self, turns: u32) -> Result<(), String> {
+        for _ in 0..turns {
+            if !self.player_alive() {
+                break;
+            }
+            self.bot_turn();
+            self.check_invariants()
+                .map_err(|e| format!("turn {}: {}", self.turn(), e))?;
+        }
+        Ok(())
+    }
+
+    /// Play a script out, checking the invariants after every turn. It stops
+    /// at the first command that can't be carried out, or check that fails.
+    fn run_script(&mut self, script: &[Line]) -> Result<(), String> {
+        for line in script {
+            self.run_command(&line.command)
+                .map_err(|e| format!("line {}: {}", line.number, e))?;
+        }
+        Ok(())
+    }
+
+    fn run_command(&mut self, command: &Command) -> Result<(), String> {
+        let needs_player = !matches!(command, Command::Expect(_) | Command::Bot(_));
+        if needs_player && !self.player_alive() {
+            return Err("the player is dead".into());
+        }
+        match *command {
+            Command::Move { dx, dy, times } => {
+                for _ in 0..times {
+                    self.act(Action::MoveOrAttack { dx, dy });
+                    self.player_turn_done()?;
+                }
+            }
+            Command::Wait(times) => {
+                for _ in 0..times {
+                    self.player_turn_done()?;
+                }
+            }
+            Command::Busy(activity) => {
+                let mut busy = Busy::start(activity, &self.game, &self.objects);
+                while busy.go_on(&mut self.game, &mut self.objects) {
+                    self.player_turn_done()?;
+                }
+            }
+            Command::PickUp => {
+                let player_pos = self.objects[PLAYER].pos();
+                if !self
+                    .objects
+                    .iter()
+                    .any(|object| object.pos() == player_pos && object.can_pick_up())
+                {
+                    return Err("there's nothing here to pick up".into());
+                }
+                self.act(Action::PickUp);
+            }
+            Command::Use { ref item, at } => {
+                let inventory_id = self.carried(item)?;
+                let (player_x, player_y) = self.objects[PLAYER].pos();
+                let mut choices = ScriptChoices {
+                    at: at.map(|(dx, dy)| (player_x + dx, player_y + dy)),
+                };
+                use_item(inventory_id, &mut choices, &mut self.game, &mut self.objects);
+            }
+            Command::Ability { ability, at } => {
+                let (player_x, player_y) = self.objects[PLAYER].pos();
+                let mut choices = ScriptChoices {
+                    at: at.map(|(dx, dy)| (player_x + dx, player_y + dy)),
+                };
+                let (game, objects) = (&mut self.game, &mut self.objects);
+                if abilities::use_ability(ability, &mut choices, game, objects) {
+                    self.player_turn_done()?;
+                }
+            }
+            Command::Cast { spell, at } => {
+                let (player_x, player_y) = self.objects[PLAYER].pos();
+                let mut choices = ScriptChoices {
+                    at: at.map(|(dx, dy)| (player_x + dx, player_y + dy)),
+                };
+                if spells::cast(spell, &mut choices, &mut self.game, &mut self.objects) {
+                    self.player_turn_done()?;
+                }
+            }
+            Command::Throw { ref item, at: (dx, dy) } => {
+                let inventory_id = self.carried(item)?;
+                let (player_x, player_y) = self.objects[PLAYER].pos();
+                let mut choices = ScriptChoices {
+                    at: Some((player_x + dx, player_y + dy)),
+                };
+                let (game, objects) = (&mut self.game, &mut self.objects);
+                if throwing::throw(inventory_id, &mut choices, game, objects) {
+                    self.player_turn_done()?;
+                }
+            }
+            Command::Fire { at: (dx, dy) } => {
+                let (player_x, player_y) = self.objects[PLAYER].pos();
+                let mut choices = ScriptChoices {
+                    at: Some((player_x + dx, player_y + dy)),
+                };
+                if archery::fire(&mut choices, &mut self.game, &mut self.objects) {
+                    self.player_turn_done()?;
+                }
+            }
+            Command::Drop(ref item) => {
+                let inventory_id = self.carried(item)?;
+                let amount = self.game.inventory[inventory_id].quantity();
+                drop_item(inventory_id, amount, &mut self.game, &mut self.objects);
+            }
+            Command::Descend => {
+                if !player_on_stairs(&self.objects)
+                    && dungeon::branch_stairs_here(&self.objects).is_none()
+                {
+                    return Err("the player isn't on the stairs down".into());
+                }
+                self.act(Action::Descend);
+                self.enter_level();
+            }
+            Command::Ascend => {
+                if !player_on(UP_STAIRS, &self.objects) {
+                    return Err("the player isn't on the stairs up".into());
+                }
+                self.act(Action::Ascend);
+                self.enter_level();
+            }
+            Command::Spawn {
+                ref monster,
+                dx,
+                dy,
+            } => {
+                let template = bestiary::templates()
+                    .iter()
+                    .find(|template| template.name == *monster)
+                    .ok_or_else(|| format!("there's no monster called {}", monster))?;
+                let (x, y) = self.objects[PLAYER].pos();
+                let (x, y) = (x + dx, y + dy);
+                if is_blocked(x, y, &self.game.map, &self.objects) {
+                    return Err(format!("there's no room for the {} there", monster));
+                }
+                self.objects.push(template.spawn(x, y));
+            }
+            Command::Give { ref item, quantity } => {
+                let template = items::templates()
+                    .iter()
+                    .find(|template| template.name == *item)
+                    .ok_or_else(|| format!("there's no item called {}", item))?;
+                for _ in 0..quantity {
+                    if self.game.inventory.len() >= 26 {
+                        return Err("the inventory is full".into());
+                    }
+                    stow(template.spawn(0, 0), &mut self.game.inventory);
+                }
+            }
+            Command::Bot(turns) => self.run_bot(turns)?,
+            Command::Expect(ref expectation) => self.check(expectation)?,
+        }
+        Ok(())
+    }
+
+    /// the place in the inventory of an item with the given name
+    fn carried(&self, name: &str) -> Result<usize, String> {
+        self.game
+            .inventory
+            .iter()
+            .position(|item| item.name == name)
+            .ok_or_else(|| format!("the player has no {}", name))
+    }
+
+    fn check(&self, expectation: &Expectation) -> Result<(), String> {
+        match *expectation {
+            Expectation::Alive if !self.player_alive() => Err("the player is dead".into()),
+            Expectation::Dead if self.player_alive() => Err("the player is alive".into()),
+            Expectation::Carrying(ref item, quantity) => {
+                let carried = &self.game.inventory[self.carried(item)?];
+                match quantity {
+                    Some(quantity) if carried.quantity() != quantity => Err(format!(
+                        "the player carries {} x{}, expected x{}",
+                        carried.name,
+                        carried.quantity(),
+                        quantity
+                    )),
+                    _ => Ok(()),
+                }
+            }
+            Expectation::Identified(ref item, identified) => {
+                let carried = &self.game.inventory[self.carried(item)?];
+                match identify::is_identified(carried, &self.game) {
+                    known if known == identified => Ok(()),
+                    true => Err(format!("the {} is identified", item)),
+                    false => Err(format!("the {} is not identified", item)),
+                }
+            }
+            Expectation::Compare(stat, comparison, expected) => {
+                let value = self.stat(stat);
+                if comparison.holds(value, expected) {
+                    Ok(())
+                } else {
+                    let (name, symbol) = (stat.name(), comparison.symbol());
+                    Err(format!("{} is {}, expected {} {}", name, value, symbol, expected))
+                }
+            }
+            Expectation::CompareStats(stat, comparison, other) => {
+                let (value, expected) = (self.stat(stat), self.stat(other));
+                if comparison.holds(value, expected) {
+                    Ok(())
+                } else {
+                    let (name, symbol) = (stat.name(), comparison.symbol());
+                    Err(format!(
+                        "{} is {}, expected {} {} ({})",
+                        name,
+                        value,
+                        symbol,
+                        other.name(),
+                        expected
+                    ))
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn stat(&self, stat: Stat) -> i32 {
+        let player = &self.objects[PLAYER];
+        match stat {
+            Stat::Depth => self.game.dungeon_level as i32,
+            Stat::Level => player.level,
+            Stat::Hp => self.player_hp(),
+            Stat::MaxHp => player.max_hp(&self.game),
+            Stat::Mana => player.fighter.map_or(0, |f| f.mana),
+            Stat::Power => player.power(&self.game),
+            Stat::Defense => player.defense(&self.game),
+            Stat::Xp => player.fighter.map_or(0, |f| f.xp),
+            Stat::Gold => self.game.gold,
+            Stat::Turn => self.game.turn as i32,
+            Stat::Load => encumbrance::load(&self.game),
+        }
+    }
+
+    /// the monsters' turn after the player has taken theirs, as scripted
+    /// carry out an action the way the player would
+    fn act(&mut self, action: Action) {
+        actions::resolve(action, &mut self.game, &mut self.objects);
+    }
+
+    fn player_turn_done(&mut self) -> Result<(), String> {
+        self.end_turn();
+        self.level_up();
+        self.check_invariants()
+    }
+
+    /// always take constitution when levelling up
+    fn level_up(&mut self) {
+        while gain_level(&mut self.objects[PLAYER], self.game.class, &mut self.game.messages) {
+            raise_stat(self.objects[PLAYER].fighter.as_mut().unwrap(), 0);
+            self.game.stat_choices.push(0);
+        }
+    }
+
+    /// let the monsters act, and see what the player can see afterwards
+    fn end_turn(&mut self) {
+        speed::player_acted(1, &mut self.game, &mut self.objects);
+        // there's no screen to show shots flying on
+        self.game.projectiles.clear();
+        self.update_fov();
+    }
+
+    /// Let the bot act until it has taken one turn, then let the monsters act.
+    /// Like in the real game, levelling up, using items, picking things up and
+    /// taking the stairs don't cost a turn.
+    pub fn bot_turn(&mut self) {
+        if !self.player_alive() {
+            return;
+        }
+
+        self.level_up();
+
+        // quaff when low
+        let player = &self.objects[PLAYER];
+        let hp = player.fighter.map_or(0, |f| f.hp);
+        if (hp as f32) < player.max_hp(&self.game) as f32 * BOT_HEAL_THRESHOLD {
+            let potion = self
+                .game
+                .inventory
+                .iter()
+                .position(|item| item.item == Some(Item::Heal));
+            if let Some(inventory_id) = potion {
+                let result = cast_heal(&mut self.game, &mut self.objects);
+                finish_item_use(inventory_id, result, &mut self.game);
+            }
+        }
+
+        // eat when hungry
+        if self.game.satiation < HUNGRY_SATIATION {
+            let food = self
+                .game
+                .inventory
+                .iter()
+                .position(|item| item.item == Some(Item::Food));
+            if let Some(inventory_id) = food {
+                let result = eat_food(&mut self.game);
+                finish_item_use(inventory_id, result, &mut self.game);
+            }
+        }
+
+        // pick up whatever lies here
+        self.act(Action::PickUp);
+
+        // fight when adjacent, otherwise explore, and head down once there's
+        // nothing left to explore
+        let step = match self.adjacent_monster() {
+            Some(step) => Some(step),
+            None => match self.step_towards(|sim, x, y| sim.is_frontier(x, y)) {
+                Some(step) => Some(step),
+                None => {
+                    if player_on_stairs(&self.objects) {
+                        self.act(Action::Descend);
+                        self.enter_level();
+                    }
+                    self.step_towards(|sim, x, y| sim.is_stairs(x, y))
+                }
+            },
+        };
+        if let Some((dx, dy)) = step {
+            self.act(Action::MoveOrAttack { dx, dy });
+        }
+
+        self.end_turn();
+    }
+
+    /// Check the rules that must hold after every turn, describing the first broken one.
+    pub fn check_invariants(&self) -> Result<(), String> {
+        let (game, objects) = (&self.game, &self.objects);
+        let player = &objects[PLAYER];
+        let fighter = player.fighter.ok_or("the player has no combat stats")?;
+        let max_hp = player.max_hp(game);
+        if fighter.hp > max_hp {
+            return Err(format!("player HP {} is above the maximum {}", fighter.hp, max_hp));
+        }
+        if player.alive != (fighter.hp > 0) {
+            return Err(format!(
+                "player alive is {} with {} HP",
+                player.alive, fighter.hp
+            ));
+        }
+        if !(0..=MAX_SATIATION).contains(&game.satiation) {
+            return Err(format!("player satiation is {}", game.satiation));
+        }
+
+        if game.inventory.len() > 26 {
+            return Err(format!("{} items in the inventory", game.inventory.len()));
+        }
+        if let Some(item) = game.inventory.iter().find(|item| item.item.is_none()) {
+            return Err(format!("{} in the inventory is not an item", item.name));
+        }
+        for slot in EQUIPMENT_SLOTS {
+            let equipped = game
+                .inventory
+                .iter()
+                .filter(|item| item.equipment.map_or(false, |e| e.equipped && e.slot == slot))
+                .count();
+            if equipped > 1 {
+                return Err(format!("{} items equipped on {}", equipped, slot));
+            }
+        }
+        let hands: Vec<_> = game
+            .inventory
+            .iter()
+            .filter_map(|item| item.equipment)
+            .filter(|e| e.equipped && e.slot.is_hand())
+            .collect();
+        if hands.len() > 1 && hands.iter().any(|e| e.two_handed) {
+            return Err("a two-handed weapon is equipped with something else in hand".into());
+        }
+
+        for (id, object) in objects.iter().enumerate() {
+            let tile = game
+                .map
+                .get(object.x, object.y)
+                .ok_or_else(|| format!("{} is outside the map", object.name))?;
+            if object.blocks && tile.blocked {
+                return Err(format!("{} is inside a wall", object.name));
+            }
+            if object.blocks
+                && objects[..id]
+                    .iter()
+                    .any(|other| other.blocks && other.pos() == object.pos())
+            {
+                return Err(format!("{} shares its tile with another", object.name));
+            }
+            if id != PLAYER && object.fighter.map_or(false, |f| f.hp <= 0) {
+                return Err(format!("{} has no HP left but is still fighting", object.name));
+            }
+        }
+        Ok(())
+    }
+
+    fn enter_level(&mut self) {
+        let map = &self.game.map;
+        self.fov = Shadowcasting::new(map.width(), map.height());
+        set_fov_map(&mut self.fov, map);
+        self.visited = vec![false; (map.width() * map.height()) as usize];
+        self.update_fov();
+    }
+
+    /// recompute what the player can see, as rendering does in the real game
+    fn update_fov(&mut self) {
+        compute_visibility(&mut self.fov, &mut self.game, &self.objects);
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        self.visited[(player_y * self.game.map.width() + player_x) as usize] = true;
+    }
+
+    fn adjacent_monster(&self) -> Option<(i32, i32)> {
+        let (player_x, player_y) = self.objects[PLAYER].pos();
+        self.objects
+            .iter()
+            .enumerate()
+            .find(|&(id, object)| {
+                id != PLAYER
+                    && object.fighter.is_some()
+                    && object.ai.is_some()
+                    && factions::are_enemies(&self.objects[PLAYER], object)
+                    && (object.x - player_x).abs() <= 1
+                    && (object.y - player_y).abs() <= 1
+            })
+            .map(|(_, object)| (object.x - player_x, object.y - player_y))
+    }
+
+    /// an explored floor tile next to unexplored ones, which the bot hasn't stood on yet
+    fn is_frontier(&self, x: i32, y: i32) -> bool {
+        !self.visited[(y * self.game.map.width() + x) as usize]
+            && DIRECTIONS.iter().any(|&(dx, dy)| {
+                self.game
+                    .map
+                    .get(x + dx, y + dy)
+                    .map_or(false, |tile| !tile.explored)
+            })
+    }
+
+    fn is_stairs(&self, x: i32, y: i32) -> bool {
+        self.objects
+            .iter()
+            .any(|object| object.pos() == (x, y) && object.name == "stairs")
+    }
+
+    /// Breadth-first search over explored floor from the player to the nearest
+    /// tile matching `is_goal`, returning the first step of the way. Monsters
+    /// don't block the search: walking into one attacks it.
+    fn step_towards(&self, is_goal: impl Fn(&Self, i32, i32) -> bool) -> Option<(i32, i32)> {
+        let map = &self.game.map;
+        let start = self.objects[PLAYER].pos();
+        // the first step taken towards each reached tile
+        let width = map.width();
+        let mut first_steps: Vec<Option<(i32, i32)>> = vec![None; (width * map.height()) as usize];
+        let mut queue = VecDeque::new();
+        queue.push_back(start);
+        while let Some((x, y)) = queue.pop_front() {
+            let first_step = first_steps[(y * width + x) as usize];
+            if first_step.is_some() && is_goal(self, x, y) {
+                return first_step;
+            }
+            for &(dx, dy) in DIRECTIONS.iter() {
+                let (next_x, next_y) = (x + dx, y + dy);
+                // monsters can be attacked out of the way, but NPCs can't;
+                // closed doors are opened by walking into them; and the bot
+                // keeps off teleporters, which would throw its route off
+                let passable = map.get(next_x, next_y).map_or(false, |tile| {
+                    tile.explored && (!tile.blocked || tile.opens()) && tile.teleport.is_none()
+                })
+                    && !self.objects.iter().any(|object| {
+                        object.blocks
+                            && object.fighter.is_none()
+                            && object.pos() == (next_x, next_y)
+                    });
+                let index = (next_y * width + next_x) as usize;
+                if passable && (next_x, next_y) != start && first_steps[index].is_none() {
+                    first_steps[index] = Some(first_step.unwrap_or((dx, dy)));
+                    queue.push_back((next_x, next_y));
+                }
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,54 @@
+//! Speed: not everything acts once a turn. Every turn, the player and each
+//! monster gain energy as quick as their speed, and act whenever they've
+//! saved up enough for an action. At `NORMAL_SPEED` that's once a turn; a
+//! bat, twice as fast, acts twice a turn, while a shambling zombie only
+//! manages every other turn. Haste doubles anyone's speed and slowness
+//! halves it, as does carrying too much, for the player. Snow slows
+//! everyone out in it.
+
+use crate::effects::Effect;
+use crate::entities::Objects;
+use crate::{ai_take_turn, encumbrance, end_player_turn, weather, Game, Object, PLAYER};
+
+/// the speed of most things, and the energy an action takes
+pub const NORMAL_SPEED: i32 = 100;
+
+impl Object {
+    /// how quickly this player or monster acts right now
+    pub fn speed(&self, game: &Game) -> i32 {
+        let mut speed = self.fighter.map_or(NORMAL_SPEED, |f| f.speed);
+        if self.has_effect(Effect::Haste) {
+            speed *= 2;
+        }
+        let burdened = self.name == "player" && encumbrance::is_burdened(game, self);
+        if self.has_effect(Effect::Slow) || burdened {
+            speed /= 2;
+        }
+        weather::slow(speed, game).max(1)
+    }
+}
+
+/// Spend the energy the player's action took, `actions` times over, and let
+/// time pass until they've the energy to act again.
+pub fn player_acted(actions: i32, game: &mut Game, objects: &mut Objects) {
+    objects[PLAYER].energy -= actions * NORMAL_SPEED;
+    while objects[PLAYER].energy < 0 && objects[PLAYER].alive {
+        end_player_turn(game, objects);
+        objects[PLAYER].energy += objects[PLAYER].speed(game);
+    }
+}
+
+/// let every monster act as many times as it's gained the energy for this
+/// turn
+pub fn monsters_act(game: &mut Game, objects: &mut Objects) {
+    for id in 0..objects.len() {
+        if objects[id].ai.is_none() {
+            continue;
+        }
+        objects[id].energy += objects[id].speed(game);
+        while objects[id].energy >= NORMAL_SPEED && objects[id].ai.is_some() {
+            objects[id].energy -= NORMAL_SPEED;
+            ai_take_turn(id, game, objects);
+        }
+    }
+}
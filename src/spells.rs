@@ -0,0 +1,115 @@
+//! Spells: lightning, fireballs and the like, cast again and again for mana
+//! instead of read once from a scroll. The player learns them from spellbooks
+//! found in the dungeon, and mages know magic missile from the start.
+
+use serde::{Deserialize, Serialize};
+
+use crate::colors::{LIGHT_BLUE, RED, WHITE};
+use crate::entities::Objects;
+use crate::{
+    cast_confuse, cast_fireball, cast_lightning, cast_magic_missile, Chooser, Game, Object,
+    UseResult, PLAYER,
+};
+
+/// turns it takes to get one point of mana back
+const MANA_REGEN_TURNS: u32 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Spell {
+    MagicMissile,
+    Lightning,
+    Confuse,
+    Fireball,
+}
+
+/// every spell there is, for looking one up by name
+pub const SPELLS: [Spell; 4] =
+    [Spell::MagicMissile, Spell::Lightning, Spell::Confuse, Spell::Fireball];
+
+impl Spell {
+    pub fn name(self) -> &'static str {
+        match self {
+            Spell::MagicMissile => "Magic missile",
+            Spell::Lightning => "Lightning bolt",
+            Spell::Confuse => "Confusion",
+            Spell::Fireball => "Fireball",
+        }
+    }
+
+    /// the mana it takes to cast
+    pub fn cost(self) -> i32 {
+        match self {
+            Spell::MagicMissile => 5,
+            Spell::Lightning => 12,
+            Spell::Confuse => 8,
+            Spell::Fireball => 20,
+        }
+    }
+}
+
+/// Learn the spell a spellbook teaches; the book is gone once it's read.
+pub fn learn(spell: Option<Spell>, game: &mut Game) -> UseResult {
+    let spell = match spell {
+        Some(spell) => spell,
+        None => {
+            game.messages
+                .add("You can't make sense of this book.", WHITE);
+            return UseResult::Cancelled;
+        }
+    };
+    if game.spells.contains(&spell) {
+        let msg = format!("You already know {}.", spell.name().to_lowercase());
+        game.messages.add(msg, WHITE);
+        return UseResult::Cancelled;
+    }
+    game.spells.push(spell);
+    let msg = format!(
+        "You learn to cast {}! The book crumbles to dust.",
+        spell.name().to_lowercase()
+    );
+    game.messages.add(msg, LIGHT_BLUE);
+    UseResult::UsedUp
+}
+
+/// Cast a spell the player knows, if they have the mana. True if it took
+/// the player's turn.
+pub fn cast(
+    spell: Spell,
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> bool {
+    if !game.spells.contains(&spell) {
+        let msg = format!("You don't know how to cast {}.", spell.name().to_lowercase());
+        game.messages.add(msg, RED);
+        return false;
+    }
+    let mana = objects[PLAYER].fighter.map_or(0, |f| f.mana);
+    if mana < spell.cost() {
+        let msg = format!("You don't have the mana to cast {}.", spell.name().to_lowercase());
+        game.messages.add(msg, RED);
+        return false;
+    }
+    let result = match spell {
+        Spell::MagicMissile => cast_magic_missile(game, objects),
+        Spell::Lightning => cast_lightning(game, objects),
+        Spell::Confuse => cast_confuse(chooser, game, objects),
+        Spell::Fireball => cast_fireball(chooser, game, objects),
+    };
+    if matches!(result, UseResult::Cancelled) {
+        return false;
+    }
+    if let Some(fighter) = objects[PLAYER].fighter.as_mut() {
+        fighter.mana -= spell.cost();
+    }
+    true
+}
+
+/// a turn's rest, which brings back some of the player's mana
+pub fn tick(game: &Game, player: &mut Object) {
+    if let Some(fighter) = player.fighter.as_mut() {
+        if game.turn.is_multiple_of(MANA_REGEN_TURNS) {
+            fighter.mana = (fighter.mana + 1).min(fighter.max_mana);
+        }
+    }
+}
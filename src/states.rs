@@ -0,0 +1,477 @@
+//! The game as a state machine. Each state is a screen the player is on, or
+//! a mode the game is in: the main menu, playing, picking from the
+//! inventory, levelling up, dead or victorious. Only one runs at a time, and
+//! each hands over to the next when it's done, so a new screen is a new
+//! state, rather than another loop nested inside the others. Asking for a
+//! target is the exception: a spell or a throw asks for one part way through,
+//! through a `Chooser` that scripted runs answer without a screen, so it's
+//! a loop of its own inside whatever state cast or threw.
+
+use std::path::Path;
+
+use crate::bestiary::Bestiary;
+use crate::colors::{LIGHT_GREY, RED};
+use crate::console::*;
+use crate::entities::Objects;
+use crate::input::KeyCode;
+use crate::resting::Busy;
+use crate::save;
+use crate::{
+    choose_stat, drop_item, gain_level, handle_keys, inventory_menu, main_menu, menu, morgue,
+    msgbox, process_event, projectile, raise_stat, record_high_score, record_run_ended, render_all,
+    speed, throwing, tutorial, use_item, win_game, BackgroundSaver, Game, Options,
+    PlayerAction, Tcod, AUTOSAVE_TURNS, BESTIARY_FILE, INVENTORY_WIDTH, PLAYER, SCREEN_HEIGHT,
+    SCREEN_WIDTH,
+};
+
+pub enum GameState {
+    /// choosing what to play
+    MainMenu,
+    /// waiting on the player's next move
+    Playing(Box<Session>),
+    /// picking an item to do something with
+    Inventory(Box<Session>, InventoryAction),
+    /// resting or searching, a turn at a time, until done or interrupted
+    Busy(Box<Session>, Busy),
+    /// picking a stat to raise, having gained a level
+    LevelUp(Box<Session>),
+    /// the player has died, and can look around before leaving
+    Dead(Box<Session>),
+    /// the player has climbed out with the crown
+    Won(Box<Session>),
+    Quit,
+}
+
+/// What the player wants to do with the item they pick from the inventory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InventoryAction {
+    Use,
+    Drop,
+    Throw,
+}
+
+/// A game under way, and what's kept between one frame and the next while
+/// it's played.
+pub struct Session {
+    pub game: Game,
+    pub objects: Objects,
+    saver: BackgroundSaver,
+    /// where the player stood when the map was last drawn, or (-1, -1) to
+    /// have the field of view recomputed anyway
+    previous_player_position: (i32, i32),
+}
+
+impl Session {
+    pub fn new(mut game: Game, objects: Objects) -> Box<Self> {
+        game.bestiary = Bestiary::load(Path::new(BESTIARY_FILE));
+        Box::new(Session {
+            game,
+            objects,
+            saver: BackgroundSaver::new(),
+            // force FOV "recompute" first time through
+            previous_player_position: (-1, -1),
+        })
+    }
+}
+
+/// Run the game, from the main menu on, until the player quits or closes
+/// the window.
+pub fn run(tcod: &mut Tcod, options: &Options) {
+    let mut state = GameState::MainMenu;
+    while !tcod.backend.is_closed() {
+        state = match state {
+            GameState::MainMenu => main_menu(tcod, options),
+            GameState::Playing(session) => play(session, tcod),
+            GameState::Inventory(session, action) => inventory(session, action, tcod),
+            GameState::Busy(session, busy) => keep_busy(session, busy, tcod),
+            GameState::LevelUp(session) => level_up(session, tcod),
+            GameState::Dead(session) => dead(session, tcod),
+            GameState::Won(session) => won(session, tcod),
+            GameState::Quit => break,
+        };
+    }
+}
+
+/// draw the map, the panel and whether a save is being written
+fn draw(session: &mut Session, tcod: &mut Tcod) {
+    tcod.con.clear();
+    let fov_recompute = session.previous_player_position != session.objects[PLAYER].pos();
+    render_all(tcod, &mut session.game, &session.objects, fov_recompute);
+
+    // report the outcome of a finished save, or show that one is still running
+    session.saver.poll(&mut session.game.messages);
+    if session.saver.is_saving() {
+        tcod.screen.set_default_foreground(LIGHT_GREY);
+        tcod.screen.print_ex(
+            SCREEN_WIDTH - 1,
+            SCREEN_HEIGHT - 1,
+            BackgroundFlag::None,
+            TextAlignment::Right,
+            "Saving...",
+        );
+    }
+    // and likewise for a finished run being posted to the leaderboard
+    match session.game.posting.poll() {
+        Some(Ok(())) => session
+            .game
+            .messages
+            .add("Your run has been posted to the leaderboard.", LIGHT_GREY),
+        Some(Err(e)) => session
+            .game
+            .messages
+            .add(format!("Could not post to the leaderboard: {}", e), RED),
+        None => {}
+    }
+    if session.game.posting.is_waiting() {
+        tcod.screen.set_default_foreground(LIGHT_GREY);
+        tcod.screen.print_ex(
+            SCREEN_WIDTH - 1,
+            SCREEN_HEIGHT - 2,
+            BackgroundFlag::None,
+            TextAlignment::Right,
+            "Posting to the leaderboard...",
+        );
+    }
+
+    tcod.flush();
+    session.previous_player_position = session.objects[PLAYER].pos();
+}
+
+/// One frame of play: show the game, and act on whatever key the player
+/// pressed.
+fn play(mut session: Box<Session>, tcod: &mut Tcod) -> GameState {
+    process_event(tcod);
+    draw(&mut session, tcod);
+
+    let Session { game, objects, .. } = &mut *session;
+    if gain_level(&mut objects[PLAYER], game.class, &mut game.messages) {
+        return GameState::LevelUp(session);
+    }
+    tutorial::show_hints(tcod, game, objects);
+
+    let before = Before::take(&session);
+    let Session { game, objects, .. } = &mut *session;
+    match handle_keys(tcod, game, objects) {
+        PlayerAction::Exit => leave(session, tcod),
+        PlayerAction::Inventory(action) => GameState::Inventory(session, action),
+        PlayerAction::Busy(activity) => {
+            let busy = Busy::start(activity, &session.game, &session.objects);
+            GameState::Busy(session, busy)
+        }
+        PlayerAction::TookTurn => after_action(session, before, true, tcod),
+        PlayerAction::DidntTakeTurn => after_action(session, before, false, tcod),
+    }
+}
+
+/// Let the player pick an item from the inventory, and do what they meant to
+/// with it.
+fn inventory(mut session: Box<Session>, action: InventoryAction, tcod: &mut Tcod) -> GameState {
+    let header = match action {
+        InventoryAction::Use => {
+            "Press the key next to an item to use it, or any other to cancel.\n"
+        }
+        InventoryAction::Drop => {
+            "Press the key next to an item to drop it, or any other to cancel.\n"
+        }
+        InventoryAction::Throw => {
+            "Press the key next to an item to throw it, or any other to cancel.\n"
+        }
+    };
+    let before = Before::take(&session);
+    let Session { game, objects, .. } = &mut *session;
+    let inventory_index = match inventory_menu(game, &objects[PLAYER], header, tcod) {
+        Some(inventory_index) => inventory_index,
+        None => return GameState::Playing(session),
+    };
+    let took_turn = match action {
+        InventoryAction::Use => {
+            use_item(inventory_index, tcod, game, objects);
+            false
+        }
+        InventoryAction::Drop => {
+            // a stack can be dropped one at a time, or all at once
+            let quantity = game.inventory[inventory_index].quantity();
+            let amount = if quantity > 1 {
+                let options = ["Just one".to_string(), format!("All {}", quantity)];
+                match menu("Drop how many?\n", &options, INVENTORY_WIDTH, tcod) {
+                    Some(0) => Some(1),
+                    Some(_) => Some(quantity),
+                    None => None,
+                }
+            } else {
+                Some(1)
+            };
+            if let Some(amount) = amount {
+                drop_item(inventory_index, amount, game, objects);
+            }
+            false
+        }
+        InventoryAction::Throw => throwing::throw(inventory_index, tcod, game, objects),
+    };
+    after_action(session, before, took_turn, tcod)
+}
+
+/// Another turn resting or searching, shown like any other, unless it's time
+/// to stop. Any key stops it too.
+fn keep_busy(mut session: Box<Session>, mut busy: Busy, tcod: &mut Tcod) -> GameState {
+    process_event(tcod);
+    draw(&mut session, tcod);
+    let before = Before::take(&session);
+    let Session { game, objects, .. } = &mut *session;
+    if tcod.key.code != KeyCode::NoKey {
+        game.messages.add(format!("You stop {}.", busy.doing()), LIGHT_GREY);
+        return GameState::Playing(session);
+    }
+    if !busy.go_on(game, objects) {
+        return GameState::Playing(session);
+    }
+    match after_action(session, before, true, tcod) {
+        GameState::Playing(session) => GameState::Busy(session, busy),
+        state => state,
+    }
+}
+
+/// ask the player which stat to raise for the level they've gained
+fn level_up(mut session: Box<Session>, tcod: &mut Tcod) -> GameState {
+    let Session { game, objects, .. } = &mut *session;
+    let fighter = objects[PLAYER].fighter.as_mut().unwrap();
+    let choice = choose_stat("Level up! Choose a stat to raise: \n", fighter, tcod);
+    raise_stat(fighter, choice);
+    game.stat_choices.push(choice);
+    GameState::Playing(session)
+}
+
+/// the player can look over the level they died on until they leave
+fn dead(mut session: Box<Session>, tcod: &mut Tcod) -> GameState {
+    process_event(tcod);
+    draw(&mut session, tcod);
+    let Session { game, objects, .. } = &mut *session;
+    match handle_keys(tcod, game, objects) {
+        PlayerAction::Exit => leave(session, tcod),
+        _ => GameState::Dead(session),
+    }
+}
+
+fn won(mut session: Box<Session>, tcod: &mut Tcod) -> GameState {
+    win_game(tcod, &mut session.game, &session.objects);
+    GameState::MainMenu
+}
+
+/// Leave the game for the main menu, saving it first, unless it's the
+/// tutorial. A dead player's run is over, so rather than saved, its slot is
+/// emptied, as a won run's is. A save that fails is reported, but the game
+/// is left all the same.
+fn leave(mut session: Box<Session>, tcod: &mut Tcod) -> GameState {
+    let Session { game, objects, saver, .. } = &mut *session;
+    if game.tutorial.is_some() {
+        return GameState::MainMenu;
+    }
+    if !objects[PLAYER].alive {
+        // an autosave still being written mustn't fill the slot again
+        if let Err(e) = saver.wait() {
+            eprintln!("Saving failed: {}", e);
+        }
+        if let Some(slot) = game.save_slot {
+            if let Err(e) = save::delete(slot) {
+                msgbox(&format!("\nCould not delete the save: {}.\n", e), 50, tcod);
+            }
+        }
+        return GameState::MainMenu;
+    }
+    record_run_ended(game, &objects[PLAYER], "quit");
+    saver.save(game, objects);
+    if let Err(e) = saver.wait() {
+        msgbox(&format!("\nCould not save the game: {}.\n", e), 50, tcod);
+    }
+    GameState::MainMenu
+}
+
+/// What was on screen before the player acted, to tell what's changed since.
+struct Before {
+    player_position: (i32, i32),
+    dungeon_level: u32,
+    was_blind: bool,
+    light_radius: i32,
+    had_smoke: bool,
+    open_doors: Vec<(i32, i32)>,
+    next_event_turn: u32,
+    turn: u32,
+    was_alive: bool,
+}
+
+impl Before {
+    fn take(session: &Session) -> Self {
+        let (game, player) = (&session.game, &session.objects[PLAYER]);
+        Before {
+            player_position: player.pos(),
+            dungeon_level: game.dungeon_level,
+            was_blind: player.is_blind(),
+            light_radius: player.light_radius(game),
+            had_smoke: game.map.has_smoke(),
+            open_doors: game.map.open_doors(),
+            next_event_turn: game.next_event_turn,
+            turn: game.turn,
+            was_alive: player.alive,
+        }
+    }
+}
+
+/// Everything that follows the player's action: the monsters get their turn
+/// if it took one, and the game is saved now and then.
+fn after_action(
+    mut session: Box<Session>,
+    before: Before,
+    took_turn: bool,
+    tcod: &mut Tcod,
+) -> GameState {
+    let Session {
+        game,
+        objects,
+        saver,
+        previous_player_position,
+    } = &mut *session;
+
+    // climbing out with the crown ends the run
+    if game.won {
+        return GameState::Won(session);
+    }
+
+    // autosave whenever a new level is entered
+    if game.dungeon_level != before.dungeon_level {
+        saver.save(game, objects);
+        // the new level has to be lit and seen even if the player stands
+        // on the same spot as before
+        *previous_player_position = (-1, -1);
+    }
+
+    // let monsters take their turn
+    if objects[PLAYER].alive && took_turn {
+        // sneaking moves take twice as long
+        let moved = before.player_position != objects[PLAYER].pos();
+        let actions = if game.sneaking && moved { 2 } else { 1 };
+        speed::player_acted(actions, game, objects);
+        projectile::animate(tcod, game, objects);
+    }
+
+    // and every so often, so a crash never loses much
+    if game.turn / AUTOSAVE_TURNS != before.turn / AUTOSAVE_TURNS {
+        saver.save(game, objects);
+    }
+
+    // going blind or seeing again, the player's light changing, smoke
+    // drifting, a door opening or closing or a level event changes what's
+    // in view without moving
+    if objects[PLAYER].is_blind() != before.was_blind
+        || objects[PLAYER].light_radius(game) != before.light_radius
+        || before.had_smoke
+        || game.map.has_smoke()
+        || game.map.open_doors() != before.open_doors
+        || game.next_event_turn != before.next_event_turn
+    {
+        *previous_player_position = (-1, -1);
+    }
+
+    if !before.was_alive || objects[PLAYER].alive {
+        return GameState::Playing(session);
+    }
+    // tell the story of a run that's just ended
+    if game.tutorial.is_none() {
+        match morgue::write(game, &objects[PLAYER]) {
+            Ok(path) => game.messages.add(
+                format!("The story of your run is told in {}.", path.display()),
+                LIGHT_GREY,
+            ),
+            Err(e) => game
+                .messages
+                .add(format!("Could not write the morgue file: {}", e), RED),
+        }
+        record_high_score(game, &objects[PLAYER], tcod);
+    }
+    GameState::Dead(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::new_game_state;
+    use crate::resting::Activity;
+
+    /// a new game, with the player hurt enough to have something to rest for
+    fn session() -> Box<Session> {
+        let (game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        objects[PLAYER].fighter.as_mut().unwrap().hp -= 10;
+        Session::new(game, objects)
+    }
+
+    #[test]
+    fn resting_goes_on_until_a_key_is_pressed() {
+        let mut tcod = Tcod::pressing("");
+        let mut session = session();
+        let busy = Busy::start(Activity::Rest, &session.game, &session.objects);
+        let turn = session.game.turn;
+        session = match keep_busy(session, busy, &mut tcod) {
+            GameState::Busy(session, _) => session,
+            _ => panic!("the player stopped resting by themselves"),
+        };
+        assert!(session.game.turn > turn);
+
+        let mut tcod = Tcod::pressing("x");
+        let busy = Busy::start(Activity::Rest, &session.game, &session.objects);
+        let session = match keep_busy(session, busy, &mut tcod) {
+            GameState::Playing(session) => session,
+            _ => panic!("a key press didn't stop the player resting"),
+        };
+        let last = session.game.messages.iter().last().unwrap();
+        assert_eq!(last.text, "You stop resting.");
+    }
+
+    #[test]
+    fn levelling_up_raises_the_stat_picked_and_plays_on() {
+        let mut tcod = Tcod::pressing("b");
+        let session = session();
+        let power = session.objects[PLAYER].fighter.unwrap().base_power;
+        let session = match level_up(session, &mut tcod) {
+            GameState::Playing(session) => session,
+            _ => panic!("levelling up didn't go back to playing"),
+        };
+        assert_eq!(session.objects[PLAYER].fighter.unwrap().base_power, power + 1);
+        assert_eq!(session.game.stat_choices.last(), Some(&1));
+    }
+
+    #[test]
+    fn climbing_out_with_the_crown_wins() {
+        let mut tcod = Tcod::pressing("");
+        let mut session = session();
+        session.game.won = true;
+        let before = Before::take(&session);
+        assert!(matches!(after_action(session, before, true, &mut tcod), GameState::Won(_)));
+    }
+
+    #[test]
+    fn dying_leaves_the_player_looking_around() {
+        let mut tcod = Tcod::pressing("");
+        let mut session = session();
+        // in the tutorial, so no morgue file or high score is written
+        session.game.tutorial = Some(Default::default());
+        let before = Before::take(&session);
+        session.objects[PLAYER].alive = false;
+        assert!(matches!(after_action(session, before, false, &mut tcod), GameState::Dead(_)));
+    }
+
+    #[test]
+    fn leaving_after_dying_leaves_no_save_to_load() {
+        let mut tcod = Tcod::pressing("");
+        let mut session = session();
+        let slot = save::SLOTS - 1;
+        session.game.save_slot = Some(slot);
+        save::write(slot, &session.game, &session.objects).unwrap();
+        session.saver.save(&session.game, &session.objects);
+        session.objects[PLAYER].alive = false;
+        assert!(matches!(leave(session, &mut tcod), GameState::MainMenu));
+        assert!(matches!(save::read(slot), Err(save::LoadError::Missing)));
+    }
+}
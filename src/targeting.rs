@@ -0,0 +1,247 @@
+//! Targeting: picking the spot on the map a spell, a shot or a throw goes to,
+//! with the mouse or the keyboard. A cursor starts on the nearest enemy in
+//! reach; the movement keys move it and Tab cycles through the enemies in
+//! view, nearest first. The line a shot would fly along is drawn out to
+//! wherever it would stop, and everything a blast would catch is lit up,
+//! before the player commits. A spot out of sight or out of range is turned
+//! down, with a word as to why.
+
+use crate::actions::Action;
+use crate::colors::{Color, BLACK, LIGHT_GREY, LIGHT_RED, WHITE};
+use crate::console::*;
+use crate::entities::Objects;
+use crate::input::{Event, KeyCode};
+use crate::{
+    factions, get_names_under_mouse, projectile, render_all, target_problem, Game, Object, Tcod,
+    PLAYER, VIEW_WIDTH,
+};
+
+/// how to aim and pick, for the prompts asking for a target
+pub const HOW_TO_AIM: &str =
+    "Aim with the movement keys or Tab, pick with Enter or a left-click, or cancel with Escape.";
+
+/// the cursor, on a spot that can be picked and one that can't
+const COLOR_CURSOR: Color = Color {
+    r: 60,
+    g: 200,
+    b: 60,
+};
+const COLOR_CURSOR_REFUSED: Color = Color {
+    r: 200,
+    g: 40,
+    b: 40,
+};
+/// the tiles a shot flies through on its way
+const COLOR_PATH: Color = Color {
+    r: 40,
+    g: 110,
+    b: 130,
+};
+/// the tiles a blast would catch
+const COLOR_BLAST: Color = Color {
+    r: 170,
+    g: 80,
+    b: 20,
+};
+
+/// Let the player pick a spot in their FOV (or anywhere on the map unless
+/// `in_fov_only`) and in range, if there is one, with the mouse or the
+/// keyboard. Returns None if they cancel, with Escape or a right-click.
+pub fn target_tile(
+    tcod: &mut Tcod,
+    game: &mut Game,
+    objects: &Objects,
+    max_range: Option<f32>,
+    in_fov_only: bool,
+    blast: Option<i32>,
+) -> Option<(i32, i32)> {
+    let enemies = enemies_in_view(game, objects);
+    let problem = |pos, game: &Game| target_problem(pos, game, objects, max_range, in_fov_only);
+    let mut cursor = enemies
+        .iter()
+        .copied()
+        .find(|&pos| problem(pos, game).is_none())
+        .unwrap_or_else(|| objects[PLAYER].pos());
+    while !tcod.backend.is_closed() {
+        render_all(tcod, game, objects, false);
+        let refused = problem(cursor, game);
+        draw(tcod, game, objects, cursor, in_fov_only, blast, refused);
+        tcod.flush();
+
+        let chosen = match tcod.backend.check_for_event() {
+            Some(Event::Mouse(mouse)) => {
+                tcod.mouse = mouse;
+                if mouse.rbutton_pressed {
+                    return None;
+                }
+                let pos = tcod.mouse_on_map();
+                if on_screen(tcod, game, pos) {
+                    cursor = pos;
+                }
+                mouse.lbutton_pressed
+            }
+            Some(Event::Key(key)) => match key.code {
+                KeyCode::Escape => return None,
+                KeyCode::Enter | KeyCode::NumPadEnter => true,
+                KeyCode::Tab => {
+                    // on to the next enemy further away than the one the
+                    // cursor's on, or back to the nearest
+                    let next = enemies.iter().position(|&pos| pos == cursor).map_or(0, |i| i + 1);
+                    if let Some(&pos) = enemies.get(next).or_else(|| enemies.first()) {
+                        cursor = pos;
+                    }
+                    false
+                }
+                _ => {
+                    if let Some(Action::Step { dx, dy } | Action::MoveOrAttack { dx, dy }) =
+                        tcod.keys.action_for(key)
+                    {
+                        let pos = (cursor.0 + dx, cursor.1 + dy);
+                        if on_screen(tcod, game, pos) {
+                            cursor = pos;
+                        }
+                    }
+                    false
+                }
+            },
+            None => false,
+        };
+        if chosen {
+            match problem(cursor, game) {
+                None => return Some(cursor),
+                Some(reason) => game.messages.add(reason, LIGHT_RED),
+            }
+        }
+    }
+    None
+}
+
+/// where the enemies the player can see are, nearest first
+fn enemies_in_view(game: &Game, objects: &Objects) -> Vec<(i32, i32)> {
+    let player = &objects[PLAYER];
+    let mut enemies: Vec<&Object> = objects
+        .iter()
+        .filter(|o| {
+            o.alive
+                && o.fighter.is_some()
+                && factions::are_enemies(player, o)
+                && game.map.is_visible(o.x, o.y)
+        })
+        .collect();
+    enemies.sort_by(|a, b| player.distance_to(a).total_cmp(&player.distance_to(b)));
+    enemies.iter().map(|enemy| enemy.pos()).collect()
+}
+
+/// whether the spot is on the map, and on the part of it that's on screen
+fn on_screen(tcod: &Tcod, game: &Game, (x, y): (i32, i32)) -> bool {
+    game.map.in_bounds(x, y) && tcod.camera.to_screen(x, y).is_some()
+}
+
+/// Draw the cursor over the map, with the path a shot would take to it and
+/// whatever a blast there would catch, and say at the top of the screen
+/// what's there and whether it can be picked.
+fn draw(
+    tcod: &mut Tcod,
+    game: &Game,
+    objects: &Objects,
+    cursor: (i32, i32),
+    in_fov_only: bool,
+    blast: Option<i32>,
+    refused: Option<&str>,
+) {
+    let camera = tcod.camera;
+    let mut highlight = |(x, y): (i32, i32), color: Color| {
+        if let Some((x, y)) = camera.to_screen(x, y) {
+            tcod.screen.set_char_background(x, y, color, BackgroundFlag::Set);
+        }
+    };
+    let player = objects[PLAYER].pos();
+    if in_fov_only && cursor != player {
+        for pos in projectile::flight(player, cursor, &game.map, objects) {
+            highlight(pos, COLOR_PATH);
+        }
+    }
+    if let Some(radius) = blast {
+        let (x, y) = cursor;
+        for ((tile_x, tile_y), tile) in game.map.iter() {
+            let caught = (tile_x - x).pow(2) + (tile_y - y).pow(2) <= radius.pow(2);
+            if caught && tile.visible && !tile.blocked {
+                highlight((tile_x, tile_y), COLOR_BLAST);
+            }
+        }
+    }
+    let color = match refused {
+        None => COLOR_CURSOR,
+        Some(_) => COLOR_CURSOR_REFUSED,
+    };
+    highlight(cursor, color);
+
+    let screen = &mut tcod.screen;
+    screen.set_default_background(BLACK);
+    screen.rect(0, 0, VIEW_WIDTH, 2, true, BackgroundFlag::Set);
+    screen.set_default_foreground(LIGHT_GREY);
+    screen.print(1, 0, "Move or Tab to aim, Enter or left-click to pick, Escape to cancel.");
+    let there = get_names_under_mouse(cursor, objects, game);
+    match refused {
+        Some(reason) => {
+            screen.set_default_foreground(LIGHT_RED);
+            screen.print(1, 1, format!("{} {}", reason, there));
+        }
+        None => {
+            screen.set_default_foreground(WHITE);
+            screen.print(1, 1, there);
+        }
+    }
+    tcod.mark_map((0, 0), (VIEW_WIDTH, 2), false);
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::bestiary;
+    use crate::classes::Class;
+    use crate::new_game_state;
+
+    /// a new game in broad daylight, with orcs in view three and two steps
+    /// off to the player's right
+    fn game() -> (Game, Objects, (i32, i32), (i32, i32)) {
+        let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        for (_, tile) in game.map.iter_mut() {
+            tile.visible = true;
+        }
+        let (x, y) = objects[PLAYER].pos();
+        let (far, near) = ((x + 3, y), (x + 2, y));
+        objects.push(bestiary::template("orc").spawn(far.0, far.1));
+        objects.push(bestiary::template("orc").spawn(near.0, near.1));
+        (game, objects, far, near)
+    }
+
+    #[test]
+    fn enemies_are_listed_nearest_first() {
+        let (game, objects, far, near) = game();
+        assert_eq!(enemies_in_view(&game, &objects), vec![near, far]);
+    }
+
+    #[test]
+    fn the_cursor_starts_on_the_nearest_enemy_and_tab_cycles() {
+        let (mut game, objects, far, near) = game();
+        let mut tcod = Tcod::pressing("\n");
+        assert_eq!(target_tile(&mut tcod, &mut game, &objects, None, true, None), Some(near));
+        let mut tcod = Tcod::pressing("\t\n");
+        assert_eq!(target_tile(&mut tcod, &mut game, &objects, None, true, None), Some(far));
+        let mut tcod = Tcod::pressing("\t\t\n");
+        assert_eq!(target_tile(&mut tcod, &mut game, &objects, None, true, None), Some(near));
+    }
+
+    #[test]
+    fn spots_out_of_range_are_turned_down() {
+        let (mut game, objects, _, _) = game();
+        let mut tcod = Tcod::pressing("\t\n\x1b");
+        assert_eq!(target_tile(&mut tcod, &mut game, &objects, Some(2.5), true, None), None);
+        let last = game.messages.iter().last().unwrap();
+        assert_eq!(last.text, "That's out of range.");
+    }
+}
@@ -0,0 +1,122 @@
+//! Opt-in balance telemetry. When enabled, per-turn and per-run metrics are
+//! appended to a file as JSON lines, one record per line, for offline
+//! balance analysis.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::Serialize;
+
+/// A single telemetry record. Serialized with an `event` tag naming the variant.
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Record<'a> {
+    RunStarted {
+        timestamp: u64,
+    },
+    Turn {
+        turn: u32,
+        dungeon_level: u32,
+        hp: i32,
+        max_hp: i32,
+        xp: i32,
+        level: i32,
+    },
+    Damage {
+        turn: u32,
+        source: &'a str,
+        target: &'a str,
+        amount: i32,
+    },
+    ItemUsed {
+        turn: u32,
+        item: &'a str,
+    },
+    ItemPickedUp {
+        turn: u32,
+        item: &'a str,
+    },
+    LevelChanged {
+        turn: u32,
+        branch: &'a str,
+        dungeon_level: u32,
+    },
+    RunEnded {
+        turn: u32,
+        dungeon_level: u32,
+        outcome: &'a str,
+        cause: Option<&'a str>,
+    },
+}
+
+/// Appends telemetry records to a file. A default `Telemetry` is disabled and
+/// silently drops everything it is given.
+#[derive(Debug, Default)]
+pub struct Telemetry {
+    writer: Option<BufWriter<File>>,
+    /// whatever last damaged the player, reported as the cause of death
+    last_damage_to_player: Option<String>,
+}
+
+impl Telemetry {
+    /// open (or create) the file at `path` and append records to it
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let mut telemetry = Telemetry {
+            writer: Some(BufWriter::new(file)),
+            last_damage_to_player: None,
+        };
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        telemetry.record(&Record::RunStarted { timestamp });
+        Ok(telemetry)
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.writer.is_some()
+    }
+
+    /// the source of the last damage the player took, if any
+    pub fn last_damage_to_player(&self) -> Option<&str> {
+        self.last_damage_to_player.as_deref()
+    }
+
+    /// append a record; if writing fails, telemetry is turned off for the rest of the run
+    pub fn record(&mut self, record: &Record) {
+        if let Record::Damage { source, target, .. } = *record {
+            if target == "player" {
+                self.last_damage_to_player = Some(source.into());
+            }
+        }
+        if let Some(writer) = self.writer.as_mut() {
+            let result = serde_json::to_writer(&mut *writer, record)
+                .map_err(io::Error::from)
+                .and_then(|_| writer.write_all(b"\n"));
+            if let Err(e) = result {
+                eprintln!("Telemetry disabled, failed to write record: {}", e);
+                self.writer = None;
+            }
+        }
+    }
+
+    /// write any buffered records out to the file
+    pub fn flush(&mut self) {
+        if let Some(writer) = self.writer.as_mut() {
+            if let Err(e) = writer.flush() {
+                eprintln!("Telemetry disabled, failed to flush: {}", e);
+                self.writer = None;
+            }
+        }
+    }
+}
+
+/// Clones are disabled: only the original game state writes telemetry, never
+/// the snapshots taken for saving.
+impl Clone for Telemetry {
+    fn clone(&self) -> Self {
+        Telemetry::default()
+    }
+}
@@ -0,0 +1,202 @@
+//! The terminal backend, built with the `terminal` feature: the screen is
+//! drawn as text in the terminal the game was started from, through
+//! crossterm, and keys and the mouse are read from it.
+
+use std::io::{self, Stdout, Write};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{
+    self, DisableMouseCapture, EnableMouseCapture, KeyEvent, KeyEventKind, KeyModifiers,
+    MouseButton, MouseEvent, MouseEventKind,
+};
+use crossterm::style::{
+    Color as TermColor, Print, ResetColor, SetBackgroundColor, SetForegroundColor,
+};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::backend::{Input, Renderer};
+use crate::colors::Color;
+use crate::console::{Cell, Offscreen};
+use crate::input::{Event, Key, KeyCode, Mouse};
+
+/// The terminal, taken over for the game until it's dropped.
+pub struct Terminal {
+    out: Stdout,
+    /// what's on the terminal now, to only redraw what's changed
+    shown: Vec<Cell>,
+    frame_time: Duration,
+    last_frame: Instant,
+    closed: bool,
+}
+
+impl Terminal {
+    /// Take over the terminal, drawing at most `fps` frames a second.
+    pub fn open(fps: i32) -> io::Result<Self> {
+        let mut out = io::stdout();
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, EnableMouseCapture, Hide)?;
+        Ok(Terminal {
+            out,
+            shown: vec![],
+            frame_time: Duration::from_secs(1) / fps.max(1) as u32,
+            last_frame: Instant::now(),
+            closed: false,
+        })
+    }
+
+    fn draw(&mut self, screen: &Offscreen) -> io::Result<()> {
+        let (width, height) = (screen.width(), screen.height());
+        let cells: Vec<Cell> = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| screen.cell(x, y))
+            .collect();
+        for (i, cell) in cells.iter().enumerate() {
+            if self.shown.get(i) == Some(cell) {
+                continue;
+            }
+            let (x, y) = (i as i32 % width, i as i32 / width);
+            queue!(
+                self.out,
+                MoveTo(x as u16, y as u16),
+                SetForegroundColor(term_color(cell.foreground)),
+                SetBackgroundColor(term_color(cell.background)),
+                Print(cell.char),
+            )?;
+        }
+        queue!(self.out, ResetColor)?;
+        self.out.flush()?;
+        self.shown = cells;
+        Ok(())
+    }
+
+    /// The next key press or mouse event waiting, if any. Anything else the
+    /// terminal reports is dropped, and Ctrl-C closes the game, as the
+    /// terminal won't while it's in raw mode.
+    fn next_event(&mut self, timeout: Duration) -> Option<Event> {
+        while event::poll(timeout).ok()? {
+            match event::read().ok()? {
+                event::Event::Key(KeyEvent {
+                    code: event::KeyCode::Char('c'),
+                    modifiers,
+                    ..
+                }) if modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.closed = true;
+                    return None;
+                }
+                event::Event::Key(key) if key.kind != KeyEventKind::Release => {
+                    if let Some(key) = game_key(key) {
+                        return Some(Event::Key(key));
+                    }
+                }
+                event::Event::Mouse(mouse) => return Some(Event::Mouse(game_mouse(mouse))),
+                // the whole screen has to be drawn again after a resize
+                event::Event::Resize(..) => self.shown.clear(),
+                _ => {}
+            }
+        }
+        None
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        // give the terminal back as it was, even if the game is panicking
+        let _ = execute!(self.out, ResetColor, Show, DisableMouseCapture, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+impl Renderer for Terminal {
+    fn present(&mut self, screen: &Offscreen, _map_cells: &[bool]) {
+        self.draw(screen).expect("could not draw to the terminal");
+        // keep to the frame rate, as libtcod's window does
+        let elapsed = self.last_frame.elapsed();
+        if elapsed < self.frame_time {
+            thread::sleep(self.frame_time - elapsed);
+        }
+        self.last_frame = Instant::now();
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        // a terminal is as big as the player makes it
+    }
+}
+
+impl Input for Terminal {
+    fn check_for_event(&mut self) -> Option<Event> {
+        self.next_event(Duration::ZERO)
+    }
+
+    fn wait_for_keypress(&mut self) -> Key {
+        loop {
+            match self.next_event(Duration::from_secs(60)) {
+                Some(Event::Key(key)) => return key,
+                // nothing to wait for once the game's closed
+                _ if self.closed => return Key::default(),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn term_color(color: Color) -> TermColor {
+    TermColor::Rgb {
+        r: color.r,
+        g: color.g,
+        b: color.b,
+    }
+}
+
+/// the game's key for a key press in the terminal; none for keys the game
+/// has no use for
+fn game_key(key: KeyEvent) -> Option<Key> {
+    let (code, printable) = match key.code {
+        event::KeyCode::Char(char) => (KeyCode::Text, char),
+        event::KeyCode::Enter => (KeyCode::Enter, '\r'),
+        event::KeyCode::Esc => (KeyCode::Escape, '\x1b'),
+        event::KeyCode::Backspace => (KeyCode::Backspace, '\x08'),
+        event::KeyCode::Tab => (KeyCode::Tab, '\t'),
+        event::KeyCode::Up => (KeyCode::Up, '\0'),
+        event::KeyCode::Down => (KeyCode::Down, '\0'),
+        event::KeyCode::Left => (KeyCode::Left, '\0'),
+        event::KeyCode::Right => (KeyCode::Right, '\0'),
+        event::KeyCode::Home => (KeyCode::Home, '\0'),
+        event::KeyCode::End => (KeyCode::End, '\0'),
+        event::KeyCode::PageUp => (KeyCode::PageUp, '\0'),
+        event::KeyCode::PageDown => (KeyCode::PageDown, '\0'),
+        event::KeyCode::Insert => (KeyCode::Insert, '\0'),
+        event::KeyCode::Delete => (KeyCode::Delete, '\0'),
+        _ => return None,
+    };
+    Some(Key {
+        code,
+        printable,
+        pressed: true,
+        alt: key.modifiers.contains(KeyModifiers::ALT),
+        ctrl: key.modifiers.contains(KeyModifiers::CONTROL),
+        shift: key.modifiers.contains(KeyModifiers::SHIFT),
+    })
+}
+
+/// the mouse as the game sees it, in screen characters
+fn game_mouse(mouse: MouseEvent) -> Mouse {
+    let (x, y) = (mouse.column as i32, mouse.row as i32);
+    Mouse {
+        x,
+        y,
+        cx: x,
+        cy: y,
+        lbutton_pressed: mouse.kind == MouseEventKind::Down(MouseButton::Left),
+        rbutton_pressed: mouse.kind == MouseEventKind::Down(MouseButton::Right),
+        wheel_up: mouse.kind == MouseEventKind::ScrollUp,
+        wheel_down: mouse.kind == MouseEventKind::ScrollDown,
+        ..Default::default()
+    }
+}
@@ -0,0 +1,41 @@
+//! Places for tests to play out in. Each is a new game with the player alone
+//! on a small map made for the purpose, so a test only has to set up what
+//! it's about, without a generated level getting in the way.
+
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+
+use crate::classes::Class;
+use crate::entities::Objects;
+use crate::map::{Map, Tile};
+use crate::{new_game_state, Game, PLAYER};
+
+/// a new game on an open, empty field of the given size, all of it in view,
+/// the player at (5, 5) and alone
+pub fn open_field(width: i32, height: i32) -> (Game, Objects) {
+    let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+    game.map = Map::new(width, height, Tile::empty());
+    for (_, tile) in game.map.iter_mut() {
+        tile.visible = true;
+    }
+    objects.truncate(PLAYER + 1);
+    objects[PLAYER].set_pos(5, 5);
+    (game, objects)
+}
+
+/// A new game in a corridor along the middle row of a map of the given size,
+/// walled in all round, and walled across at `wall_at`, if anywhere. The
+/// player is alone, at its west end.
+pub fn corridor(width: i32, height: i32, wall_at: Option<i32>) -> (Game, Objects) {
+    let (mut game, mut objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+    game.map = Map::new(width, height, Tile::wall());
+    let y = height / 2;
+    for x in 1..width - 1 {
+        if Some(x) != wall_at {
+            game.map[(x, y)] = Tile::empty();
+        }
+    }
+    objects.truncate(PLAYER + 1);
+    objects[PLAYER].set_pos(1, y);
+    (game, objects)
+}
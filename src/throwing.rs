@@ -0,0 +1,186 @@
+//! Throwing things. A thrown item flies in a straight line towards the spot
+//! the player picks, stopping short of walls and at the first monster in the
+//! way. Potions shatter and splash everything around where they land; daggers,
+//! swords and rocks hurt what they hit and then lie on the floor to be picked
+//! up again.
+
+use crate::bus::{self, GameEvent};
+use crate::colors::{LIGHT_GREY, LIGHT_VIOLET, RED, WHITE, YELLOW};
+use crate::effects::{self, Effect, StatusEffect};
+use crate::entities::Objects;
+use crate::identify;
+use crate::noise::{self, Noise};
+use crate::projectile::{self, Projectile};
+use crate::{
+    fighters_within, take_from_inventory, target_area, Category, Chooser, Game, Item, Object,
+    BLINDNESS_NUM_TURNS, HASTE_NUM_TURNS, HEAL_AMOUNT, PLAYER,
+};
+
+/// how far the player can throw something
+const THROW_RANGE: f32 = 8.0;
+/// what anything worth throwing does, before a blade's own power bonus
+const THROW_DAMAGE: i32 = 3;
+/// how far a shattered potion splashes
+const SHATTER_RADIUS: i32 = 1;
+
+/// whether an item is a potion, which shatters when thrown
+fn is_potion(item: Option<Item>) -> bool {
+    use Item::*;
+    matches!(
+        item,
+        Some(Heal)
+            | Some(Blindness)
+            | Some(Telepathy)
+            | Some(NightVision)
+            | Some(Retraining)
+            | Some(Haste)
+    )
+}
+
+/// what an item does to whatever it hits when thrown; nothing, for anything
+/// but blades and rocks
+fn thrown_damage(item: &Object) -> i32 {
+    match item.item {
+        Some(Item::Sword) => THROW_DAMAGE + item.equipment.map_or(0, |e| e.power()),
+        Some(Item::Rock) => THROW_DAMAGE,
+        _ => 0,
+    }
+}
+
+/// Throw a carried item at a spot the player chooses. True if it took the
+/// player's turn.
+pub fn throw(
+    inventory_id: usize,
+    chooser: &mut impl Chooser,
+    game: &mut Game,
+    objects: &mut Objects,
+) -> bool {
+    if game.inventory[inventory_id].is_stuck() {
+        let msg = format!("The {} won't come off!", game.inventory[inventory_id].name);
+        game.messages.add_in(Category::Combat, msg, RED);
+        return false;
+    }
+    let what = format!("the {}", identify::name(&game.inventory[inventory_id], game));
+    // a potion splashes everyone next to where it shatters
+    let splash = is_potion(game.inventory[inventory_id].item).then_some(SHATTER_RADIUS);
+    let target = match target_area(chooser, game, objects, &what, Some(THROW_RANGE), splash) {
+        Some(target) => target,
+        None => return false,
+    };
+    let mut item = take_from_inventory(inventory_id, 1, &mut game.inventory);
+    if item.equipment.is_some() {
+        item.dequip(&mut game.messages);
+    }
+    let name = identify::name(&item, game);
+    game.messages.add_in(Category::Combat, format!("You throw the {}.", name), WHITE);
+
+    let from = objects[PLAYER].pos();
+    let path = projectile::flight(from, target, &game.map, objects);
+    let (x, y) = path.last().copied().unwrap_or(from);
+    game.projectiles.push(Projectile {
+        path,
+        char: item.char,
+        color: item.color,
+    });
+    game.noises.push(Noise {
+        x,
+        y,
+        loudness: noise::COMBAT_NOISE,
+    });
+
+    if is_potion(item.item) {
+        shatter(&item, (x, y), game, objects);
+        return true;
+    }
+    let hit = objects
+        .iter()
+        .position(|obj| obj.pos() == (x, y) && obj.fighter.is_some() && obj.name != "player");
+    if let Some(monster_id) = hit {
+        hit_monster(&item, monster_id, game, objects);
+    }
+    land(item, (x, y), game, objects);
+    true
+}
+
+fn hit_monster(item: &Object, monster_id: usize, game: &mut Game, objects: &mut Objects) {
+    let damage = thrown_damage(item);
+    let monster = &mut objects[monster_id];
+    if damage == 0 {
+        game.messages.add_in(
+            Category::Combat,
+            format!("The {} bounces harmlessly off the {}.", item.name, monster.name),
+            LIGHT_GREY,
+        );
+        return;
+    }
+    game.messages.add_in(
+        Category::Combat,
+        format!(
+            "The {} hits the {} for {} hit points.",
+            item.name, monster.name, damage
+        ),
+        WHITE,
+    );
+    bus::publish(
+        game,
+        GameEvent::DamageDealt {
+            source: item.name.clone(),
+            target: monster.name.clone(),
+            amount: damage,
+        },
+    );
+    if let Some(xp) = monster.take_damage(damage, game) {
+        objects[PLAYER].fighter.as_mut().unwrap().xp += xp;
+    }
+}
+
+/// a potion breaking, and splashing everyone around where it lands
+fn shatter(potion: &Object, pos: (i32, i32), game: &mut Game, objects: &mut Objects) {
+    let name = identify::name(potion, game);
+    game.messages
+        .add_in(Category::Combat, format!("The {} shatters!", name), LIGHT_VIOLET);
+    for id in fighters_within(objects, pos, SHATTER_RADIUS) {
+        let obj = &mut objects[id];
+        match potion.item {
+            Some(Item::Heal) => {
+                obj.heal(HEAL_AMOUNT, game);
+                game.messages
+                    .add_in(
+                        Category::Combat,
+                        format!("The {} looks healthier.", obj.name),
+                        LIGHT_VIOLET,
+                    );
+            }
+            Some(Item::Blindness) => {
+                obj.blind(BLINDNESS_NUM_TURNS);
+                game.messages
+                    .add_in(
+                        Category::Combat,
+                        format!("The {} is blinded!", obj.name),
+                        LIGHT_VIOLET,
+                    );
+            }
+            Some(Item::Haste) => {
+                let haste = StatusEffect {
+                    effect: Effect::Haste,
+                    turns: HASTE_NUM_TURNS,
+                };
+                effects::apply(obj, haste, game);
+            }
+            // the rest only work when drunk
+            _ => {}
+        }
+    }
+}
+
+/// a thrown or shot item coming to rest on the floor, unless it falls in
+/// deep water
+pub fn land(mut item: Object, (x, y): (i32, i32), game: &mut Game, objects: &mut Objects) {
+    if game.map[(x, y)].deep_water {
+        game.messages
+            .add_in(Category::Combat, format!("The {} sinks out of sight.", item.name), YELLOW);
+        return;
+    }
+    item.set_pos(x, y);
+    objects.push(item);
+}
@@ -0,0 +1,348 @@
+//! The tileset backend, built with the `tiles` feature: a window of its own,
+//! through SDL2, with the screen drawn from a tileset image instead of a
+//! font. The tileset is a grid of tiles laid out like code page 437, as for
+//! most roguelike tilesets, so text is drawn from the tile at each
+//! character's code. On the map, glyphs listed in `data/tiles.json` are drawn
+//! as the sprite given for them there instead, in the sprite's own colors.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use sdl2::event::Event as SdlEvent;
+use sdl2::image::LoadSurface;
+use sdl2::keyboard::{Keycode, Mod};
+use sdl2::mouse::MouseButton;
+use sdl2::pixels::{Color as SdlColor, PixelFormatEnum};
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, WindowCanvas};
+use sdl2::surface::Surface;
+use sdl2::video::FullscreenType;
+use sdl2::EventPump;
+use serde::Deserialize;
+
+use crate::backend::{Input, Renderer};
+use crate::colors::Color;
+use crate::console::Offscreen;
+use crate::input::{Event, Key, KeyCode, Mouse};
+
+const TILE_DATA: &str = include_str!("../data/tiles.json");
+
+/// the color sprites use for the parts of a tile they don't cover
+const TRANSPARENT: Color = Color::new(255, 0, 255);
+
+/// How the tileset is laid out, and which of its tiles the map's glyphs are
+/// drawn as.
+#[derive(Debug, Deserialize)]
+struct Layout {
+    /// how many tiles across and down the tileset is
+    columns: u32,
+    rows: u32,
+    /// the column and row of the sprite for each glyph that has one
+    sprites: BTreeMap<char, (u32, u32)>,
+}
+
+/// read a tileset layout from JSON laid out like `data/tiles.json`, or say
+/// what's wrong with it
+pub fn parse(json: &str) -> Result<(), String> {
+    parse_layout(json).map(|_| ())
+}
+
+fn parse_layout(json: &str) -> Result<Layout, String> {
+    let layout: Layout = serde_json::from_str(json).map_err(|e| e.to_string())?;
+    let outside = layout
+        .sprites
+        .iter()
+        .find(|(_, &(column, row))| column >= layout.columns || row >= layout.rows);
+    if let Some((glyph, _)) = outside {
+        return Err(format!("the sprite for '{}' is off the tileset", glyph));
+    }
+    Ok(layout)
+}
+
+/// the tileset's layout, read from the data file the first time it's needed,
+/// or what's wrong with it
+fn layout() -> Result<&'static Layout, String> {
+    static LAYOUT: OnceLock<Result<Layout, String>> = OnceLock::new();
+    LAYOUT
+        .get_or_init(|| {
+            parse_layout(TILE_DATA).map_err(|e| format!("data/tiles.json is invalid: {}", e))
+        })
+        .as_ref()
+        .map_err(Clone::clone)
+}
+
+/// A window showing the screen in tiles.
+pub struct Tileset {
+    canvas: WindowCanvas,
+    events: EventPump,
+    /// the tileset in white, to be tinted with the color of the text
+    glyphs: Texture,
+    /// the tileset in its own colors, for sprites
+    sprites: Texture,
+    tile_width: u32,
+    tile_height: u32,
+    closed: bool,
+}
+
+impl Tileset {
+    /// Open a window for a screen of the given size, in tiles, drawn from
+    /// the tileset image at `path`.
+    pub fn open(path: &Path, width: i32, height: i32, title: &str) -> Result<Self, String> {
+        let layout = layout()?;
+        let image = Surface::from_file(path)
+            .and_then(|image| image.convert_format(PixelFormatEnum::RGBA32))
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        let (image_width, image_height) = (image.width(), image.height());
+        let tile_width = image_width / layout.columns;
+        let tile_height = image_height / layout.rows;
+        if tile_width == 0 || tile_height == 0 {
+            return Err(format!("{} is too small for its tiles", path.display()));
+        }
+
+        let sdl = sdl2::init()?;
+        let video = sdl.video()?;
+        video.text_input().start();
+        let window = video
+            .window(title, width as u32 * tile_width, height as u32 * tile_height)
+            .position_centered()
+            .build()
+            .map_err(|e| e.to_string())?;
+        let canvas = window
+            .into_canvas()
+            .present_vsync()
+            .build()
+            .map_err(|e| e.to_string())?;
+
+        // the glyphs are bright where they're drawn, and the sprites are
+        // opaque everywhere but the transparent color
+        let mut glyph_pixels = vec![];
+        let mut sprite_pixels = vec![];
+        let pitch = image.pitch() as usize;
+        image.with_lock(|pixels| {
+            for y in 0..image_height as usize {
+                for x in 0..image_width as usize {
+                    let i = y * pitch + x * 4;
+                    let color = Color::new(pixels[i], pixels[i + 1], pixels[i + 2]);
+                    let brightness = color.r.max(color.g).max(color.b);
+                    glyph_pixels.extend([255, 255, 255, brightness]);
+                    let alpha = if color == TRANSPARENT { 0 } else { 255 };
+                    sprite_pixels.extend([color.r, color.g, color.b, alpha]);
+                }
+            }
+        });
+        let texture = |pixels: &[u8]| -> Result<Texture, String> {
+            let mut texture = canvas
+                .texture_creator()
+                .create_texture_static(PixelFormatEnum::RGBA32, image_width, image_height)
+                .map_err(|e| e.to_string())?;
+            texture
+                .update(None, pixels, image_width as usize * 4)
+                .map_err(|e| e.to_string())?;
+            texture.set_blend_mode(BlendMode::Blend);
+            Ok(texture)
+        };
+        let glyphs = texture(&glyph_pixels)?;
+        let sprites = texture(&sprite_pixels)?;
+
+        Ok(Tileset {
+            events: sdl.event_pump()?,
+            canvas,
+            glyphs,
+            sprites,
+            tile_width,
+            tile_height,
+            closed: false,
+        })
+    }
+
+    /// the part of the tileset with the tile at the given column and row
+    fn tile(&self, (column, row): (u32, u32)) -> Rect {
+        let (width, height) = (self.tile_width, self.tile_height);
+        Rect::new((column * width) as i32, (row * height) as i32, width, height)
+    }
+
+    fn draw(&mut self, screen: &Offscreen, map_cells: &[bool]) -> Result<(), String> {
+        let layout = layout()?;
+        let width = screen.width();
+        let (tile_width, tile_height) = (self.tile_width, self.tile_height);
+        for y in 0..screen.height() {
+            for x in 0..width {
+                let cell = Rect::new(
+                    x * tile_width as i32,
+                    y * tile_height as i32,
+                    tile_width,
+                    tile_height,
+                );
+                let background = screen.get_char_background(x, y);
+                self.canvas.set_draw_color(sdl_color(background));
+                self.canvas.fill_rect(cell)?;
+
+                let char = screen.get_char(x, y);
+                let on_map = map_cells.get((y * width + x) as usize) == Some(&true);
+                match layout.sprites.get(&char).filter(|_| on_map) {
+                    Some(&sprite) => {
+                        let source = self.tile(sprite);
+                        self.canvas.copy(&self.sprites, source, cell)?;
+                    }
+                    None => {
+                        let code = char as u32;
+                        if code == 0 || code == ' ' as u32 || code >= layout.columns * layout.rows {
+                            continue;
+                        }
+                        let foreground = screen.cell(x, y).foreground;
+                        let source = self.tile((code % layout.columns, code / layout.columns));
+                        self.glyphs
+                            .set_color_mod(foreground.r, foreground.g, foreground.b);
+                        self.canvas.copy(&self.glyphs, source, cell)?;
+                    }
+                }
+            }
+        }
+        self.canvas.present();
+        Ok(())
+    }
+
+    /// the next key press or mouse event waiting, if any
+    fn next_event(&mut self, wait: bool) -> Option<Event> {
+        loop {
+            let event = if wait {
+                self.events.wait_event()
+            } else {
+                self.events.poll_event()?
+            };
+            if let Some(event) = self.game_event(event) {
+                return Some(event);
+            }
+            if self.closed {
+                return None;
+            }
+        }
+    }
+
+    /// The game's event for an SDL one, if any. Characters typed come as
+    /// text, and other keys as key codes.
+    fn game_event(&mut self, event: SdlEvent) -> Option<Event> {
+        let (tile_width, tile_height) = (self.tile_width as i32, self.tile_height as i32);
+        let mouse = |x: i32, y: i32| Mouse {
+            x,
+            y,
+            cx: x / tile_width,
+            cy: y / tile_height,
+            ..Default::default()
+        };
+        match event {
+            SdlEvent::Quit { .. } => {
+                self.closed = true;
+                None
+            }
+            SdlEvent::TextInput { text, .. } => text.chars().next().map(|char| {
+                Event::Key(Key {
+                    code: KeyCode::Text,
+                    printable: char,
+                    pressed: true,
+                    ..Default::default()
+                })
+            }),
+            SdlEvent::KeyDown {
+                keycode: Some(keycode),
+                keymod,
+                ..
+            } => key_code(keycode).map(|code| {
+                Event::Key(Key {
+                    code,
+                    pressed: true,
+                    alt: keymod.intersects(Mod::LALTMOD | Mod::RALTMOD),
+                    ctrl: keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD),
+                    shift: keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD),
+                    ..Default::default()
+                })
+            }),
+            SdlEvent::MouseMotion { x, y, .. } => Some(Event::Mouse(mouse(x, y))),
+            SdlEvent::MouseButtonDown {
+                mouse_btn, x, y, ..
+            } => Some(Event::Mouse(Mouse {
+                lbutton_pressed: mouse_btn == MouseButton::Left,
+                rbutton_pressed: mouse_btn == MouseButton::Right,
+                mbutton_pressed: mouse_btn == MouseButton::Middle,
+                ..mouse(x, y)
+            })),
+            _ => None,
+        }
+    }
+}
+
+impl Renderer for Tileset {
+    fn present(&mut self, screen: &Offscreen, map_cells: &[bool]) {
+        self.draw(screen, map_cells)
+            .unwrap_or_else(|e| panic!("could not draw the screen: {}", e));
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed
+    }
+
+    fn toggle_fullscreen(&mut self) {
+        let window = self.canvas.window_mut();
+        let fullscreen = match window.fullscreen_state() {
+            FullscreenType::Off => FullscreenType::Desktop,
+            _ => FullscreenType::Off,
+        };
+        // staying in a window is no great loss
+        let _ = window.set_fullscreen(fullscreen);
+    }
+}
+
+impl Input for Tileset {
+    fn check_for_event(&mut self) -> Option<Event> {
+        self.next_event(false)
+    }
+
+    fn wait_for_keypress(&mut self) -> Key {
+        loop {
+            match self.next_event(true) {
+                Some(Event::Key(key)) => return key,
+                // nothing to wait for once the window's closed
+                _ if self.closed => return Key::default(),
+                _ => {}
+            }
+        }
+    }
+}
+
+fn sdl_color(color: Color) -> SdlColor {
+    SdlColor::RGB(color.r, color.g, color.b)
+}
+
+/// the game's key code for a key that doesn't type a character; none for
+/// those that do, or that the game has no use for
+fn key_code(keycode: Keycode) -> Option<KeyCode> {
+    let code = match keycode {
+        Keycode::Return => KeyCode::Enter,
+        Keycode::Escape => KeyCode::Escape,
+        Keycode::Backspace => KeyCode::Backspace,
+        Keycode::Tab => KeyCode::Tab,
+        Keycode::Up => KeyCode::Up,
+        Keycode::Down => KeyCode::Down,
+        Keycode::Left => KeyCode::Left,
+        Keycode::Right => KeyCode::Right,
+        Keycode::Home => KeyCode::Home,
+        Keycode::End => KeyCode::End,
+        Keycode::PageUp => KeyCode::PageUp,
+        Keycode::PageDown => KeyCode::PageDown,
+        Keycode::Insert => KeyCode::Insert,
+        Keycode::Delete => KeyCode::Delete,
+        Keycode::Kp1 => KeyCode::NumPad1,
+        Keycode::Kp2 => KeyCode::NumPad2,
+        Keycode::Kp3 => KeyCode::NumPad3,
+        Keycode::Kp4 => KeyCode::NumPad4,
+        Keycode::Kp5 => KeyCode::NumPad5,
+        Keycode::Kp6 => KeyCode::NumPad6,
+        Keycode::Kp7 => KeyCode::NumPad7,
+        Keycode::Kp8 => KeyCode::NumPad8,
+        Keycode::Kp9 => KeyCode::NumPad9,
+        Keycode::KpEnter => KeyCode::NumPadEnter,
+        _ => return None,
+    };
+    Some(code)
+}
@@ -0,0 +1,205 @@
+//! The town above the dungeon, level 0: laid out by hand in `data/town.txt`,
+//! with shops to restock at, an inn to rest at and the way down into the
+//! dungeon. Nothing here attacks unless given reason to, with guards about
+//! to see to thieves, and the shops get new wares every time the player
+//! comes back up. How light it is, and whether the shops are open, goes by
+//! the time of day; see `daylight`.
+
+use std::collections::HashSet;
+
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+
+use crate::colors::{Color, LIGHT_BLUE, LIGHT_SEPIA, LIGHT_YELLOW, WHITE};
+use crate::entities::Objects;
+use crate::factions::Faction;
+use crate::items::{self, ItemTemplate};
+use crate::map::{from_dungeon_level, load_static_map, Map, Marks};
+use crate::trade::{Shop, Trader};
+use crate::{bestiary, dungeon};
+use crate::{Attitude, Object, PLAYER};
+
+const TOWN_MAP: &str = include_str!("../data/town.txt");
+
+const ARMOURER: &str = "armourer";
+const APOTHECARY: &str = "apothecary";
+/// the monster every shopkeeper is, whatever their shop
+const SHOPKEEPER: &str = "shopkeeper";
+pub const GUARD: &str = "town guard";
+
+// what shops keep in stock
+const SHOP_MIN_WARES: usize = 4;
+const SHOP_MAX_WARES: usize = 8;
+const SHOP_GOLD: i32 = 200;
+/// how many levels deeper than the player has been that shops' wares come from
+const SHOP_LOOKAHEAD: u32 = 2;
+
+/// the marks the town map can have, for who and what goes where
+const TOWN_MARKS: &str = "@<APGI";
+
+/// Read a town map laid out like `data/town.txt`, or say what's wrong with
+/// it: as for any map laid out by hand, with nothing marked on it but what
+/// the town has.
+pub fn parse(text: &str) -> Result<(Map, Marks), String> {
+    let (map, marks) = load_static_map(text)?;
+    for &(mark, _) in &marks {
+        if !TOWN_MARKS.contains(mark) {
+            return Err(format!("nothing goes at '{}'", mark));
+        }
+    }
+    if !marks.iter().any(|&(mark, _)| mark == '@') {
+        return Err("there's nowhere marked for the player".into());
+    }
+    Ok((map, marks))
+}
+
+/// whether the town map in the data reads, or what's wrong with it
+pub fn load() -> Result<(), String> {
+    parse(TOWN_MAP).map(|_| ()).map_err(|e| format!("data/town.txt is invalid: {}", e))
+}
+
+/// Lay out the town, with the player at the town square. `run` has made
+/// sure the town map in the data reads.
+pub fn make_town(objects: &mut Objects, rng: &mut impl Rng) -> Map {
+    let (mut map, marks) =
+        parse(TOWN_MAP).unwrap_or_else(|e| panic!("data/town.txt is invalid: {}", e));
+    // the whole town is known from the start
+    for (_, tile) in map.iter_mut() {
+        tile.explored = true;
+    }
+
+    objects.truncate(PLAYER + 1);
+    for (mark, (x, y)) in marks {
+        match mark {
+            '@' => objects[PLAYER].set_pos(x, y),
+            '<' => objects.push(Object::new(x, y, '<', dungeon::DOWN_STAIRS, WHITE, false)),
+            'A' => objects.push(shopkeeper(ARMOURER, x, y, LIGHT_BLUE, &map)),
+            'P' => objects.push(shopkeeper(APOTHECARY, x, y, LIGHT_YELLOW, &map)),
+            'G' => objects.push(bestiary::template(GUARD).spawn(x, y)),
+            'I' => {
+                let mut innkeeper = Object::new(x, y, '@', "innkeeper", LIGHT_SEPIA, true);
+                innkeeper.dialogue = Some("innkeeper".into());
+                innkeeper.attitude = Attitude::Friendly;
+                innkeeper.faction = Faction::Neutral;
+                objects.push(innkeeper);
+            }
+            _ => {}
+        }
+    }
+    restock(objects, 0, rng);
+    map
+}
+
+fn shopkeeper(name: &str, x: i32, y: i32, color: Color, map: &Map) -> Object {
+    let mut shopkeeper = bestiary::template(SHOPKEEPER).spawn(x, y);
+    shopkeeper.name = name.into();
+    shopkeeper.color = color;
+    shopkeeper.trader = Some(Trader {
+        wares: vec![],
+        gold: SHOP_GOLD,
+        shop: Some(shop_around(x, y, map)),
+    });
+    shopkeeper
+}
+
+/// the room the given spot is in, out to its walls and doors
+fn shop_around(x: i32, y: i32, map: &Map) -> Shop {
+    let mut shop = Shop {
+        left: x,
+        top: y,
+        right: x,
+        bottom: y,
+    };
+    let mut seen = HashSet::from([(x, y)]);
+    let mut to_visit = vec![(x, y)];
+    while let Some((x, y)) = to_visit.pop() {
+        shop.left = shop.left.min(x);
+        shop.top = shop.top.min(y);
+        shop.right = shop.right.max(x);
+        shop.bottom = shop.bottom.max(y);
+        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+            let next = (x + dx, y + dy);
+            let floor = map
+                .get(next.0, next.1)
+                .map_or(false, |tile| !tile.blocked && tile.door.is_none());
+            if floor && seen.insert(next) {
+                to_visit.push(next);
+            }
+        }
+    }
+    shop
+}
+
+/// Give every shop new wares and gold to buy with. They stock what's found a
+/// little deeper than the player has been so far, `deepest`.
+pub fn restock(objects: &mut Objects, deepest: u32, rng: &mut impl Rng) {
+    let level = deepest.max(1) + SHOP_LOOKAHEAD;
+    for object in objects.iter_mut() {
+        let name = object.name.clone();
+        if let Some(trader) = object.trader.as_mut() {
+            trader.wares = stock(&name, level, rng);
+            trader.gold = trader.gold.max(SHOP_GOLD);
+        }
+    }
+}
+
+/// the wares for the shop of the given name: the armourer sells gear, and the
+/// apothecary everything else
+fn stock(shop: &str, level: u32, rng: &mut impl Rng) -> Vec<Object> {
+    let sells = |template: &ItemTemplate| (template.equipment.is_some()) == (shop == ARMOURER);
+    let templates: Vec<_> = items::templates()
+        .iter()
+        .filter(|template| sells(template))
+        .collect();
+    let weights = templates
+        .iter()
+        .map(|template| from_dungeon_level(&template.spawn, level));
+    let dist = match WeightedIndex::new(weights) {
+        Ok(dist) => dist,
+        // nothing to sell yet
+        Err(_) => return vec![],
+    };
+    let count = rng.gen_range(SHOP_MIN_WARES..=SHOP_MAX_WARES);
+    (0..count)
+        .map(|_| templates[rng.sample(&dist)].spawn(0, 0))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::colors::WHITE;
+
+    fn town() -> (Map, Objects) {
+        let mut objects = Objects::from(vec![Object::new(0, 0, '@', "player", WHITE, true)]);
+        let map = make_town(&mut objects, &mut StdRng::seed_from_u64(1));
+        (map, objects)
+    }
+
+    #[test]
+    fn the_town_has_its_shops_guards_and_way_down() {
+        let (map, objects) = town();
+        assert!(!map[objects[PLAYER].pos()].blocked);
+        for name in [ARMOURER, APOTHECARY, GUARD, "innkeeper", dungeon::DOWN_STAIRS] {
+            assert!(objects.iter().any(|o| o.name == name), "no {} in town", name);
+        }
+        assert!(map.iter().all(|(_, tile)| tile.explored));
+    }
+
+    #[test]
+    fn shops_sell_their_own_kind_of_wares_in_their_own_room() {
+        let (_, objects) = town();
+        for shopkeeper in objects.iter().filter(|o| o.trader.is_some()) {
+            let trader = shopkeeper.trader.as_ref().unwrap();
+            let shop = trader.shop.unwrap();
+            assert!(shop.contains(shopkeeper.pos()));
+            assert!(!shop.contains(objects[PLAYER].pos()));
+            assert!(trader.wares.len() >= SHOP_MIN_WARES);
+            let gear = shopkeeper.name == ARMOURER;
+            assert!(trader.wares.iter().all(|ware| ware.equipment.is_some() == gear));
+        }
+    }
+}
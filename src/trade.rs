@@ -0,0 +1,429 @@
+//! Trading with neutral merchants met in the dungeon. The trade screen shows
+//! the player's pack on the left and the merchant's wares on the right; the
+//! player marks items on both sides, and whatever the two piles are worth
+//! apart is settled in gold.
+//!
+//! In a shop in town, the player can also take wares to pay for later. Taking
+//! them out of the shop unpaid is theft: they're stolen goods from then on,
+//! the shopkeeper and the town guards turn on the player, and the town's
+//! shops charge them more ever after.
+
+use std::cmp;
+
+use serde::{Deserialize, Serialize};
+
+use crate::colors::{GREEN, LIGHT_GREY, ORANGE, RED, WHITE, YELLOW};
+use crate::console::*;
+use crate::entities::{EntityId, Objects};
+use crate::input::KeyCode;
+use crate::items::item_value;
+use crate::town::GUARD;
+use crate::{stow, Game, Object, Tcod, PLAYER, SCREEN_HEIGHT, SCREEN_WIDTH};
+
+const TRADE_WIDTH: i32 = 76;
+const PANE_WIDTH: i32 = TRADE_WIDTH / 2;
+
+/// merchants buy items back for this fraction of what they sell them for
+const RESALE_FRACTION: f32 = 0.5;
+/// how much more the town's shops charge, and less they pay, for each
+/// point the player's reputation there is below nothing
+const MARKUP_PER_THEFT: f32 = 0.25;
+
+/// Someone willing to trade: what they have for sale, and how much gold they
+/// can pay out.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Trader {
+    pub wares: Vec<Object>,
+    pub gold: i32,
+    /// the shop they keep, if they keep one, rather than wander the dungeon
+    #[serde(default)]
+    pub shop: Option<Shop>,
+}
+
+/// The room a shopkeeper keeps shop in, from corner to corner: wares taken
+/// from them have to be paid for before the player leaves it.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct Shop {
+    pub left: i32,
+    pub top: i32,
+    pub right: i32,
+    pub bottom: i32,
+}
+
+impl Shop {
+    pub fn contains(&self, (x, y): (i32, i32)) -> bool {
+        (self.left..=self.right).contains(&x) && (self.top..=self.bottom).contains(&y)
+    }
+}
+
+/// what the player is paid for an item, with prices marked up by `markup`
+fn sell_price(item: &Object, markup: f32) -> i32 {
+    (item_value(item) as f32 * RESALE_FRACTION / markup) as i32
+}
+
+/// what the player pays for an item, with prices marked up by `markup`
+fn buy_price(item: &Object, markup: f32) -> i32 {
+    (item_value(item) as f32 * markup) as i32
+}
+
+/// how much more than usual the trader charges the player: shops in town
+/// charge thieves more, and merchants in the dungeon don't ask
+fn markup(game: &Game, trader: &Trader) -> f32 {
+    match trader.shop {
+        Some(_) => 1.0 + MARKUP_PER_THEFT * (-game.reputation).max(0) as f32,
+        None => 1.0,
+    }
+}
+
+/// whether this item is owed to the given shopkeeper; none for a trader
+/// with no shop, who lets nothing go unpaid for
+fn owed_to(item: &Object, shopkeeper: Option<EntityId>) -> bool {
+    shopkeeper.is_some() && item.owed == shopkeeper
+}
+
+/// Open the trade screen with `merchant`, until the player makes a deal or
+/// walks away.
+pub fn trade(tcod: &mut Tcod, game: &mut Game, merchant: &mut Object) {
+    let (name, id) = (merchant.name.as_str(), merchant.id);
+    let trader = merchant.trader.as_mut().expect("only traders trade");
+    // which items are marked on each side
+    let mut offered = vec![false; game.inventory.len()];
+    let mut wanted = vec![false; trader.wares.len()];
+    let mut note = String::new();
+    let markup = markup(game, trader);
+
+    loop {
+        let total = |items: &[Object], marked: &[bool], price: fn(&Object, f32) -> i32| -> i32 {
+            items
+                .iter()
+                .zip(marked)
+                .filter(|&(_, &marked)| marked)
+                .map(|(item, _)| price(item, markup))
+                .sum()
+        };
+        let give = total(&game.inventory, &offered, sell_price);
+        // whatever was taken earlier is paid for along with what's marked
+        let owed: i32 = game
+            .inventory
+            .iter()
+            .filter(|item| owed_to(item, id))
+            .map(|item| buy_price(item, markup))
+            .sum();
+        let take = total(&trader.wares, &wanted, buy_price) + owed;
+        let balance = give - take;
+        draw_trade_screen(tcod, game, trader, name, &offered, &wanted, markup, balance, &note);
+
+        let key = tcod.backend.wait_for_keypress();
+        note.clear();
+        match key.code {
+            KeyCode::Escape => return,
+            KeyCode::Enter => {
+                let sold = offered.iter().filter(|&&marked| marked).count();
+                let bought = wanted.iter().filter(|&&marked| marked).count();
+                if give == 0 && take == 0 {
+                    note = "Mark something to trade first.".into();
+                } else if give < take && game.gold < take - give {
+                    note = "You can't afford that.".into();
+                } else if give > take && trader.gold < give - take {
+                    note = format!("The {} can't pay that much.", name);
+                } else if game.inventory.len() - sold + bought > 26 {
+                    note = "You couldn't carry all that.".into();
+                } else if trader.wares.len() - bought + sold > 26 {
+                    note = format!("The {} has no room for all that.", name);
+                } else {
+                    settle(game, trader, name, id, &offered, &wanted, balance);
+                    return;
+                }
+            }
+            KeyCode::Spacebar => {
+                let taken = wanted.iter().filter(|&&marked| marked).count();
+                if trader.shop.is_none() {
+                    note = format!("The {} wants paying on the spot.", name);
+                } else if taken == 0 {
+                    note = "Mark something to take first.".into();
+                } else if game.inventory.len() + taken > 26 {
+                    note = "You couldn't carry all that.".into();
+                } else {
+                    take_unpaid(game, trader, name, id, &wanted);
+                    return;
+                }
+            }
+            _ => {
+                // lowercase letters mark the player's items, uppercase the trader's
+                let letter = key.printable;
+                if letter.is_ascii_lowercase() {
+                    let index = letter as usize - 'a' as usize;
+                    if let Some(item) = game.inventory.get(index) {
+                        if item.equipment.map_or(false, |e| e.equipped) {
+                            note = format!("Take off the {} before selling it.", item.name);
+                        } else if item.owed.is_some() {
+                            note = format!("You haven't paid for the {} yet.", item.name);
+                        } else if item.stolen && trader.shop.is_some() {
+                            note = format!("The {} won't touch stolen goods.", name);
+                        } else {
+                            offered[index] = !offered[index];
+                        }
+                    }
+                } else if letter.is_ascii_uppercase() {
+                    let index = letter as usize - 'A' as usize;
+                    if index < wanted.len() {
+                        wanted[index] = !wanted[index];
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// swap the marked items, and pay the difference in gold
+fn settle(
+    game: &mut Game,
+    trader: &mut Trader,
+    name: &str,
+    id: Option<EntityId>,
+    offered: &[bool],
+    wanted: &[bool],
+    balance: i32,
+) {
+    let mut sold = vec![];
+    for index in (0..offered.len()).rev().filter(|&index| offered[index]) {
+        sold.push(game.inventory.remove(index));
+    }
+    for item in game.inventory.iter_mut().filter(|item| owed_to(item, id)) {
+        item.owed = None;
+    }
+    for index in (0..wanted.len()).rev().filter(|&index| wanted[index]) {
+        stow(trader.wares.remove(index), &mut game.inventory);
+    }
+    trader.wares.extend(sold);
+    game.gold += balance;
+    trader.gold -= balance;
+
+    let msg = match balance {
+        0 => format!("You trade with the {}.", name),
+        b if b > 0 => format!("You trade with the {}, and get {} gold.", name, b),
+        b => format!("You trade with the {}, and pay {} gold.", name, -b),
+    };
+    game.messages.add(msg, GREEN);
+}
+
+/// put the marked wares in the player's pack, to be paid for before they
+/// leave the shop
+fn take_unpaid(
+    game: &mut Game,
+    trader: &mut Trader,
+    name: &str,
+    id: Option<EntityId>,
+    wanted: &[bool],
+) {
+    for index in (0..wanted.len()).rev().filter(|&index| wanted[index]) {
+        let mut item = trader.wares.remove(index);
+        item.owed = id;
+        stow(item, &mut game.inventory);
+    }
+    let msg = format!("\"Pay for those before you go,\" says the {}.", name);
+    game.messages.add(msg, YELLOW);
+}
+
+/// Anything the player carries out of a shop unpaid for is stolen: the
+/// shopkeeper and the town guards turn on them, and the town thinks less of
+/// them.
+pub fn catch_thieves(game: &mut Game, objects: &mut Objects) {
+    let player = objects[PLAYER].pos();
+    for id in 0..objects.len() {
+        let name = objects[id].name.clone();
+        let shopkeeper = objects[id].id;
+        let left = match &objects[id].trader {
+            Some(Trader {
+                shop: Some(shop), ..
+            }) => !shop.contains(player),
+            _ => false,
+        };
+        if !left || !game.inventory.iter().any(|item| owed_to(item, shopkeeper)) {
+            continue;
+        }
+        for item in game.inventory.iter_mut().filter(|item| owed_to(item, shopkeeper)) {
+            item.owed = None;
+            item.stolen = true;
+        }
+        game.reputation -= 1;
+        let msg = format!("You leave the {}'s shop without paying!", name);
+        game.messages.add(msg, ORANGE);
+        objects[id].provoke(game);
+        for guard in objects.iter_mut().filter(|object| object.name == GUARD) {
+            guard.provoke(game);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn draw_trade_screen(
+    tcod: &mut Tcod,
+    game: &Game,
+    trader: &Trader,
+    name: &str,
+    offered: &[bool],
+    wanted: &[bool],
+    markup: f32,
+    balance: i32,
+    note: &str,
+) {
+    let rows = cmp::max(game.inventory.len(), trader.wares.len()) as i32;
+    let height = rows + 7;
+    let mut window = Offscreen::new(TRADE_WIDTH, height);
+    window.set_default_foreground(WHITE);
+    window.print(0, 0, "Your pack");
+    window.print(PANE_WIDTH, 0, format!("The {}'s wares", name));
+
+    // the player's items by lowercase letter, the trader's by uppercase
+    let panes = [
+        (0, &game.inventory, offered, b'a', sell_price as fn(&Object, f32) -> i32),
+        (PANE_WIDTH, &trader.wares, wanted, b'A', buy_price),
+    ];
+    for (x, items, marked, first_letter, price) in panes {
+        for (index, item) in items.iter().enumerate() {
+            let letter = (first_letter + index as u8) as char;
+            let mark = if marked[index] { '*' } else { ' ' };
+            let equipped = item.equipment.map_or(false, |e| e.equipped);
+            window.set_default_foreground(if marked[index] {
+                YELLOW
+            } else if equipped {
+                LIGHT_GREY
+            } else {
+                WHITE
+            });
+            let name = item.display_name(game);
+            let text = format!("{}({}) {} - {}g", mark, letter, name, price(item, markup));
+            window.print_rect(x, 2 + index as i32, PANE_WIDTH - 1, 1, text);
+        }
+    }
+
+    window.set_default_foreground(WHITE);
+    let deal = match balance {
+        0 => "an even trade".to_string(),
+        b if b > 0 => format!("you get {} gold", b),
+        b => format!("you pay {} gold", -b),
+    };
+    window.print(
+        0,
+        rows + 3,
+        format!("You have {} gold. As marked, {}.", game.gold, deal),
+    );
+    window.set_default_foreground(RED);
+    window.print(0, rows + 4, note);
+    window.set_default_foreground(WHITE);
+    window.print(
+        0,
+        rows + 6,
+        match trader.shop {
+            Some(_) => "Letters mark, Enter trades, Space takes wares to pay later, Esc leaves.",
+            None => "Letters mark items, Enter makes the trade, Escape leaves.",
+        },
+    );
+
+    let x = SCREEN_WIDTH / 2 - TRADE_WIDTH / 2;
+    let y = SCREEN_HEIGHT / 2 - height / 2;
+    blit(&window, (0, 0), (TRADE_WIDTH, height), &mut tcod.screen, (x, y), 1.0, 0.85);
+    tcod.mark_map((x, y), (TRADE_WIDTH, height), false);
+    tcod.flush();
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::{items, new_game_state, Attitude};
+
+    /// a new game in town, with a dagger taken from the armourer unpaid
+    fn game_with_unpaid_dagger() -> (Game, Objects, usize) {
+        let (mut game, objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let armourer = objects.iter().position(|o| o.name == "armourer").unwrap();
+        let template = items::templates().iter().find(|t| t.name == "dagger").unwrap();
+        let mut dagger = template.spawn(0, 0);
+        dagger.owed = objects[armourer].id;
+        game.inventory.push(dagger);
+        (game, objects, armourer)
+    }
+
+    #[test]
+    fn merchants_buy_back_for_less_than_they_sell() {
+        let (game, _, _) = game_with_unpaid_dagger();
+        let dagger = game.inventory.last().unwrap();
+        let price = buy_price(dagger, 1.0);
+        assert_eq!(price, item_value(dagger));
+        assert!(price > 0);
+        assert_eq!(sell_price(dagger, 1.0), (price as f32 * RESALE_FRACTION) as i32);
+    }
+
+    #[test]
+    fn leaving_a_shop_unpaid_is_theft() {
+        let (mut game, mut objects, armourer) = game_with_unpaid_dagger();
+        catch_thieves(&mut game, &mut objects);
+        let dagger = game.inventory.last().unwrap();
+        assert!(dagger.stolen && dagger.owed.is_none());
+        assert_eq!(objects[armourer].attitude, Attitude::Hostile);
+        let guards: Vec<&Object> = objects.iter().filter(|o| o.name == GUARD).collect();
+        assert!(!guards.is_empty());
+        assert!(guards.iter().all(|guard| guard.attitude == Attitude::Hostile));
+        assert_eq!(game.reputation, -1);
+    }
+
+    #[test]
+    fn taking_wares_inside_the_shop_is_not_theft() {
+        let (mut game, mut objects, armourer) = game_with_unpaid_dagger();
+        let shop = objects[armourer].trader.as_ref().unwrap().shop.unwrap();
+        objects[PLAYER].set_pos(shop.left, shop.top);
+        catch_thieves(&mut game, &mut objects);
+        assert!(!game.inventory.last().unwrap().stolen);
+        assert_eq!(objects[armourer].attitude, Attitude::Neutral);
+        assert_eq!(game.reputation, 0);
+    }
+
+    #[test]
+    fn thieves_pay_more_in_town() {
+        let (mut game, objects, armourer) = game_with_unpaid_dagger();
+        let shop = objects[armourer].trader.as_ref().unwrap();
+        let dagger = game.inventory.last().unwrap();
+        let honest = buy_price(dagger, markup(&game, shop));
+        game.reputation = -2;
+        let dagger = game.inventory.last().unwrap();
+        assert!(buy_price(dagger, markup(&game, shop)) > honest);
+        let peddler = Trader {
+            wares: vec![],
+            gold: 0,
+            shop: None,
+        };
+        assert_eq!(markup(&game, &peddler), 1.0);
+    }
+
+    #[test]
+    fn bartering_swaps_the_marked_items_and_settles_the_difference() {
+        let (mut game, _) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        game.gold = 100;
+        game.inventory.push(items::template("healing potion").spawn(0, 0));
+        let mut peddler = Object {
+            name: "goblin peddler".into(),
+            trader: Some(Trader {
+                wares: vec![items::template("sword").spawn(0, 0)],
+                gold: 100,
+                shop: None,
+            }),
+            ..Default::default()
+        };
+        let wares = &peddler.trader.as_ref().unwrap().wares;
+        let balance = sell_price(&game.inventory[2], 1.0) - buy_price(&wares[0], 1.0);
+        // the equipped dagger can't be sold, so marking it does nothing
+        let mut tcod = Tcod::pressing("acA\n");
+        trade(&mut tcod, &mut game, &mut peddler);
+        let names = |items: &[Object]| -> Vec<String> {
+            items.iter().map(|item| item.name.clone()).collect()
+        };
+        let peddler = peddler.trader.unwrap();
+        assert_eq!(names(&game.inventory), ["dagger", "helmet", "sword"]);
+        assert_eq!(names(&peddler.wares), ["healing potion"]);
+        assert_eq!((game.gold, peddler.gold), (100 + balance, 100 - balance));
+    }
+}
@@ -0,0 +1,221 @@
+//! Traps. Spike pits, poison darts, fire, gas and teleport traps lie hidden on
+//! the floor until the player steps on one or spots it. Every step gives the player
+//! a small chance to notice traps right next to them, and searching on purpose
+//! looks further and more carefully; either way it's a roll against the
+//! player's perception.
+
+use rand::distributions::WeightedIndex;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::colors::{Color, LIGHT_GREY, RED};
+use crate::effects::{self, Effect, StatusEffect};
+use crate::entities::Objects;
+use crate::map::{from_dungeon_level, is_blocked, Transition};
+use crate::{Game, Object, COLOR_TELEPORTER, PLAYER, POISON_NUM_TURNS};
+
+/// what a search has to beat, with a d20 plus the player's perception
+const TRAP_DIFFICULTY: i32 = 18;
+/// noticing traps in passing is harder than searching for them
+const PASSIVE_SEARCH_PENALTY: i32 = 6;
+const SPIKE_DAMAGE: i32 = 8;
+const BURN_NUM_TURNS: i32 = 5;
+const CONFUSION_NUM_TURNS: i32 = 6;
+
+const COLOR_TRAP: Color = Color {
+    r: 200,
+    g: 60,
+    b: 60,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum TrapKind {
+    SpikePit,
+    PoisonDart,
+    Fire,
+    /// a puff of gas that confuses
+    Gas,
+    Teleport,
+}
+
+impl TrapKind {
+    fn name(self) -> &'static str {
+        match self {
+            TrapKind::SpikePit => "spike pit",
+            TrapKind::PoisonDart => "poison dart trap",
+            TrapKind::Fire => "fire trap",
+            TrapKind::Gas => "gas trap",
+            TrapKind::Teleport => "teleport trap",
+        }
+    }
+}
+
+/// A trap lying on the floor, sprung by whoever steps on it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Trap {
+    pub kind: TrapKind,
+    /// whether the player has yet to find it
+    pub hidden: bool,
+}
+
+/// a hidden trap of the given kind at the given spot
+pub fn make_trap(kind: TrapKind, x: i32, y: i32) -> Object {
+    let color = match kind {
+        TrapKind::Teleport => COLOR_TELEPORTER,
+        _ => COLOR_TRAP,
+    };
+    let mut trap = Object::new(x, y, '^', kind.name(), color, false);
+    trap.trap = Some(Trap { kind, hidden: true });
+    trap
+}
+
+/// A trap at the given spot, of a kind found at the given dungeon level.
+pub fn random_trap(x: i32, y: i32, level: u32, rng: &mut impl Rng) -> Object {
+    let kinds = [
+        (TrapKind::SpikePit, vec![Transition { level: 1, value: 50 }]),
+        (TrapKind::PoisonDart, vec![Transition { level: 2, value: 30 }]),
+        (TrapKind::Teleport, vec![Transition { level: 3, value: 20 }]),
+        (TrapKind::Fire, vec![Transition { level: 4, value: 20 }]),
+        (TrapKind::Gas, vec![Transition { level: 2, value: 20 }]),
+    ];
+    let weights = kinds
+        .iter()
+        .map(|(_, table)| from_dungeon_level(table, level));
+    let dist = WeightedIndex::new(weights).unwrap();
+    make_trap(kinds[rng.sample(&dist)].0, x, y)
+}
+
+/// Spring any trap lying where the player stands.
+pub fn spring(game: &mut Game, objects: &mut Objects) {
+    let pos = objects[PLAYER].pos();
+    let trap_id = objects
+        .iter()
+        .position(|object| object.pos() == pos && object.trap.is_some());
+    let trap_id = match trap_id {
+        Some(trap_id) => trap_id,
+        None => return,
+    };
+    let trap = objects[trap_id].trap.as_mut().unwrap();
+    trap.hidden = false;
+    let kind = trap.kind;
+    match kind {
+        TrapKind::SpikePit => {
+            game.messages.add(
+                format!("You fall into a spike pit, taking {} damage!", SPIKE_DAMAGE),
+                RED,
+            );
+            objects[PLAYER].take_damage(SPIKE_DAMAGE, game);
+        }
+        TrapKind::PoisonDart => {
+            game.messages.add("A poisoned dart shoots out of the wall!", RED);
+            let poison = StatusEffect {
+                effect: Effect::Poison,
+                turns: POISON_NUM_TURNS,
+            };
+            effects::apply(&mut objects[PLAYER], poison, game);
+        }
+        TrapKind::Fire => {
+            game.messages.add("Flames burst up from the floor!", RED);
+            let burn = StatusEffect {
+                effect: Effect::Burn,
+                turns: BURN_NUM_TURNS,
+            };
+            effects::apply(&mut objects[PLAYER], burn, game);
+        }
+        TrapKind::Gas => {
+            game.messages.add("A cloud of sickly gas puffs up around you!", RED);
+            let confusion = StatusEffect {
+                effect: Effect::Confusion,
+                turns: CONFUSION_NUM_TURNS,
+            };
+            effects::apply(&mut objects[PLAYER], confusion, game);
+        }
+        TrapKind::Teleport => {
+            let free: Vec<_> = game
+                .map
+                .iter()
+                .filter(|(_, tile)| !tile.blocked && !tile.deep_water && tile.teleport.is_none())
+                .map(|(pos, _)| pos)
+                .filter(|&(x, y)| !is_blocked(x, y, &game.map, objects))
+                .collect();
+            if !free.is_empty() {
+                let (x, y) = free[game.rng.gen_range(0..free.len())];
+                objects[PLAYER].set_pos(x, y);
+                let msg = "A glyph flares under your feet, and you are somewhere else!";
+                game.messages.add(msg, COLOR_TELEPORTER);
+            }
+        }
+    }
+}
+
+/// Look for hidden traps within `radius` of the player, each found with a
+/// perception roll against `TRAP_DIFFICULTY`. Returns whether any were.
+pub fn search(game: &mut Game, objects: &mut Objects, radius: i32) -> bool {
+    search_with_bonus(game, objects, radius, 0)
+}
+
+/// the search made without meaning to, every turn, of the tiles next to the
+/// player
+pub fn notice(game: &mut Game, objects: &mut Objects) {
+    search_with_bonus(game, objects, 1, -PASSIVE_SEARCH_PENALTY);
+}
+
+fn search_with_bonus(game: &mut Game, objects: &mut Objects, radius: i32, bonus: i32) -> bool {
+    let (x, y) = objects[PLAYER].pos();
+    let mut found = false;
+    let perception = objects[PLAYER].fighter.map_or(0, |f| f.perception);
+    for object in objects.iter_mut() {
+        let nearby = (object.x - x).abs() <= radius && (object.y - y).abs() <= radius;
+        if !nearby || !object.is_hidden() || !game.map.is_visible(object.x, object.y) {
+            continue;
+        }
+        if game.rng.gen_range(1..=20) + perception + bonus >= TRAP_DIFFICULTY {
+            object.trap.as_mut().unwrap().hidden = false;
+            game.messages
+                .add(format!("You find a {}!", object.name), LIGHT_GREY);
+            found = true;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::open_field;
+
+    #[test]
+    fn stepping_on_a_spike_pit_hurts_and_gives_it_away() {
+        let (mut game, mut objects) = open_field(20, 20);
+        objects.push(make_trap(TrapKind::SpikePit, 5, 5));
+        let hp = objects[PLAYER].fighter.unwrap().hp;
+        spring(&mut game, &mut objects);
+        assert_eq!(objects[PLAYER].fighter.unwrap().hp, hp - SPIKE_DAMAGE);
+        assert!(!objects[PLAYER + 1].is_hidden());
+    }
+
+    #[test]
+    fn teleport_traps_send_the_player_somewhere_free() {
+        let (mut game, mut objects) = open_field(20, 20);
+        objects.push(make_trap(TrapKind::Teleport, 5, 5));
+        game.map[(6, 5)].blocked = true;
+        spring(&mut game, &mut objects);
+        let (x, y) = objects[PLAYER].pos();
+        assert_ne!((x, y), (5, 5));
+        assert!(!game.map[(x, y)].blocked);
+    }
+
+    #[test]
+    fn searching_finds_only_traps_in_reach() {
+        let (mut game, mut objects) = open_field(20, 20);
+        objects[PLAYER].fighter.as_mut().unwrap().perception = TRAP_DIFFICULTY;
+        objects.push(make_trap(TrapKind::Gas, 7, 5));
+        objects.push(make_trap(TrapKind::Fire, 9, 5));
+        assert!(search(&mut game, &mut objects, 2));
+        assert!(!objects[PLAYER + 1].is_hidden());
+        assert!(objects[PLAYER + 2].is_hidden());
+        // noticing in passing only looks next to the player
+        notice(&mut game, &mut objects);
+        assert!(objects[PLAYER + 2].is_hidden());
+    }
+}
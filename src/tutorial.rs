@@ -0,0 +1,110 @@
+//! The tutorial: a small hand-made level, with hints that pop up the first
+//! time the player runs into each thing worth explaining.
+
+use rand::prelude::*;
+
+use crate::classes::Class;
+use crate::colors::LIGHT_CYAN;
+use crate::entities::Objects;
+use crate::map::make_tutorial_map;
+use crate::{msgbox, new_game_state, Game, Messages, Object, Tcod, PLAYER};
+
+const HINT_WIDTH: i32 = 50;
+
+/// Something to explain, and when to explain it.
+struct Hint {
+    trigger: fn(&Game, &Objects) -> bool,
+    text: &'static str,
+}
+
+const HINTS: &[Hint] = &[
+    Hint {
+        trigger: |_, _| true,
+        text: "Move with the arrow keys or the numpad; Home, PgUp, End and PgDn move \
+               diagonally. Head east, through the rooms, to find the stairs.",
+    },
+    Hint {
+        trigger: |_, objects| {
+            let here = objects[PLAYER].pos();
+            objects.iter().any(|o| o.item.is_some() && o.pos() == here)
+        },
+        text: "There's something lying here. Press 'g' to pick it up.",
+    },
+    Hint {
+        trigger: |game, _| game.inventory.iter().any(|item| item.equipment.is_none()),
+        text: "Press 'i' for your inventory, and the letter of an item to use it. \
+               A healing potion gives back some of your HP, shown at the bottom left.",
+    },
+    Hint {
+        trigger: |game, _| {
+            let unequipped = |item: &Object| item.equipment.map_or(false, |e| !e.equipped);
+            game.inventory.iter().any(unequipped)
+        },
+        text: "Equipment is used from the inventory too: pick it there to put it on, or \
+               to take it off. 'd' drops anything you don't want.",
+    },
+    Hint {
+        trigger: |game, objects| {
+            objects
+                .iter()
+                .any(|o| o.fighter.is_some() && o.ai.is_some() && game.map.is_visible(o.x, o.y))
+        },
+        text: "An orc! Attack it by walking into it. Keep an eye on your HP, and drink a \
+               potion if it runs low.",
+    },
+    Hint {
+        trigger: |_, objects| objects.iter().any(|o| o.corpse.is_some()),
+        text: "Killing monsters earns experience, and with enough of it you level up. \
+               Press 'c' to see how far you've got.",
+    },
+    Hint {
+        trigger: |game, _| game.inventory.iter().any(|item| item.name.contains("confusion")),
+        text: "Some scrolls need a target. When asked, move the cursor onto a monster (or a \
+               spot, for some) within range, or Tab between the monsters in view, and press \
+               Enter or left-click. Escape or a right-click changes your mind.",
+    },
+    Hint {
+        trigger: |game, objects| {
+            objects
+                .iter()
+                .any(|o| o.name == "stairs" && game.map.is_visible(o.x, o.y))
+        },
+        text: "Those are the stairs down. Stand on them and press '<' to finish the \
+               tutorial.",
+    },
+];
+
+/// Which hints have been shown so far.
+#[derive(Clone, Debug, Default)]
+pub struct Tutorial {
+    shown: Vec<bool>,
+}
+
+/// set up the tutorial level and a fresh player
+pub fn new_tutorial() -> (Game, Objects) {
+    let (mut game, mut objects) = new_game_state(StdRng::from_entropy(), Class::Warrior);
+    game.map = make_tutorial_map(&mut objects);
+    game.messages = Messages::new();
+    // everything goes by its real name while learning the game
+    game.appearances.clear();
+    game.messages
+        .add("Welcome to the tutorial! Hints appear as you go.", LIGHT_CYAN);
+    game.tutorial = Some(Tutorial {
+        shown: vec![false; HINTS.len()],
+    });
+    (game, objects)
+}
+
+/// show each hint the first time its trigger fires
+pub fn show_hints(tcod: &mut Tcod, game: &mut Game, objects: &Objects) {
+    let due: Vec<usize> = match game.tutorial.as_ref() {
+        Some(tutorial) => (0..HINTS.len())
+            .filter(|&i| !tutorial.shown[i] && (HINTS[i].trigger)(game, objects))
+            .collect(),
+        None => return,
+    };
+    for i in due {
+        msgbox(&format!("\n{}\n", HINTS[i].text), HINT_WIDTH, tcod);
+        game.tutorial.as_mut().unwrap().shown[i] = true;
+    }
+}
@@ -0,0 +1,271 @@
+//! Weather on the surface. The sky over the town changes every so often, in
+//! spells the game's seed decides, so a replayed game has the same weather.
+//! Rain and snow put out torches, fog and anything falling cut how far the
+//! player can see, and wading through snow slows everyone down. Whatever's
+//! falling is drawn over the map, moving from one frame to the next. None of
+//! it reaches below ground, though it carries on overhead.
+
+use crate::colors::{self, Color, LIGHT_BLUE, LIGHT_GREY, WHITE};
+use crate::console::*;
+use crate::{daylight, Camera, Game, Object, VIEW_HEIGHT, VIEW_WIDTH};
+
+/// how many turns each spell of weather lasts
+const SPELL_TURNS: u32 = 300;
+/// how often each kind of weather comes, spell by spell
+const CHANCES: [(Weather, u64); 4] = [
+    (Weather::Clear, 5),
+    (Weather::Rain, 2),
+    (Weather::Fog, 2),
+    (Weather::Snow, 1),
+];
+/// how fast anything goes through snow, in percent of its speed
+const SNOW_SPEED: i32 = 70;
+
+/// how much of the map has a drop or a flake falling on it, in percent
+const RAIN_DENSITY: u64 = 6;
+const SNOW_DENSITY: u64 = 4;
+/// how thick the fog is over what's seen through it, at the thinnest and
+/// the thickest
+const FOG_THICKNESS: (f32, f32) = (0.3, 0.6);
+const COLOR_RAIN: Color = LIGHT_BLUE;
+const COLOR_FOG: Color = Color {
+    r: 150,
+    g: 150,
+    b: 160,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Weather {
+    Clear,
+    Rain,
+    Fog,
+    Snow,
+}
+
+impl Weather {
+    /// how far the player can see through it, or 0 for as far as the light goes
+    fn sight_radius(self) -> i32 {
+        match self {
+            Weather::Clear => 0,
+            Weather::Rain => 10,
+            Weather::Snow => 7,
+            Weather::Fog => 4,
+        }
+    }
+
+    /// whether it puts out torches
+    fn douses_torches(self) -> bool {
+        matches!(self, Weather::Rain | Weather::Snow)
+    }
+
+    /// what the player is out in, for where the panel says they are
+    pub fn describe(self) -> Option<&'static str> {
+        match self {
+            Weather::Clear => None,
+            Weather::Rain => Some("in the rain"),
+            Weather::Fog => Some("in fog"),
+            Weather::Snow => Some("in the snow"),
+        }
+    }
+
+    /// what's said when it comes on
+    fn arrival(self) -> &'static str {
+        match self {
+            Weather::Clear => "The sky clears.",
+            Weather::Rain => "It starts to rain.",
+            Weather::Fog => "A fog rolls in.",
+            Weather::Snow => "It starts to snow.",
+        }
+    }
+}
+
+/// A well-mixed number from the given ones, for anything here that has to
+/// look random but come out the same every time.
+fn scramble(a: u64, b: u64) -> u64 {
+    let mut z = a ^ b.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// the weather over the surface on the given turn of the game with the given
+/// seed; the first spell is always clear
+pub fn weather_at(seed: u64, turn: u32) -> Weather {
+    let spell = turn / SPELL_TURNS;
+    if spell == 0 {
+        return Weather::Clear;
+    }
+    let total: u64 = CHANCES.iter().map(|&(_, chance)| chance).sum();
+    let mut roll = scramble(seed, spell as u64) % total;
+    for (weather, chance) in CHANCES {
+        if roll < chance {
+            return weather;
+        }
+        roll -= chance;
+    }
+    Weather::Clear
+}
+
+/// the weather the player is out in; always clear below ground
+pub fn around(game: &Game) -> Weather {
+    if daylight::on_surface(game) {
+        weather_at(game.seed, game.turn)
+    } else {
+        Weather::Clear
+    }
+}
+
+/// how far the player can see through the weather, or 0 for as far as the
+/// light goes
+pub fn sight_radius(game: &Game) -> i32 {
+    around(game).sight_radius()
+}
+
+/// whether torches go out in the weather the player is in
+pub fn douses_torches(game: &Game) -> bool {
+    around(game).douses_torches()
+}
+
+/// how fast something of the given speed goes in the weather
+pub fn slow(speed: i32, game: &Game) -> i32 {
+    match around(game) {
+        Weather::Snow => speed * SNOW_SPEED / 100,
+        _ => speed,
+    }
+}
+
+/// Tell the player in town when the weather changes on the turn that's just
+/// begun, and what it does to their torch.
+pub fn pass_time(game: &mut Game, player: &Object) {
+    if !daylight::on_surface(game) || game.turn == 0 {
+        return;
+    }
+    let before = weather_at(game.seed, game.turn - 1);
+    let now = weather_at(game.seed, game.turn);
+    if before == now {
+        return;
+    }
+    game.messages.add(now.arrival(), LIGHT_GREY);
+    if player.light.is_some() {
+        match (before.douses_torches(), now.douses_torches()) {
+            (false, true) => game.messages.add("Your torch sputters out.", LIGHT_GREY),
+            (true, false) => game.messages.add("You light your torch again.", LIGHT_GREY),
+            _ => {}
+        }
+    }
+}
+
+/// Draw the weather the player is in over the part of the map that's in
+/// view and open to the sky: drops of rain or flakes of snow falling a
+/// little further every frame, or drifting fog.
+pub fn draw(con: &mut Offscreen, game: &Game, camera: Camera, frame: u32) {
+    let weather = around(game);
+    if weather == Weather::Clear {
+        return;
+    }
+    let frame = frame as i64;
+    let view = (0..VIEW_HEIGHT).flat_map(|y| (0..VIEW_WIDTH).map(move |x| (x, y)));
+    for (x, y) in view {
+        let (map_x, map_y) = camera.to_map(x, y);
+        let open = game
+            .map
+            .get(map_x, map_y)
+            .map_or(false, |tile| tile.visible && !tile.block_sight);
+        if !open {
+            continue;
+        }
+        // where this cell's drop or flake started from, to fall here now
+        let (start_x, start_y) = match weather {
+            Weather::Rain => (map_x as i64 + frame, map_y as i64 - 2 * frame),
+            Weather::Snow => (map_x as i64 + (frame / 4) % 2, map_y as i64 - frame / 2),
+            _ => (map_x as i64 / 4 + frame / 8, map_y as i64 / 3),
+        };
+        let roll = scramble(start_x as u64, (start_y as u64) << 32) % 100;
+        let empty = matches!(con.get_char(x, y), ' ' | '\0');
+        match weather {
+            Weather::Rain if roll < RAIN_DENSITY && empty => {
+                con.set_default_foreground(COLOR_RAIN);
+                con.put_char(x, y, '/', BackgroundFlag::None);
+            }
+            Weather::Snow if roll < SNOW_DENSITY && empty => {
+                con.set_default_foreground(WHITE);
+                con.put_char(x, y, '*', BackgroundFlag::None);
+            }
+            Weather::Fog => {
+                let (thinnest, thickest) = FOG_THICKNESS;
+                let thickness = thinnest + (thickest - thinnest) * roll as f32 / 100.0;
+                let under = con.get_char_background(x, y);
+                let color = colors::lerp(under, COLOR_FOG, thickness);
+                con.set_char_background(x, y, color, BackgroundFlag::Set);
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    use super::*;
+    use crate::classes::Class;
+    use crate::speed::NORMAL_SPEED;
+    use crate::{new_game_state, PLAYER};
+
+    /// the first turn of the first spell of the given weather in the game
+    /// with the given seed
+    fn first(weather: Weather, seed: u64) -> u32 {
+        (0..)
+            .map(|spell| spell * SPELL_TURNS)
+            .find(|&turn| weather_at(seed, turn) == weather)
+            .unwrap()
+    }
+
+    #[test]
+    fn the_weather_starts_clear_and_follows_the_seed() {
+        for seed in 0..20 {
+            assert_eq!(weather_at(seed, 0), Weather::Clear);
+            assert_eq!(weather_at(seed, SPELL_TURNS - 1), Weather::Clear);
+            let turn = 7 * SPELL_TURNS;
+            assert_eq!(weather_at(seed, turn), weather_at(seed, turn + SPELL_TURNS - 1));
+        }
+        let seen: Vec<Weather> = (1..200).map(|spell| weather_at(1, spell * SPELL_TURNS)).collect();
+        for weather in [Weather::Clear, Weather::Rain, Weather::Fog, Weather::Snow] {
+            assert!(seen.contains(&weather), "{:?} never comes", weather);
+        }
+    }
+
+    #[test]
+    fn rain_and_snow_put_out_torches_and_snow_slows_everyone() {
+        let (mut game, objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        let player = &objects[PLAYER];
+        let torch = player.light_radius(&game);
+        assert!(torch > 0);
+        game.turn = first(Weather::Rain, game.seed);
+        assert_eq!(player.light_radius(&game), 0);
+        assert_eq!(player.speed(&game), NORMAL_SPEED);
+        game.turn = first(Weather::Snow, game.seed);
+        assert_eq!(player.light_radius(&game), 0);
+        assert_eq!(player.speed(&game), NORMAL_SPEED * SNOW_SPEED / 100);
+        // none of it below ground
+        game.dungeon_level = 1;
+        assert_eq!(player.light_radius(&game), torch);
+        assert_eq!(player.speed(&game), NORMAL_SPEED);
+    }
+
+    #[test]
+    fn fog_cuts_sight_and_the_player_hears_the_weather_change() {
+        let (mut game, objects) = new_game_state(StdRng::seed_from_u64(1), Class::Warrior);
+        assert_eq!(sight_radius(&game), 0);
+        game.turn = first(Weather::Fog, game.seed);
+        assert_eq!(sight_radius(&game), Weather::Fog.sight_radius());
+        pass_time(&mut game, &objects[PLAYER]);
+        let last = game.messages.iter().last().unwrap();
+        assert_eq!(last.text, "A fog rolls in.");
+        game.turn = first(Weather::Rain, game.seed);
+        pass_time(&mut game, &objects[PLAYER]);
+        let last = game.messages.iter().last().unwrap();
+        assert_eq!(last.text, "Your torch sputters out.");
+    }
+}
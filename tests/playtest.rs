@@ -0,0 +1,37 @@
+//! Lets the scripted bot play complete games against fixed seeds, checking
+//! that nothing panics and that the core invariants hold after every turn.
+
+use roguelike::sim::Simulation;
+
+const SEEDS: std::ops::Range<u64> = 0..32;
+const MAX_TURNS: u32 = 5000;
+
+#[test]
+fn bot_plays_fixed_seeds() {
+    for seed in SEEDS {
+        let mut sim = Simulation::new(seed);
+        sim.check_invariants()
+            .unwrap_or_else(|e| panic!("seed {}, new game: {}", seed, e));
+        while sim.player_alive() && sim.turn() < MAX_TURNS {
+            sim.bot_turn();
+            sim.check_invariants()
+                .unwrap_or_else(|e| panic!("seed {}, turn {}: {}", seed, sim.turn(), e));
+        }
+    }
+}
+
+#[test]
+fn same_seed_plays_the_same_game() {
+    let mut first = Simulation::new(42);
+    let mut second = Simulation::new(42);
+    while first.player_alive() && first.turn() < 1000 {
+        first.bot_turn();
+        second.bot_turn();
+        assert_eq!(
+            (first.dungeon_level(), first.player_hp()),
+            (second.dungeon_level(), second.player_hp()),
+            "games diverged on turn {}",
+            first.turn()
+        );
+    }
+}
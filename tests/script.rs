@@ -0,0 +1,85 @@
+//! Plays the scripts in `tests/scripts` headless, as `--headless --script`
+//! would, failing on the first command or check in them that fails.
+
+use roguelike::sim::run_headless;
+
+const SEED: u64 = 7;
+
+fn play(name: &str, script: &str) {
+    if let Err(e) = run_headless(SEED, Some(script)) {
+        panic!("{}: {}", name, e);
+    }
+}
+
+#[test]
+fn combat() {
+    play("combat", include_str!("scripts/combat.txt"));
+}
+
+#[test]
+fn item_use() {
+    play("items", include_str!("scripts/items.txt"));
+}
+
+#[test]
+fn abilities() {
+    play("abilities", include_str!("scripts/abilities.txt"));
+}
+
+#[test]
+fn spells() {
+    play("spells", include_str!("scripts/spells.txt"));
+}
+
+#[test]
+fn archery() {
+    play("archery", include_str!("scripts/archery.txt"));
+}
+
+#[test]
+fn throwing() {
+    play("throwing", include_str!("scripts/throwing.txt"));
+}
+
+#[test]
+fn encumbrance() {
+    play("encumbrance", include_str!("scripts/encumbrance.txt"));
+}
+
+#[test]
+fn equipment() {
+    play("equipment", include_str!("scripts/equipment.txt"));
+}
+
+#[test]
+fn enchantment() {
+    play("enchantment", include_str!("scripts/enchantment.txt"));
+}
+
+#[test]
+fn identification() {
+    play("identify", include_str!("scripts/identify.txt"));
+}
+
+#[test]
+fn level_transitions() {
+    play("stairs", include_str!("scripts/stairs.txt"));
+}
+
+#[test]
+fn failed_checks_are_reported() {
+    let e = run_headless(SEED, Some("wait\nexpect depth = 3\n")).unwrap_err();
+    assert_eq!(e, "line 2: depth is 0, expected = 3");
+}
+
+#[test]
+fn stats_are_checked_against_each_other() {
+    let e = run_headless(SEED, Some("expect hp = max_hp\nexpect hp < max_hp\n")).unwrap_err();
+    assert_eq!(e, "line 2: hp is 100, expected < max_hp (100)");
+}
+
+#[test]
+fn bad_scripts_are_rejected() {
+    let e = run_headless(SEED, Some("wait\nmove up\n")).unwrap_err();
+    assert_eq!(e, "line 2: unknown direction: up");
+}